@@ -1,35 +1,50 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use log::info;
 
 use rg_common::arguments::Arguments;
-use rg_common::{AppFiles, VarRegistry};
+use rg_common::{AppFiles, CancellationToken, Capabilities, CommandRegistry, VarRegistry};
 
 use rg_common::config::Config;
 
+use crate::watchdog::Watchdog;
+
 pub(crate) struct App {
     arguments: Arguments,
-    exit_flag: AtomicBool,
+    exit: CancellationToken,
     started_at: Instant,
     config: Arc<Mutex<Config>>,
     files: Arc<Mutex<AppFiles>>,
     vars: VarRegistry<Config>,
+    commands: CommandRegistry,
+    caps: Capabilities,
+    watchdog: Watchdog,
 }
 
 impl App {
     pub(crate) fn new(args: Arguments) -> Self {
         let mut files = AppFiles::new(&args);
-        let cfg = Arc::new(Mutex::new(Config::load("config.toml", &mut files)));
-        info!("Loaded config: {:?}", cfg.lock().unwrap());
+        let cfg = Config::load_or_create("config.toml", &mut files)
+            .unwrap_or_else(|e| panic!("Unable to load config: {e:?}"));
+        info!("Loaded config: {:?}", cfg);
+        Self::with_config(args, files, cfg)
+    }
+
+    pub(crate) fn with_config(args: Arguments, files: AppFiles, config: Config) -> Self {
+        let threshold = Duration::from_secs_f64(config.watchdog.threshold.max(0.0));
+        let cfg = Arc::new(Mutex::new(config));
         App {
             arguments: args,
-            exit_flag: AtomicBool::new(false),
+            exit: CancellationToken::new(),
             started_at: Instant::now(),
             config: cfg.clone(),
             files: Arc::new(Mutex::new(files)),
             vars: VarRegistry::new(cfg),
+            commands: CommandRegistry::default(),
+            caps: Capabilities::new(),
+            watchdog: Watchdog::new(threshold),
         }
     }
 
@@ -37,12 +52,60 @@ impl App {
         &self.arguments
     }
 
+    ///
+    /// Path of the writable user file inside profile dir
+    ///
+    pub(crate) fn user_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.files.lock().unwrap().user_path(path)
+    }
+
+    ///
+    /// Dir of the writable user files
+    ///
+    pub(crate) fn profile_dir(&self) -> PathBuf {
+        self.files.lock().unwrap().profile_dir().to_path_buf()
+    }
+
+    pub(crate) fn commands(&self) -> &CommandRegistry {
+        &self.commands
+    }
+
+    ///
+    /// Optional subsystems available in this run
+    ///
+    pub(crate) fn caps(&self) -> &Capabilities {
+        &self.caps
+    }
+
+    ///
+    /// Long-running threads register here to be checked for hangs
+    ///
+    pub(crate) fn watchdog(&self) -> &Watchdog {
+        &self.watchdog
+    }
+
+    pub(crate) fn vars(&self) -> &VarRegistry<Config> {
+        &self.vars
+    }
+
     pub(crate) fn config(&self) -> &Arc<Mutex<Config>> {
         &self.config
     }
 
     pub(crate) fn exit_flag(&self) -> bool {
-        self.exit_flag.load(Ordering::Relaxed)
+        self.exit.is_cancelled()
+    }
+
+    ///
+    /// Token cancelled on exit, long-running work should use its child
+    ///
+    pub(crate) fn exit_token(&self) -> &CancellationToken {
+        &self.exit
+    }
+
+    pub(crate) fn exit(&self) {
+        info!("Exit requested");
+        self.exit.cancel();
     }
 
     pub(crate) fn elapsed(&self) -> Duration {
@@ -1,85 +1,236 @@
-use std::collections::vec_deque::Iter;
 use std::collections::VecDeque;
+use std::env;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use log::{LevelFilter, Record};
+use log::{info, Level, LevelFilter, Record};
 use log4rs::append::console::ConsoleAppender;
-use log4rs::append::file::FileAppender;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::Trigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::{LogFile, RollingFileAppender};
 use log4rs::append::Append;
 use log4rs::config::{Appender, Logger, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use log4rs::{Config, Handle};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
 
 use crate::error::AppError;
 
+const LOG_FILE: &str = "app.log";
+const LOG_FILE_PATTERN: &str = "app.{}.log";
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+const LOG_FILES_TO_KEEP: u32 = 5;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+///
+/// Target of the `grep` and `log_page` command output, such lines are not searched or paged,
+/// so results of previous commands are not shown again
+///
+pub(crate) const GREP_TARGET: &str = "grep";
+
+///
+/// Line retained in console buffer
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LogLine {
+    pub level: Level,
+    /// Module path of the source
+    pub target: String,
+    pub text: String,
+}
+
+impl Display for LogLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} - {}", self.level, self.text)
+    }
+}
+
+///
+/// Console view filter: minimum severity and optional source (module path or its part)
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LogFilter {
+    level: LevelFilter,
+    source: Option<String>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            level: LevelFilter::Trace,
+            source: None,
+        }
+    }
+}
+
+impl LogFilter {
+    pub(crate) fn new(level: LevelFilter, source: Option<&str>) -> Self {
+        LogFilter {
+            level,
+            source: source.filter(|s| !s.is_empty()).map(str::to_string),
+        }
+    }
+
+    pub(crate) fn matches(&self, line: &LogLine) -> bool {
+        line.level <= self.level
+            && self.source.as_ref().is_none_or(|src| {
+                line.target.starts_with(src.as_str()) || line.target.split("::").any(|p| p == src)
+            })
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct AppLogger {
-    tx: SyncSender<String>,
+    tx: SyncSender<LogLine>,
 }
 
 pub(crate) struct AppLoggerBuffer {
-    rx: Receiver<String>,
+    rx: Receiver<LogLine>,
     max_size: usize,
-    buffer: VecDeque<String>,
+    buffer: VecDeque<LogLine>,
+    filter: LogFilter,
 }
 
-impl AppLoggerBuffer {}
-
 fn create_app_logger(max_size: usize) -> (AppLogger, AppLoggerBuffer) {
-    let (tx, rx): (SyncSender<String>, Receiver<String>) = mpsc::sync_channel(max_size);
+    let (tx, rx): (SyncSender<LogLine>, Receiver<LogLine>) = mpsc::sync_channel(max_size);
     let buf = AppLoggerBuffer {
         rx,
         max_size,
         buffer: VecDeque::new(),
+        filter: LogFilter::default(),
     };
     let logger = AppLogger { tx };
     (logger, buf)
 }
 
-pub(crate) fn init() -> Result<(Handle, AppLoggerBuffer), AppError> {
-    let stdout = ConsoleAppender::builder().build();
-    let file = FileAppender::builder()
+fn day_of(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |v| v.as_secs() / SECONDS_PER_DAY)
+}
+
+///
+/// Rolls log file when it exceeds size limit or when day (UTC) changes
+///
+#[derive(Debug)]
+struct SizeOrDayTrigger {
+    limit: u64,
+    day: AtomicU64,
+}
+
+impl SizeOrDayTrigger {
+    fn new(limit: u64, log_file: &Path) -> Self {
+        // Log left from previous days should be rolled on first write
+        let day = fs::metadata(log_file)
+            .and_then(|m| m.modified())
+            .map_or_else(|_| day_of(SystemTime::now()), day_of);
+        SizeOrDayTrigger {
+            limit,
+            day: AtomicU64::new(day),
+        }
+    }
+}
+
+impl Trigger for SizeOrDayTrigger {
+    fn trigger(&self, file: &LogFile) -> anyhow::Result<bool> {
+        let today = day_of(SystemTime::now());
+        let day_changed = self.day.swap(today, Ordering::Relaxed) != today;
+        Ok(day_changed || file.len_estimate() > self.limit)
+    }
+
+    fn is_pre_process(&self) -> bool {
+        false
+    }
+}
+
+fn rolling_file_appender(log_dir: &Path) -> Result<RollingFileAppender, AppError> {
+    let log_file = log_dir.join(LOG_FILE);
+    let pattern = log_dir.join(LOG_FILE_PATTERN);
+    let roller =
+        FixedWindowRoller::builder().build(&pattern.to_string_lossy(), LOG_FILES_TO_KEEP)?;
+    let trigger = SizeOrDayTrigger::new(MAX_LOG_SIZE, &log_file);
+    let appender = RollingFileAppender::builder()
         .encoder(Box::new(PatternEncoder::new("{d} - {m}{n}")))
-        .build("app.log")?;
-    let (logger, buf) = create_app_logger(400);
-    let config = Config::builder()
-        .appender(Appender::builder().build("stdout", Box::new(stdout)))
-        .appender(Appender::builder().build("file", Box::new(file)))
-        .appender(Appender::builder().build("app", Box::new(logger)))
-        .logger(Logger::builder().build("app", LevelFilter::Debug))
         .build(
-            Root::builder()
-                .appender("stdout")
-                .appender("app")
-                .appender("file")
-                .build(LevelFilter::Info),
+            log_file,
+            Box::new(CompoundPolicy::new(Box::new(trigger), Box::new(roller))),
         )?;
+    Ok(appender)
+}
 
-    let handle = log4rs::init_config(config)?;
-    Ok((handle, buf))
+///
+/// Writes environment info at the top of each session to simplify bug reports triage
+///
+pub(crate) fn log_session_header() {
+    info!("===== Session started =====");
+    info!("Version: {}", env!("CARGO_PKG_VERSION"));
+    info!("OS: {} ({})", env::consts::OS, env::consts::ARCH);
+    info!("Arguments: {}", env::args().collect::<Vec<_>>().join(" "));
+    info!("===========================");
+}
+
+///
+/// Existing log files, current one first
+///
+fn log_files(log_dir: &Path) -> Vec<PathBuf> {
+    let rolled = (0..LOG_FILES_TO_KEEP)
+        .map(|i| log_dir.join(LOG_FILE_PATTERN.replace("{}", &i.to_string())));
+    std::iter::once(log_dir.join(LOG_FILE))
+        .chain(rolled)
+        .filter(|p| p.is_file())
+        .collect()
+}
+
+///
+/// Packs current and rolled log files into zip archive. Returns number of packed files.
+///
+pub(crate) fn dump_logs(log_dir: &Path, archive: &Path) -> io::Result<usize> {
+    let files = log_files(log_dir);
+    let mut zip = ZipWriter::new(File::create(archive)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for path in files.iter() {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        zip.start_file(name, options)?;
+        io::copy(&mut File::open(path)?, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(files.len())
 }
 
-pub(crate) fn build_dedicated_config() -> Result<Config, AppError> {
+pub(crate) fn init(log_dir: &Path) -> Result<(Handle, AppLoggerBuffer), AppError> {
     let stdout = ConsoleAppender::builder().build();
-    let file = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new("{d} - {m}{n}")))
-        .build("app.log")?;
+    let file = rolling_file_appender(log_dir)?;
+    let (logger, buf) = create_app_logger(400);
     let config = Config::builder()
         .appender(Appender::builder().build("stdout", Box::new(stdout)))
         .appender(Appender::builder().build("file", Box::new(file)))
+        .appender(Appender::builder().build("app", Box::new(logger)))
+        .logger(Logger::builder().build("app", LevelFilter::Debug))
         .build(
             Root::builder()
                 .appender("stdout")
+                .appender("app")
                 .appender("file")
                 .build(LevelFilter::Info),
         )?;
 
-    Ok(config)
+    let handle = log4rs::init_config(config)?;
+    Ok((handle, buf))
 }
 
 impl Append for AppLogger {
     fn append(&self, record: &Record) -> anyhow::Result<()> {
-        let msg = format!("{} - {}", record.level(), record.args());
+        let msg = LogLine {
+            level: record.level(),
+            target: record.target().to_string(),
+            text: record.args().to_string(),
+        };
         match self.tx.try_send(msg) {
             Ok(_) => Ok(()),
             Err(e) => {
@@ -104,18 +255,54 @@ impl AppLoggerBuffer {
         }
     }
 
-    pub(crate) fn iter(&self) -> Iter<String> {
-        self.buffer.iter()
+    pub(crate) fn set_filter(&mut self, filter: LogFilter) {
+        self.filter = filter;
+    }
+
+    ///
+    /// Retained lines passing current filter, oldest first
+    ///
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = &LogLine> {
+        self.buffer.iter().filter(|l| self.filter.matches(l))
+    }
+
+    ///
+    /// Page of filtered lines counting from the newest one (page 0), lines are ordered oldest first
+    ///
+    pub(crate) fn page(&self, page: usize, page_size: usize) -> Vec<&LogLine> {
+        let mut result: Vec<_> = self
+            .iter()
+            .filter(|l| l.target != GREP_TARGET)
+            .rev()
+            .skip(page.saturating_mul(page_size))
+            .take(page_size)
+            .collect();
+        result.reverse();
+        result
+    }
+
+    ///
+    /// Filtered lines containing `pattern`, case insensitive
+    ///
+    pub(crate) fn search(&self, pattern: &str) -> Vec<&LogLine> {
+        let pattern = pattern.to_lowercase();
+        self.iter()
+            .filter(|l| l.target != GREP_TARGET && l.text.to_lowercase().contains(&pattern))
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod test {
 
-    use log::Record;
+    use std::io::Read;
+    use std::{env, fs, process};
+
+    use log::{Level, LevelFilter, Record};
     use log4rs::append::Append;
+    use zip::ZipArchive;
 
-    use crate::app_logger::create_app_logger;
+    use crate::app_logger::{create_app_logger, dump_logs, LogFilter, GREP_TARGET};
 
     #[test]
     fn buffer_overflow() {
@@ -157,4 +344,72 @@ mod test {
         buf.update();
         assert_eq!(5, buf.buffer.len());
     }
+
+    #[test]
+    fn filter_and_search() {
+        let (logger, mut buf) = create_app_logger(10);
+        let lines = [
+            (Level::Info, "app::net", "Connected to server"),
+            (Level::Warn, "app::net", "Packet lost"),
+            (Level::Error, "rg_ecs::entity", "Archetype mismatch"),
+            (Level::Debug, "app::client", "Frame done"),
+            (Level::Info, GREP_TARGET, "packet lost"),
+        ];
+        for (level, target, text) in lines {
+            logger
+                .append(
+                    &Record::builder()
+                        .level(level)
+                        .target(target)
+                        .args(format_args!("{text}"))
+                        .build(),
+                )
+                .unwrap();
+        }
+        buf.update();
+        assert_eq!(5, buf.iter().count());
+
+        buf.set_filter(LogFilter::new(LevelFilter::Warn, None));
+        assert_eq!(2, buf.iter().count());
+        buf.set_filter(LogFilter::new(LevelFilter::Info, Some("net")));
+        let texts: Vec<_> = buf.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(vec!["Connected to server", "Packet lost"], texts);
+        buf.set_filter(LogFilter::new(LevelFilter::Trace, Some("rg_ecs")));
+        assert_eq!(1, buf.iter().count());
+
+        buf.set_filter(LogFilter::default());
+        let found = buf.search("PACKET");
+        assert_eq!(1, found.len());
+        assert_eq!("WARN - Packet lost", found[0].to_string());
+
+        // Output of console commands is not paged
+        let page: Vec<_> = buf.page(1, 2).iter().map(|l| l.level).collect();
+        assert_eq!(vec![Level::Info, Level::Warn], page);
+        assert!(buf.page(2, 2).is_empty());
+    }
+
+    #[test]
+    fn dump() {
+        let dir = env::temp_dir().join(format!("rg_log_dump_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("app.log"), "current").unwrap();
+        fs::write(dir.join("app.0.log"), "x".repeat(600)).unwrap();
+        fs::write(dir.join("other.log"), "ignored").unwrap();
+        let archive = dir.join("logs.zip");
+
+        assert_eq!(2, dump_logs(&dir, &archive).unwrap());
+
+        let mut zip = ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+        assert_eq!(2, zip.len());
+        let mut text = String::new();
+        zip.by_name("app.log")
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!("current", text);
+        assert_eq!(600, zip.by_name("app.0.log").unwrap().size());
+        assert!(zip.by_name("other.log").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
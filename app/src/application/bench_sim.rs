@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rg_common::{Arguments, Stopwatch};
+use serde::Serialize;
+
+use crate::{app::App, client::Client, error::AppError, server::Server};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+///
+/// Machine-readable result of the simulation benchmark
+///
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    clients: usize,
+    connected: usize,
+    ticks: usize,
+    connect_secs: f64,
+    total_secs: f64,
+    ticks_per_sec: f64,
+    tick_mean_ms: f64,
+    tick_p50_ms: f64,
+    tick_p99_ms: f64,
+    tick_max_ms: f64,
+}
+
+impl BenchReport {
+    fn new(
+        clients: usize,
+        connected: usize,
+        connect_time: Duration,
+        total_time: Duration,
+        mut ticks: Vec<Duration>,
+    ) -> Self {
+        ticks.sort_unstable();
+        let ms = |d: Duration| 1000.0 * d.as_secs_f64();
+        let percentile = |p: f64| {
+            ticks
+                .get(((ticks.len() as f64 - 1.0) * p).round() as usize)
+                .map_or(0.0, |v| ms(*v))
+        };
+        let sum: Duration = ticks.iter().sum();
+        BenchReport {
+            clients,
+            connected,
+            ticks: ticks.len(),
+            connect_secs: connect_time.as_secs_f64(),
+            total_secs: total_time.as_secs_f64(),
+            ticks_per_sec: ticks.len() as f64 / total_time.as_secs_f64().max(f64::EPSILON),
+            tick_mean_ms: ms(sum) / ticks.len().max(1) as f64,
+            tick_p50_ms: percentile(0.5),
+            tick_p99_ms: percentile(0.99),
+            tick_max_ms: ticks.last().map_or(0.0, |v| ms(*v)),
+        }
+    }
+}
+
+fn step_clients(app: &Arc<App>, clients: &mut [Client]) {
+    for c in clients.iter_mut() {
+        c.frame_start();
+        c.update(app);
+        c.frame_end();
+    }
+}
+
+///
+/// Runs server and fake clients over loopback without rendering and logging,
+/// then prints report to stdout.
+///
+pub(crate) fn run_bench_sim(args: Arguments) -> Result<(), AppError> {
+    let app = Arc::new(App::new(args.clone()));
+    let mut server = Server::new(&app)?;
+    let mut clients: Vec<_> = (0..args.bench_clients())
+        .map(|i| {
+            let mut client = Client::new(&app);
+            client.set_name(format!("Client {}", i + 1));
+            client
+        })
+        .collect();
+
+    let mut watch = Stopwatch::start();
+    while watch.elapsed() < CONNECT_TIMEOUT && !clients.iter().all(|c| c.is_connected()) {
+        step_clients(&app, &mut clients);
+        server.update()?;
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let connect_time = watch.restart();
+    let connected = server.client_count();
+
+    let mut ticks = Vec::with_capacity(args.bench_ticks());
+    for _ in 0..args.bench_ticks() {
+        step_clients(&app, &mut clients);
+        // Only server update is measured
+        watch.lap();
+        server.update()?;
+        ticks.push(watch.lap());
+    }
+    let report = BenchReport::new(
+        clients.len(),
+        connected,
+        connect_time,
+        watch.elapsed(),
+        ticks,
+    );
+    let out = toml::to_string(&report).map_err(|e| AppError {
+        message: e.to_string(),
+    })?;
+    println!("{out}");
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use rg_common::Arguments;
+use serde::Serialize;
+
+use crate::{app::App, client::Client, error::AppError, net::PlayerInput, server::server_init};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+///
+/// Machine-readable result of the bot run
+///
+#[derive(Debug, Serialize)]
+struct BotReport {
+    bots: usize,
+    connected: usize,
+    connect_secs: f64,
+    total_secs: f64,
+    inputs_sent: u64,
+    inputs_per_sec: f64,
+    bytes_sent_per_sec: f64,
+    ping_mean_ms: f64,
+    ping_p50_ms: f64,
+    ping_p99_ms: f64,
+    ping_max_ms: f64,
+}
+
+impl BotReport {
+    fn new(
+        bots: &[Client],
+        connect_time: Duration,
+        total_time: Duration,
+        inputs_sent: u64,
+        mut pings: Vec<f64>,
+    ) -> Self {
+        pings.sort_unstable_by(f64::total_cmp);
+        let percentile = |p: f64| {
+            pings
+                .get(((pings.len() as f64 - 1.0) * p).round() as usize)
+                .map_or(0.0, |v| 1000.0 * v)
+        };
+        let secs = total_time.as_secs_f64().max(f64::EPSILON);
+        BotReport {
+            bots: bots.len(),
+            connected: bots.iter().filter(|c| c.is_connected()).count(),
+            connect_secs: connect_time.as_secs_f64(),
+            total_secs: total_time.as_secs_f64(),
+            inputs_sent,
+            inputs_per_sec: inputs_sent as f64 / secs,
+            bytes_sent_per_sec: bots.iter().map(Client::bytes_sent).sum::<u64>() as f64 / secs,
+            ping_mean_ms: 1000.0 * pings.iter().sum::<f64>() / pings.len().max(1) as f64,
+            ping_p50_ms: percentile(0.5),
+            ping_p99_ms: percentile(0.99),
+            ping_max_ms: pings.last().map_or(0.0, |v| 1000.0 * v),
+        }
+    }
+}
+
+fn random_input(rng: &mut impl Rng) -> PlayerInput {
+    PlayerInput {
+        forward: rng.gen_range(-1..=1),
+        side: rng.gen_range(-1..=1),
+        yaw: rng.gen_range(0.0..360.0),
+        pitch: rng.gen_range(-89.0..89.0),
+        buttons: rng.gen(),
+    }
+}
+
+fn step_bots(app: &Arc<App>, bots: &mut [Client]) {
+    for c in bots.iter_mut() {
+        c.frame_start();
+        c.update(app);
+        c.frame_end();
+    }
+}
+
+///
+/// Connects headless clients to the server and makes them send random inputs, then prints report to stdout.
+/// Server is started in-process unless `--connect` is given.
+///
+pub(crate) fn run_bots(args: Arguments) -> Result<(), AppError> {
+    let app = Arc::new(App::new(args.clone()));
+    let sv_handle = match args.connect() {
+        Some(addr) => {
+            app.config().lock().unwrap().server.bound_to = Some(addr.to_string());
+            None
+        }
+        None => Some(server_init(&app)?.1),
+    };
+    let mut bots: Vec<_> = (0..args.bots())
+        .map(|i| {
+            let mut bot = Client::new(&app);
+            bot.set_name(format!("Bot {}", i + 1));
+            bot
+        })
+        .collect();
+
+    let started_at = Instant::now();
+    while started_at.elapsed() < CONNECT_TIMEOUT && !bots.iter().all(|c| c.is_connected()) {
+        step_bots(&app, &mut bots);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let connect_time = started_at.elapsed();
+
+    let mut rng = rand::thread_rng();
+    let period = Duration::from_secs_f64(1.0 / args.bot_rate().max(f64::EPSILON));
+    let run_time = Duration::from_secs_f64(args.bot_secs().max(0.0));
+    let mut inputs_sent = 0;
+    let mut pings = Vec::new();
+    // Ping replies seen by each bot, so every reply is sampled once
+    let mut ping_seqs: Vec<_> = bots.iter().map(Client::ping_seq).collect();
+    let mut next_input = Instant::now();
+    let started_at = Instant::now();
+    while started_at.elapsed() < run_time {
+        let now = Instant::now();
+        let send = now >= next_input;
+        if send {
+            next_input += period;
+        }
+        for (c, ping_seq) in bots.iter_mut().zip(ping_seqs.iter_mut()) {
+            c.frame_start();
+            c.update(&app);
+            if c.ping_seq() != *ping_seq {
+                *ping_seq = c.ping_seq();
+                pings.extend(c.ping());
+            }
+            if send && c.is_connected() {
+                c.send_input(&random_input(&mut rng));
+                inputs_sent += 1;
+            }
+            c.frame_end();
+        }
+        // Keep polling sockets between inputs so pings are not inflated
+        std::thread::sleep(
+            next_input
+                .saturating_duration_since(Instant::now())
+                .min(Duration::from_millis(5)),
+        );
+    }
+    let report = BotReport::new(
+        &bots,
+        connect_time,
+        started_at.elapsed(),
+        inputs_sent,
+        pings,
+    );
+    app.exit();
+    if let Some(handle) = sv_handle {
+        handle.join().expect("Unable to join server thread!");
+    }
+    let out = toml::to_string(&report).map_err(|e| AppError {
+        message: e.to_string(),
+    })?;
+    println!("{out}");
+    Ok(())
+}
@@ -1,29 +1,191 @@
-use std::{sync::Arc, thread, time::Duration};
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use log::info;
-use rg_common::Arguments;
+use log::{info, LevelFilter};
+use rg_common::{
+    commands::{CmdError, CommandBuilder},
+    files::profile_dir,
+    Arguments,
+};
 
-use crate::{app::App, app_logger, client::Client, error::AppError, server::server_init};
+use crate::{
+    app::App,
+    app_logger::{self, LogFilter, GREP_TARGET},
+    client::{Client, ClientRequest},
+    error::AppError,
+    server::server_init,
+    watchdog::watchdog_init,
+};
+
+const LOG_PAGE_SIZE: usize = 20;
 
 pub(crate) fn run_client_server(args: Arguments) -> Result<(), AppError> {
-    let (handle, log_buf) = app_logger::init().expect("Unable to init app logger!");
+    let log_dir = profile_dir(&args);
+    let (_handle, log_buf) = app_logger::init(&log_dir).expect("Unable to init app logger!");
+    let log_buf = Arc::new(Mutex::new(log_buf));
+    app_logger::log_session_header();
     info!("Begin initialization...");
 
     let app = Arc::new(App::new(args));
+    let mut builder = CommandBuilder::new(app.commands());
+    builder.add("log_dump", move |_| {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |v| v.as_secs());
+        let archive = log_dir.join(format!("log_dump_{stamp}.zip"));
+        let count = app_logger::dump_logs(&log_dir, &archive)
+            .map_err(|e| CmdError::Failed(e.to_string()))?;
+        info!("Saved {count} log file(s) to {:?}", archive);
+        Ok(())
+    });
+    let buf_clone = log_buf.clone();
+    builder.add("console_filter", move |args| {
+        let filter = match args {
+            [] => LogFilter::default(),
+            [level] | [level, _] => LogFilter::new(
+                LevelFilter::from_str(level)
+                    .map_err(|_| CmdError::ParseError(level.to_string()))?,
+                args.get(1).map(String::as_str),
+            ),
+            _ => return Err(CmdError::ArgNumberMismatch(2)),
+        };
+        buf_clone
+            .lock()
+            .map_err(|_| CmdError::LockPoisoned)?
+            .set_filter(filter);
+        Ok(())
+    });
+    let buf_clone = log_buf.clone();
+    builder.add1("grep", move |pattern: String| {
+        let buf = buf_clone.lock().map_err(|_| CmdError::LockPoisoned)?;
+        let found = buf.search(&pattern);
+        for line in found.iter() {
+            info!(target: GREP_TARGET, "{line}");
+        }
+        info!(target: GREP_TARGET, "Found {} line(s)", found.len());
+        Ok(())
+    });
+    let buf_clone = log_buf.clone();
+    builder.add("log_page", move |args| {
+        let page = match args {
+            [] => 0,
+            [page] => page
+                .parse()
+                .map_err(|_| CmdError::ParseError(page.to_string()))?,
+            _ => return Err(CmdError::ArgNumberMismatch(1)),
+        };
+        let buf = buf_clone.lock().map_err(|_| CmdError::LockPoisoned)?;
+        for line in buf.page(page, LOG_PAGE_SIZE) {
+            info!(target: GREP_TARGET, "{line}");
+        }
+        info!(target: GREP_TARGET, "Page {page}, older lines are on the next page");
+        Ok(())
+    });
+    let app_clone = app.clone();
+    builder.add("eval", move |args| {
+        let value = app_clone
+            .vars()
+            .eval(&args.join(" "))
+            .map_err(|e| CmdError::Failed(e.to_string()))?;
+        info!("{value}");
+        Ok(())
+    });
+    let app_clone = app.clone();
+    builder.add("set", move |args| {
+        let [name, value @ ..] = args else {
+            return Err(CmdError::ArgNumberMismatch(2));
+        };
+        if value.is_empty() {
+            return Err(CmdError::ArgNumberMismatch(2));
+        }
+        // Expression may be split into several arguments by spaces
+        let value = app_clone
+            .vars()
+            .expand(&value.join(" "))
+            .map_err(|e| CmdError::Failed(e.to_string()))?;
+        app_clone
+            .vars()
+            .try_set_value(name, &value)
+            .map_err(|e| CmdError::Failed(e.to_string()))
+    });
+    let app_clone = app.clone();
+    builder.add("caps", move |_| {
+        for c in app_clone.caps().list() {
+            info!("{c}");
+        }
+        Ok(())
+    });
+    // Client lives in the main loop, so commands only queue requests for it
+    let requests: Arc<Mutex<Vec<ClientRequest>>> = Arc::default();
+    let r = Arc::clone(&requests);
+    builder.add("client_status", move |_| {
+        r.lock()?.push(ClientRequest::Status);
+        Ok(())
+    });
+    let r = Arc::clone(&requests);
+    builder.add("scores", move |_| {
+        r.lock()?.push(ClientRequest::Scores);
+        Ok(())
+    });
+    let r = Arc::clone(&requests);
+    builder.add("chat", move |_| {
+        r.lock()?.push(ClientRequest::Chat);
+        Ok(())
+    });
+    for (name, team_only) in [("say", false), ("say_team", true)] {
+        let r = Arc::clone(&requests);
+        builder.add(name, move |args| {
+            if args.is_empty() {
+                return Err(CmdError::ArgNumberMismatch(1));
+            }
+            r.lock()?.push(ClientRequest::Say {
+                text: args.join(" "),
+                team_only,
+            });
+            Ok(())
+        });
+    }
+    let r = Arc::clone(&requests);
+    builder.add1("team", move |team: u8| {
+        r.lock()?.push(ClientRequest::JoinTeam { team });
+        Ok(())
+    });
+    let app_clone = app.clone();
+    builder.add("quit", move |_| {
+        app_clone.exit();
+        Ok(())
+    });
+    let _log_commands = builder.build();
     //let mut state: Box<dyn AppState> = Box::new(InitialState::default());
     info!("Entering main loop...");
     let mut client = Client::new(&app);
     let (_, sv_handle) = server_init(&app).expect("Server initialization failed!");
+    let wd_handle = watchdog_init(&app).expect("Watchdog initialization failed!");
+    let heartbeat = app.watchdog().register("main");
     while !app.exit_flag() {
+        heartbeat.beat();
+        if let Ok(mut buf) = log_buf.lock() {
+            buf.update();
+        }
         client.frame_start();
 
         client.update(&app);
+        let pending = std::mem::take(&mut *requests.lock().unwrap());
+        for request in pending {
+            client.execute(request);
+        }
 
         client.frame_end();
 
-        thread::sleep(Duration::from_millis(5));
+        app.exit_token().wait_timeout(Duration::from_millis(5));
     }
     sv_handle.join().expect("Unable to join server thread!");
+    if let Some(handle) = wd_handle {
+        handle.join().expect("Unable to join watchdog thread!");
+    }
     info!("Leaving main loop.");
     Ok(())
 }
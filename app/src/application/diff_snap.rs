@@ -0,0 +1,33 @@
+use std::{fs, path::Path};
+
+use rg_ecs::snapshot::{diff, WorldSnapshot};
+
+use crate::error::AppError;
+
+fn load(path: &Path) -> Result<WorldSnapshot, AppError> {
+    let text = fs::read_to_string(path)?;
+    toml::from_str(&text).map_err(|e| AppError {
+        message: format!("Unable to parse {path:?}: {e}"),
+    })
+}
+
+///
+/// Compares two world snapshots and reports the first diverging entity/component.
+/// Fails if snapshots differ, so it can be used in scripts.
+///
+pub(crate) fn run_diff_snap(left: &str, right: &str) -> Result<(), AppError> {
+    let (a, b) = (load(Path::new(left))?, load(Path::new(right))?);
+    if a.tick != b.tick {
+        println!("Note: comparing different ticks ({} vs {})", a.tick, b.tick);
+    }
+    match diff(&a, &b) {
+        None => {
+            println!("Snapshots match ({} entities)", a.entities.len());
+            Ok(())
+        }
+        Some(d) => {
+            println!("First divergence at tick {}: {d}", a.tick);
+            Err(AppError::from("Snapshots diverge"))
+        }
+    }
+}
@@ -1,4 +1,10 @@
+mod bench_sim;
+mod bots;
 mod client_server;
 mod dedicated;
+mod diff_snap;
 
+pub(crate) use bench_sim::run_bench_sim;
+pub(crate) use bots::run_bots;
 pub(crate) use client_server::run_client_server;
+pub(crate) use diff_snap::run_diff_snap;
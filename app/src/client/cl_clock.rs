@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+
+use rg_common::Tick;
+
+///
+/// Single round trip measurement, all values are in seconds
+///
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    // Local time when reply was received
+    local: f64,
+    rtt: f64,
+    // Server time minus local time
+    offset: f64,
+}
+
+///
+/// Estimates server clock from ping replies. Offset is derived from the samples with the lowest round trip
+/// (least affected by queuing delays), drift is the slope of these offsets over local time.
+///
+#[derive(Debug, Default)]
+pub(crate) struct ClockSync {
+    samples: VecDeque<Sample>,
+    // Offset at `base` local time and its rate of change
+    base: f64,
+    offset: f64,
+    drift: f64,
+    tick: Tick,
+    valid: bool,
+}
+
+impl ClockSync {
+    const WINDOW: usize = 32;
+    ///
+    /// Samples with round trip within this factor (plus jitter allowance) of the best one are used for estimation
+    ///
+    const RTT_FACTOR: f64 = 1.5;
+    const RTT_JITTER: f64 = 0.001;
+    ///
+    /// Min time span of the samples to estimate drift
+    ///
+    const MIN_DRIFT_SPAN: f64 = 1.0;
+    ///
+    /// Max drift, 1000 ppm is way above any real quartz
+    ///
+    const MAX_DRIFT: f64 = 0.001;
+
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    ///
+    /// Adds measurement: ping was sent at local time `sent`, reply with server's `server_time` arrived at `received`
+    ///
+    pub(crate) fn add(&mut self, sent: f64, received: f64, server_time: f64, tick: Tick) {
+        let rtt = received - sent;
+        if rtt < 0.0 {
+            return;
+        }
+        if self.samples.len() == Self::WINDOW {
+            self.samples.pop_front();
+        }
+        // Assume symmetric path, so server time was sampled in the middle of round trip
+        self.samples.push_back(Sample {
+            local: received,
+            rtt,
+            offset: server_time + 0.5 * rtt - received,
+        });
+        self.tick = self.tick.max(tick);
+        self.estimate();
+    }
+
+    fn estimate(&mut self) {
+        let best = self
+            .samples
+            .iter()
+            .map(|s| s.rtt)
+            .fold(f64::INFINITY, f64::min);
+        let limit = best * Self::RTT_FACTOR + Self::RTT_JITTER;
+        let good: Vec<_> = self.samples.iter().filter(|s| s.rtt <= limit).collect();
+        if good.is_empty() {
+            return;
+        }
+        let n = good.len() as f64;
+        let mean_local = good.iter().map(|s| s.local).sum::<f64>() / n;
+        let mean_offset = good.iter().map(|s| s.offset).sum::<f64>() / n;
+        let span = good.last().unwrap().local - good.first().unwrap().local;
+        // Least squares slope
+        let drift = if good.len() > 2 && span >= Self::MIN_DRIFT_SPAN {
+            let (num, den) = good.iter().fold((0.0, 0.0), |(num, den), s| {
+                let dx = s.local - mean_local;
+                (num + dx * (s.offset - mean_offset), den + dx * dx)
+            });
+            if den > 0.0 {
+                num / den
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        };
+        self.base = mean_local;
+        self.offset = mean_offset;
+        self.drift = drift.clamp(-Self::MAX_DRIFT, Self::MAX_DRIFT);
+        self.valid = true;
+    }
+
+    pub(crate) fn is_synchronized(&self) -> bool {
+        self.valid
+    }
+
+    ///
+    /// Estimated difference between server and local clocks (in seconds) at local time `local`
+    ///
+    pub(crate) fn offset(&self, local: f64) -> Option<f64> {
+        self.valid
+            .then_some(self.offset + self.drift * (local - self.base))
+    }
+
+    ///
+    /// Estimated drift of the server clock relative to local one (seconds per second)
+    ///
+    pub(crate) fn drift(&self) -> f64 {
+        self.drift
+    }
+
+    ///
+    /// Last server tick reported by server
+    ///
+    pub(crate) fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    ///
+    /// Converts local time to server time
+    ///
+    pub(crate) fn server_time(&self, local: f64) -> Option<f64> {
+        self.offset(local).map(|v| local + v)
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use rg_common::Tick;
+
+    use super::ClockSync;
+
+    fn assert_near(expected: f64, actual: f64, eps: f64) {
+        assert!(
+            (expected - actual).abs() <= eps,
+            "Expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn offset_with_jitter() {
+        let mut c = ClockSync::new();
+        assert!(!c.is_synchronized());
+        assert_eq!(None, c.server_time(1.0));
+        // Server is 100 sec. ahead, one way delay is 20ms, some replies are delayed by queues
+        let delays = [0.0, 0.05, 0.0, 0.2, 0.001, 0.0, 0.08, 0.0];
+        for (i, extra) in delays.iter().enumerate() {
+            let sent = i as f64 * 0.1;
+            let server = sent + 0.02 + 100.0;
+            c.add(sent, sent + 0.04 + extra, server, Tick::new(i as u64));
+        }
+        assert!(c.is_synchronized());
+        assert_eq!(Tick::new(7), c.tick());
+        assert_near(100.0, c.offset(1.0).unwrap(), 0.001);
+        assert_near(105.0, c.server_time(5.0).unwrap(), 0.001);
+    }
+
+    #[test]
+    fn drift() {
+        let mut c = ClockSync::new();
+        // Server clock runs 100 ppm faster
+        for i in 0..20 {
+            let sent = i as f64 * 0.5;
+            let server = 10.0 + (sent + 0.01) * 1.0001;
+            c.add(sent, sent + 0.02, server, Tick::default());
+        }
+        assert_near(0.0001, c.drift(), 1e-6);
+        let local = 60.0;
+        assert_near(10.0 + local * 1.0001, c.server_time(local).unwrap(), 1e-4);
+        c.reset();
+        assert!(!c.is_synchronized());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::net::ScoreEntry;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ScoreRow {
+    pub name: String,
+    pub score: i32,
+    /// Round trip in milliseconds
+    pub ping: u16,
+    /// Zero means no team
+    pub team: u8,
+}
+
+///
+/// Last scoreboard received from server, ordered for display: grouped by team, best score first
+///
+#[derive(Debug, Default)]
+pub(crate) struct Scoreboard {
+    rows: Vec<ScoreRow>,
+}
+
+impl Scoreboard {
+    pub(crate) fn update(&mut self, entries: &[ScoreEntry]) {
+        self.rows = entries
+            .iter()
+            .map(|e| ScoreRow {
+                name: e.name.to_string(),
+                score: e.score,
+                ping: e.ping,
+                team: e.team,
+            })
+            .collect();
+        self.rows.sort_by(|a, b| {
+            a.team
+                .cmp(&b.team)
+                .then_with(|| b.score.cmp(&a.score))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+    }
+
+    pub(crate) fn rows(&self) -> &[ScoreRow] {
+        &self.rows
+    }
+
+    ///
+    /// Rows of each team in team order
+    ///
+    pub(crate) fn teams(&self) -> impl Iterator<Item = (u8, &[ScoreRow])> {
+        self.rows
+            .chunk_by(|a, b| a.team == b.team)
+            .map(|v| (v[0].team, v))
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use crate::net::ScoreEntry;
+
+    use super::Scoreboard;
+
+    fn entry(name: &str, score: i32, team: u8) -> ScoreEntry<'_> {
+        ScoreEntry {
+            name,
+            score,
+            ping: 10,
+            team,
+        }
+    }
+
+    #[test]
+    fn grouped_by_team() {
+        let mut s = Scoreboard::default();
+        s.update(&[
+            entry("a", 1, 2),
+            entry("b", 5, 1),
+            entry("c", 3, 2),
+            entry("d", 5, 1),
+            entry("e", 0, 1),
+        ]);
+        let names: Vec<_> = s.rows().iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(vec!["b", "d", "e", "c", "a"], names);
+        let teams: Vec<_> = s.teams().map(|(t, rows)| (t, rows.len())).collect();
+        assert_eq!(vec![(1, 3), (2, 2)], teams);
+    }
+}
@@ -1,26 +1,82 @@
 use std::io::Read;
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 
 use log::{error, info, warn};
+use rg_common::config::ReconnectConfig;
+use rg_common::{Memoized, SessionId, Tick};
 use rsa::RsaPublicKey;
 
 use crate::app::App;
+use crate::client::cl_clock::ClockSync;
 use crate::client::cl_pub_key::PublicKey;
+use crate::client::cl_scoreboard::Scoreboard;
 use crate::error::AppError;
-use crate::net::Message::{Accepted, Hello, Ping, Pong, ServerInfo};
-use crate::net::{Endpoint, Message, NetEndpoint, MAX_DATAGRAM_SIZE};
+use crate::net::Message::{
+    Accepted, Challenge, ChallengeResponse, Disconnect, Hello, MatchEnded, ModeStatus, Ping, Pong,
+    Reconnect, ServerInfo, Session, VoteEnded, VoteStatus,
+};
+use crate::net::{
+    Bytes, Endpoint, Message, NetEndpoint, PlayerInput, MAX_DATAGRAM_SIZE, PROTOCOL_VERSION,
+};
+use crate::net_rate::LinkQuality;
 
-#[derive(Eq, PartialEq)]
+type Resolver = Memoized<String, Option<SocketAddr>, fn(&String) -> Option<SocketAddr>>;
+
+///
+/// Server addresses resolved so far. Shared by all clients of the process (bots), so host name is not looked up
+/// on every connection attempt. Failures are cached too, but expire sooner than DNS records usually do.
+///
+fn resolver() -> &'static Resolver {
+    static RESOLVER: OnceLock<Resolver> = OnceLock::new();
+    RESOLVER.get_or_init(|| Memoized::new(Duration::from_secs(30), 16, resolve))
+}
+
+fn resolve(addr: &String) -> Option<SocketAddr> {
+    match addr.to_socket_addrs() {
+        Ok(mut addrs) => addrs.next(),
+        Err(e) => {
+            warn!("Failed to resolve {addr}: {e}");
+            None
+        }
+    }
+}
+
+///
+/// Console request executed by client on its next frame
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ClientRequest {
+    Status,
+    /// Prints the last scoreboard
+    Scores,
+    /// Prints recently received chat lines
+    Chat,
+    Say {
+        text: String,
+        team_only: bool,
+    },
+    JoinTeam {
+        team: u8,
+    },
+}
+
+#[derive(Debug, Eq, PartialEq)]
 enum ClientState {
     INIT,
     DISCONNECTED,
     CONNECTING,
     CONNECTED,
+    Reconnecting,
+    /// Dropped by server, no reconnection attempts
+    Kicked,
+    /// Connection lost and reconnection is disabled or attempts are exhausted
+    Lost,
 }
 
 pub(crate) struct Client {
+    name: String,
     endpoint: Box<dyn Endpoint>,
     recv_buf: Option<Vec<u8>>,
     server_addr: Option<SocketAddr>,
@@ -28,16 +84,44 @@ pub(crate) struct Client {
     state: ClientState,
     last_seen: Option<Instant>,
     last_send: Option<Instant>,
+    started_at: Instant,
+    ping: Option<f64>,
+    // Number of ping replies received, tells fresh measurement from the old one
+    ping_seq: u32,
+    session_token: Option<SessionId>,
+    reconnect_started: Option<Instant>,
+    reconnect: ReconnectConfig,
+    // Number of full handshake attempts made since connection was lost
+    attempts: Option<u32>,
+    rate: u32,
+    ticket: Vec<u8>,
+    clock: ClockSync,
+    // Last game mode name and its HUD state from server
+    game_mode: Option<(String, Vec<u8>)>,
+    scoreboard: Scoreboard,
+    team: u8,
+    // Last chat lines (sender, text)
+    chat: Vec<(String, String)>,
+    disconnect_reason: Option<String>,
+    input_seq: u32,
+    bytes_sent: u64,
+    // Connection quality band reported by server
+    link_quality: Option<u8>,
+    map: Option<String>,
+    // Next map and whole seconds left before it
+    intermission: Option<(String, u32)>,
 }
 
 impl Client {
-    const MAX_LAST_SEEN: Duration = Duration::from_secs(3);
+    const MAX_LAST_SEEN: Duration = Duration::from_secs(10);
     const CONN_RETRY_INTERVAL: Duration = Duration::from_secs(3);
+    const MAX_CHAT_LINES: usize = 32;
 
     fn send(&mut self, msg: &Message) {
         match self.endpoint.send(msg) {
             Ok(n) => {
                 self.last_send = Some(Instant::now());
+                self.bytes_sent += n as u64;
                 info!("Sent {n} bytes to server!");
             }
             Err(ref e) => {
@@ -49,8 +133,35 @@ impl Client {
     fn process_message(&mut self, msg: &Message) -> Result<(), AppError> {
         match msg {
             Accepted => {
+                if self.attempts.take().is_some() {
+                    info!("Reconnected to server!");
+                    self.clock.reset();
+                } else if self.state == ClientState::Reconnecting {
+                    info!("Session resumed!");
+                } else {
+                    info!("Connected to server!");
+                    self.clock.reset();
+                }
                 self.state = ClientState::CONNECTED;
-                info!("Connected to server!");
+                self.reconnect_started = None;
+            }
+            Session { token } => {
+                self.session_token = Some(*token);
+            }
+            Disconnect { reason } => {
+                warn!("Disconnected by server: {reason}");
+                self.state = ClientState::Kicked;
+                self.session_token = None;
+                self.disconnect_reason = Some(reason.to_string());
+            }
+            Challenge { nonce } => {
+                // Our address has changed, server wants to make sure it's really us
+                if let Some(token) = self.session_token {
+                    self.send(&ChallengeResponse {
+                        token,
+                        nonce: *nonce,
+                    });
+                }
             }
             ServerInfo { key } => {
                 let key = bitcode::deserialize::<RsaPublicKey>(key)
@@ -59,14 +170,96 @@ impl Client {
                 info!("Got server's public key!");
                 self.send_connect_message();
             }
-            Pong { time } => {
+            Pong { time, clock } => {
+                let now = self.started_at.elapsed().as_secs_f64();
+                let ping = now - time;
+                self.ping = Some(ping);
+                self.ping_seq = self.ping_seq.wrapping_add(1);
+                info!("Ping to server is {:.2} ms.", 1000.0 * ping);
+                if let Some(clock) = clock {
+                    self.clock.add(*time, now, clock.time, clock.tick);
+                }
+            }
+            Ping { time } => {
+                // Reply is not counted as regular send, so it doesn't postpone our own pings
+                let reply = Pong {
+                    time: *time,
+                    clock: None,
+                };
+                if let Err(e) = self.endpoint.send(&reply) {
+                    error!("Failed to reply to ping: {e:?}");
+                }
+            }
+            VoteStatus {
+                kind,
+                arg,
+                yes,
+                no,
+                time_left,
+            } => {
+                info!("Vote \"{kind} {arg}\": yes {yes}, no {no}, {time_left:.0} sec. left");
+            }
+            VoteEnded { kind, arg, passed } => {
                 info!(
-                    "Ping to server is {:.2} ms.",
-                    1000.0 * (Instant::now().elapsed().as_secs_f64() - time)
+                    "Vote \"{kind} {arg}\" {}",
+                    if *passed { "passed" } else { "failed" }
                 );
             }
-            Ping { time } => {
-                self.send(&Pong { time: *time });
+            ModeStatus { mode, state } => {
+                if self.game_mode.as_ref().is_none_or(|(m, _)| m != mode) {
+                    info!("Game mode: {mode}");
+                }
+                self.game_mode = Some((mode.to_string(), state.to_vec()));
+            }
+            MatchEnded { mode, winner } => {
+                if winner.is_empty() {
+                    info!("Match of {mode} is over: draw");
+                } else {
+                    info!("Match of {mode} is over: {winner} wins");
+                }
+            }
+            Message::Scoreboard { entries } => self.scoreboard.update(entries),
+            Message::TeamAssigned { team } => {
+                if self.team != *team {
+                    info!("Team: {team}");
+                }
+                self.team = *team;
+            }
+            Message::Intermission {
+                next_map,
+                time_left,
+            } => {
+                if self.intermission.is_none() && !next_map.is_empty() {
+                    info!("Next map: {next_map} in {time_left} sec.");
+                }
+                self.intermission = Some((next_map.to_string(), *time_left));
+            }
+            Message::MapChanged { map } => {
+                if !map.is_empty() {
+                    info!("Map: {map}");
+                }
+                self.map = (!map.is_empty()).then(|| map.to_string());
+                self.intermission = None;
+            }
+            Message::LinkQuality { band } => {
+                if self.link_quality.is_some_and(|v| v > *band) {
+                    info!(
+                        "Connection quality dropped to {band} of {}",
+                        LinkQuality::MAX
+                    );
+                }
+                self.link_quality = Some(*band);
+            }
+            Message::Chat {
+                from,
+                text,
+                team_only,
+            } => {
+                info!("{}{from}: {text}", if *team_only { "(team) " } else { "" });
+                self.chat.push((from.to_string(), text.to_string()));
+                if self.chat.len() > Self::MAX_CHAT_LINES {
+                    self.chat.remove(0);
+                }
             }
             m => {
                 warn!("Unsupported message from server: {m:?}");
@@ -80,6 +273,7 @@ impl Client {
         loop {
             match self.endpoint.receive_data(buf.as_mut()) {
                 Ok(Some(mut data)) => {
+                    self.last_seen = Some(Instant::now());
                     while let Some(ref m) = data.read() {
                         self.process_message(m).unwrap();
                     }
@@ -100,10 +294,17 @@ impl Client {
     fn send_connect_message(&mut self) {
         let key = self.server_key.as_ref().unwrap();
         let encoded = key.encode_str("123456").unwrap();
+        let ticket = std::mem::take(&mut self.ticket);
+        let name = std::mem::take(&mut self.name);
         self.send(&Message::Connect {
-            name: "Test",
-            password: encoded,
-        })
+            version: PROTOCOL_VERSION,
+            name: &name,
+            password: Bytes(&encoded),
+            rate: self.rate,
+            ticket: Bytes(&ticket),
+        });
+        self.ticket = ticket;
+        self.name = name;
     }
 
     fn is_time_to_resend(&self) -> bool {
@@ -122,18 +323,90 @@ impl Client {
         }
     }
 
+    ///
+    /// Checks if connection to server is lost. With session token at hand we try to resume the session first,
+    /// falling back to the full handshake if server doesn't respond in time.
+    ///
+    fn check_connection(&mut self) {
+        match self.state {
+            ClientState::CONNECTED => {
+                let lost = self
+                    .last_seen
+                    .is_some_and(|v| v.elapsed() > Self::MAX_LAST_SEEN);
+                if !lost {
+                    return;
+                }
+                warn!("Connection to server lost!");
+                if !self.reconnect.enabled {
+                    self.give_up("Connection to server lost");
+                } else if let Some(token) = self.session_token {
+                    self.state = ClientState::Reconnecting;
+                    self.reconnect_started = Some(Instant::now());
+                    self.send(&Reconnect { token });
+                } else {
+                    self.state = ClientState::DISCONNECTED;
+                    self.attempts = Some(0);
+                }
+            }
+            ClientState::Reconnecting => {
+                let window = Duration::from_secs_f64(self.reconnect.window.max(0.0));
+                let expired = self.reconnect_started.is_none_or(|v| v.elapsed() > window);
+                if expired {
+                    warn!("Unable to resume session, reconnecting...");
+                    self.state = ClientState::DISCONNECTED;
+                    self.session_token = None;
+                    self.reconnect_started = None;
+                    self.attempts = Some(0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    ///
+    /// Counts handshake attempt if connection was lost. Returns false when attempts are exhausted.
+    ///
+    fn next_attempt(&mut self) -> bool {
+        let Some(attempt) = self.attempts.as_mut() else {
+            return true;
+        };
+        if self.reconnect.attempts > 0 && *attempt >= self.reconnect.attempts {
+            self.give_up("Server is not responding");
+            return false;
+        }
+        *attempt += 1;
+        warn!("Reconnecting to server, attempt {attempt}...");
+        true
+    }
+
+    fn give_up(&mut self, reason: &str) {
+        warn!("{reason}, giving up.");
+        self.state = ClientState::Lost;
+        self.session_token = None;
+        self.reconnect_started = None;
+        self.attempts = None;
+        self.disconnect_reason = Some(reason.to_string());
+    }
+
     pub(crate) fn update(&mut self, app: &Arc<App>) {
+        if let Ok(cfg) = app.config().lock() {
+            self.reconnect.clone_from(&cfg.client.reconnect);
+        }
         self.receive_from_server();
+        self.check_connection();
         if self.is_time_to_resend() {
             match self.state {
                 ClientState::INIT => {
-                    if let Some(addr) = app.config().lock().unwrap().server.bound_to.as_ref() {
-                        match self
-                            .endpoint
-                            .connect(addr.parse().expect("Unable to parse server address!"))
-                        {
+                    let addr = app.config().lock().unwrap().server.bound_to.clone();
+                    if let Some(addr) = addr {
+                        let Some(server_addr) = resolver().get(&addr) else {
+                            error!("Unable to resolve server address {addr}");
+                            return;
+                        };
+                        match self.endpoint.connect(server_addr) {
                             Ok(_) => {
-                                info!("Client socket connected to {}", addr);
+                                info!("Client socket connected to {} ({})", addr, server_addr);
+                                self.server_addr = Some(server_addr);
                                 self.state = ClientState::DISCONNECTED;
                             }
                             Err(e) => {
@@ -143,20 +416,31 @@ impl Client {
                     }
                 }
                 ClientState::DISCONNECTED => {
-                    self.send(&Hello);
-                    self.state = ClientState::CONNECTING;
+                    if self.next_attempt() {
+                        self.send(&Hello);
+                        self.state = ClientState::CONNECTING;
+                    }
                 }
                 ClientState::CONNECTING => {
+                    if !self.next_attempt() {
+                        return;
+                    }
                     if !self.server_key.is_some() {
                         self.send(&Hello);
                     } else {
                         self.send_connect_message();
                     };
                 }
+                ClientState::Reconnecting => {
+                    if let Some(token) = self.session_token {
+                        self.send(&Reconnect { token });
+                    }
+                }
+                ClientState::Kicked | ClientState::Lost => {}
                 ClientState::CONNECTED => {
                     for i in 0..10 {
                         self.send(&Ping {
-                            time: Instant::now().elapsed().as_secs_f64(),
+                            time: self.started_at.elapsed().as_secs_f64(),
                         });
                     }
                 }
@@ -173,10 +457,38 @@ impl Client {
     }
 
     pub(crate) fn new(app: &Arc<App>) -> Self {
+        Self::with_endpoint(
+            app,
+            NetEndpoint::new().expect("Unable to create client socket!"),
+        )
+    }
+
+    ///
+    /// Creates client talking over the given endpoint, server address is taken from config
+    ///
+    pub(crate) fn with_endpoint(app: &Arc<App>, mut endpoint: NetEndpoint) -> Self {
         info!("Starting client...");
-        let endpoint = NetEndpoint::new().expect("Unable to create client socket!");
+        let (name, rate, checksum, ticket, reconnect) = {
+            let cfg = &app.config().lock().unwrap().client;
+            let ticket = cfg.ticket.as_deref().map_or_else(Vec::new, |v| {
+                decode_hex(v).unwrap_or_else(|| {
+                    warn!("Ignoring malformed ticket!");
+                    Vec::new()
+                })
+            });
+            (
+                cfg.name.clone(),
+                cfg.rate,
+                cfg.checksum,
+                ticket,
+                cfg.reconnect.clone(),
+            )
+        };
+        endpoint.set_rate(rate);
+        endpoint.set_checksum(checksum);
         //endpoint.connect(&server_addr).expect("Unable to set server address on client socket!");
         Client {
+            name,
             endpoint: Box::new(endpoint),
             recv_buf: Some(Vec::with_capacity(MAX_DATAGRAM_SIZE)),
             server_addr: None,
@@ -184,6 +496,300 @@ impl Client {
             state: ClientState::INIT,
             last_seen: None,
             last_send: None,
+            started_at: Instant::now(),
+            ping: None,
+            ping_seq: 0,
+            session_token: None,
+            reconnect_started: None,
+            reconnect,
+            attempts: None,
+            rate,
+            ticket,
+            clock: ClockSync::new(),
+            game_mode: None,
+            scoreboard: Scoreboard::default(),
+            team: 0,
+            chat: Vec::new(),
+            disconnect_reason: None,
+            input_seq: 0,
+            bytes_sent: 0,
+            link_quality: None,
+            map: None,
+            intermission: None,
         }
     }
+
+    ///
+    /// Changes player name sent to server on the next connection, server rejects players with the same name
+    ///
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub(crate) fn is_connected(&self) -> bool {
+        self.state == ClientState::CONNECTED
+    }
+
+    ///
+    /// Returns last measured round trip time to server (in seconds)
+    ///
+    pub(crate) fn ping(&self) -> Option<f64> {
+        self.ping
+    }
+
+    ///
+    /// Returns number of ping replies received so far, changes each time [`Client::ping`] is updated
+    ///
+    pub(crate) fn ping_seq(&self) -> u32 {
+        self.ping_seq
+    }
+
+    ///
+    /// Returns connection quality band from 0 (unusable) to 4 (excellent) for HUD indicator.
+    /// None until server measures it or after connection is lost.
+    ///
+    pub(crate) fn link_quality(&self) -> Option<u8> {
+        self.is_connected().then_some(self.link_quality).flatten()
+    }
+
+    ///
+    /// Returns estimated server time (seconds since server start), shared time base for interpolation
+    /// and lag compensation. None until the first ping reply is received.
+    ///
+    pub(crate) fn server_time(&self) -> Option<f64> {
+        self.clock
+            .server_time(self.started_at.elapsed().as_secs_f64())
+    }
+
+    ///
+    /// Returns current game mode and its HUD state, decoding the state is up to the mode-specific HUD
+    ///
+    pub(crate) fn game_mode(&self) -> Option<(&str, &[u8])> {
+        self.game_mode
+            .as_ref()
+            .map(|(mode, state)| (mode.as_str(), state.as_slice()))
+    }
+
+    ///
+    /// Map being played, None if server has no map rotation
+    ///
+    pub(crate) fn map(&self) -> Option<&str> {
+        self.map.as_deref()
+    }
+
+    ///
+    /// Next map (empty if the same map is replayed) and whole seconds left while match is over
+    ///
+    pub(crate) fn intermission(&self) -> Option<(&str, u32)> {
+        self.intermission
+            .as_ref()
+            .map(|(map, secs)| (map.as_str(), *secs))
+    }
+
+    ///
+    /// Sends chat line, team only lines are delivered to teammates
+    ///
+    pub(crate) fn say(&mut self, text: &str, team_only: bool) {
+        self.send(&Message::Say { text, team_only });
+    }
+
+    ///
+    /// Sends movement input, it's ignored by server until client is connected
+    ///
+    pub(crate) fn send_input(&mut self, input: &PlayerInput) {
+        self.input_seq += 1;
+        let msg = Message::Input {
+            seq: self.input_seq,
+            input: *input,
+        };
+        // Not counted as regular send, so it doesn't postpone our own pings
+        match self.endpoint.send(&msg) {
+            Ok(n) => self.bytes_sent += n as u64,
+            Err(e) => error!("Failed to send input: {e:?}"),
+        }
+    }
+
+    ///
+    /// Total bytes passed to the socket
+    ///
+    pub(crate) fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    pub(crate) fn join_team(&mut self, team: u8) {
+        self.send(&Message::JoinTeam { team });
+    }
+
+    ///
+    /// Team assigned by server, zero if server has no teams
+    ///
+    pub(crate) fn team(&self) -> u8 {
+        self.team
+    }
+
+    pub(crate) fn chat(&self) -> &[(String, String)] {
+        &self.chat
+    }
+
+    ///
+    /// Last scoreboard from server, to be shown by HUD while scoreboard key is held
+    ///
+    pub(crate) fn scoreboard(&self) -> &Scoreboard {
+        &self.scoreboard
+    }
+
+    ///
+    /// Returns last server tick seen in ping replies
+    ///
+    pub(crate) fn server_tick(&self) -> Option<Tick> {
+        self.clock.is_synchronized().then_some(self.clock.tick())
+    }
+
+    pub(crate) fn execute(&mut self, request: ClientRequest) {
+        match request {
+            ClientRequest::Status => self.log_status(),
+            ClientRequest::Scores => self.log_scores(),
+            ClientRequest::Chat => {
+                for (from, text) in self.chat() {
+                    info!("{from}: {text}");
+                }
+            }
+            ClientRequest::Say { text, team_only } => self.say(&text, team_only),
+            ClientRequest::JoinTeam { team } => self.join_team(team),
+        }
+    }
+
+    fn log_scores(&self) {
+        let scoreboard = self.scoreboard();
+        if scoreboard.rows().is_empty() {
+            info!("No scores yet");
+            return;
+        }
+        for (team, rows) in scoreboard.teams() {
+            if team > 0 {
+                info!("Team {team}:");
+            }
+            for row in rows {
+                info!("{:<16} {:>5} {:>5} ms", row.name, row.score, row.ping);
+            }
+        }
+    }
+
+    ///
+    /// Logs connection state, ping, game state and server clock estimate
+    ///
+    pub(crate) fn log_status(&self) {
+        match self.ping {
+            Some(ping) => info!("State: {:?}, ping: {:.2} ms", self.state, 1000.0 * ping),
+            None => info!("State: {:?}", self.state),
+        }
+        if let Some(notice) = self.notice() {
+            info!("{notice}");
+        }
+        if let Some(quality) = self.link_quality() {
+            info!("Link quality: {quality} of 4");
+        }
+        info!(
+            "Map: {}, mode: {}, team: {}",
+            self.map().unwrap_or("-"),
+            self.game_mode().map_or("-", |(mode, _)| mode),
+            self.team()
+        );
+        if let Some((map, secs)) = self.intermission() {
+            let map = if map.is_empty() { "the same map" } else { map };
+            info!("Match is over, {map} starts in {secs} sec.");
+        }
+        let local = self.started_at.elapsed().as_secs_f64();
+        match (self.server_time(), self.server_tick()) {
+            (Some(time), Some(tick)) => info!(
+                "Server time: {time:.3} sec., tick: {tick}, clock offset: {:.3} sec., drift: {:.1} ppm",
+                time - local,
+                1_000_000.0 * self.clock.drift()
+            ),
+            _ => info!("Clock is not synchronized with server yet"),
+        }
+    }
+
+    ///
+    /// Reason given by server for dropping the client
+    ///
+    #[cfg(test)]
+    pub(crate) fn disconnect_reason(&self) -> Option<&str> {
+        self.disconnect_reason.as_deref()
+    }
+
+    ///
+    /// Returns token issued by server for resuming the session after transient disconnect
+    ///
+    #[cfg(test)]
+    pub(crate) fn session_token(&self) -> Option<SessionId> {
+        self.session_token
+    }
+
+    ///
+    /// Connection status to show on screen, None while connection is fine
+    ///
+    pub(crate) fn notice(&self) -> Option<String> {
+        match self.state {
+            ClientState::Reconnecting => Some("Connection lost, resuming session...".to_string()),
+            ClientState::DISCONNECTED | ClientState::CONNECTING => {
+                let attempt = self.attempts?;
+                Some(match self.reconnect.attempts {
+                    0 => format!("Reconnecting, attempt {attempt}..."),
+                    max => format!("Reconnecting, attempt {attempt} of {max}..."),
+                })
+            }
+            ClientState::Kicked | ClientState::Lost => self
+                .disconnect_reason
+                .as_ref()
+                .map(|v| format!("Disconnected: {v}")),
+            _ => None,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn simulate_timeout(&mut self) {
+        self.last_seen = Some(Instant::now() - 2 * Self::MAX_LAST_SEEN);
+    }
+
+    ///
+    /// Makes next connection attempt due immediately
+    ///
+    #[cfg(test)]
+    pub(crate) fn skip_retry_delay(&mut self) {
+        self.last_send = None;
+        self.reconnect_started = None;
+    }
+
+    ///
+    /// Rebinds client to the new local port as if client has switched networks
+    ///
+    #[cfg(test)]
+    pub(crate) fn simulate_roaming(&mut self, app: &Arc<App>) {
+        let server = self.server_addr.expect("Not connected!");
+        let mut endpoint = NetEndpoint::new().expect("Unable to create client socket!");
+        endpoint.set_rate(self.rate);
+        endpoint.set_checksum(app.config().lock().unwrap().client.checksum);
+        endpoint.connect(server).expect("Unable to connect socket!");
+        self.endpoint = Box::new(endpoint);
+        self.simulate_timeout();
+    }
+
+    #[cfg(test)]
+    pub(crate) fn local_addr(&self) -> std::net::SocketAddr {
+        self.endpoint
+            .local_addr()
+            .expect("Unable to get local address!")
+    }
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
 }
@@ -1,4 +1,6 @@
+mod cl_clock;
 mod cl_pub_key;
+mod cl_scoreboard;
 pub mod client;
 
-pub(crate) use client::Client;
+pub(crate) use client::{Client, ClientRequest};
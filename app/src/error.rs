@@ -2,6 +2,7 @@ use std::{fmt::Display, io};
 
 use log::SetLoggerError;
 use log4rs::config::runtime::ConfigErrors;
+use rg_common::ErrorReport;
 
 #[derive(Debug)]
 pub struct AppError {
@@ -47,3 +48,19 @@ impl From<SetLoggerError> for AppError {
         }
     }
 }
+
+impl From<anyhow::Error> for AppError {
+    fn from(value: anyhow::Error) -> Self {
+        AppError {
+            message: value.to_string(),
+        }
+    }
+}
+
+impl From<ErrorReport> for AppError {
+    fn from(value: ErrorReport) -> Self {
+        AppError {
+            message: value.to_string(),
+        }
+    }
+}
@@ -10,11 +10,24 @@ mod application;
 mod client;
 mod error;
 mod net;
+#[cfg(test)]
+mod net_loopback;
+mod net_rate;
+#[cfg(test)]
+mod net_tests;
+mod net_transport;
 mod server;
+mod watchdog;
 
 fn main() -> Result<(), AppError> {
     let args = Arguments::parse();
-    if args.dedicated() {
+    if let Some((left, right)) = args.diff_snap() {
+        application::run_diff_snap(left, right)
+    } else if args.bench_sim() {
+        application::run_bench_sim(args)
+    } else if args.bots() > 0 {
+        application::run_bots(args)
+    } else if args.dedicated() {
         todo!("Not implemented!");
     } else {
         application::run_client_server(args)
@@ -1,37 +1,50 @@
 use std::cmp::min;
+use std::collections::VecDeque;
 use std::fmt::{Debug, Formatter};
 use std::io;
 use std::io::ErrorKind::WouldBlock;
 use std::io::{Error, Write};
 use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
 use std::num::NonZeroUsize;
+use std::time::Instant;
 
-use bitcode::__private::{Buffer, Decoder, Encoder, View};
-use bitcode::{Decode, Encode};
+use bitcode::__private::{Buffer, Encoder};
+use bitcode::Encode;
+use log::warn;
+pub(crate) use rg_net::PacketView;
+use rg_net::{check_header, write_header, MAX_PAYLOAD_SIZE};
+pub use rg_net::{
+    Bytes, Message, PlayerInput, ScoreEntry, ServerClock, MAX_DATAGRAM_SIZE, PROTOCOL_VERSION,
+};
 
-pub const MAX_DATAGRAM_SIZE: usize = 65507;
-
-#[derive(Debug, Clone, Encode, Decode)]
-pub enum Message<'a> {
-    Ack,
-    Connect { name: &'a str, password: Vec<u8> },
-    Accepted,
-    Hello,
-    ServerInfo { key: Vec<u8> },
-    Ping { time: f64 },
-    Pong { time: f64 },
-}
+use crate::net_rate::{NetStats, RateLimiter};
+use crate::net_transport::Transport;
 
 pub(crate) trait Endpoint: Debug {
     fn connect(&self, addr: SocketAddr) -> io::Result<()>;
     fn local_addr(&self) -> io::Result<SocketAddr>;
-    fn peer_addr(&self) -> io::Result<SocketAddr>;
     fn clear_buffers(&mut self);
     fn take_error(&self) -> io::Result<Option<Error>>;
     fn flush(&mut self) -> io::Result<usize>;
     fn send_to(&mut self, msg: &Message, addr: &SocketAddr) -> io::Result<usize>;
     fn send(&mut self, msg: &Message) -> io::Result<usize>;
-    fn receive_data<'a>(&mut self, buf: &'a mut Vec<u8>) -> io::Result<Option<ReceivedData<'a>>>;
+    ///
+    /// Sends message which may be postponed or dropped if connection is over its bandwidth budget
+    ///
+    fn send_low_priority(&mut self, msg: &Message) -> io::Result<usize>;
+    ///
+    /// Limits outgoing bytes per second, zero disables limit
+    ///
+    fn set_rate(&mut self, bytes_per_sec: u32);
+    ///
+    /// Enables checksum of the outgoing datagrams. Incoming ones are verified if sender included checksum.
+    ///
+    fn set_checksum(&mut self, enabled: bool);
+    ///
+    /// Returns traffic shaping counters accumulated since the previous call
+    ///
+    fn take_stats(&mut self) -> NetStats;
+    fn receive_data<'a>(&mut self, buf: &'a mut Vec<u8>) -> io::Result<Option<PacketView<'a>>>;
 }
 
 pub(crate) trait ServerEndpoint: Endpoint {
@@ -42,43 +55,70 @@ pub(crate) trait ServerEndpoint: Endpoint {
 }
 
 pub struct NetEndpoint {
-    socket: UdpSocket,
+    socket: Box<dyn Transport>,
+    // Destination of buffered data for endpoints sharing unconnected socket
+    peer: Option<SocketAddr>,
     send_buf: Vec<u8>,
     scratch: Vec<u8>,
+    // Datagram being sent: header followed by payload
+    packet: Vec<u8>,
+    checksum: bool,
     encoder: <Message<'static> as bitcode::Encode>::Encoder,
-    decoder: <Message<'static> as bitcode::Decode<'static>>::Decoder,
+    limiter: RateLimiter,
+    // Encoded low priority messages waiting for bandwidth
+    deferred: VecDeque<Vec<u8>>,
+    stats: NetStats,
 }
 
 impl Debug for NetEndpoint {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Endpoint")
             .field("socket", &self.socket)
+            .field("peer", &self.peer)
             .field("send_buf", &self.send_buf)
             .field("scratch", &self.scratch)
+            .field("checksum", &self.checksum)
+            .field("limiter", &self.limiter)
+            .field("stats", &self.stats)
             .finish_non_exhaustive()
     }
 }
 
 impl NetEndpoint {
-    fn from_socket(socket: UdpSocket) -> Self {
+    const MAX_DEFERRED: usize = 32;
+
+    fn from_socket(socket: Box<dyn Transport>, peer: Option<SocketAddr>, checksum: bool) -> Self {
         NetEndpoint {
             socket,
-            send_buf: Vec::with_capacity(MAX_DATAGRAM_SIZE),
-            scratch: Vec::with_capacity(MAX_DATAGRAM_SIZE),
+            peer,
+            send_buf: Vec::with_capacity(MAX_PAYLOAD_SIZE),
+            scratch: Vec::with_capacity(MAX_PAYLOAD_SIZE),
+            packet: Vec::with_capacity(MAX_DATAGRAM_SIZE),
+            checksum,
             encoder: <Message<'_> as bitcode::Encode>::Encoder::default(),
-            decoder: <Message<'_> as bitcode::Decode>::Decoder::default(),
+            limiter: RateLimiter::new(0),
+            deferred: VecDeque::new(),
+            stats: NetStats::default(),
         }
     }
 
     pub fn with_address<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
         let socket = UdpSocket::bind(addr)?;
         socket.set_nonblocking(true)?;
-        Ok(Self::from_socket(socket))
+        Ok(Self::from_socket(Box::new(socket), None, false))
     }
     pub fn new() -> io::Result<Self> {
         Self::with_address((Ipv4Addr::UNSPECIFIED, 0))
     }
 
+    ///
+    /// Creates endpoint over non-UDP transport, e.g. [`crate::net_loopback::Loopback`]
+    ///
+    #[cfg(test)]
+    pub(crate) fn with_transport(transport: Box<dyn Transport>) -> Self {
+        Self::from_socket(transport, None, false)
+    }
+
     fn encode_to_scratch(&mut self, msg: &Message) -> usize {
         self.encoder.reserve(NonZeroUsize::new(1).unwrap());
         encode_inline_never(&mut self.encoder, msg);
@@ -87,17 +127,46 @@ impl NetEndpoint {
         self.scratch.len()
     }
 
+    fn defer(&mut self, data: Vec<u8>) {
+        if self.deferred.len() == Self::MAX_DEFERRED {
+            self.deferred.pop_front();
+            self.stats.dropped += 1;
+        }
+        self.deferred.push_back(data);
+        self.stats.deferred += 1;
+    }
+
+    ///
+    /// Moves deferred messages to send buffer while they fit into budget and datagram
+    ///
+    fn take_deferred(&mut self) {
+        while let Some(data) = self.deferred.front() {
+            let total = self.send_buf.len() + data.len();
+            if total >= MAX_PAYLOAD_SIZE || !self.limiter.allows(total) {
+                break;
+            }
+            self.send_buf.extend_from_slice(data);
+            self.deferred.pop_front();
+        }
+    }
+
     fn flush_exact(&mut self, amount: usize) -> io::Result<usize> {
-        let buf = &mut self.send_buf;
-        assert!(amount <= buf.len());
-        assert!(amount <= MAX_DATAGRAM_SIZE);
-        let mut left = amount;
-        while left > 0 {
-            match self.socket.send(&buf[..left]) {
-                Ok(written) => {
-                    left -= written;
-                    buf.drain(..written);
-                }
+        assert!(amount <= self.send_buf.len());
+        assert!(amount <= MAX_PAYLOAD_SIZE);
+        if amount == 0 {
+            return Ok(0);
+        }
+        let payload = &self.send_buf[..amount];
+        self.packet.clear();
+        write_header(&mut self.packet, payload, self.checksum);
+        self.packet.extend_from_slice(payload);
+        loop {
+            let result = match self.peer {
+                Some(ref peer) => self.socket.send_to(&self.packet, peer),
+                None => self.socket.send(&self.packet),
+            };
+            match result {
+                Ok(_) => break,
                 Err(e) => {
                     if e.kind() == WouldBlock {
                         std::thread::yield_now();
@@ -107,7 +176,8 @@ impl NetEndpoint {
                 }
             }
         }
-        Ok(amount - left)
+        self.send_buf.drain(..amount);
+        Ok(amount)
     }
 }
 
@@ -120,10 +190,6 @@ impl Endpoint for NetEndpoint {
         self.socket.local_addr()
     }
 
-    fn peer_addr(&self) -> io::Result<SocketAddr> {
-        self.socket.peer_addr()
-    }
-
     fn clear_buffers(&mut self) {
         self.send_buf.clear();
     }
@@ -133,43 +199,79 @@ impl Endpoint for NetEndpoint {
     }
 
     fn flush(&mut self) -> io::Result<usize> {
+        self.limiter.refill(Instant::now());
+        self.take_deferred();
         let buf = &self.send_buf;
-        assert!(buf.len() <= MAX_DATAGRAM_SIZE);
-        self.flush_exact(min(buf.len(), MAX_DATAGRAM_SIZE))
+        assert!(buf.len() <= MAX_PAYLOAD_SIZE);
+        let sent = self.flush_exact(min(buf.len(), MAX_PAYLOAD_SIZE))?;
+        self.limiter.consume(sent);
+        Ok(sent)
     }
 
     fn send_to(&mut self, msg: &Message, addr: &SocketAddr) -> io::Result<usize> {
         self.encode_to_scratch(msg);
-        self.socket.send_to(&self.scratch, addr)
+        self.packet.clear();
+        write_header(&mut self.packet, &self.scratch, self.checksum);
+        self.packet.extend_from_slice(&self.scratch);
+        self.socket.send_to(&self.packet, addr)
     }
 
     fn send(&mut self, msg: &Message) -> io::Result<usize> {
         self.encode_to_scratch(msg);
-        if self.send_buf.len() + self.scratch.len() >= MAX_DATAGRAM_SIZE {
+        if self.send_buf.len() + self.scratch.len() >= MAX_PAYLOAD_SIZE {
             self.flush()?;
         }
         self.send_buf.write(&self.scratch)
     }
 
-    fn receive_data<'a>(&mut self, buf: &'a mut Vec<u8>) -> io::Result<Option<ReceivedData<'a>>> {
+    fn send_low_priority(&mut self, msg: &Message) -> io::Result<usize> {
+        let len = self.encode_to_scratch(msg);
+        let total = self.send_buf.len() + len;
+        // Keep order of low priority messages
+        if self.deferred.is_empty() && total < MAX_PAYLOAD_SIZE && self.limiter.allows(total) {
+            return self.send_buf.write(&self.scratch);
+        }
+        let data = self.scratch.clone();
+        self.defer(data);
+        Ok(0)
+    }
+
+    fn set_rate(&mut self, bytes_per_sec: u32) {
+        self.limiter.set_rate(bytes_per_sec);
+    }
+
+    fn set_checksum(&mut self, enabled: bool) {
+        self.checksum = enabled;
+    }
+
+    fn take_stats(&mut self) -> NetStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    fn receive_data<'a>(&mut self, buf: &'a mut Vec<u8>) -> io::Result<Option<PacketView<'a>>> {
         buf.resize(MAX_DATAGRAM_SIZE, 0);
-        match self.socket.recv_from(buf.as_mut_slice()) {
-            Ok((amount, addr)) => {
-                if amount > 0 {
-                    buf.truncate(amount);
-                    Ok(Some(ReceivedData::new(buf.as_slice(), addr)))
-                } else {
-                    Ok(None)
+        // Skip corrupted datagrams, so caller doesn't take them for the end of data
+        let (header, amount, addr) = loop {
+            match self.socket.recv_from(buf.as_mut_slice()) {
+                Ok((0, _)) => return Ok(None),
+                Ok((amount, addr)) => {
+                    if let Some(header) = check_header(&buf[..amount]) {
+                        break (header, amount, addr);
+                    }
+                    self.stats.corrupted += 1;
+                    warn!("Dropping corrupted datagram from {addr}");
+                }
+                Err(e) => {
+                    return if e.kind() == WouldBlock {
+                        Ok(None) // no data yet
+                    } else {
+                        Err(e)
+                    };
                 }
             }
-            Err(e) => {
-                return if e.kind() == WouldBlock {
-                    Ok(None) // no data yet
-                } else {
-                    Err(e)
-                };
-            }
-        }
+        };
+        buf.truncate(amount);
+        Ok(Some(PacketView::new(&buf[header..], addr)))
     }
 }
 
@@ -178,46 +280,77 @@ impl ServerEndpoint for NetEndpoint {
         &self,
         addr: &SocketAddr,
     ) -> io::Result<Box<dyn Endpoint + Sync + Send>> {
+        // Socket is shared by all clients, so it's never connected. Cloned endpoint just remembers its peer.
         let socket = self.socket.try_clone()?;
-        self.socket.connect(addr)?;
-        Ok(Box::new(Self::from_socket(socket)))
+        Ok(Box::new(Self::from_socket(
+            socket,
+            Some(*addr),
+            self.checksum,
+        )))
     }
 }
 
-pub(crate) struct ReceivedData<'a> {
-    pub addr: SocketAddr,
-    slice: &'a [u8],
-    decoder: Option<<Message<'a> as bitcode::Decode<'a>>::Decoder>,
+#[inline(never)]
+fn encode_inline_never<T: Encode + ?Sized>(encoder: &mut T::Encoder, t: &T) {
+    encoder.encode(t);
 }
 
-impl<'a> ReceivedData<'a> {
-    pub fn new(slice: &'a [u8], addr: SocketAddr) -> Self {
-        ReceivedData {
-            addr,
-            slice,
-            decoder: Some(<Message<'_> as bitcode::Decode>::Decoder::default()),
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::{Endpoint, Message, NetEndpoint};
+
+    #[test]
+    fn low_priority_messages_are_deferred() {
+        let receiver = NetEndpoint::with_address((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut sender = NetEndpoint::with_address((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        sender.connect(receiver.local_addr().unwrap()).unwrap();
+        sender.set_rate(100);
+
+        // Regular messages ignore the budget
+        let ping = Message::Ping { time: 1.0 };
+        for _ in 0..20 {
+            sender.send(&ping).unwrap();
         }
-    }
+        sender.send_low_priority(&ping).unwrap();
+        assert!(sender.flush().unwrap() > 25);
+        assert_eq!(1, sender.take_stats().deferred);
 
-    pub fn read(&mut self) -> Option<Message> {
-        if self.slice.is_empty() {
-            return None;
+        for _ in 0..40 {
+            sender.send_low_priority(&ping).unwrap();
         }
-        let mut slice = &mut std::mem::take(&mut self.slice);
-        let mut decoder = <Message<'_> as bitcode::Decode>::Decoder::default();
-        decoder.populate(&mut slice, 1).unwrap();
-        let msg: Message = decode_inline_never(&mut decoder);
-        self.slice = slice;
-        return Some(msg);
+        let stats = sender.take_stats();
+        assert_eq!(40, stats.deferred);
+        // Queue is limited
+        assert_eq!(9, stats.dropped);
+        assert_eq!(0, sender.flush().unwrap());
+        assert_eq!(0, sender.take_stats().deferred);
+
+        // Without limit deferred messages go out with next flush
+        sender.set_rate(0);
+        assert!(sender.flush().unwrap() > 0);
+        assert!(sender.deferred.is_empty());
     }
-}
 
-#[inline(never)]
-fn encode_inline_never<T: Encode + ?Sized>(encoder: &mut T::Encoder, t: &T) {
-    encoder.encode(t);
-}
+    #[test]
+    fn corrupted_datagrams_are_rejected() {
+        let mut receiver = NetEndpoint::with_address((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut sender = NetEndpoint::with_address((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = receiver.local_addr().unwrap();
+        sender.set_checksum(true);
+        sender.socket.send_to(&[1, 0, 0, 0, 0, 42], &addr).unwrap();
+        sender.send_to(&Message::Ping { time: 2.0 }, &addr).unwrap();
 
-#[inline(never)]
-pub(crate) fn decode_inline_never<'a, T: Decode<'a>>(decoder: &mut T::Decoder) -> T {
-    decoder.decode()
+        let mut buf = Vec::new();
+        let mut view = loop {
+            if let Some(view) = receiver.receive_data(&mut buf).unwrap() {
+                break view;
+            }
+            std::thread::yield_now();
+        };
+        assert!(matches!(view.read(), Some(Message::Ping { time }) if time == 2.0));
+        assert!(view.read().is_none());
+        assert_eq!(1, receiver.take_stats().corrupted);
+    }
 }
@@ -0,0 +1,179 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, ErrorKind};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use crate::net_transport::Transport;
+
+#[derive(Debug, Default)]
+struct Hub {
+    queues: HashMap<SocketAddr, VecDeque<(Vec<u8>, SocketAddr)>>,
+    next_port: u16,
+    loss: f32,
+}
+
+///
+/// In-process network of [`Loopback`] transports. May drop a share of datagrams to simulate bad link.
+///
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LoopbackNet {
+    hub: Arc<Mutex<Hub>>,
+}
+
+impl LoopbackNet {
+    /// Datagrams queued for the single address, the rest is dropped like by the full socket buffer
+    const MAX_QUEUED: usize = 1024;
+
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Sets share of datagrams lost in transit, 0..1
+    ///
+    pub(crate) fn set_loss(&self, loss: f32) {
+        self.hub.lock().unwrap().loss = loss.clamp(0.0, 1.0);
+    }
+
+    ///
+    /// Creates transport with the next free address
+    ///
+    pub(crate) fn bind(&self) -> Loopback {
+        let mut hub = self.hub.lock().unwrap();
+        hub.next_port += 1;
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, hub.next_port));
+        hub.queues.insert(addr, VecDeque::new());
+        Loopback {
+            net: self.clone(),
+            addr,
+            peer: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+///
+/// Transport of [`LoopbackNet`]
+///
+#[derive(Debug)]
+pub(crate) struct Loopback {
+    net: LoopbackNet,
+    addr: SocketAddr,
+    // Shared by clones like connected state of the socket
+    peer: Arc<Mutex<Option<SocketAddr>>>,
+}
+
+impl Transport for Loopback {
+    fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        *self.peer.lock().unwrap() = Some(addr);
+        Ok(())
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+
+    fn take_error(&self) -> io::Result<Option<io::Error>> {
+        Ok(None)
+    }
+
+    fn send(&self, data: &[u8]) -> io::Result<usize> {
+        let peer = self.peer.lock().unwrap().ok_or(ErrorKind::NotConnected)?;
+        self.send_to(data, &peer)
+    }
+
+    fn send_to(&self, data: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        let mut hub = self.net.hub.lock().unwrap();
+        if hub.loss > 0.0 && rand::random::<f32>() < hub.loss {
+            return Ok(data.len());
+        }
+        // Nobody listens there, dropped silently as with UDP
+        if let Some(queue) = hub.queues.get_mut(addr) {
+            if queue.len() < LoopbackNet::MAX_QUEUED {
+                queue.push_back((data.to_vec(), self.addr));
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let peer = *self.peer.lock().unwrap();
+        let mut hub = self.net.hub.lock().unwrap();
+        let queue = hub
+            .queues
+            .get_mut(&self.addr)
+            .ok_or(ErrorKind::NotConnected)?;
+        while let Some((data, from)) = queue.pop_front() {
+            if peer.is_some_and(|v| v != from) {
+                continue;
+            }
+            // Datagram is truncated if it doesn't fit
+            let amount = data.len().min(buf.len());
+            buf[..amount].copy_from_slice(&data[..amount]);
+            return Ok((amount, from));
+        }
+        Err(ErrorKind::WouldBlock.into())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(Loopback {
+            net: self.net.clone(),
+            addr: self.addr,
+            peer: self.peer.clone(),
+        }))
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+
+    use crate::net_transport::Transport;
+
+    use super::LoopbackNet;
+
+    #[test]
+    fn loopback() {
+        let net = LoopbackNet::new();
+        let server = net.bind();
+        let client = net.bind();
+        let other = net.bind();
+        let server_addr = server.local_addr().unwrap();
+        client.connect(server_addr).unwrap();
+
+        client.send(b"hello").unwrap();
+        let mut buf = [0u8; 16];
+        let (amount, from) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(b"hello", &buf[..amount]);
+        assert_eq!(client.local_addr().unwrap(), from);
+        assert_eq!(
+            ErrorKind::WouldBlock,
+            server.recv_from(&mut buf).unwrap_err().kind()
+        );
+
+        // Connected transport ignores strangers
+        let clone = client.try_clone().unwrap();
+        other.send_to(b"spam", &from).unwrap();
+        server.send_to(b"reply", &from).unwrap();
+        let (amount, _) = clone.recv_from(&mut buf).unwrap();
+        assert_eq!(b"reply", &buf[..amount]);
+        assert!(client.recv_from(&mut buf).is_err());
+    }
+
+    #[test]
+    fn lossy_link() {
+        let net = LoopbackNet::new();
+        let a = net.bind();
+        let b = net.bind();
+        let addr = b.local_addr().unwrap();
+        net.set_loss(1.0);
+        assert_eq!(3, a.send_to(b"bye", &addr).unwrap());
+        let mut buf = [0u8; 16];
+        assert!(b.recv_from(&mut buf).is_err());
+        net.set_loss(0.0);
+        a.send_to(b"hi", &addr).unwrap();
+        assert!(b.recv_from(&mut buf).is_ok());
+    }
+}
@@ -0,0 +1,415 @@
+use std::time::Instant;
+
+use rg_common::config::CongestionConfig;
+
+///
+/// Counters of the outgoing traffic shaping and rejected incoming datagrams
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct NetStats {
+    /// Low priority messages postponed because of exhausted budget
+    pub deferred: u32,
+    /// Low priority messages discarded because deferred queue was full
+    pub dropped: u32,
+    /// Received datagrams with wrong checksum or header
+    pub corrupted: u32,
+}
+
+///
+/// Token bucket limiting outgoing bytes per second. Zero rate means no limit.
+///
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    rate: u32,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    ///
+    /// Max burst, fraction of the per second budget which may be accumulated while idle
+    ///
+    const BURST: f64 = 0.25;
+
+    pub(crate) fn new(rate: u32) -> Self {
+        RateLimiter {
+            rate,
+            tokens: rate as f64 * Self::BURST,
+            updated_at: Instant::now(),
+        }
+    }
+
+    pub(crate) fn set_rate(&mut self, rate: u32) {
+        if rate != self.rate {
+            *self = Self::new(rate);
+        }
+    }
+
+    pub(crate) fn is_limited(&self) -> bool {
+        self.rate > 0
+    }
+
+    ///
+    /// Adds tokens for the time passed since the previous call
+    ///
+    pub(crate) fn refill(&mut self, now: Instant) {
+        let dt = now.saturating_duration_since(self.updated_at);
+        self.updated_at = now;
+        let max = self.rate as f64 * Self::BURST;
+        self.tokens = (self.tokens + self.rate as f64 * dt.as_secs_f64()).min(max);
+    }
+
+    ///
+    /// Checks if `amount` bytes may be sent without exceeding the budget
+    ///
+    pub(crate) fn allows(&self, amount: usize) -> bool {
+        !self.is_limited() || self.tokens >= amount as f64
+    }
+
+    ///
+    /// Takes sent bytes from the budget. Budget may become negative as regular messages are never held back.
+    ///
+    pub(crate) fn consume(&mut self, amount: usize) {
+        if self.is_limited() {
+            self.tokens -= amount as f64;
+        }
+    }
+}
+
+///
+/// Effective rate of the connection: client's wish capped by server limit, zero means no limit
+///
+pub(crate) fn effective_rate(client_rate: u32, max_rate: u32) -> u32 {
+    match (client_rate, max_rate) {
+        (0, max) => max,
+        (rate, 0) => rate,
+        (rate, max) => rate.min(max),
+    }
+}
+
+///
+/// Adapts send rate of the connection to the link quality: additive increase while link is fine,
+/// multiplicative decrease when pings are lost or round trip grows above the baseline (queues are filling up).
+/// Zero rate means no limit.
+///
+#[derive(Debug)]
+pub(crate) struct CongestionControl {
+    min_rate: u32,
+    max_rate: u32,
+    rate: u32,
+    /// Pings sent since the previous update
+    sent: u32,
+    /// Pings sent before the previous update, their pongs are expected by now
+    expected: u32,
+    received: u32,
+    /// Lowest round trip seen, in seconds
+    min_rtt: Option<f64>,
+    /// Smoothed round trip, in seconds
+    srtt: Option<f64>,
+    quality: LinkQuality,
+}
+
+impl CongestionControl {
+    const DECREASE: f64 = 0.7;
+    /// Number of updates to grow from zero to max rate
+    const INCREASE_STEPS: u32 = 20;
+    /// Round trip is considered growing if it exceeds baseline by this factor plus slack
+    const RTT_FACTOR: f64 = 1.5;
+    const RTT_SLACK: f64 = 0.02;
+
+    ///
+    /// Starts at max rate: connection's own rate or configured max for unlimited ones
+    ///
+    pub(crate) fn new(cfg: &CongestionConfig, rate: u32) -> Self {
+        let max_rate = if cfg.enabled {
+            effective_rate(rate, cfg.max_rate)
+        } else {
+            rate
+        };
+        CongestionControl {
+            min_rate: if cfg.enabled {
+                cfg.min_rate.min(max_rate)
+            } else {
+                max_rate
+            },
+            max_rate,
+            rate: max_rate,
+            sent: 0,
+            expected: 0,
+            received: 0,
+            min_rtt: None,
+            srtt: None,
+            quality: LinkQuality::default(),
+        }
+    }
+
+    pub(crate) fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub(crate) fn on_ping_sent(&mut self) {
+        self.sent += 1;
+    }
+
+    pub(crate) fn on_pong(&mut self, rtt: f64) {
+        self.received += 1;
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |v| v.min(rtt)));
+        self.srtt = Some(self.srtt.map_or(rtt, |v| v + (rtt - v) / 8.0));
+        self.quality.on_pong(rtt);
+    }
+
+    ///
+    /// Link quality band, see [`LinkQuality::band`]
+    ///
+    pub(crate) fn quality(&self) -> Option<u8> {
+        self.quality.band()
+    }
+
+    fn is_delay_growing(&self) -> bool {
+        match (self.min_rtt, self.srtt) {
+            (Some(min), Some(srtt)) => srtt > min * Self::RTT_FACTOR + Self::RTT_SLACK,
+            _ => false,
+        }
+    }
+
+    ///
+    /// Called once per ping interval before sending the next ping, returns new rate
+    ///
+    pub(crate) fn update(&mut self) -> u32 {
+        let lost = self.expected.saturating_sub(self.received);
+        self.quality.on_interval(self.expected, lost);
+        self.expected = self.sent;
+        self.sent = 0;
+        self.received = 0;
+        if self.max_rate == 0 || self.min_rate == self.max_rate {
+            return self.rate;
+        }
+        self.rate = if lost > 0 || self.is_delay_growing() {
+            ((self.rate as f64 * Self::DECREASE) as u32).max(self.min_rate)
+        } else if self.quality.is_jittery() {
+            // Jittery link, hold the rate until it settles
+            self.rate
+        } else {
+            let step = (self.max_rate / Self::INCREASE_STEPS).max(1);
+            self.rate.saturating_add(step).min(self.max_rate)
+        };
+        self.rate
+    }
+}
+
+///
+/// Connection quality from 0 (unusable) to 4 (excellent), the worst of round trip, jitter and loss scores.
+/// Measured with server pings, so it changes at most once per ping interval.
+///
+#[derive(Debug, Default)]
+pub(crate) struct LinkQuality {
+    srtt: Option<f64>,
+    last_rtt: Option<f64>,
+    /// Smoothed difference of consecutive round trips, in seconds
+    jitter: f64,
+    /// Smoothed share of lost pings
+    loss: f64,
+    band: Option<u8>,
+}
+
+impl LinkQuality {
+    pub(crate) const MAX: u8 = 4;
+    /// Lowest band good enough for fluent play
+    pub(crate) const FAIR: u8 = 2;
+    /// Upper limits of bands 4, 3, 2 and 1, anything above gives 0
+    const RTT: [f64; 4] = [0.06, 0.12, 0.2, 0.35];
+    const JITTER: [f64; 4] = [0.01, 0.025, 0.05, 0.1];
+    const LOSS: [f64; 4] = [0.01, 0.03, 0.07, 0.15];
+
+    fn score(value: f64, limits: &[f64; 4]) -> u8 {
+        Self::MAX - limits.iter().take_while(|&&v| value >= v).count() as u8
+    }
+
+    pub(crate) fn on_pong(&mut self, rtt: f64) {
+        if let Some(last) = self.last_rtt {
+            self.jitter += ((rtt - last).abs() - self.jitter) / 8.0;
+        }
+        self.last_rtt = Some(rtt);
+        self.srtt = Some(self.srtt.map_or(rtt, |v| v + (rtt - v) / 8.0));
+    }
+
+    ///
+    /// Called once per ping interval with number of pings due and lost since the previous call
+    ///
+    pub(crate) fn on_interval(&mut self, expected: u32, lost: u32) {
+        if expected > 0 {
+            let share = lost.min(expected) as f64 / expected as f64;
+            self.loss += (share - self.loss) / 4.0;
+        }
+        self.band = self.srtt.map(|srtt| {
+            Self::score(srtt, &Self::RTT)
+                .min(Self::score(self.jitter, &Self::JITTER))
+                .min(Self::score(self.loss, &Self::LOSS))
+        });
+    }
+
+    ///
+    /// Jitter alone drops quality below fair
+    ///
+    pub(crate) fn is_jittery(&self) -> bool {
+        Self::score(self.jitter, &Self::JITTER) < Self::FAIR
+    }
+
+    ///
+    /// Current band or `None` until the first round trip is measured
+    ///
+    pub(crate) fn band(&self) -> Option<u8> {
+        self.band
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rg_common::config::CongestionConfig;
+
+    use super::{effective_rate, CongestionControl, LinkQuality, RateLimiter};
+
+    fn ping_round(cc: &mut CongestionControl, rtt: Option<f64>) -> u32 {
+        if let Some(rtt) = rtt {
+            cc.on_pong(rtt);
+        }
+        let rate = cc.update();
+        cc.on_ping_sent();
+        rate
+    }
+
+    #[test]
+    fn unlimited() {
+        let mut r = RateLimiter::new(0);
+        assert!(!r.is_limited());
+        r.consume(1_000_000);
+        assert!(r.allows(1_000_000));
+    }
+
+    #[test]
+    fn budget() {
+        let mut r = RateLimiter::new(1000);
+        let start = r.updated_at;
+        // Initial burst
+        assert!(r.allows(250));
+        assert!(!r.allows(251));
+        r.consume(450);
+        assert!(!r.allows(1));
+        r.refill(start + Duration::from_millis(300));
+        assert!(r.allows(100));
+        assert!(!r.allows(101));
+        // Idle time doesn't accumulate more than burst
+        r.refill(start + Duration::from_secs(10));
+        assert!(r.allows(250));
+        assert!(!r.allows(251));
+        r.set_rate(1000);
+        assert!(!r.allows(251));
+        r.set_rate(2000);
+        assert!(r.allows(500));
+    }
+
+    #[test]
+    fn effective() {
+        assert_eq!(0, effective_rate(0, 0));
+        assert_eq!(5000, effective_rate(0, 5000));
+        assert_eq!(3000, effective_rate(3000, 0));
+        assert_eq!(3000, effective_rate(3000, 5000));
+        assert_eq!(5000, effective_rate(8000, 5000));
+    }
+
+    #[test]
+    fn congestion() {
+        let cfg = CongestionConfig {
+            enabled: true,
+            min_rate: 1000,
+            max_rate: 0,
+        };
+        // Unlimited connection is not adapted without configured max
+        let mut cc = CongestionControl::new(&cfg, 0);
+        assert_eq!(0, ping_round(&mut cc, None));
+        assert_eq!(0, ping_round(&mut cc, None));
+
+        let mut cc = CongestionControl::new(&cfg, 10000);
+        assert_eq!(10000, ping_round(&mut cc, None));
+        assert_eq!(10000, ping_round(&mut cc, Some(0.05)));
+        // Lost ping
+        assert_eq!(7000, ping_round(&mut cc, None));
+        assert_eq!(7500, ping_round(&mut cc, Some(0.05)));
+        // Growing round trip
+        for _ in 0..10 {
+            ping_round(&mut cc, Some(0.5));
+        }
+        assert_eq!(1000, cc.rate());
+        // Recovery
+        for _ in 0..40 {
+            ping_round(&mut cc, Some(0.05));
+        }
+        assert_eq!(10000, cc.rate());
+
+        let disabled = CongestionConfig {
+            enabled: false,
+            ..cfg
+        };
+        let mut cc = CongestionControl::new(&disabled, 10000);
+        ping_round(&mut cc, None);
+        assert_eq!(10000, ping_round(&mut cc, None));
+    }
+
+    #[test]
+    fn link_quality() {
+        let mut q = LinkQuality::default();
+        q.on_interval(0, 0);
+        assert_eq!(None, q.band());
+        q.on_pong(0.03);
+        q.on_interval(1, 0);
+        assert_eq!(Some(LinkQuality::MAX), q.band());
+        // Slow but steady
+        for _ in 0..30 {
+            q.on_pong(0.15);
+            q.on_interval(1, 0);
+        }
+        assert_eq!(Some(2), q.band());
+        // Jitter
+        for i in 0..30 {
+            q.on_pong(if i % 2 == 0 { 0.03 } else { 0.15 });
+            q.on_interval(1, 0);
+        }
+        assert_eq!(Some(0), q.band());
+        // Loss
+        let mut q = LinkQuality::default();
+        q.on_pong(0.03);
+        q.on_interval(1, 1);
+        assert_eq!(Some(0), q.band());
+        for _ in 0..20 {
+            q.on_pong(0.03);
+            q.on_interval(1, 0);
+        }
+        assert_eq!(Some(LinkQuality::MAX), q.band());
+    }
+
+    #[test]
+    fn jittery_link_holds_rate() {
+        let cfg = CongestionConfig {
+            enabled: true,
+            min_rate: 1000,
+            max_rate: 0,
+        };
+        let mut cc = CongestionControl::new(&cfg, 10000);
+        ping_round(&mut cc, None);
+        ping_round(&mut cc, Some(0.1));
+        assert_eq!(7000, ping_round(&mut cc, None));
+        for i in 0..10 {
+            ping_round(&mut cc, Some(if i % 2 == 0 { 0.2 } else { 0.1 }));
+        }
+        assert!(cc.quality() < Some(LinkQuality::FAIR));
+        assert!(cc.quality.is_jittery());
+        let rate = cc.rate();
+        assert!(rate < 10000);
+        assert_eq!(rate, ping_round(&mut cc, Some(0.2)));
+    }
+}
@@ -0,0 +1,484 @@
+//!
+//! In-process network tests: real server and client talking over loopback UDP sockets or [`LoopbackNet`].
+//!
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs, process, thread};
+
+use rg_common::config::{
+    AuthConfig, AutosaveConfig, ClientConfig, Config, CongestionConfig, DeathmatchConfig,
+    MatchLogConfig, MetricsConfig, ReconnectConfig, RotationConfig, ServerConfig, StatsConfig,
+    TeamsConfig, VoteConfig, WatchdogConfig,
+};
+use rg_common::{AppFiles, Arguments};
+
+use crate::app::App;
+use crate::client::Client;
+use crate::net::{NetEndpoint, PlayerInput};
+use crate::net_loopback::LoopbackNet;
+use crate::net_rate::LinkQuality;
+use crate::server::sv_game_mode::DeathmatchHud;
+use crate::server::sv_security::Ticket;
+use crate::server::Server;
+
+const CLIENT_PASSWORD: &str = "123456";
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Harness {
+    app: Arc<App>,
+    server: Server,
+    client: Client,
+}
+
+pub(crate) fn config(password: Option<&str>) -> Config {
+    Config {
+        server: ServerConfig {
+            address: "127.0.0.1:0".to_string(),
+            bound_to: None,
+            key_bits: 512,
+            password: password.map(str::to_string),
+            max_rate: 0,
+            checksum: true,
+            vote: VoteConfig::default(),
+            metrics: MetricsConfig::default(),
+            stats: StatsConfig {
+                enabled: false,
+                ..StatsConfig::default()
+            },
+            auth: AuthConfig::default(),
+            game_mode: "sandbox".to_string(),
+            deathmatch: DeathmatchConfig::default(),
+            teams: TeamsConfig::default(),
+            autosave: AutosaveConfig {
+                enabled: false,
+                restore: false,
+                ..AutosaveConfig::default()
+            },
+            congestion: CongestionConfig::default(),
+            match_log: MatchLogConfig::default(),
+            rotation: RotationConfig::default(),
+        },
+        client: ClientConfig {
+            name: "Test".to_string(),
+            rate: 0,
+            checksum: true,
+            ticket: None,
+            reconnect: ReconnectConfig::default(),
+        },
+        watchdog: WatchdogConfig::default(),
+    }
+}
+
+///
+/// Every harness gets its own temp profile, so tests never touch the user data dir
+///
+fn profile() -> AppFiles {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let dir = env::temp_dir().join(format!(
+        "rg_net_tests_{}_{}",
+        process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    AppFiles::with_profile(dir)
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(self.app.profile_dir());
+    }
+}
+
+impl Harness {
+    fn new(password: Option<&str>) -> Self {
+        Self::with_config(config(password))
+    }
+
+    fn with_config(config: Config) -> Self {
+        let args = Arguments::parse();
+        let files = profile();
+        let app = Arc::new(App::with_config(args, files, config));
+        let server = Server::new(&app).unwrap();
+        let client = Client::new(&app);
+        Harness {
+            app,
+            server,
+            client,
+        }
+    }
+
+    ///
+    /// Server and client talk over in-process network instead of UDP sockets
+    ///
+    fn over_loopback(config: Config, net: &LoopbackNet) -> Self {
+        let args = Arguments::parse();
+        let files = profile();
+        let app = Arc::new(App::with_config(args, files, config));
+        let server =
+            Server::with_endpoint(&app, NetEndpoint::with_transport(Box::new(net.bind()))).unwrap();
+        let client = Client::with_endpoint(&app, NetEndpoint::with_transport(Box::new(net.bind())));
+        Harness {
+            app,
+            server,
+            client,
+        }
+    }
+
+    ///
+    /// Runs single client frame followed by single server update
+    ///
+    fn step(&mut self) {
+        self.step_client();
+        self.server.update().expect("Server update failed!");
+    }
+
+    ///
+    /// Runs single client frame only, as if server is down
+    ///
+    fn step_client(&mut self) {
+        self.client.frame_start();
+        self.client.update(&self.app);
+        self.client.frame_end();
+    }
+
+    ///
+    /// Steps both sides until predicate holds or timeout expires. Returns the last value of predicate.
+    ///
+    fn run_until<P>(&mut self, timeout: Duration, predicate: P) -> bool
+    where
+        P: Fn(&Harness) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            self.step();
+            if predicate(self) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        predicate(self)
+    }
+}
+
+#[test]
+fn handshake() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    assert_eq!(1, h.server.client_count());
+}
+
+#[test]
+fn handshake_without_password() {
+    let mut h = Harness::new(None);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    assert_eq!(1, h.server.client_count());
+}
+
+#[test]
+fn lossy_loopback() {
+    let net = LoopbackNet::new();
+    let mut h = Harness::over_loopback(config(Some(CLIENT_PASSWORD)), &net);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    assert_eq!(1, h.server.client_count());
+    let token = h.client.session_token();
+    // Link goes down completely
+    net.set_loss(1.0);
+    h.client.simulate_timeout();
+    h.step();
+    assert!(!h.run_until(Duration::from_millis(100), |h| h.client.is_connected()));
+    net.set_loss(0.0);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    assert_eq!(token, h.client.session_token());
+}
+
+#[test]
+fn ping_pong() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.ping().is_some()));
+    let ping = h.client.ping().unwrap();
+    assert!((0.0..1.0).contains(&ping), "Unexpected ping: {ping}");
+}
+
+#[test]
+fn link_quality() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    assert_eq!(None, h.client.link_quality());
+    // Band is known after the first ping interval with a reply, local link is excellent
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.link_quality().is_some()));
+    assert_eq!(Some(LinkQuality::MAX), h.client.link_quality());
+}
+
+#[test]
+fn clock_sync() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.server_time().is_some()));
+    let client_time = h.client.server_time().unwrap();
+    let server_time = h.server.clock().time;
+    // Both run in the same process, so the estimate is off by a fraction of round trip at most
+    assert!(
+        (client_time - server_time).abs() < 0.1,
+        "Client {client_time}, server {server_time}"
+    );
+    assert!(h.client.server_tick().unwrap() <= h.server.clock().tick);
+    h.client.log_status();
+}
+
+#[test]
+fn player_input() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    let input = PlayerInput {
+        forward: 1,
+        side: -1,
+        yaw: 90.0,
+        pitch: 10.0,
+        buttons: 1,
+    };
+    h.client.send_input(&input);
+    h.client.frame_end();
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.server.inputs().any(|v| *v == input)));
+    assert!(h.client.bytes_sent() > 0);
+}
+
+#[test]
+fn mode_status() {
+    let mut cfg = config(Some(CLIENT_PASSWORD));
+    cfg.server.game_mode = "deathmatch".to_string();
+    cfg.server.deathmatch.frag_limit = 7;
+    let mut h = Harness::with_config(cfg);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.game_mode().is_some()));
+    let (mode, state) = h.client.game_mode().unwrap();
+    assert_eq!("deathmatch", mode);
+    let hud: DeathmatchHud = bitcode::decode(state).unwrap();
+    assert_eq!(7, hud.frag_limit);
+}
+
+#[test]
+fn slay_counts_as_suicide() {
+    let mut cfg = config(Some(CLIENT_PASSWORD));
+    cfg.server.game_mode = "deathmatch".to_string();
+    let mut h = Harness::with_config(cfg);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.scoreboard().rows().len() == 1));
+    h.app
+        .commands()
+        .invoke(vec!["slay".to_string(), "1".to_string()])
+        .unwrap();
+    assert!(
+        h.run_until(STEP_TIMEOUT, |h| h.client.scoreboard().rows()[0].score
+            == -1)
+    );
+}
+
+#[test]
+fn map_rotation() {
+    let mut cfg = config(Some(CLIENT_PASSWORD));
+    cfg.server.game_mode = "deathmatch".to_string();
+    cfg.server.deathmatch.time_limit = 0.3;
+    cfg.server.rotation.maps = "dm1, dm2".to_string();
+    cfg.server.rotation.intermission = 1.5;
+    let mut h = Harness::with_config(cfg);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.map() == Some("dm1")));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.intermission().is_some()));
+    assert_eq!(Some(("dm2", 2)), h.client.intermission());
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.map() == Some("dm2")));
+    assert_eq!(None, h.client.intermission());
+}
+
+#[test]
+fn scoreboard() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.scoreboard().rows().len() == 1));
+    // Server pings clients every second, then scoreboard carries the round trip
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.scoreboard().rows()[0].ping > 0));
+}
+
+#[test]
+fn teams_and_chat() {
+    let mut cfg = config(Some(CLIENT_PASSWORD));
+    cfg.server.teams.count = 2;
+    let mut h = Harness::with_config(cfg);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.team() == 1));
+    // Single player may move to the empty team, sizes stay within one
+    // Messages are buffered until the end of client frame
+    h.client.join_team(2);
+    h.client.frame_end();
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.team() == 2));
+    h.client.say("hello team", true);
+    h.client.frame_end();
+    assert!(h.run_until(STEP_TIMEOUT, |h| !h.client.chat().is_empty()));
+    assert_eq!("hello team", h.client.chat()[0].1);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h
+        .client
+        .scoreboard()
+        .rows()
+        .first()
+        .is_some_and(|r| r.team == 2)));
+}
+
+#[test]
+fn reconnect_resumes_session() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()
+        && h.client.session_token().is_some()));
+    let token = h.client.session_token();
+    h.client.simulate_timeout();
+    h.step();
+    assert!(!h.client.is_connected());
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    // Full handshake would issue new token
+    assert_eq!(token, h.client.session_token());
+    assert_eq!(1, h.server.client_count());
+}
+
+#[test]
+fn reconnect_gives_up() {
+    let mut cfg = config(Some(CLIENT_PASSWORD));
+    cfg.client.reconnect.attempts = 2;
+    let mut h = Harness::with_config(cfg);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    // Server is down from now on
+    h.client.simulate_timeout();
+    h.step_client();
+    assert_eq!(
+        Some("Connection lost, resuming session..."),
+        h.client.notice().as_deref()
+    );
+    // Session can't be resumed, falls back to handshake
+    h.client.skip_retry_delay();
+    h.step_client();
+    assert_eq!(
+        Some("Reconnecting, attempt 1 of 2..."),
+        h.client.notice().as_deref()
+    );
+    for _ in 0..3 {
+        h.client.skip_retry_delay();
+        h.step_client();
+    }
+    assert_eq!(
+        Some("Disconnected: Server is not responding"),
+        h.client.notice().as_deref()
+    );
+    // No more attempts
+    let sent = h.client.bytes_sent();
+    h.client.skip_retry_delay();
+    h.step_client();
+    assert_eq!(sent, h.client.bytes_sent());
+}
+
+#[test]
+fn reconnect_disabled() {
+    let mut cfg = config(Some(CLIENT_PASSWORD));
+    cfg.client.reconnect.enabled = false;
+    let mut h = Harness::with_config(cfg);
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    h.client.simulate_timeout();
+    h.step();
+    assert_eq!(
+        Some("Disconnected: Connection to server lost"),
+        h.client.notice().as_deref()
+    );
+    assert!(!h.run_until(Duration::from_millis(100), |h| h.client.is_connected()));
+}
+
+#[test]
+fn roaming_client_keeps_session() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()
+        && h.client.session_token().is_some()));
+    let token = h.client.session_token();
+    let old_addr = h.client.local_addr();
+    h.client.simulate_roaming(&h.app);
+    assert_ne!(old_addr, h.client.local_addr());
+    h.step();
+    assert!(!h.client.is_connected());
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    assert_eq!(token, h.client.session_token());
+    assert_eq!(1, h.server.client_count());
+    // Session now talks to the new address
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.ping().is_some()));
+}
+
+#[test]
+fn kick() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    let cmd = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    h.app.commands().invoke(cmd(&["status"])).unwrap();
+    h.app.commands().invoke(cmd(&["kick", "7"])).unwrap();
+    h.step();
+    assert_eq!(1, h.server.client_count());
+    h.app
+        .commands()
+        .invoke(cmd(&["kick", "1", "no", "campers"]))
+        .unwrap();
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.disconnect_reason().is_some()));
+    assert_eq!(Some("no campers"), h.client.disconnect_reason());
+    assert!(!h.client.is_connected());
+    assert_eq!(0, h.server.client_count());
+    // Kicked client doesn't come back on its own
+    assert!(!h.run_until(Duration::from_millis(300), |h| h.server.client_count() > 0));
+}
+
+#[test]
+fn wrong_password_is_rejected() {
+    let mut h = Harness::new(Some("not a client password"));
+    assert!(!h.run_until(Duration::from_millis(500), |h| h.client.is_connected()));
+    assert_eq!(0, h.server.client_count());
+}
+
+#[test]
+fn duplicate_name_is_rejected() {
+    let mut h = Harness::new(Some(CLIENT_PASSWORD));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    let mut other = Client::new(&h.app);
+    let deadline = Instant::now() + STEP_TIMEOUT;
+    while other.disconnect_reason().is_none() && Instant::now() < deadline {
+        other.frame_start();
+        other.update(&h.app);
+        other.frame_end();
+        h.step();
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(Some("Test is already connected"), other.disconnect_reason());
+    assert!(!other.is_connected());
+    assert_eq!(1, h.server.client_count());
+    assert!(h.client.is_connected());
+}
+
+fn ticket_config(ticket: Option<&Ticket>) -> Config {
+    let mut config = config(None);
+    config.server.auth = AuthConfig {
+        mode: "ticket".to_string(),
+        ticket_secret: Some("auth service secret".to_string()),
+    };
+    config.client.ticket = ticket.map(|t| {
+        t.sign(b"auth service secret")
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    });
+    config
+}
+
+#[test]
+fn ticket_auth() {
+    let expires = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 60;
+    let ticket = Ticket {
+        player_id: "account-1".to_string(),
+        name: "Ticket Holder".to_string(),
+        expires,
+    };
+    let mut h = Harness::with_config(ticket_config(Some(&ticket)));
+    assert!(h.run_until(STEP_TIMEOUT, |h| h.client.is_connected()));
+    assert_eq!(1, h.server.client_count());
+
+    let mut h = Harness::with_config(ticket_config(None));
+    assert!(!h.run_until(Duration::from_millis(500), |h| h.client.is_connected()));
+    assert_eq!(0, h.server.client_count());
+}
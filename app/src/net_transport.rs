@@ -0,0 +1,53 @@
+use std::fmt::Debug;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+///
+/// Datagram transport under [`crate::net::NetEndpoint`]. Calls never block: `recv_from` fails with
+/// [`ErrorKind::WouldBlock`] when there is nothing to read, so endpoints are polled once per frame.
+///
+pub(crate) trait Transport: Debug + Send + Sync {
+    ///
+    /// Sets destination of [`Transport::send`], datagrams from other addresses are not received after that
+    ///
+    fn connect(&self, addr: SocketAddr) -> io::Result<()>;
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+    fn take_error(&self) -> io::Result<Option<io::Error>>;
+    fn send(&self, data: &[u8]) -> io::Result<usize>;
+    fn send_to(&self, data: &[u8], addr: &SocketAddr) -> io::Result<usize>;
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+    ///
+    /// Returns another handle of the same transport
+    ///
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>>;
+}
+
+impl Transport for UdpSocket {
+    fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        UdpSocket::connect(self, addr)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+
+    fn take_error(&self) -> io::Result<Option<io::Error>> {
+        UdpSocket::take_error(self)
+    }
+
+    fn send(&self, data: &[u8]) -> io::Result<usize> {
+        UdpSocket::send(self, data)
+    }
+
+    fn send_to(&self, data: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        UdpSocket::send_to(self, data, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(UdpSocket::try_clone(self)?))
+    }
+}
@@ -1,7 +1,19 @@
 mod key_pair;
 pub mod server;
+mod sv_admin;
 mod sv_client;
+pub(crate) mod sv_game_mode;
 mod sv_init;
+mod sv_match_log;
+mod sv_metrics;
+mod sv_rotation;
+mod sv_save;
+mod sv_scoreboard;
+pub(crate) mod sv_security;
+mod sv_stats;
+mod sv_teams;
+mod sv_timers;
+mod sv_vote;
 
 pub(crate) use server::Server;
 pub(crate) use sv_init::server_init;
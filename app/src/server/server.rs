@@ -1,115 +1,924 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::io;
 use std::net::SocketAddr;
-use std::str::from_utf8;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use log::{error, info, warn};
+use rg_common::commands::{CmdError, CommandBuilder, CommandOwner};
+use rg_common::config::{CongestionConfig, StatsConfig};
+use rg_common::{ClientId, FrameTimer, GameClock, SessionId, Tick};
 
 use crate::app::App;
 use crate::error::AppError;
-use crate::net::{Endpoint, Message, NetEndpoint, ServerEndpoint, MAX_DATAGRAM_SIZE};
+use crate::net::{
+    Bytes, Endpoint, Message, NetEndpoint, ServerClock, ServerEndpoint, MAX_DATAGRAM_SIZE,
+    PROTOCOL_VERSION,
+};
+use crate::net_rate::{effective_rate, CongestionControl};
 use crate::server::key_pair::KeyPair;
+use crate::server::sv_admin::{parse_kick, AdminRequest, ClientStatus};
 use crate::server::sv_client::Client;
+use crate::server::sv_game_mode::{GameMode, GameModes, MatchOutcome};
+use crate::server::sv_match_log::{MatchEvent, MatchLog};
+use crate::server::sv_metrics::Metrics;
+use crate::server::sv_rotation::{Intermission, MapRotation};
+use crate::server::sv_save::{Autosave, SaveSlots, ServerSave};
+use crate::server::sv_scoreboard::{ScoreRow, ScoreboardSync};
+use crate::server::sv_security::{auth_provider, AuthProvider, Credentials};
+use crate::server::sv_stats::{
+    JsonFileStore, MemoryStore, PlayerId, PlayerStatsTracker, StatsStore,
+};
+use crate::server::sv_teams::Teams;
+use crate::server::sv_timers::Timers;
+use crate::server::sv_vote::{VoteAction, VoteKind, VoteResult, Votes};
 
-use super::key_pair::KeyPairError;
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+struct ClientAddr(SocketAddr);
 
-#[derive(Debug, Eq, PartialEq, Hash)]
-struct ClientId(SocketAddr);
+///
+/// Session waiting for the new address to be confirmed
+///
+#[derive(Debug)]
+struct Migration {
+    token: SessionId,
+    nonce: u64,
+    started_at: Instant,
+}
+
+///
+/// Events fired by server timers
+///
+#[derive(Debug, Clone, PartialEq)]
+enum ServerEvent {
+    DropStaleClients,
+    SaveStats,
+    BroadcastModeStatus,
+    PingClients,
+    UpdateScoreboard,
+    Autosave,
+}
 
 pub(crate) struct Server {
     endpoint: Box<dyn ServerEndpoint + Send + Sync>,
     recv_buf: Option<Vec<u8>>,
-    clients: HashMap<ClientId, Client>,
+    frame_timer: Option<FrameTimer>,
+    clients: HashMap<ClientAddr, Client>,
+    migrations: HashMap<ClientAddr, Migration>,
+    next_client_id: ClientId,
+    admin: Arc<Mutex<Vec<AdminRequest>>>,
     keys: KeyPair,
-    password: Option<String>,
-    exit_flag: AtomicBool,
+    auth: Box<dyn AuthProvider>,
+    max_rate: u32,
+    congestion: CongestionConfig,
+    votes: Votes<ClientAddr>,
+    metrics: Metrics,
+    timers: Timers<ServerEvent>,
+    game_clock: GameClock,
+    stats: Arc<Mutex<PlayerStatsTracker>>,
+    mode: Box<dyn GameMode>,
+    scoreboard: ScoreboardSync,
+    teams: Teams,
+    autosave: Autosave,
+    match_log: MatchLog,
+    rotation: MapRotation,
+    _commands: CommandOwner,
 }
 
 impl Server {
+    pub(crate) const TICK: Duration = Duration::from_millis(10);
+    const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+    const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(5);
+    const CAP_STATS: &'static str = "sv_player_stats";
+
     pub(crate) fn update(&mut self) -> Result<(), AppError> {
+        let tick_start = Instant::now();
         let mut buf = self.recv_buf.take().unwrap_or_else(|| Vec::new());
+        let mut timer = self.frame_timer.take().unwrap_or_default();
+        timer.begin_frame();
 
+        // Clients share server socket, so everything is received by `listen` and dispatched by address
         for (_, c) in self.clients.iter_mut() {
-            c.update(&mut buf)?;
+            c.clear_buffers();
         }
 
-        self.listen(&mut buf)?;
+        self.game_clock.update(tick_start);
+        {
+            let _scope = timer.scope("listen");
+            self.listen(&mut buf)?;
+            self.metrics.add_net_stats(self.endpoint.take_stats());
+        }
 
-        for (id, c) in self.clients.iter_mut() {
-            if let Err(e) = c.flush() {
-                warn!("Flush failed for {id:?}: {e:?}");
+        {
+            let _scope = timer.scope("events");
+            for event in self.timers.advance() {
+                self.on_event(event);
+            }
+        }
+
+        {
+            let _scope = timer.scope("game");
+            self.update_votes();
+            self.update_admin();
+            self.update_teams();
+            self.route_chat();
+            self.update_mode();
+        }
+
+        {
+            let _scope = timer.scope("flush");
+            for (id, c) in self.clients.iter_mut() {
+                match c.flush() {
+                    Ok(n) => self.metrics.add_sent(n),
+                    Err(e) => warn!("Flush failed for {id:?}: {e:?}"),
+                }
+                self.metrics.add_net_stats(c.take_stats());
             }
         }
 
         self.recv_buf.replace(buf);
+        self.metrics
+            .record_tick(tick_start.elapsed(), &timer.report());
+        self.frame_timer.replace(timer);
+        self.metrics.update(self.clients.len());
         Ok(())
     }
 
-    pub(crate) fn is_exit(&self) -> bool {
-        self.exit_flag.load(Ordering::Relaxed)
+    pub(crate) fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    ///
+    /// Latest inputs of clients which sent any
+    ///
+    #[cfg(test)]
+    pub(crate) fn inputs(&self) -> impl Iterator<Item = &crate::net::PlayerInput> {
+        self.clients.values().filter_map(Client::input)
+    }
+
+    fn on_event(&mut self, event: ServerEvent) {
+        match event {
+            ServerEvent::DropStaleClients => {
+                let stale: Vec<_> = self
+                    .clients
+                    .iter()
+                    .filter(|(_, c)| c.last_seen().elapsed() > Self::RECONNECT_TIMEOUT)
+                    .map(|(id, _)| *id)
+                    .collect();
+                for id in stale {
+                    if let Some(c) = self.remove_client(&id, "timed out") {
+                        info!("Dropping {} ({id:?}): timed out", c.name());
+                    }
+                }
+            }
+            ServerEvent::SaveStats => {
+                if let Err(e) = self.stats.lock().unwrap().save() {
+                    warn!("Unable to save player stats: {e:?}");
+                }
+            }
+            ServerEvent::BroadcastModeStatus => {
+                let state = self.mode.hud_state();
+                let msg = Message::ModeStatus {
+                    mode: self.mode.name(),
+                    state: Bytes(&state),
+                };
+                Self::broadcast_low_priority(&mut self.clients, &msg);
+            }
+            ServerEvent::PingClients => {
+                let msg = Message::Ping {
+                    time: self.clock().time,
+                };
+                for c in self.clients.values_mut() {
+                    c.adapt_rate();
+                    c.on_ping_sent();
+                }
+                Self::broadcast(&mut self.clients, &msg);
+            }
+            ServerEvent::UpdateScoreboard => self.update_scoreboard(),
+            ServerEvent::Autosave => {
+                if self.autosave.is_due(Instant::now()) {
+                    self.save_game();
+                }
+            }
+        }
+    }
+
+    ///
+    /// Executes console requests
+    ///
+    fn update_admin(&mut self) {
+        let requests = std::mem::take(&mut *self.admin.lock().unwrap());
+        for request in requests {
+            match request {
+                AdminRequest::Status => {
+                    let mut rows: Vec<_> = self
+                        .clients
+                        .iter()
+                        .map(|(key, c)| ClientStatus {
+                            id: c.id(),
+                            name: c.name().to_string(),
+                            addr: key.0,
+                            ping: c.ping(),
+                            loss: c.loss(),
+                            quality: c.quality(),
+                            idle: c.last_seen().elapsed(),
+                        })
+                        .collect();
+                    rows.sort_by_key(|r| r.id);
+                    info!("{}", ClientStatus::HEADER);
+                    for row in rows {
+                        info!("{row}");
+                    }
+                    info!("{} client(s)", self.clients.len());
+                }
+                AdminRequest::Kick { id, reason } => {
+                    let Some(key) = self
+                        .clients
+                        .iter()
+                        .find(|(_, c)| c.id() == id)
+                        .map(|(key, _)| *key)
+                    else {
+                        warn!("No client with id {id}");
+                        continue;
+                    };
+                    let mut client = self.remove_client(&key, &reason).unwrap();
+                    info!("Kicked {} ({key:?}): {reason}", client.name());
+                    // Client is gone, so send right away
+                    let sent = client
+                        .send(&Message::Disconnect { reason: &reason })
+                        .and_then(|_| client.flush());
+                    match sent {
+                        Ok(n) => self.metrics.add_sent(n),
+                        Err(e) => warn!("Unable to notify kicked client: {e:?}"),
+                    }
+                }
+                AdminRequest::Slay { id } => {
+                    let Some(player) = self
+                        .clients
+                        .values()
+                        .find(|c| c.id() == id)
+                        .map(|c| c.player_id().clone())
+                    else {
+                        warn!("No client with id {id}");
+                        continue;
+                    };
+                    self.on_kill(&player, &player);
+                }
+                AdminRequest::Save => self.save_game(),
+                AdminRequest::Restore { slot } => self.restore_game(slot),
+                AdminRequest::NextMap { map } => self.next_map(map.as_deref()),
+                AdminRequest::Rotation => info!("Rotation: {}", self.rotation),
+            }
+        }
+    }
+
+    ///
+    /// Saves server state to the newest slot, player stats are saved too
+    ///
+    fn save_game(&mut self) {
+        let save = ServerSave::new(
+            self.mode.name(),
+            self.game_clock.state(),
+            self.mode.save_state(),
+        );
+        match self.autosave.save(&save) {
+            Ok(path) => info!("Saved server state to {path:?}"),
+            Err(e) => warn!("Unable to save server state: {e:?}"),
+        }
+        if let Err(e) = self.stats.lock().unwrap().save() {
+            warn!("Unable to save player stats: {e:?}");
+        }
+    }
+
+    ///
+    /// Restores game time and match state. Match state is skipped if save was made in another game mode.
+    ///
+    fn restore_game(&mut self, slot: u32) {
+        let path = self.autosave.slots().path(slot);
+        let save = match self.autosave.slots().read(slot) {
+            Ok(save) => save,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                info!("No save at {path:?}");
+                return;
+            }
+            Err(e) => {
+                warn!("Unable to load {path:?}: {e}");
+                return;
+            }
+        };
+        self.game_clock.restore(&save.clock);
+        if save.mode != self.mode.name() {
+            info!(
+                "Save was made in {} mode, match state is not restored",
+                save.mode
+            );
+        } else if let Some(state) = save.mode_state {
+            if !self.mode.restore_state(state) {
+                warn!("Invalid {} match state in {path:?}", save.mode);
+            }
+            for c in self.clients.values() {
+                self.mode.on_player_join(c.player_id());
+            }
+        }
+        info!("Restored server state from {path:?}");
     }
 
-    pub(crate) fn shutdown(&mut self) {
-        self.exit_flag.store(true, Ordering::Release);
+    fn update_scoreboard(&mut self) {
+        let rows = self
+            .clients
+            .values()
+            .map(|c| ScoreRow {
+                name: c.name().to_string(),
+                score: self.mode.score(c.player_id()).unwrap_or_default(),
+                ping: c
+                    .ping()
+                    .map_or(0, |v| (v * 1000.0).round().min(u16::MAX as f64) as u16),
+                team: self.teams.team(c.player_id()),
+            })
+            .collect();
+        let Some(rows) = self.scoreboard.update(rows, Instant::now()) else {
+            return;
+        };
+        let msg = Message::Scoreboard {
+            entries: rows.iter().map(ScoreRow::entry).collect(),
+        };
+        Self::broadcast_low_priority(&mut self.clients, &msg);
+    }
+
+    ///
+    /// Single place where kills are accounted, suicide has the same killer and victim
+    ///
+    fn on_kill(&mut self, killer: &PlayerId, victim: &PlayerId) {
+        if self.rotation.is_intermission() {
+            return;
+        }
+        self.mode.on_kill(killer, victim);
+        self.stats.lock().unwrap().record_kill(killer, victim);
+    }
+
+    fn update_mode(&mut self) {
+        if self.rotation.is_intermission() {
+            match self.rotation.on_tick(self.game_clock.delta()) {
+                Some(Intermission::Countdown(secs)) => self.broadcast_intermission(secs),
+                Some(Intermission::Over) => self.start_next_match(),
+                None => {}
+            }
+            return;
+        }
+        self.mode.on_tick(self.game_clock.delta());
+        let Some(outcome) = self.mode.outcome() else {
+            return;
+        };
+        let winner = match outcome {
+            MatchOutcome::Winner(id) => self
+                .clients
+                .values()
+                .find(|c| *c.player_id() == id)
+                .map_or_else(|| id.to_string(), |c| c.name().to_string()),
+            MatchOutcome::Draw => String::new(),
+        };
+        self.end_match(&winner);
+    }
+
+    ///
+    /// Announces the result and starts intermission, empty winner means draw
+    ///
+    fn end_match(&mut self, winner: &str) {
+        if winner.is_empty() {
+            info!("Match is over: draw");
+        } else {
+            info!("Match is over: {winner} wins");
+        }
+        Self::broadcast(
+            &mut self.clients,
+            &Message::MatchEnded {
+                mode: self.mode.name(),
+                winner,
+            },
+        );
+        self.match_log.record(
+            self.clock(),
+            &MatchEvent::MatchEnd {
+                mode: self.mode.name(),
+                winner,
+            },
+        );
+        let secs = self.rotation.start_intermission();
+        self.broadcast_intermission(secs);
+    }
+
+    fn broadcast_intermission(&mut self, secs: u32) {
+        let msg = Message::Intermission {
+            next_map: self.rotation.next_map().unwrap_or_default(),
+            time_left: secs,
+        };
+        Self::broadcast(&mut self.clients, &msg);
+    }
+
+    ///
+    /// Switches to the next map and restarts the match. There are no levels to load yet,
+    /// so map change only renames the match and notifies clients.
+    ///
+    fn start_next_match(&mut self) {
+        if let Some(map) = self.rotation.advance() {
+            info!("Map: {map}");
+        }
+        let msg = Message::MapChanged {
+            map: self.rotation.map().unwrap_or_default(),
+        };
+        Self::broadcast(&mut self.clients, &msg);
+        self.match_log.rotate();
+        self.record_match_start();
+        let players: Vec<_> = self
+            .clients
+            .values()
+            .map(|c| c.player_id().clone())
+            .collect();
+        self.mode.init(&players);
     }
 
-    pub fn new(app: &Arc<App>) -> Self {
+    fn record_match_start(&mut self) {
+        self.match_log.record(
+            self.clock(),
+            &MatchEvent::MatchStart {
+                mode: self.mode.name(),
+                map: self.rotation.map(),
+            },
+        );
+    }
+
+    ///
+    /// Ends the match early, next map is the given one or the next in rotation
+    ///
+    fn next_map(&mut self, map: Option<&str>) {
+        if let Some(map) = map {
+            if !self.rotation.set_next(map) {
+                warn!("Map {map} is not in rotation: {}", self.rotation);
+                return;
+            }
+        }
+        if self.rotation.is_intermission() {
+            let secs = self.rotation.start_intermission();
+            self.broadcast_intermission(secs);
+        } else {
+            self.end_match("");
+        }
+    }
+
+    ///
+    /// Removes client which has left the game (unlike session moved to the new address)
+    ///
+    fn remove_client(&mut self, id: &ClientAddr, reason: &str) -> Option<Client> {
+        let client = self.clients.remove(id)?;
+        self.match_log.record(
+            self.clock(),
+            &MatchEvent::Leave {
+                player: client.player_id(),
+                name: client.name(),
+                reason,
+            },
+        );
+        self.votes.remove_voter(id);
+        self.stats.lock().unwrap().leave(client.player_id());
+        self.mode.on_player_leave(client.player_id());
+        self.teams.remove(client.player_id());
+        for (player_id, team) in self.teams.rebalance() {
+            if let Some(c) = self
+                .clients
+                .values_mut()
+                .find(|c| *c.player_id() == player_id)
+            {
+                info!("{} moved to team {team} to balance teams", c.name());
+                if let Err(e) = c.send(&Message::TeamAssigned { team }) {
+                    warn!("Send failed for {}: {e:?}", c.name());
+                }
+            }
+        }
+        Some(client)
+    }
+
+    fn update_teams(&mut self) {
+        for c in self.clients.values_mut() {
+            let Some(team) = c.take_team_request() else {
+                continue;
+            };
+            match self.teams.switch(c.player_id(), team) {
+                Ok(_) => info!("{} joined team {team}", c.name()),
+                Err(e) => warn!("{} can't join team {team}: {e:?}", c.name()),
+            }
+            // Confirm current team either way
+            let team = self.teams.team(c.player_id());
+            if let Err(e) = c.send(&Message::TeamAssigned { team }) {
+                warn!("Send failed for {}: {e:?}", c.name());
+            }
+        }
+    }
+
+    ///
+    /// Delivers chat lines to everyone or to the teammates of the sender only
+    ///
+    fn route_chat(&mut self) {
+        let mut lines = Vec::new();
+        for c in self.clients.values_mut() {
+            for (text, team_only) in c.take_chat() {
+                info!(
+                    "{}{}: {text}",
+                    if team_only { "(team) " } else { "" },
+                    c.name()
+                );
+                lines.push((c.player_id().clone(), c.name().to_string(), text, team_only));
+            }
+        }
+        let clock = self.clock();
+        for (sender, name, text, team_only) in lines.iter() {
+            self.match_log.record(
+                clock,
+                &MatchEvent::Chat {
+                    player: sender,
+                    name,
+                    text,
+                    team_only: *team_only,
+                },
+            );
+            let msg = Message::Chat {
+                from: name,
+                text,
+                team_only: *team_only,
+            };
+            for c in self.clients.values_mut() {
+                let to = c.player_id();
+                if *team_only && to != sender && !self.teams.same_team(sender, to) {
+                    continue;
+                }
+                if let Err(e) = c.send(&msg) {
+                    warn!("Send failed for {}: {e:?}", c.name());
+                }
+            }
+        }
+    }
+
+    fn broadcast(clients: &mut HashMap<ClientAddr, Client>, msg: &Message) {
+        for (id, c) in clients.iter_mut() {
+            if let Err(e) = c.send(msg) {
+                warn!("Send failed for {id:?}: {e:?}");
+            }
+        }
+    }
+
+    ///
+    /// Same as [`Server::broadcast`] but message may be delayed or dropped for clients over their bandwidth budget
+    ///
+    fn broadcast_low_priority(clients: &mut HashMap<ClientAddr, Client>, msg: &Message) {
+        for (id, c) in clients.iter_mut() {
+            if let Err(e) = c.send_low_priority(msg) {
+                warn!("Send failed for {id:?}: {e:?}");
+            }
+        }
+    }
+
+    fn update_votes(&mut self) {
+        let now = Instant::now();
+        let mut changed = false;
+        let actions: Vec<_> = self
+            .clients
+            .iter_mut()
+            .flat_map(|(id, c)| c.take_vote_actions().into_iter().map(|a| (*id, a)))
+            .collect();
+        for (id, action) in actions {
+            let name = self.clients[&id].name();
+            let result = match action {
+                VoteAction::Call { kind, arg } => {
+                    let find_client = |target: &str| {
+                        self.clients
+                            .values()
+                            .find(|c| c.name() == target)
+                            .map(Client::id)
+                    };
+                    let Some(kind) = VoteKind::parse(&kind, &arg, find_client) else {
+                        warn!("Ignoring invalid vote \"{kind} {arg}\" from {name}");
+                        continue;
+                    };
+                    info!("{name} called vote: {kind}");
+                    self.votes.start(id, kind, now)
+                }
+                VoteAction::Cast(yes) => self.votes.cast(id, yes),
+            };
+            match result {
+                Ok(_) => changed = true,
+                Err(e) => warn!("Vote request from {name} rejected: {e:?}"),
+            }
+        }
+        if changed {
+            if let Some(p) = self.votes.progress(now) {
+                let msg = Message::VoteStatus {
+                    kind: p.kind.name(),
+                    arg: p.kind.arg(),
+                    yes: p.yes,
+                    no: p.no,
+                    time_left: p.time_left.as_secs_f32(),
+                };
+                Self::broadcast_low_priority(&mut self.clients, &msg);
+            }
+        }
+        let (kind, passed) = match self.votes.update(self.clients.len(), now) {
+            Some(VoteResult::Passed(kind)) => (kind, true),
+            Some(VoteResult::Failed(kind)) => (kind, false),
+            None => return,
+        };
+        info!("Vote {kind} {}", if passed { "passed" } else { "failed" });
+        Self::broadcast(
+            &mut self.clients,
+            &Message::VoteEnded {
+                kind: kind.name(),
+                arg: kind.arg(),
+                passed,
+            },
+        );
+        if passed {
+            self.execute_vote(kind);
+        }
+    }
+
+    ///
+    /// Queues passed vote as console request, so it's executed the same way as admin command
+    ///
+    fn execute_vote(&mut self, kind: VoteKind) {
+        let request = match kind {
+            VoteKind::Kick { id, .. } => AdminRequest::Kick {
+                id,
+                reason: "Kicked by vote".to_string(),
+            },
+            VoteKind::Map(map) => AdminRequest::NextMap { map: Some(map) },
+        };
+        self.admin.lock().unwrap().push(request);
+    }
+
+    pub fn new(app: &Arc<App>) -> Result<Self, AppError> {
+        let addr: SocketAddr = app
+            .config()
+            .lock()
+            .unwrap()
+            .server
+            .address
+            .parse()
+            .expect("Invalid address!");
+        let endpoint = NetEndpoint::with_address(addr).expect("Unable to create server endpoint!");
+        Self::with_endpoint(app, endpoint)
+    }
+
+    ///
+    /// Creates server listening on the given endpoint instead of configured address
+    ///
+    pub(crate) fn with_endpoint(
+        app: &Arc<App>,
+        mut endpoint: NetEndpoint,
+    ) -> Result<Self, AppError> {
         info!("Starting server...");
         let mut cfg_guard = app.config().lock().unwrap();
         let cfg = &mut cfg_guard.server;
-        let addr: SocketAddr = cfg.address.parse().expect("Invalid address!");
-        let endpoint = NetEndpoint::with_address(addr).expect("Unable to create server endpoint!");
+        endpoint.set_checksum(cfg.checksum);
         let keys = KeyPair::new(cfg.key_bits).expect("Unable to generate server key!");
-        let password = cfg.password.to_owned();
+        let auth = auth_provider(&cfg.auth, cfg.password.to_owned())?;
+        let max_rate = cfg.max_rate;
+        let congestion = cfg.congestion.clone();
         let server_address = endpoint
             .local_addr()
             .expect("Unable to get server address!");
         info!("Server bound to {:?}", server_address);
         cfg.bound_to = Some(server_address.to_string());
-        Server {
+        let votes = Votes::new(&cfg.vote);
+        let mut timers = Timers::new(Self::TICK);
+        timers.schedule_every(Duration::from_secs(1), ServerEvent::DropStaleClients);
+        let metrics = Metrics::new(Arc::clone(app.config()), app.profile_dir());
+        let stats = Arc::new(Mutex::new(PlayerStatsTracker::new(Self::stats_store(
+            app, &cfg.stats,
+        ))));
+        timers.schedule_every(
+            Duration::from_secs_f64(cfg.stats.save_interval.max(1.0)),
+            ServerEvent::SaveStats,
+        );
+        timers.schedule_every(Duration::from_secs(1), ServerEvent::BroadcastModeStatus);
+        timers.schedule_every(Duration::from_secs(1), ServerEvent::PingClients);
+        timers.schedule_every(Duration::from_millis(500), ServerEvent::UpdateScoreboard);
+        timers.schedule_every(Duration::from_secs(1), ServerEvent::Autosave);
+        let autosave = Autosave::new(
+            Arc::clone(app.config()),
+            SaveSlots::new(app.user_path(&cfg.autosave.path), cfg.autosave.keep),
+        );
+        let restore = cfg.autosave.restore;
+        let match_log = MatchLog::new(
+            Arc::clone(app.config()),
+            app.user_path(&cfg.match_log.path),
+            cfg.match_log.keep,
+        );
+        let mut mode = GameModes::new().select(cfg);
+        mode.init(&[]);
+        info!("Game mode: {}", mode.name());
+        let rotation = MapRotation::new(&cfg.rotation.maps, cfg.rotation.intermission);
+        if let Some(map) = rotation.map() {
+            info!("Map: {map}");
+        }
+        let teams = Teams::new(cfg.teams.count, cfg.teams.balance);
+        let admin = Arc::new(Mutex::new(Vec::new()));
+        let commands = Self::register_commands(app, &stats, &admin);
+        // Match log reads config on its own
+        drop(cfg_guard);
+        let mut server = Server {
             endpoint: Box::new(endpoint),
             recv_buf: Some(Vec::with_capacity(MAX_DATAGRAM_SIZE)),
+            frame_timer: Some(FrameTimer::new()),
             clients: HashMap::new(),
+            migrations: HashMap::new(),
+            next_client_id: ClientId::new(1),
+            admin,
             keys,
-            password,
-            exit_flag: AtomicBool::new(false),
+            auth,
+            max_rate,
+            congestion,
+            votes,
+            metrics,
+            timers,
+            game_clock: GameClock::new(Self::TICK),
+            stats,
+            mode,
+            scoreboard: ScoreboardSync::default(),
+            teams,
+            autosave,
+            match_log,
+            rotation,
+            _commands: commands,
+        };
+        if restore {
+            server.restore_game(0);
         }
+        server.record_match_start();
+        Ok(server)
     }
 
-    fn check_password(&self, encoded: &[u8]) -> bool {
-        if let Some(password) = &self.password {
-            return self
-                .keys
-                .decode(encoded)
-                //.map_err(|e| anyhow::Error::from(e))
-                .and_then(|v| {
-                    from_utf8(&v)
-                        .map(|p| password.eq(p))
-                        .map_err(|e| KeyPairError::default())
-                })
-                .unwrap_or(false);
+    fn stats_store(app: &App, cfg: &StatsConfig) -> Box<dyn StatsStore> {
+        if !cfg.enabled {
+            app.caps().set(Self::CAP_STATS, false, "disabled");
+            return Box::new(MemoryStore::default());
+        }
+        let path = app.user_path(&cfg.path);
+        match JsonFileStore::open(path.clone()) {
+            Ok(store) => {
+                app.caps()
+                    .set(Self::CAP_STATS, true, path.display().to_string());
+                Box::new(store)
+            }
+            Err(e) => {
+                warn!("Unable to open player stats {path:?}: {e:?}, stats won't be saved!");
+                app.caps().set(Self::CAP_STATS, false, e.to_string());
+                Box::new(MemoryStore::default())
+            }
         }
-        true
+    }
+
+    fn register_commands(
+        app: &App,
+        stats: &Arc<Mutex<PlayerStatsTracker>>,
+        admin: &Arc<Mutex<Vec<AdminRequest>>>,
+    ) -> CommandOwner {
+        let mut builder = CommandBuilder::new(app.commands());
+        let a = Arc::clone(admin);
+        builder.add("status", move |_| {
+            a.lock()?.push(AdminRequest::Status);
+            Ok(())
+        });
+        let a = Arc::clone(admin);
+        builder.add("kick", move |args| {
+            let request = parse_kick(args).ok_or(CmdError::ArgNumberMismatch(1))?;
+            a.lock()?.push(request);
+            Ok(())
+        });
+        let a = Arc::clone(admin);
+        builder.add1("slay", move |id: u32| {
+            a.lock()?.push(AdminRequest::Slay {
+                id: ClientId::new(id),
+            });
+            Ok(())
+        });
+        let a = Arc::clone(admin);
+        builder.add("host_save", move |_| {
+            a.lock()?.push(AdminRequest::Save);
+            Ok(())
+        });
+        let a = Arc::clone(admin);
+        builder.add("host_restore", move |args| {
+            let slot = match args {
+                [] => 0,
+                [slot] => slot
+                    .parse()
+                    .map_err(|_| CmdError::ParseError(slot.to_string()))?,
+                _ => return Err(CmdError::ArgNumberMismatch(1)),
+            };
+            a.lock()?.push(AdminRequest::Restore { slot });
+            Ok(())
+        });
+        let a = Arc::clone(admin);
+        builder.add("nextmap", move |args| {
+            let map = match args {
+                [] => None,
+                [map] => Some(map.to_owned()),
+                _ => return Err(CmdError::ArgNumberMismatch(1)),
+            };
+            a.lock()?.push(AdminRequest::NextMap { map });
+            Ok(())
+        });
+        let a = Arc::clone(admin);
+        builder.add("rotation", move |_| {
+            a.lock()?.push(AdminRequest::Rotation);
+            Ok(())
+        });
+        let s = Arc::clone(stats);
+        builder.add1("stats", move |name: String| {
+            let id = PlayerId::from_name(&name);
+            match s.lock()?.get(&id) {
+                Some(v) => info!(
+                    "{id}: kills {}, deaths {}, sessions {}, playtime {:.0} min.",
+                    v.kills,
+                    v.deaths,
+                    v.sessions,
+                    v.playtime / 60.0
+                ),
+                None => info!("No stats for {id}"),
+            }
+            Ok(())
+        });
+        let s = Arc::clone(stats);
+        builder.add("stats_top", move |_| {
+            for (i, (id, v)) in s.lock()?.top(10).iter().enumerate() {
+                info!("{}. {id}: {} kills, {} deaths", i + 1, v.kills, v.deaths);
+            }
+            Ok(())
+        });
+        builder.build()
     }
 
     fn on_connect(
         &mut self,
-        key: ClientId,
-        name: &str,
-        password: &[u8],
+        key: ClientAddr,
+        credentials: Credentials,
+        rate: u32,
         addr: &SocketAddr,
     ) -> Result<(), AppError> {
-        if !self.check_password(password) {
-            info!("Wrong password from {:?}!", addr);
+        let identity = match self.auth.authenticate(&self.keys, &credentials) {
+            Ok(identity) => identity,
+            Err(e) => {
+                info!("Rejected {:?} from {:?}: {e}", credentials.name, addr);
+                return Ok(());
+            }
+        };
+        if !self.clients.contains_key(&key)
+            && self
+                .clients
+                .values()
+                .any(|c| *c.player_id() == identity.player_id)
+        {
+            // Runtime state (teams, scores, stats) is keyed by player id, so it can't be shared
+            info!(
+                "Rejected {:?} from {:?}: already connected",
+                identity.name, addr
+            );
+            let reason = format!("{} is already connected", identity.name);
+            let sent = self
+                .endpoint
+                .send_to(&Message::Disconnect { reason: &reason }, addr)?;
+            self.metrics.add_sent(sent);
             return Ok(());
         }
+        let clock = self.clock();
         match self.clients.entry(key) {
             Entry::Vacant(v) => {
                 let endpoint = self.endpoint.try_clone_and_connect(addr)?;
-                let client = v.insert(Client::new(name, endpoint));
-                client.send(&Message::Accepted).map(|_| ())?;
+                let congestion =
+                    CongestionControl::new(&self.congestion, effective_rate(rate, self.max_rate));
+                self.stats.lock().unwrap().join(identity.player_id.clone());
+                self.mode.on_player_join(&identity.player_id);
+                let team = self.teams.assign(&identity.player_id);
+                let id = self.next_client_id.take_next();
+                self.match_log.record(
+                    clock,
+                    &MatchEvent::Join {
+                        player: &identity.player_id,
+                        name: &identity.name,
+                    },
+                );
+                let client = v.insert(Client::new(id, identity, endpoint, congestion));
+                client.send(&Message::Accepted)?;
+                if self.teams.is_enabled() {
+                    client.send(&Message::TeamAssigned { team })?;
+                }
+                if let Some(map) = self.rotation.map() {
+                    client.send(&Message::MapChanged { map })?;
+                }
+                client
+                    .send(&Message::Session {
+                        token: client.token(),
+                    })
+                    .map(|_| ())?;
                 Ok(())
             }
             Entry::Occupied(ref mut o) => {
@@ -119,21 +928,154 @@ impl Server {
         }
     }
 
-    fn pass_to_client(&mut self, key: ClientId, msg: &Message) -> Result<(), AppError> {
+    fn find_session(&self, token: SessionId) -> Option<ClientAddr> {
+        self.clients
+            .iter()
+            .find(|(_, c)| c.token() == token)
+            .map(|(id, _)| *id)
+    }
+
+    ///
+    /// Resumes session of the client. If client's address has changed (roaming between networks), new address
+    /// is challenged first, so session can't be redirected to address which doesn't belong to the client.
+    ///
+    fn on_reconnect(
+        &mut self,
+        key: ClientAddr,
+        token: SessionId,
+        addr: &SocketAddr,
+    ) -> Result<(), AppError> {
+        let Some(old) = self.find_session(token) else {
+            info!("Unknown session token from {addr:?}");
+            return Ok(());
+        };
+        if old == key {
+            return self.pass_to_client(key, &Message::Reconnect { token });
+        }
+        if self.clients.contains_key(&key) {
+            warn!("Address {addr:?} is already used by another session!");
+            return Ok(());
+        }
+        self.migrations
+            .retain(|_, m| m.started_at.elapsed() < Self::CHALLENGE_TIMEOUT);
+        let nonce = match self.migrations.get(&key) {
+            Some(m) if m.token == token => m.nonce,
+            _ => {
+                let nonce = rand::random();
+                self.migrations.insert(
+                    key,
+                    Migration {
+                        token,
+                        nonce,
+                        started_at: Instant::now(),
+                    },
+                );
+                nonce
+            }
+        };
+        let sent = self.endpoint.send_to(&Message::Challenge { nonce }, addr)?;
+        self.metrics.add_sent(sent);
+        Ok(())
+    }
+
+    ///
+    /// Moves existing session to the new address of the client once address is confirmed
+    ///
+    fn on_challenge_response(
+        &mut self,
+        key: ClientAddr,
+        token: SessionId,
+        nonce: u64,
+        addr: &SocketAddr,
+    ) -> Result<(), AppError> {
+        let confirmed = self.migrations.get(&key).is_some_and(|m| {
+            m.token == token && m.nonce == nonce && m.started_at.elapsed() < Self::CHALLENGE_TIMEOUT
+        });
+        if !confirmed {
+            info!("Ignoring unexpected challenge response from {addr:?}");
+            return Ok(());
+        }
+        self.migrations.remove(&key);
+        let Some(old) = self.find_session(token) else {
+            return Ok(());
+        };
+        if self.clients.contains_key(&key) {
+            warn!("Address {addr:?} is already used by another session!");
+            return Ok(());
+        }
+        if self.clients[&old].last_seen().elapsed() > Self::RECONNECT_TIMEOUT {
+            let client = self.remove_client(&old, "session expired").unwrap();
+            info!("Session of {} has expired", client.name());
+            return Ok(());
+        }
+        let mut client = self.clients.remove(&old).unwrap();
+        self.votes.remove_voter(&old);
+        info!("Session of {} moved from {old:?} to {key:?}", client.name());
+        client.set_endpoint(self.endpoint.try_clone_and_connect(addr)?);
+        client.touch();
+        client.send(&Message::Accepted)?;
+        self.clients.insert(key, client);
+        Ok(())
+    }
+
+    ///
+    /// Current server tick and time, shared time base for clients
+    ///
+    pub(crate) fn clock(&self) -> ServerClock {
+        ServerClock {
+            tick: Tick::new(self.timers.tick()),
+            time: self.game_clock.wall_time().as_secs_f64(),
+        }
+    }
+
+    fn pass_to_client(&mut self, key: ClientAddr, msg: &Message) -> Result<(), AppError> {
+        let clock = self.clock();
         if let Entry::Occupied(ref mut o) = self.clients.entry(key) {
-            o.get_mut().process_message(msg)
+            o.get_mut().process_message(msg, clock)
         } else {
             Ok(())
         }
     }
 
     fn process_message(&mut self, msg: &Message, addr: &SocketAddr) -> Result<(), AppError> {
-        let key = ClientId(*addr);
+        let key = ClientAddr(*addr);
         match msg {
-            Message::Connect { name, password } => self.on_connect(key, name, password, addr),
+            Message::Connect { version, .. } if *version != PROTOCOL_VERSION => {
+                info!(
+                    "Rejected {:?}: protocol version {version}, expected {PROTOCOL_VERSION}",
+                    addr
+                );
+                let reason = format!("Protocol version mismatch, server speaks {PROTOCOL_VERSION}");
+                let sent = self
+                    .endpoint
+                    .send_to(&Message::Disconnect { reason: &reason }, addr)?;
+                self.metrics.add_sent(sent);
+                Ok(())
+            }
+            Message::Connect {
+                name,
+                password,
+                rate,
+                ticket,
+                ..
+            } => {
+                let credentials = Credentials {
+                    name,
+                    password,
+                    ticket,
+                };
+                self.on_connect(key, credentials, *rate, addr)
+            }
+            Message::Reconnect { token } => self.on_reconnect(key, *token, addr),
+            Message::ChallengeResponse { token, nonce } => {
+                self.on_challenge_response(key, *token, *nonce, addr)
+            }
             Message::Hello => {
                 let key = bitcode::serialize(self.keys.public_key()).unwrap();
-                self.endpoint.send_to(&Message::ServerInfo { key }, addr)?;
+                let sent = self
+                    .endpoint
+                    .send_to(&Message::ServerInfo { key: Bytes(&key) }, addr)?;
+                self.metrics.add_sent(sent);
                 Ok(())
             }
             other => self.pass_to_client(key, other),
@@ -145,8 +1087,11 @@ impl Server {
             match self.endpoint.receive_data(buf.as_mut()) {
                 Ok(Some(mut data)) => {
                     let addr = data.addr;
+                    self.metrics.add_received(data.len());
                     while let Some(ref m) = data.read() {
-                        self.process_message(m, &addr).unwrap();
+                        if let Err(e) = self.process_message(m, &addr) {
+                            warn!("Failed to process message from {addr:?}: {e:?}");
+                        }
                     }
                 }
                 Ok(None) => {
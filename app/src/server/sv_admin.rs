@@ -0,0 +1,143 @@
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rg_common::ClientId;
+
+///
+/// Console request executed by server on its next update
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum AdminRequest {
+    Status,
+    Kick {
+        id: ClientId,
+        reason: String,
+    },
+    /// Kills the player, counted as suicide
+    Slay {
+        id: ClientId,
+    },
+    Save,
+    Restore {
+        slot: u32,
+    },
+    /// Ends the match and moves to the given or the next map in rotation
+    NextMap {
+        map: Option<String>,
+    },
+    Rotation,
+}
+
+///
+/// Row of the `status` command output
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ClientStatus {
+    pub id: ClientId,
+    pub name: String,
+    pub addr: SocketAddr,
+    /// Round trip in seconds
+    pub ping: Option<f64>,
+    /// Fraction of server pings left without reply
+    pub loss: f32,
+    /// Link quality band 0..4
+    pub quality: Option<u8>,
+    pub idle: Duration,
+}
+
+impl ClientStatus {
+    pub(crate) const HEADER: &'static str =
+        " id name             address                ping   loss q   idle";
+}
+
+impl Display for ClientStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ping = self
+            .ping
+            .map_or_else(|| "-".to_string(), |v| format!("{:.0}", 1000.0 * v));
+        let quality = self
+            .quality
+            .map_or_else(|| "-".to_string(), |v| v.to_string());
+        write!(
+            f,
+            "{:>3} {:<16} {:<22} {:>4} {:>5.1}% {:>1} {:>5.1}s",
+            self.id,
+            self.name,
+            self.addr.to_string(),
+            ping,
+            100.0 * self.loss,
+            quality,
+            self.idle.as_secs_f32()
+        )
+    }
+}
+
+///
+/// Parses `kick <id> [reason]` arguments
+///
+pub(crate) fn parse_kick(args: &[String]) -> Option<AdminRequest> {
+    let (id, reason) = args.split_first()?;
+    Some(AdminRequest::Kick {
+        id: ClientId::new(id.parse().ok()?),
+        reason: if reason.is_empty() {
+            "Kicked by admin".to_string()
+        } else {
+            reason.join(" ")
+        },
+    })
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rg_common::ClientId;
+
+    use super::{parse_kick, AdminRequest, ClientStatus};
+
+    fn args(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn kick_args() {
+        assert_eq!(
+            Some(AdminRequest::Kick {
+                id: ClientId::new(3),
+                reason: "too much spam".to_string()
+            }),
+            parse_kick(&args(&["3", "too", "much", "spam"]))
+        );
+        assert_eq!(
+            Some(AdminRequest::Kick {
+                id: ClientId::new(1),
+                reason: "Kicked by admin".to_string()
+            }),
+            parse_kick(&args(&["1"]))
+        );
+        assert_eq!(None, parse_kick(&args(&["bob"])));
+        assert_eq!(None, parse_kick(&[]));
+    }
+
+    #[test]
+    fn status_row() {
+        let row = ClientStatus {
+            id: ClientId::new(2),
+            name: "alice".to_string(),
+            addr: "127.0.0.1:5000".parse().unwrap(),
+            ping: Some(0.042),
+            loss: 0.25,
+            quality: Some(1),
+            idle: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            "  2 alice            127.0.0.1:5000           42  25.0% 1   1.5s",
+            row.to_string()
+        );
+        assert_eq!(ClientStatus::HEADER.len(), row.to_string().len());
+    }
+}
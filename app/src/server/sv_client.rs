@@ -1,26 +1,174 @@
 use std::io;
 use std::time::Instant;
 
-use log::{error, info, warn};
+use log::{debug, info, trace, warn};
+use rg_common::{ClientId, SessionId};
 
 use crate::error::AppError;
-use crate::net::Message::{Ping, Pong};
-use crate::net::{Endpoint, Message};
+use crate::net::Message::{
+    Accepted, CallVote, CastVote, Input, JoinTeam, Ping, Pong, Reconnect, Say,
+};
+use crate::net::{Endpoint, Message, PlayerInput, ServerClock};
+use crate::net_rate::{CongestionControl, NetStats};
+use crate::server::sv_security::Identity;
+use crate::server::sv_stats::PlayerId;
+use crate::server::sv_teams::Team;
+use crate::server::sv_vote::VoteAction;
 
 #[derive(Debug)]
 pub struct Client {
+    id: ClientId,
     name: String,
+    player_id: PlayerId,
+    token: SessionId,
     last_seen: Instant,
     endpoint: Box<dyn Endpoint + Sync + Send>,
+    vote_actions: Vec<VoteAction>,
+    congestion: CongestionControl,
+    ping: Option<f64>,
+    pings_sent: u32,
+    pongs_received: u32,
+    chat: Vec<(String, bool)>,
+    team_request: Option<Team>,
+    /// Latest input and its sequence number
+    input: Option<(u32, PlayerInput)>,
 }
 
 impl Client {
-    pub fn new(name: &str, endpoint: Box<dyn Endpoint + Sync + Send>) -> Self {
+    const MAX_CHAT_LENGTH: usize = 256;
+
+    pub(crate) fn new(
+        id: ClientId,
+        identity: Identity,
+        mut endpoint: Box<dyn Endpoint + Sync + Send>,
+        congestion: CongestionControl,
+    ) -> Self {
+        endpoint.set_rate(congestion.rate());
         Client {
-            name: name.to_string(),
+            id,
+            name: identity.name,
+            player_id: identity.player_id,
+            token: SessionId::new(rand::random()),
             last_seen: Instant::now(),
             endpoint,
+            vote_actions: Vec::new(),
+            congestion,
+            ping: None,
+            pings_sent: 0,
+            pongs_received: 0,
+            chat: Vec::new(),
+            team_request: None,
+            input: None,
+        }
+    }
+
+    ///
+    /// Short number identifying client in console commands
+    ///
+    pub(crate) fn id(&self) -> ClientId {
+        self.id
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn player_id(&self) -> &PlayerId {
+        &self.player_id
+    }
+
+    ///
+    /// Round trip to client in seconds, measured by server pings
+    ///
+    pub(crate) fn ping(&self) -> Option<f64> {
+        self.ping
+    }
+
+    pub(crate) fn on_ping_sent(&mut self) {
+        self.pings_sent += 1;
+        self.congestion.on_ping_sent();
+    }
+
+    ///
+    /// Adjusts send rate to the link quality, expected to be called right before the next ping
+    ///
+    pub(crate) fn adapt_rate(&mut self) {
+        let rate = self.congestion.rate();
+        let quality = self.congestion.quality();
+        let new_rate = self.congestion.update();
+        if new_rate != rate {
+            debug!("Send rate of {} changed to {new_rate} B/s", self.name);
+            self.endpoint.set_rate(new_rate);
         }
+        if let Some(band) = self.congestion.quality().filter(|&v| Some(v) != quality) {
+            debug!("Link quality of {} is {band}", self.name);
+            if let Err(e) = self.endpoint.send(&Message::LinkQuality { band }) {
+                warn!("Failed to send link quality to {}: {e:?}", self.name);
+            }
+        }
+    }
+
+    ///
+    /// Connection quality band from 0 to 4, `None` until the first round trip is measured
+    ///
+    pub(crate) fn quality(&self) -> Option<u8> {
+        self.congestion.quality()
+    }
+
+    ///
+    /// Fraction of server pings left without reply. The last ping may still be in flight, so it's not counted.
+    ///
+    pub(crate) fn loss(&self) -> f32 {
+        let expected = self.pings_sent.saturating_sub(1);
+        if expected == 0 {
+            return 0.0;
+        }
+        expected.saturating_sub(self.pongs_received) as f32 / expected as f32
+    }
+
+    ///
+    /// Opaque token client may use to resume this session
+    ///
+    pub(crate) fn token(&self) -> SessionId {
+        self.token
+    }
+
+    pub(crate) fn last_seen(&self) -> Instant {
+        self.last_seen
+    }
+
+    ///
+    /// Rebinds session to the new endpoint (client's address has changed)
+    ///
+    pub(crate) fn set_endpoint(&mut self, mut endpoint: Box<dyn Endpoint + Sync + Send>) {
+        endpoint.set_rate(self.congestion.rate());
+        self.endpoint = endpoint;
+    }
+
+    ///
+    /// Returns vote requests received since the last call
+    ///
+    pub(crate) fn take_vote_actions(&mut self) -> Vec<VoteAction> {
+        std::mem::take(&mut self.vote_actions)
+    }
+
+    ///
+    /// Returns chat lines (text, team only) received since the last call
+    ///
+    pub(crate) fn take_chat(&mut self) -> Vec<(String, bool)> {
+        std::mem::take(&mut self.chat)
+    }
+
+    pub(crate) fn take_team_request(&mut self) -> Option<Team> {
+        self.team_request.take()
+    }
+
+    ///
+    /// Latest movement input received from client
+    ///
+    #[cfg(test)]
+    pub(crate) fn input(&self) -> Option<&PlayerInput> {
+        self.input.as_ref().map(|(_, input)| input)
     }
 
     pub(crate) fn touch(&mut self) {
@@ -31,7 +179,15 @@ impl Client {
         self.endpoint.send(msg)
     }
 
-    fn clear_buffers(&mut self) {
+    pub(crate) fn send_low_priority(&mut self, msg: &Message) -> io::Result<usize> {
+        self.endpoint.send_low_priority(msg)
+    }
+
+    pub(crate) fn take_stats(&mut self) -> NetStats {
+        self.endpoint.take_stats()
+    }
+
+    pub(crate) fn clear_buffers(&mut self) {
         self.endpoint.clear_buffers();
     }
 
@@ -39,49 +195,64 @@ impl Client {
         self.endpoint.flush()
     }
 
-    pub(crate) fn process_message(&mut self, msg: &Message) -> Result<(), AppError> {
+    pub(crate) fn process_message(
+        &mut self,
+        msg: &Message,
+        clock: ServerClock,
+    ) -> Result<(), AppError> {
         self.touch();
-        info!("Got from connected client: {msg:?}");
+        // Inputs come every frame, keep them out of the regular log
+        if matches!(msg, Input { .. }) {
+            trace!("Got from connected client: {msg:?}");
+        } else {
+            info!("Got from connected client: {msg:?}");
+        }
         match msg {
             // Message::Ack(_) => {}
             // Message::Connect(_) => {}
             // Message::Accepted => {}
             // Message::Hello => {}
-            Pong { time } => {
-                info!(
-                    "Ping to client is {:.6} sec.",
-                    Instant::now().elapsed().as_secs_f64() - time
-                );
+            Pong { time, .. } => {
+                // Server pings with its own clock
+                let rtt = (clock.time - time).max(0.0);
+                self.ping = Some(rtt);
+                self.pongs_received += 1;
+                self.congestion.on_pong(rtt);
             }
             Ping { time } => {
-                self.endpoint.send(&Pong { time: *time })?;
+                self.endpoint.send(&Pong {
+                    time: *time,
+                    clock: Some(clock),
+                })?;
             }
-            m => {
-                warn!("Ignoring unsupported message: {m:?}");
+            CallVote { kind, arg } => self.vote_actions.push(VoteAction::Call {
+                kind: kind.to_string(),
+                arg: arg.to_string(),
+            }),
+            Reconnect { token } if *token == self.token => {
+                info!("Session of {} resumed", self.name);
+                self.endpoint.send(&Accepted)?;
             }
-        }
-        Ok(())
-    }
-
-    pub(crate) fn update(&mut self, buf: &mut Vec<u8>) -> Result<(), AppError> {
-        self.clear_buffers();
-        loop {
-            match self.endpoint.receive_data(buf.as_mut()) {
-                Ok(Some(mut data)) => {
-                    self.last_seen = Instant::now();
-                    while let Some(ref m) = data.read() {
-                        self.process_message(m)?;
-                    }
-                }
-
-                Ok(None) => {
-                    break;
+            CastVote { yes } => {
+                self.vote_actions.push(VoteAction::Cast(*yes));
+            }
+            JoinTeam { team } => {
+                self.team_request = Some(*team);
+            }
+            Say { text, team_only } => {
+                let text: String = text.chars().take(Self::MAX_CHAT_LENGTH).collect();
+                if !text.trim().is_empty() {
+                    self.chat.push((text, *team_only));
                 }
-                Err(e) => {
-                    error!("Failed to receive from client: {e:?}");
-                    break;
+            }
+            Input { seq, input } => {
+                if self.input.is_none_or(|(last, _)| *seq > last) {
+                    self.input = Some((*seq, *input));
                 }
             }
+            m => {
+                warn!("Ignoring unsupported message: {m:?}");
+            }
         }
         Ok(())
     }
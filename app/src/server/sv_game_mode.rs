@@ -0,0 +1,372 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use bitcode::{Decode, Encode};
+use log::warn;
+use rg_common::config::ServerConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::server::sv_stats::PlayerId;
+
+///
+/// How the match has ended
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MatchOutcome {
+    Winner(PlayerId),
+    Draw,
+}
+
+///
+/// Rules of the match. Server notifies mode about players and time, mode keeps the score and decides when match is over.
+///
+pub(crate) trait GameMode: Send {
+    fn name(&self) -> &'static str;
+
+    ///
+    /// Starts new match with players already on server
+    ///
+    fn init(&mut self, players: &[PlayerId]);
+
+    fn on_player_join(&mut self, id: &PlayerId);
+
+    fn on_player_leave(&mut self, id: &PlayerId);
+
+    fn on_tick(&mut self, dt: Duration);
+
+    fn on_kill(&mut self, killer: &PlayerId, victim: &PlayerId);
+
+    ///
+    /// Score of the player, `None` if mode doesn't keep score or player is unknown
+    ///
+    fn score(&self, id: &PlayerId) -> Option<i32>;
+
+    ///
+    /// Returns outcome once win condition is met
+    ///
+    fn outcome(&self) -> Option<MatchOutcome>;
+
+    ///
+    /// Mode-specific state for the client HUD, encoded with bitcode
+    ///
+    fn hud_state(&self) -> Vec<u8>;
+
+    ///
+    /// Match state for the save game, `None` if mode has nothing to save
+    ///
+    fn save_state(&self) -> Option<toml::Table> {
+        None
+    }
+
+    ///
+    /// Restores state returned by [`GameMode::save_state`], returns false if state is not valid for this mode
+    ///
+    fn restore_state(&mut self, _state: toml::Table) -> bool {
+        false
+    }
+}
+
+///
+/// Free play without score or end
+///
+#[derive(Debug, Default)]
+pub(crate) struct Sandbox;
+
+impl GameMode for Sandbox {
+    fn name(&self) -> &'static str {
+        "sandbox"
+    }
+
+    fn init(&mut self, _players: &[PlayerId]) {}
+
+    fn on_player_join(&mut self, _id: &PlayerId) {}
+
+    fn on_player_leave(&mut self, _id: &PlayerId) {}
+
+    fn on_tick(&mut self, _dt: Duration) {}
+
+    fn on_kill(&mut self, _killer: &PlayerId, _victim: &PlayerId) {}
+
+    fn score(&self, _id: &PlayerId) -> Option<i32> {
+        None
+    }
+
+    fn outcome(&self) -> Option<MatchOutcome> {
+        None
+    }
+
+    fn hud_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+///
+/// HUD state of [`Deathmatch`]
+///
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub(crate) struct DeathmatchHud {
+    /// Seconds, zero if there is no time limit
+    pub time_left: f32,
+    pub frag_limit: u32,
+    pub leader_score: i32,
+}
+
+///
+/// Saved state of [`Deathmatch`]. Scores of disconnected players are kept, so they get them back on return.
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct DeathmatchState {
+    /// Seconds since the match start
+    elapsed: f64,
+    scores: BTreeMap<PlayerId, i32>,
+}
+
+///
+/// Everyone for themselves: frag for each kill, minus one for suicide. Match ends when someone reaches
+/// frag limit or time is over, whoever leads then wins.
+///
+#[derive(Debug)]
+pub(crate) struct Deathmatch {
+    frag_limit: u32,
+    time_limit: Duration,
+    elapsed: Duration,
+    scores: HashMap<PlayerId, i32>,
+}
+
+impl Deathmatch {
+    pub(crate) fn new(frag_limit: u32, time_limit: Duration) -> Self {
+        Deathmatch {
+            frag_limit,
+            time_limit,
+            elapsed: Duration::ZERO,
+            scores: HashMap::new(),
+        }
+    }
+
+    ///
+    /// Player with the highest score, `None` if there are no players or leaders are tied
+    ///
+    fn leader(&self) -> Option<(&PlayerId, i32)> {
+        let best = *self.scores.values().max()?;
+        let mut leaders = self.scores.iter().filter(|(_, s)| **s == best);
+        let (id, _) = leaders.next()?;
+        leaders.next().is_none().then_some((id, best))
+    }
+}
+
+impl GameMode for Deathmatch {
+    fn name(&self) -> &'static str {
+        "deathmatch"
+    }
+
+    fn init(&mut self, players: &[PlayerId]) {
+        self.elapsed = Duration::ZERO;
+        self.scores = players.iter().map(|id| (id.clone(), 0)).collect();
+    }
+
+    fn on_player_join(&mut self, id: &PlayerId) {
+        self.scores.entry(id.clone()).or_default();
+    }
+
+    fn on_player_leave(&mut self, id: &PlayerId) {
+        self.scores.remove(id);
+    }
+
+    fn on_tick(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+
+    fn on_kill(&mut self, killer: &PlayerId, victim: &PlayerId) {
+        let delta = if killer == victim { -1 } else { 1 };
+        if let Some(score) = self.scores.get_mut(killer) {
+            *score += delta;
+        }
+    }
+
+    fn score(&self, id: &PlayerId) -> Option<i32> {
+        self.scores.get(id).copied()
+    }
+
+    fn outcome(&self) -> Option<MatchOutcome> {
+        let leader = self.leader();
+        let limit_reached =
+            self.frag_limit > 0 && leader.is_some_and(|(_, score)| score >= self.frag_limit as i32);
+        let time_is_over = !self.time_limit.is_zero() && self.elapsed >= self.time_limit;
+        if !limit_reached && !time_is_over {
+            return None;
+        }
+        Some(match leader {
+            Some((id, _)) => MatchOutcome::Winner(id.clone()),
+            None => MatchOutcome::Draw,
+        })
+    }
+
+    fn hud_state(&self) -> Vec<u8> {
+        bitcode::encode(&DeathmatchHud {
+            time_left: self.time_limit.saturating_sub(self.elapsed).as_secs_f32(),
+            frag_limit: self.frag_limit,
+            leader_score: self.scores.values().max().copied().unwrap_or_default(),
+        })
+    }
+
+    fn save_state(&self) -> Option<toml::Table> {
+        toml::Table::try_from(DeathmatchState {
+            elapsed: self.elapsed.as_secs_f64(),
+            scores: self.scores.clone().into_iter().collect(),
+        })
+        .ok()
+    }
+
+    fn restore_state(&mut self, state: toml::Table) -> bool {
+        let Ok(state) = state.try_into::<DeathmatchState>() else {
+            return false;
+        };
+        self.elapsed = Duration::from_secs_f64(state.elapsed.max(0.0));
+        self.scores = state.scores.into_iter().collect();
+        true
+    }
+}
+
+type ModeFactory = fn(&ServerConfig) -> Box<dyn GameMode>;
+
+///
+/// Game modes selectable by name
+///
+pub(crate) struct GameModes {
+    factories: BTreeMap<&'static str, ModeFactory>,
+}
+
+impl GameModes {
+    pub(crate) fn new() -> Self {
+        let mut result = GameModes {
+            factories: BTreeMap::new(),
+        };
+        result.register("sandbox", |_| Box::new(Sandbox));
+        result.register("deathmatch", |cfg| {
+            Box::new(Deathmatch::new(
+                cfg.deathmatch.frag_limit,
+                Duration::from_secs_f64(cfg.deathmatch.time_limit.max(0.0)),
+            ))
+        });
+        result
+    }
+
+    pub(crate) fn register(&mut self, name: &'static str, factory: ModeFactory) {
+        self.factories.insert(name, factory);
+    }
+
+    pub(crate) fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.factories.keys().copied()
+    }
+
+    pub(crate) fn create(&self, name: &str, cfg: &ServerConfig) -> Option<Box<dyn GameMode>> {
+        self.factories.get(name).map(|f| f(cfg))
+    }
+
+    ///
+    /// Creates mode selected by config, unknown mode falls back to sandbox
+    ///
+    pub(crate) fn select(&self, cfg: &ServerConfig) -> Box<dyn GameMode> {
+        self.create(&cfg.game_mode, cfg).unwrap_or_else(|| {
+            warn!(
+                "Unknown game mode \"{}\", using sandbox! Available modes: {}",
+                cfg.game_mode,
+                self.names().collect::<Vec<_>>().join(", ")
+            );
+            Box::new(Sandbox)
+        })
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::server::sv_stats::PlayerId;
+
+    use super::{Deathmatch, DeathmatchHud, GameMode, GameModes, MatchOutcome};
+
+    #[test]
+    fn deathmatch_frag_limit() {
+        let alice = PlayerId::from_name("alice");
+        let bob = PlayerId::from_name("bob");
+        let mut dm = Deathmatch::new(2, Duration::ZERO);
+        dm.init(std::slice::from_ref(&alice));
+        dm.on_player_join(&bob);
+
+        dm.on_kill(&alice, &bob);
+        dm.on_kill(&bob, &bob);
+        assert_eq!(Some(1), dm.score(&alice));
+        assert_eq!(Some(-1), dm.score(&bob));
+        dm.on_tick(Duration::from_secs(3600));
+        assert_eq!(None, dm.outcome());
+
+        dm.on_kill(&alice, &bob);
+        assert_eq!(Some(MatchOutcome::Winner(alice.clone())), dm.outcome());
+        let hud: DeathmatchHud = bitcode::decode(&dm.hud_state()).unwrap();
+        assert_eq!(2, hud.leader_score);
+
+        dm.on_player_leave(&alice);
+        assert_eq!(None, dm.score(&alice));
+        dm.init(&[alice.clone(), bob.clone()]);
+        assert_eq!(Some(0), dm.score(&bob));
+        assert_eq!(None, dm.outcome());
+    }
+
+    #[test]
+    fn deathmatch_time_limit() {
+        let alice = PlayerId::from_name("alice");
+        let bob = PlayerId::from_name("bob");
+        let mut dm = Deathmatch::new(0, Duration::from_secs(60));
+        dm.init(&[alice.clone(), bob.clone()]);
+        dm.on_tick(Duration::from_secs(45));
+        assert_eq!(None, dm.outcome());
+        let hud: DeathmatchHud = bitcode::decode(&dm.hud_state()).unwrap();
+        assert_eq!(15.0, hud.time_left);
+
+        dm.on_tick(Duration::from_secs(15));
+        assert_eq!(Some(MatchOutcome::Draw), dm.outcome());
+        dm.on_kill(&bob, &alice);
+        assert_eq!(Some(MatchOutcome::Winner(bob)), dm.outcome());
+    }
+
+    #[test]
+    fn deathmatch_save() {
+        let alice = PlayerId::from_name("alice");
+        let bob = PlayerId::from_name("bob");
+        let mut dm = Deathmatch::new(10, Duration::from_secs(60));
+        dm.init(&[alice.clone(), bob.clone()]);
+        dm.on_kill(&alice, &bob);
+        dm.on_tick(Duration::from_secs(20));
+        let state = dm.save_state().unwrap();
+
+        let mut restored = Deathmatch::new(10, Duration::from_secs(60));
+        restored.init(&[]);
+        assert!(restored.restore_state(state));
+        assert_eq!(Some(1), restored.score(&alice));
+        assert_eq!(Some(0), restored.score(&bob));
+        let hud: DeathmatchHud = bitcode::decode(&restored.hud_state()).unwrap();
+        assert_eq!(40.0, hud.time_left);
+
+        assert!(!restored.restore_state(toml::Table::new()));
+        assert!(super::Sandbox.save_state().is_none());
+    }
+
+    #[test]
+    fn registry() {
+        let modes = GameModes::new();
+        assert_eq!(
+            vec!["deathmatch", "sandbox"],
+            modes.names().collect::<Vec<_>>()
+        );
+        let mut cfg = crate::net_tests::config(None).server;
+        cfg.game_mode = "deathmatch".to_string();
+        assert_eq!("deathmatch", modes.select(&cfg).name());
+        cfg.game_mode = "capture".to_string();
+        assert!(modes.create("capture", &cfg).is_none());
+        assert_eq!("sandbox", modes.select(&cfg).name());
+    }
+}
@@ -0,0 +1,216 @@
+use std::fs::{self, File};
+use std::io::{self, LineWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+use rg_common::config::Config;
+use serde::Serialize;
+
+use crate::net::ServerClock;
+use crate::server::sv_stats::PlayerId;
+
+///
+/// Gameplay event recorded to the match log
+///
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum MatchEvent<'a> {
+    MatchStart {
+        mode: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        map: Option<&'a str>,
+    },
+    Join {
+        player: &'a PlayerId,
+        name: &'a str,
+    },
+    Leave {
+        player: &'a PlayerId,
+        name: &'a str,
+        reason: &'a str,
+    },
+    Chat {
+        player: &'a PlayerId,
+        name: &'a str,
+        text: &'a str,
+        team_only: bool,
+    },
+    MatchEnd {
+        mode: &'a str,
+        /// Empty for draw
+        winner: &'a str,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    tick: u64,
+    time: f64,
+    #[serde(flatten)]
+    event: &'a MatchEvent<'a>,
+}
+
+///
+/// Writes events of each match to its own JSON lines file `match_<unix time>_<n>.jsonl`.
+/// Enabled flag is read from config on every event, so logging may be switched in console.
+///
+pub(crate) struct MatchLog {
+    config: Arc<Mutex<Config>>,
+    dir: PathBuf,
+    keep: u32,
+    file: Option<LineWriter<File>>,
+    // Number of files opened by this process, makes names unique within the same second
+    count: u32,
+}
+
+impl MatchLog {
+    pub(crate) fn new(config: Arc<Mutex<Config>>, dir: PathBuf, keep: u32) -> Self {
+        MatchLog {
+            config,
+            dir,
+            keep: keep.max(1),
+            file: None,
+            count: 0,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.config
+            .lock()
+            .is_ok_and(|guard| guard.server.match_log.enabled)
+    }
+
+    pub(crate) fn record(&mut self, clock: ServerClock, event: &MatchEvent) {
+        if !self.is_enabled() {
+            self.file = None;
+            return;
+        }
+        if self.file.is_none() {
+            match self.open() {
+                Ok(file) => self.file = Some(file),
+                Err(e) => {
+                    warn!("Unable to open match log in {:?}: {e}", self.dir);
+                    return;
+                }
+            }
+        }
+        let line = Line {
+            tick: clock.tick.get(),
+            time: clock.time,
+            event,
+        };
+        let result = serde_json::to_string(&line)
+            .map_err(io::Error::other)
+            .and_then(|text| writeln!(self.file.as_mut().unwrap(), "{text}"));
+        if let Err(e) = result {
+            warn!("Unable to write match log: {e}");
+            self.file = None;
+        }
+    }
+
+    ///
+    /// Closes file of the current match, the next event goes to the new one
+    ///
+    pub(crate) fn rotate(&mut self) {
+        self.file = None;
+    }
+
+    fn open(&mut self) -> io::Result<LineWriter<File>> {
+        fs::create_dir_all(&self.dir)?;
+        self.prune(self.keep - 1)?;
+        self.count += 1;
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |v| v.as_secs());
+        let path = self
+            .dir
+            .join(format!("match_{stamp}_{:04}.jsonl", self.count));
+        info!("Writing match log to {path:?}");
+        Ok(LineWriter::new(File::create(path)?))
+    }
+
+    ///
+    /// Removes the oldest logs leaving at most `keep` files
+    ///
+    fn prune(&self, keep: u32) -> io::Result<()> {
+        let mut names: Vec<_> = fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("match_") && name.ends_with(".jsonl"))
+            .collect();
+        names.sort_unstable();
+        let excess = names.len().saturating_sub(keep as usize);
+        for name in names.into_iter().take(excess) {
+            fs::remove_file(self.dir.join(name))?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use rg_common::Tick;
+
+    use crate::net::ServerClock;
+    use crate::net_tests::config;
+    use crate::server::sv_stats::PlayerId;
+
+    use super::{MatchEvent, MatchLog};
+
+    #[test]
+    fn rotation() {
+        let dir = std::env::temp_dir().join(format!("rg_match_logs_{}", std::process::id()));
+        let cfg = Arc::new(Mutex::new(config(None)));
+        let mut log = MatchLog::new(cfg.clone(), dir.clone(), 2);
+        let clock = ServerClock {
+            tick: Tick::new(7),
+            time: 0.5,
+        };
+        let player = PlayerId::from_name("Alice");
+        let join = MatchEvent::Join {
+            player: &player,
+            name: "Alice",
+        };
+        // Disabled by default
+        log.record(clock, &join);
+        assert!(!dir.exists());
+
+        cfg.lock().unwrap().server.match_log.enabled = true;
+        for _ in 0..3 {
+            log.record(
+                clock,
+                &MatchEvent::MatchStart {
+                    mode: "deathmatch",
+                    map: Some("dm1"),
+                },
+            );
+            log.record(clock, &join);
+            log.rotate();
+        }
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        files.sort();
+        assert_eq!(2, files.len());
+        let text = std::fs::read_to_string(&files[1]).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(2, lines.len());
+        assert_eq!("match_start", lines[0]["event"]);
+        assert_eq!("dm1", lines[0]["map"]);
+        assert_eq!("join", lines[1]["event"]);
+        assert_eq!("alice", lines[1]["player"]);
+        assert_eq!(7, lines[1]["tick"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,404 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+use rg_common::config::Config;
+use rg_common::stopwatch::TimingRecord;
+use serde::Serialize;
+
+use crate::net_rate::NetStats;
+
+///
+/// Server state aggregated over the single dump interval
+///
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct MetricsSample {
+    pub timestamp: f64,
+    pub interval: f64,
+    pub ticks: u32,
+    pub tick_mean_ms: f64,
+    pub tick_max_ms: f64,
+    /// Mean time per tick spent in each of [`TICK_PHASES`]
+    pub listen_ms: f64,
+    pub events_ms: f64,
+    pub game_ms: f64,
+    pub flush_ms: f64,
+    pub players: usize,
+    pub bytes_in_per_sec: f64,
+    pub bytes_out_per_sec: f64,
+    pub deferred: u32,
+    pub dropped: u32,
+    pub corrupted: u32,
+}
+
+impl MetricsSample {
+    const CSV_HEADER: &'static str = "timestamp,interval,ticks,tick_mean_ms,tick_max_ms,listen_ms,events_ms,game_ms,flush_ms,players,bytes_in_per_sec,bytes_out_per_sec,deferred,dropped,corrupted";
+
+    fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(
+            out,
+            "{:.3},{:.3},{},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{},{:.1},{:.1},{},{},{}",
+            self.timestamp,
+            self.interval,
+            self.ticks,
+            self.tick_mean_ms,
+            self.tick_max_ms,
+            self.listen_ms,
+            self.events_ms,
+            self.game_ms,
+            self.flush_ms,
+            self.players,
+            self.bytes_in_per_sec,
+            self.bytes_out_per_sec,
+            self.deferred,
+            self.dropped,
+            self.corrupted
+        )
+    }
+
+    fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        serde_json::to_writer(&mut *out, self)?;
+        writeln!(out)
+    }
+}
+
+///
+/// Top level scopes of the server tick reported separately
+///
+const TICK_PHASES: [&str; 4] = ["listen", "events", "game", "flush"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+///
+/// Periodically dumps server metrics to file. Settings are read from config on every update, so they can be changed in console.
+///
+pub(crate) struct Metrics {
+    config: Arc<Mutex<Config>>,
+    profile: PathBuf,
+    // Configured path of the current file relative to profile dir, picked up by update
+    path: String,
+    out: Option<File>,
+    // Set on write failure to not spam the log, cleared when path changes
+    failed: bool,
+    window_start: Instant,
+    ticks: u32,
+    tick_total: Duration,
+    tick_max: Duration,
+    phase_total: [Duration; TICK_PHASES.len()],
+    bytes_in: u64,
+    bytes_out: u64,
+    net_stats: NetStats,
+}
+
+impl Metrics {
+    pub(crate) fn new(config: Arc<Mutex<Config>>, profile: PathBuf) -> Self {
+        Metrics {
+            config,
+            profile,
+            path: String::new(),
+            out: None,
+            failed: false,
+            window_start: Instant::now(),
+            ticks: 0,
+            tick_total: Duration::ZERO,
+            tick_max: Duration::ZERO,
+            phase_total: Default::default(),
+            bytes_in: 0,
+            bytes_out: 0,
+            net_stats: NetStats::default(),
+        }
+    }
+
+    ///
+    /// Records tick duration and its breakdown reported by the frame timer
+    ///
+    pub(crate) fn record_tick(&mut self, duration: Duration, timings: &[TimingRecord]) {
+        self.ticks += 1;
+        self.tick_total += duration;
+        self.tick_max = self.tick_max.max(duration);
+        for r in timings.iter().filter(|r| r.depth == 0) {
+            if let Some(i) = TICK_PHASES.iter().position(|p| *p == r.name) {
+                self.phase_total[i] += r.inclusive;
+            }
+        }
+    }
+
+    pub(crate) fn add_received(&mut self, bytes: usize) {
+        self.bytes_in += bytes as u64;
+    }
+
+    pub(crate) fn add_sent(&mut self, bytes: usize) {
+        self.bytes_out += bytes as u64;
+    }
+
+    pub(crate) fn add_net_stats(&mut self, stats: NetStats) {
+        self.net_stats.deferred += stats.deferred;
+        self.net_stats.dropped += stats.dropped;
+        self.net_stats.corrupted += stats.corrupted;
+    }
+
+    fn format(&self) -> Format {
+        match Path::new(&self.path).extension().and_then(|v| v.to_str()) {
+            Some("json") | Some("jsonl") => Format::Json,
+            _ => Format::Csv,
+        }
+    }
+
+    fn file_path(&self) -> PathBuf {
+        self.profile.join(&self.path)
+    }
+
+    ///
+    /// Returns aggregated sample and starts new interval if current one is over
+    ///
+    fn take_sample(
+        &mut self,
+        now: Instant,
+        players: usize,
+        interval: Duration,
+    ) -> Option<MetricsSample> {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < interval {
+            return None;
+        }
+        let secs = elapsed.as_secs_f64();
+        let sample = MetricsSample {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |v| v.as_secs_f64()),
+            interval: secs,
+            ticks: self.ticks,
+            tick_mean_ms: self.mean_ms(self.tick_total),
+            tick_max_ms: 1000.0 * self.tick_max.as_secs_f64(),
+            listen_ms: self.phase_mean_ms(0),
+            events_ms: self.phase_mean_ms(1),
+            game_ms: self.phase_mean_ms(2),
+            flush_ms: self.phase_mean_ms(3),
+            players,
+            bytes_in_per_sec: self.bytes_in as f64 / secs,
+            bytes_out_per_sec: self.bytes_out as f64 / secs,
+            deferred: self.net_stats.deferred,
+            dropped: self.net_stats.dropped,
+            corrupted: self.net_stats.corrupted,
+        };
+        self.reset(now);
+        Some(sample)
+    }
+
+    fn mean_ms(&self, total: Duration) -> f64 {
+        1000.0 * total.as_secs_f64() / self.ticks.max(1) as f64
+    }
+
+    fn phase_mean_ms(&self, index: usize) -> f64 {
+        self.mean_ms(self.phase_total[index])
+    }
+
+    fn reset(&mut self, now: Instant) {
+        self.window_start = now;
+        self.ticks = 0;
+        self.tick_total = Duration::ZERO;
+        self.tick_max = Duration::ZERO;
+        self.phase_total = Default::default();
+        self.bytes_in = 0;
+        self.bytes_out = 0;
+        self.net_stats = NetStats::default();
+    }
+
+    fn open(&self) -> io::Result<File> {
+        let path = self.file_path();
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if self.format() == Format::Csv && file.metadata()?.len() == 0 {
+            writeln!(file, "{}", MetricsSample::CSV_HEADER)?;
+        }
+        info!("Writing server metrics to {:?}", path);
+        Ok(file)
+    }
+
+    fn write(&mut self, sample: &MetricsSample) -> io::Result<()> {
+        if self.out.is_none() {
+            self.out = Some(self.open()?);
+        }
+        let format = self.format();
+        let out = self.out.as_mut().unwrap();
+        match format {
+            Format::Csv => sample.write_csv(out),
+            Format::Json => sample.write_json(out),
+        }
+    }
+
+    ///
+    /// Writes sample once per interval. Export is stopped after the first failure until path is changed.
+    ///
+    pub(crate) fn update(&mut self, players: usize) {
+        let now = Instant::now();
+        let interval = {
+            let Ok(guard) = self.config.lock() else {
+                return;
+            };
+            let cfg = &guard.server.metrics;
+            if cfg.path != self.path {
+                self.path.clone_from(&cfg.path);
+                self.out = None;
+                self.failed = false;
+            }
+            cfg.enabled
+                .then(|| Duration::from_secs_f64(cfg.interval.max(0.1)))
+        };
+        let Some(interval) = interval.filter(|_| !self.failed) else {
+            // File is closed and nothing is accumulated while export is off
+            self.out = None;
+            self.reset(now);
+            return;
+        };
+        if let Some(sample) = self.take_sample(now, players, interval) {
+            if let Err(e) = self.write(&sample) {
+                warn!("Unable to write metrics to {:?}: {e:?}", self.file_path());
+                self.failed = true;
+            }
+        }
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use rg_common::stopwatch::TimingRecord;
+
+    use crate::net_tests::config;
+
+    use super::{Format, Metrics, MetricsSample};
+
+    #[test]
+    fn sample() {
+        let cfg = Arc::new(Mutex::new(config(None)));
+        cfg.lock().unwrap().server.metrics.path = "metrics.json".to_string();
+        let mut m = Metrics::new(cfg, PathBuf::new());
+        m.update(0);
+        assert_eq!(Format::Json, m.format());
+        let start = m.window_start;
+        let interval = Duration::from_secs(2);
+        let phase = |name, ms| TimingRecord {
+            name,
+            depth: 0,
+            calls: 1,
+            inclusive: Duration::from_millis(ms),
+            exclusive: Duration::from_millis(ms),
+        };
+        m.record_tick(Duration::from_millis(1), &[phase("listen", 1)]);
+        m.record_tick(
+            Duration::from_millis(3),
+            &[phase("listen", 1), phase("game", 2), phase("unknown", 5)],
+        );
+        m.add_received(1000);
+        m.add_sent(4000);
+        assert!(m
+            .take_sample(start + Duration::from_secs(1), 2, interval)
+            .is_none());
+        let s = m
+            .take_sample(start + Duration::from_secs(2), 2, interval)
+            .unwrap();
+        assert_eq!(2, s.ticks);
+        assert_eq!(2.0, s.tick_mean_ms);
+        assert_eq!(3.0, s.tick_max_ms);
+        assert_eq!(1.0, s.listen_ms);
+        assert_eq!(1.0, s.game_ms);
+        assert_eq!(0.0, s.flush_ms);
+        assert_eq!(2, s.players);
+        assert_eq!(500.0, s.bytes_in_per_sec);
+        assert_eq!(2000.0, s.bytes_out_per_sec);
+        // Next interval starts from scratch
+        let s = m
+            .take_sample(start + Duration::from_secs(4), 0, interval)
+            .unwrap();
+        assert_eq!(0, s.ticks);
+        assert_eq!(0.0, s.bytes_out_per_sec);
+    }
+
+    #[test]
+    fn settings_are_reread() {
+        let dir = std::env::temp_dir().join(format!("rg_metrics_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cfg = Arc::new(Mutex::new(config(None)));
+        cfg.lock().unwrap().server.metrics.enabled = false;
+        let mut m = Metrics::new(cfg.clone(), dir.clone());
+        let lines =
+            |name: &str| std::fs::read_to_string(dir.join(name)).map_or(0, |v| v.lines().count());
+        let step = |m: &mut Metrics| {
+            m.window_start -= Duration::from_secs(1);
+            m.update(1);
+        };
+
+        step(&mut m);
+        assert_eq!(0, lines("m.csv"));
+        {
+            let mut cfg = cfg.lock().unwrap();
+            cfg.server.metrics.enabled = true;
+            cfg.server.metrics.interval = 0.5;
+            cfg.server.metrics.path = "m.csv".to_string();
+        }
+        step(&mut m);
+        // Header and the sample
+        assert_eq!(2, lines("m.csv"));
+
+        cfg.lock().unwrap().server.metrics.path = "m.jsonl".to_string();
+        step(&mut m);
+        assert_eq!(2, lines("m.csv"));
+        assert_eq!(1, lines("m.jsonl"));
+
+        cfg.lock().unwrap().server.metrics.enabled = false;
+        step(&mut m);
+        assert_eq!(1, lines("m.jsonl"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn formats() {
+        let s = MetricsSample {
+            timestamp: 1.5,
+            interval: 10.0,
+            ticks: 1000,
+            tick_mean_ms: 0.25,
+            tick_max_ms: 1.0,
+            listen_ms: 0.05,
+            events_ms: 0.0,
+            game_ms: 0.125,
+            flush_ms: 0.0625,
+            players: 3,
+            bytes_in_per_sec: 100.0,
+            bytes_out_per_sec: 200.0,
+            deferred: 5,
+            dropped: 1,
+            corrupted: 2,
+        };
+        let mut csv = Vec::new();
+        s.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            "1.500,10.000,1000,0.2500,1.0000,0.0500,0.0000,0.1250,0.0625,3,100.0,200.0,5,1,2\n",
+            String::from_utf8(csv).unwrap()
+        );
+        assert_eq!(
+            MetricsSample::CSV_HEADER.split(',').count(),
+            "1.500,10.000,1000,0.2500,1.0000,0.0500,0.0000,0.1250,0.0625,3,100.0,200.0,5,1,2"
+                .split(',')
+                .count()
+        );
+        let mut json = Vec::new();
+        s.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with("{\"timestamp\":1.5,"));
+        assert!(json.ends_with("\"deferred\":5,\"dropped\":1,\"corrupted\":2}\n"));
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+///
+/// Progress of the intermission between matches
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Intermission {
+    /// Whole seconds left before the next map
+    Countdown(u32),
+    Over,
+}
+
+///
+/// Ordered list of maps played one after another. When match is over, intermission with countdown
+/// is started and the next map follows it.
+///
+#[derive(Debug)]
+pub(crate) struct MapRotation {
+    maps: Vec<String>,
+    index: usize,
+    map: Option<String>,
+    // Chosen by admin or vote, played instead of the next map in order
+    next: Option<String>,
+    intermission: Duration,
+    time_left: Option<Duration>,
+}
+
+impl MapRotation {
+    pub(crate) fn new(maps: &str, intermission: f64) -> Self {
+        let maps: Vec<_> = maps
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_owned)
+            .collect();
+        MapRotation {
+            map: maps.first().cloned(),
+            maps,
+            index: 0,
+            next: None,
+            intermission: Duration::from_secs_f64(intermission.max(0.0)),
+            time_left: None,
+        }
+    }
+
+    ///
+    /// Current map, `None` if rotation is not configured
+    ///
+    pub(crate) fn map(&self) -> Option<&str> {
+        self.map.as_deref()
+    }
+
+    pub(crate) fn next_map(&self) -> Option<&str> {
+        self.next.as_deref().or_else(|| {
+            (!self.maps.is_empty()).then(|| self.maps[(self.index + 1) % self.maps.len()].as_str())
+        })
+    }
+
+    ///
+    /// Overrides the next map. Map must be in rotation unless rotation is empty.
+    ///
+    pub(crate) fn set_next(&mut self, map: &str) -> bool {
+        if !self.maps.is_empty() && !self.maps.iter().any(|v| v == map) {
+            return false;
+        }
+        self.next = Some(map.to_owned());
+        true
+    }
+
+    pub(crate) fn is_intermission(&self) -> bool {
+        self.time_left.is_some()
+    }
+
+    pub(crate) fn start_intermission(&mut self) -> u32 {
+        self.time_left = Some(self.intermission);
+        Self::whole_secs(self.intermission)
+    }
+
+    fn whole_secs(time: Duration) -> u32 {
+        time.as_secs_f64().ceil() as u32
+    }
+
+    ///
+    /// Advances intermission countdown, reports every whole second and the end of it
+    ///
+    pub(crate) fn on_tick(&mut self, dt: Duration) -> Option<Intermission> {
+        let left = self.time_left?;
+        let secs = Self::whole_secs(left);
+        let left = left.saturating_sub(dt);
+        if left.is_zero() {
+            self.time_left = None;
+            return Some(Intermission::Over);
+        }
+        self.time_left = Some(left);
+        let new_secs = Self::whole_secs(left);
+        (new_secs < secs).then_some(Intermission::Countdown(new_secs))
+    }
+
+    ///
+    /// Switches to the next map, returns it
+    ///
+    pub(crate) fn advance(&mut self) -> Option<&str> {
+        self.time_left = None;
+        match self.next.take() {
+            Some(map) => {
+                if let Some(index) = self.maps.iter().position(|v| *v == map) {
+                    self.index = index;
+                }
+                self.map = Some(map);
+            }
+            None if !self.maps.is_empty() => {
+                self.index = (self.index + 1) % self.maps.len();
+                self.map = Some(self.maps[self.index].clone());
+            }
+            None => {}
+        }
+        self.map()
+    }
+}
+
+impl Display for MapRotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.maps.is_empty() {
+            return write!(f, "no rotation, map: {}", self.map().unwrap_or("-"));
+        }
+        for (i, map) in self.maps.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            if self.map() == Some(map) {
+                write!(f, "[{map}]")?;
+            } else {
+                write!(f, "{map}")?;
+            }
+        }
+        write!(f, "; next: {}", self.next_map().unwrap_or("-"))
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Intermission, MapRotation};
+
+    #[test]
+    fn rotation() {
+        let mut r = MapRotation::new(" dm1, dm2,,dm3 ", 0.0);
+        assert_eq!(Some("dm1"), r.map());
+        assert_eq!(Some("dm2"), r.next_map());
+        assert_eq!("[dm1], dm2, dm3; next: dm2", r.to_string());
+        assert_eq!(Some("dm2"), r.advance());
+        assert_eq!(Some("dm3"), r.advance());
+        assert_eq!(Some("dm1"), r.next_map());
+
+        assert!(!r.set_next("e1m1"));
+        assert!(r.set_next("dm2"));
+        assert_eq!(Some("dm2"), r.next_map());
+        assert_eq!(Some("dm2"), r.advance());
+        // Order continues from the chosen map
+        assert_eq!(Some("dm3"), r.next_map());
+
+        let mut r = MapRotation::new("", 0.0);
+        assert_eq!(None, r.next_map());
+        assert_eq!(None, r.advance());
+        assert!(r.set_next("e1m1"));
+        assert_eq!(Some("e1m1"), r.advance());
+        assert_eq!("no rotation, map: e1m1", r.to_string());
+    }
+
+    #[test]
+    fn countdown() {
+        let mut r = MapRotation::new("dm1,dm2", 2.5);
+        assert_eq!(None, r.on_tick(Duration::from_secs(1)));
+        assert_eq!(3, r.start_intermission());
+        assert!(r.is_intermission());
+        assert_eq!(None, r.on_tick(Duration::from_millis(400)));
+        assert_eq!(
+            Some(Intermission::Countdown(2)),
+            r.on_tick(Duration::from_millis(400))
+        );
+        assert_eq!(
+            Some(Intermission::Countdown(1)),
+            r.on_tick(Duration::from_secs(1))
+        );
+        assert_eq!(Some(Intermission::Over), r.on_tick(Duration::from_secs(1)));
+        assert!(!r.is_intermission());
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rg_common::config::Config;
+use rg_common::GameClockState;
+use serde::{Deserialize, Serialize};
+
+///
+/// Server state persisted across restarts
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ServerSave {
+    pub version: u32,
+    /// Unix time in seconds
+    pub saved_at: u64,
+    /// Name of the game mode
+    pub mode: String,
+    pub clock: GameClockState,
+    /// Match state of the game mode, see [`crate::server::sv_game_mode::GameMode::save_state`]
+    #[serde(default)]
+    pub mode_state: Option<toml::Table>,
+}
+
+impl ServerSave {
+    pub(crate) const VERSION: u32 = 1;
+
+    pub(crate) fn new(mode: &str, clock: GameClockState, mode_state: Option<toml::Table>) -> Self {
+        ServerSave {
+            version: Self::VERSION,
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |v| v.as_secs()),
+            mode: mode.to_string(),
+            clock,
+            mode_state,
+        }
+    }
+}
+
+///
+/// Rotated save files `<prefix>_<slot>.toml`, slot 0 is the newest one
+///
+#[derive(Debug)]
+pub(crate) struct SaveSlots {
+    prefix: PathBuf,
+    keep: u32,
+}
+
+impl SaveSlots {
+    pub(crate) fn new(prefix: PathBuf, keep: u32) -> Self {
+        SaveSlots {
+            prefix,
+            keep: keep.max(1),
+        }
+    }
+
+    pub(crate) fn path(&self, slot: u32) -> PathBuf {
+        let mut name = self.prefix.file_name().unwrap_or_default().to_os_string();
+        name.push(format!("_{slot}.toml"));
+        self.prefix.with_file_name(name)
+    }
+
+    ///
+    /// Shifts existing saves by one slot dropping the oldest one, then writes new save to slot 0
+    ///
+    pub(crate) fn write(&self, save: &ServerSave) -> io::Result<PathBuf> {
+        let text = toml::to_string(save).map_err(io::Error::other)?;
+        if let Some(dir) = self.prefix.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        for slot in (0..self.keep - 1).rev() {
+            let from = self.path(slot);
+            if from.exists() {
+                fs::rename(&from, self.path(slot + 1))?;
+            }
+        }
+        let path = self.path(0);
+        // Write to temporary file first to not leave broken save if interrupted
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, &path)?;
+        Ok(path)
+    }
+
+    pub(crate) fn read(&self, slot: u32) -> io::Result<ServerSave> {
+        let text = fs::read_to_string(self.path(slot))?;
+        let save: ServerSave =
+            toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if save.version != ServerSave::VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported save version {}", save.version),
+            ));
+        }
+        Ok(save)
+    }
+}
+
+///
+/// Decides when to save. Interval is read from config on every check, so it can be changed in console.
+///
+pub(crate) struct Autosave {
+    config: Arc<Mutex<Config>>,
+    slots: SaveSlots,
+    last_save: Instant,
+}
+
+impl Autosave {
+    pub(crate) fn new(config: Arc<Mutex<Config>>, slots: SaveSlots) -> Self {
+        Autosave {
+            config,
+            slots,
+            last_save: Instant::now(),
+        }
+    }
+
+    pub(crate) fn slots(&self) -> &SaveSlots {
+        &self.slots
+    }
+
+    pub(crate) fn is_due(&self, now: Instant) -> bool {
+        let Ok(guard) = self.config.lock() else {
+            return false;
+        };
+        let cfg = &guard.server.autosave;
+        cfg.enabled
+            && cfg.interval > 0.0
+            && now.saturating_duration_since(self.last_save)
+                >= Duration::from_secs_f64(cfg.interval)
+    }
+
+    pub(crate) fn save(&mut self, save: &ServerSave) -> io::Result<PathBuf> {
+        self.last_save = Instant::now();
+        self.slots.write(save)
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use rg_common::GameClock;
+
+    use crate::net_tests::config;
+
+    use super::{Autosave, SaveSlots, ServerSave};
+
+    fn save(tick: u64) -> ServerSave {
+        let mut clock = GameClock::new(Duration::from_millis(10));
+        clock.advance(Duration::from_millis(10 * tick));
+        let mut state = toml::Table::new();
+        state.insert("answer".to_string(), 42.into());
+        ServerSave::new("deathmatch", clock.state(), Some(state))
+    }
+
+    #[test]
+    fn rotation() {
+        let dir = std::env::temp_dir().join(format!("rg_saves_{}", std::process::id()));
+        let slots = SaveSlots::new(dir.join("saves").join("autosave"), 3);
+        assert_eq!(dir.join("saves").join("autosave_1.toml"), slots.path(1));
+        for tick in 1..=4 {
+            slots.write(&save(tick)).unwrap();
+        }
+        assert_eq!(save(4).clock, slots.read(0).unwrap().clock);
+        assert_eq!(save(3).clock, slots.read(1).unwrap().clock);
+        let oldest = slots.read(2).unwrap();
+        assert_eq!(save(2).clock, oldest.clock);
+        assert_eq!(save(2).mode_state, oldest.mode_state);
+        assert!(!slots.path(3).exists());
+        assert!(slots.read(3).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn interval() {
+        let cfg = Arc::new(Mutex::new(config(None)));
+        cfg.lock().unwrap().server.autosave.enabled = true;
+        cfg.lock().unwrap().server.autosave.interval = 60.0;
+        let autosave = Autosave::new(cfg.clone(), SaveSlots::new("autosave".into(), 1));
+        let now = Instant::now();
+        assert!(!autosave.is_due(now + Duration::from_secs(30)));
+        assert!(autosave.is_due(now + Duration::from_secs(61)));
+        cfg.lock().unwrap().server.autosave.interval = 10.0;
+        assert!(autosave.is_due(now + Duration::from_secs(30)));
+        cfg.lock().unwrap().server.autosave.enabled = false;
+        assert!(!autosave.is_due(now + Duration::from_secs(30)));
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+use crate::net::ScoreEntry;
+
+///
+/// Scoreboard line as seen by server
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ScoreRow {
+    pub name: String,
+    pub score: i32,
+    /// Round trip in milliseconds
+    pub ping: u16,
+    /// Zero means no team
+    pub team: u8,
+}
+
+impl ScoreRow {
+    pub(crate) fn entry(&self) -> ScoreEntry<'_> {
+        ScoreEntry {
+            name: &self.name,
+            score: self.score,
+            ping: self.ping,
+            team: self.team,
+        }
+    }
+}
+
+///
+/// Decides when scoreboard has to be sent to clients: as soon as it changes, otherwise periodically
+/// to refresh clients which missed low priority update.
+///
+#[derive(Debug, Default)]
+pub(crate) struct ScoreboardSync {
+    rows: Vec<ScoreRow>,
+    sent_at: Option<Instant>,
+}
+
+impl ScoreboardSync {
+    const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+    ///
+    /// Returns rows to send if they differ from the last sent ones or refresh is due
+    ///
+    pub(crate) fn update(&mut self, mut rows: Vec<ScoreRow>, now: Instant) -> Option<&[ScoreRow]> {
+        // Order of the clients on server is arbitrary
+        rows.sort_by(|a, b| a.name.cmp(&b.name));
+        let refresh = self
+            .sent_at
+            .is_none_or(|t| now.saturating_duration_since(t) >= Self::REFRESH_INTERVAL);
+        if rows == self.rows && !refresh {
+            return None;
+        }
+        self.rows = rows;
+        self.sent_at = Some(now);
+        Some(&self.rows)
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{ScoreRow, ScoreboardSync};
+
+    fn row(name: &str, score: i32) -> ScoreRow {
+        ScoreRow {
+            name: name.to_string(),
+            score,
+            ping: 20,
+            team: 0,
+        }
+    }
+
+    #[test]
+    fn sync() {
+        let mut s = ScoreboardSync::default();
+        let now = Instant::now();
+        let sent = s.update(vec![row("b", 1), row("a", 2)], now).unwrap();
+        assert_eq!(vec![row("a", 2), row("b", 1)], sent);
+        assert!(s
+            .update(vec![row("a", 2), row("b", 1)], now + Duration::from_secs(1))
+            .is_none());
+        assert!(s
+            .update(vec![row("b", 3), row("a", 2)], now + Duration::from_secs(2))
+            .is_some());
+        assert!(s
+            .update(vec![row("b", 3), row("a", 2)], now + Duration::from_secs(7))
+            .is_some());
+    }
+}
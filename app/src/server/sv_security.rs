@@ -0,0 +1,306 @@
+use std::fmt::{Display, Formatter};
+use std::str::from_utf8;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bitcode::{Decode, Encode};
+use hmac::{Hmac, Mac};
+use rg_common::config::AuthConfig;
+use sha2::Sha256;
+
+use crate::error::AppError;
+use crate::server::key_pair::KeyPair;
+use crate::server::sv_stats::PlayerId;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const TAG_SIZE: usize = 32;
+
+///
+/// Credentials from the `Connect` message
+///
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Credentials<'a> {
+    pub name: &'a str,
+    /// Password encrypted with server's public key
+    pub password: &'a [u8],
+    pub ticket: &'a [u8],
+}
+
+///
+/// Authenticated player
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Identity {
+    pub player_id: PlayerId,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum AuthError {
+    WrongPassword,
+    MissingTicket,
+    InvalidTicket,
+    TicketExpired,
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::WrongPassword => write!(f, "Wrong password!"),
+            AuthError::MissingTicket => write!(f, "No ticket!"),
+            AuthError::InvalidTicket => write!(f, "Invalid ticket!"),
+            AuthError::TicketExpired => write!(f, "Ticket has expired!"),
+        }
+    }
+}
+
+///
+/// Decides whether connecting client is allowed in and who it is
+///
+pub(crate) trait AuthProvider: Send + Sync {
+    fn authenticate(
+        &self,
+        keys: &KeyPair,
+        credentials: &Credentials,
+    ) -> Result<Identity, AuthError>;
+}
+
+///
+/// Name and optional server password, player id is derived from the name
+///
+pub(crate) struct OfflineAuth {
+    password: Option<String>,
+}
+
+impl OfflineAuth {
+    pub(crate) fn new(password: Option<String>) -> Self {
+        OfflineAuth { password }
+    }
+}
+
+impl AuthProvider for OfflineAuth {
+    fn authenticate(
+        &self,
+        keys: &KeyPair,
+        credentials: &Credentials,
+    ) -> Result<Identity, AuthError> {
+        if let Some(password) = &self.password {
+            let matches = keys
+                .decode(credentials.password)
+                .is_ok_and(|v| from_utf8(&v).is_ok_and(|p| p == password));
+            if !matches {
+                return Err(AuthError::WrongPassword);
+            }
+        }
+        Ok(Identity {
+            player_id: PlayerId::from_name(credentials.name),
+            name: credentials.name.to_string(),
+        })
+    }
+}
+
+///
+/// Ticket issued by external auth service
+///
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub(crate) struct Ticket {
+    pub player_id: String,
+    pub name: String,
+    /// Unix time in seconds
+    pub expires: u64,
+}
+
+impl Ticket {
+    ///
+    /// Encodes ticket and appends HMAC-SHA256 tag, the same way auth service does
+    ///
+    #[cfg(test)]
+    pub(crate) fn sign(&self, secret: &[u8]) -> Vec<u8> {
+        let mut result = bitcode::encode(self);
+        let mut mac = HmacSha256::new_from_slice(secret).expect("Any key size is valid for HMAC");
+        mac.update(&result);
+        result.extend_from_slice(&mac.finalize().into_bytes());
+        result
+    }
+
+    ///
+    /// Checks tag and decodes ticket, expiration is not checked here
+    ///
+    pub(crate) fn verify(data: &[u8], secret: &[u8]) -> Result<Ticket, AuthError> {
+        if data.len() <= TAG_SIZE {
+            return Err(AuthError::InvalidTicket);
+        }
+        let (body, tag) = data.split_at(data.len() - TAG_SIZE);
+        let mut mac = HmacSha256::new_from_slice(secret).expect("Any key size is valid for HMAC");
+        mac.update(body);
+        mac.verify_slice(tag)
+            .map_err(|_| AuthError::InvalidTicket)?;
+        bitcode::decode(body).map_err(|_| AuthError::InvalidTicket)
+    }
+}
+
+///
+/// Accepts clients with valid ticket signed by external auth service sharing `secret` with this server
+///
+pub(crate) struct TicketAuth {
+    secret: Vec<u8>,
+}
+
+impl TicketAuth {
+    pub(crate) fn new(secret: &[u8]) -> Self {
+        TicketAuth {
+            secret: secret.to_vec(),
+        }
+    }
+}
+
+impl AuthProvider for TicketAuth {
+    fn authenticate(
+        &self,
+        _keys: &KeyPair,
+        credentials: &Credentials,
+    ) -> Result<Identity, AuthError> {
+        if credentials.ticket.is_empty() {
+            return Err(AuthError::MissingTicket);
+        }
+        let ticket = Ticket::verify(credentials.ticket, &self.secret)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |v| v.as_secs());
+        if ticket.expires <= now {
+            return Err(AuthError::TicketExpired);
+        }
+        Ok(Identity {
+            player_id: PlayerId::new(ticket.player_id),
+            name: ticket.name,
+        })
+    }
+}
+
+///
+/// Creates provider selected by config. Unknown mode or ticket mode without secret is an error,
+/// server must not silently let everybody in.
+///
+pub(crate) fn auth_provider(
+    cfg: &AuthConfig,
+    password: Option<String>,
+) -> Result<Box<dyn AuthProvider>, AppError> {
+    match (cfg.mode.as_str(), cfg.ticket_secret.as_deref()) {
+        ("ticket", Some(secret)) if !secret.is_empty() => {
+            Ok(Box::new(TicketAuth::new(secret.as_bytes())))
+        }
+        ("ticket", _) => Err("Auth mode \"ticket\" requires ticket_secret!".into()),
+        ("offline", _) => Ok(Box::new(OfflineAuth::new(password))),
+        (mode, _) => Err(AppError {
+            message: format!("Unknown auth mode \"{mode}\"!"),
+        }),
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use rg_common::config::AuthConfig;
+
+    use crate::server::key_pair::KeyPair;
+    use crate::server::sv_stats::PlayerId;
+
+    use super::{
+        auth_provider, AuthError, AuthProvider, Credentials, OfflineAuth, Ticket, TicketAuth,
+    };
+
+    const SECRET: &[u8] = b"shared secret";
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn credentials<'a>(password: &'a [u8], ticket: &'a [u8]) -> Credentials<'a> {
+        Credentials {
+            name: "Player",
+            password,
+            ticket,
+        }
+    }
+
+    #[test]
+    fn offline() {
+        let keys = KeyPair::new(512).unwrap();
+        let good = keys.encode(b"secret").unwrap();
+        let bad = keys.encode(b"guess").unwrap();
+        let auth = OfflineAuth::new(Some("secret".to_string()));
+        let identity = auth.authenticate(&keys, &credentials(&good, &[])).unwrap();
+        assert_eq!(PlayerId::from_name("player"), identity.player_id);
+        assert_eq!(
+            Err(AuthError::WrongPassword),
+            auth.authenticate(&keys, &credentials(&bad, &[]))
+        );
+        assert_eq!(
+            Err(AuthError::WrongPassword),
+            auth.authenticate(&keys, &credentials(b"garbage", &[]))
+        );
+        assert!(OfflineAuth::new(None)
+            .authenticate(&keys, &credentials(&[], &[]))
+            .is_ok());
+    }
+
+    #[test]
+    fn ticket() {
+        let keys = KeyPair::new(512).unwrap();
+        let auth = TicketAuth::new(SECRET);
+        let ticket = Ticket {
+            player_id: "account-42".to_string(),
+            name: "Real Name".to_string(),
+            expires: now() + 60,
+        };
+        let signed = ticket.sign(SECRET);
+        let identity = auth
+            .authenticate(&keys, &credentials(&[], &signed))
+            .unwrap();
+        assert_eq!(PlayerId::new("account-42".to_string()), identity.player_id);
+        assert_eq!("Real Name", identity.name);
+
+        let mut tampered = signed.clone();
+        tampered[0] ^= 1;
+        assert_eq!(
+            Err(AuthError::InvalidTicket),
+            auth.authenticate(&keys, &credentials(&[], &tampered))
+        );
+        assert_eq!(
+            Err(AuthError::InvalidTicket),
+            auth.authenticate(&keys, &credentials(&[], &ticket.sign(b"other secret")))
+        );
+        assert_eq!(
+            Err(AuthError::MissingTicket),
+            auth.authenticate(&keys, &credentials(&[], &[]))
+        );
+        let expired = Ticket {
+            expires: now() - 1,
+            ..ticket
+        };
+        assert_eq!(
+            Err(AuthError::TicketExpired),
+            auth.authenticate(&keys, &credentials(&[], &expired.sign(SECRET)))
+        );
+    }
+
+    #[test]
+    fn misconfigured() {
+        let config = |mode: &str, secret: Option<&str>| AuthConfig {
+            mode: mode.to_string(),
+            ticket_secret: secret.map(str::to_string),
+        };
+        assert!(auth_provider(&config("offline", None), None).is_ok());
+        assert!(auth_provider(&config("ticket", Some("secret")), None).is_ok());
+        assert!(auth_provider(&config("ticket", None), None).is_err());
+        assert!(auth_provider(&config("ticket", Some("")), None).is_err());
+        assert!(auth_provider(&config("tiket", Some("secret")), None).is_err());
+    }
+}
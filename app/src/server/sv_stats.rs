@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+///
+/// Stable player identifier. Until authentication provides account ids it's derived from the player name.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct PlayerId(String);
+
+impl PlayerId {
+    pub(crate) fn new(id: String) -> Self {
+        PlayerId(id)
+    }
+
+    pub(crate) fn from_name(name: &str) -> Self {
+        PlayerId(name.trim().to_lowercase())
+    }
+}
+
+impl Display for PlayerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+///
+/// Persistent per-player statistics
+///
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct PlayerStats {
+    pub kills: u32,
+    pub deaths: u32,
+    /// Total time on server in seconds
+    pub playtime: f64,
+    pub sessions: u32,
+    /// Unix time in seconds
+    pub last_seen: u64,
+}
+
+///
+/// Storage backend of the player statistics. Writes may be buffered until [`StatsStore::flush`].
+///
+pub(crate) trait StatsStore: Send {
+    fn get(&self, id: &PlayerId) -> io::Result<Option<PlayerStats>>;
+    fn put(&mut self, id: &PlayerId, stats: &PlayerStats) -> io::Result<()>;
+    fn all(&self) -> io::Result<Vec<(PlayerId, PlayerStats)>>;
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+///
+/// Non-persistent store, used when statistics are disabled
+///
+#[derive(Debug, Default)]
+pub(crate) struct MemoryStore {
+    data: HashMap<PlayerId, PlayerStats>,
+}
+
+impl StatsStore for MemoryStore {
+    fn get(&self, id: &PlayerId) -> io::Result<Option<PlayerStats>> {
+        Ok(self.data.get(id).cloned())
+    }
+
+    fn put(&mut self, id: &PlayerId, stats: &PlayerStats) -> io::Result<()> {
+        self.data.insert(id.clone(), stats.clone());
+        Ok(())
+    }
+
+    fn all(&self) -> io::Result<Vec<(PlayerId, PlayerStats)>> {
+        Ok(self
+            .data
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+///
+/// Keeps all records in memory and rewrites JSON file on flush
+///
+#[derive(Debug)]
+pub(crate) struct JsonFileStore {
+    path: PathBuf,
+    data: MemoryStore,
+    dirty: bool,
+}
+
+impl JsonFileStore {
+    pub(crate) fn open(path: PathBuf) -> io::Result<Self> {
+        let data = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(JsonFileStore {
+            path,
+            data: MemoryStore { data },
+            dirty: false,
+        })
+    }
+}
+
+impl StatsStore for JsonFileStore {
+    fn get(&self, id: &PlayerId) -> io::Result<Option<PlayerStats>> {
+        self.data.get(id)
+    }
+
+    fn put(&mut self, id: &PlayerId, stats: &PlayerStats) -> io::Result<()> {
+        self.dirty = true;
+        self.data.put(id, stats)
+    }
+
+    fn all(&self) -> io::Result<Vec<(PlayerId, PlayerStats)>> {
+        self.data.all()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        // Write to temporary file first to not lose everything if interrupted
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.data.data)?)?;
+        fs::rename(&tmp, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |v| v.as_secs())
+}
+
+///
+/// Tracks statistics of connected players on top of the store
+///
+pub(crate) struct PlayerStatsTracker {
+    store: Box<dyn StatsStore>,
+    // Player -> start of the current session (or of the time not yet accounted in playtime)
+    online: HashMap<PlayerId, Instant>,
+}
+
+impl PlayerStatsTracker {
+    pub(crate) fn new(store: Box<dyn StatsStore>) -> Self {
+        PlayerStatsTracker {
+            store,
+            online: HashMap::new(),
+        }
+    }
+
+    fn update<F>(&mut self, id: &PlayerId, f: F)
+    where
+        F: FnOnce(&mut PlayerStats),
+    {
+        let result = self.store.get(id).and_then(|stats| {
+            let mut stats = stats.unwrap_or_default();
+            f(&mut stats);
+            self.store.put(id, &stats)
+        });
+        if let Err(e) = result {
+            warn!("Unable to update stats of {id}: {e:?}");
+        }
+    }
+
+    ///
+    /// Adds time passed since the last call to the playtime of online players
+    ///
+    fn accrue(&mut self, id: &PlayerId, now: Instant) {
+        let Some(since) = self.online.get_mut(id) else {
+            return;
+        };
+        let played = now.saturating_duration_since(*since).as_secs_f64();
+        *since = now;
+        self.update(id, |s| {
+            s.playtime += played;
+            s.last_seen = unix_time();
+        });
+    }
+
+    pub(crate) fn join(&mut self, id: PlayerId) {
+        self.update(&id, |s| {
+            s.sessions += 1;
+            s.last_seen = unix_time();
+        });
+        self.online.insert(id, Instant::now());
+    }
+
+    pub(crate) fn leave(&mut self, id: &PlayerId) {
+        self.accrue(id, Instant::now());
+        self.online.remove(id);
+    }
+
+    pub(crate) fn record_kill(&mut self, killer: &PlayerId, victim: &PlayerId) {
+        if killer != victim {
+            self.update(killer, |s| s.kills += 1);
+        }
+        self.update(victim, |s| s.deaths += 1);
+    }
+
+    pub(crate) fn get(&self, id: &PlayerId) -> Option<PlayerStats> {
+        let mut stats = self.store.get(id).ok().flatten()?;
+        if let Some(since) = self.online.get(id) {
+            stats.playtime += since.elapsed().as_secs_f64();
+        }
+        Some(stats)
+    }
+
+    ///
+    /// Returns up to `count` players with the most kills
+    ///
+    pub(crate) fn top(&self, count: usize) -> Vec<(PlayerId, PlayerStats)> {
+        let mut all = self.store.all().unwrap_or_default();
+        all.sort_by(|a, b| b.1.kills.cmp(&a.1.kills).then_with(|| a.0.cmp(&b.0)));
+        all.truncate(count);
+        all
+    }
+
+    pub(crate) fn save(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let online: Vec<_> = self.online.keys().cloned().collect();
+        for id in online.iter() {
+            self.accrue(id, now);
+        }
+        self.store.flush()
+    }
+}
+
+impl Drop for PlayerStatsTracker {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            warn!("Unable to save player stats: {e:?}");
+        }
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{JsonFileStore, MemoryStore, PlayerId, PlayerStatsTracker, StatsStore};
+
+    #[test]
+    fn tracker() {
+        let mut t = PlayerStatsTracker::new(Box::new(MemoryStore::default()));
+        let alice = PlayerId::from_name(" Alice");
+        let bob = PlayerId::from_name("bob");
+        assert_eq!(PlayerId::from_name("alice"), alice);
+        assert!(t.get(&alice).is_none());
+
+        t.join(alice.clone());
+        t.join(bob.clone());
+        t.record_kill(&alice, &bob);
+        t.record_kill(&alice, &bob);
+        t.record_kill(&bob, &bob);
+        // Pretend Alice joined earlier
+        *t.online.get_mut(&alice).unwrap() -= Duration::from_secs(10);
+        t.leave(&alice);
+        t.join(alice.clone());
+
+        let a = t.get(&alice).unwrap();
+        assert_eq!(2, a.kills);
+        assert_eq!(0, a.deaths);
+        assert_eq!(2, a.sessions);
+        assert!(a.playtime >= 10.0);
+        assert!(a.last_seen > 0);
+        let b = t.get(&bob).unwrap();
+        assert_eq!((0, 3, 1), (b.kills, b.deaths, b.sessions));
+
+        let top: Vec<_> = t.top(5).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(vec![alice, bob], top);
+        assert_eq!(1, t.top(1).len());
+    }
+
+    #[test]
+    fn json_file() {
+        let dir = std::env::temp_dir().join(format!("rg_stats_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stats.json");
+        let id = PlayerId::from_name("player");
+        {
+            let store = JsonFileStore::open(path.clone()).unwrap();
+            let mut t = PlayerStatsTracker::new(Box::new(store));
+            t.join(id.clone());
+            t.record_kill(&id, &PlayerId::from_name("bot"));
+            *t.online.get_mut(&id).unwrap() = Instant::now() - Duration::from_secs(5);
+            // Saved on drop
+        }
+        let store = JsonFileStore::open(path.clone()).unwrap();
+        let stats = store.get(&id).unwrap().unwrap();
+        assert_eq!(1, stats.kills);
+        assert_eq!(1, stats.sessions);
+        assert!(stats.playtime >= 5.0);
+        assert_eq!(2, store.all().unwrap().len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use crate::server::sv_stats::PlayerId;
+
+///
+/// Team number, 1-based. Zero means no team.
+///
+pub(crate) type Team = u8;
+
+pub(crate) const NO_TEAM: Team = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TeamError {
+    Disabled,
+    NoSuchTeam,
+    Unbalanced,
+}
+
+///
+/// Splits players into teams. New players join the smallest team, with balancing enabled
+/// players can't switch to the team which is already bigger than theirs.
+///
+#[derive(Debug)]
+pub(crate) struct Teams {
+    count: Team,
+    balance: bool,
+    members: HashMap<PlayerId, Team>,
+}
+
+impl Teams {
+    pub(crate) fn new(count: u32, balance: bool) -> Self {
+        Teams {
+            count: count.min(Team::MAX as u32) as Team,
+            balance,
+            members: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.count > 0
+    }
+
+    fn size(&self, team: Team) -> usize {
+        self.members.values().filter(|t| **t == team).count()
+    }
+
+    fn smallest(&self) -> Team {
+        (1..=self.count)
+            .min_by_key(|t| self.size(*t))
+            .unwrap_or(NO_TEAM)
+    }
+
+    ///
+    /// Puts player into the smallest team, returns assigned team
+    ///
+    pub(crate) fn assign(&mut self, id: &PlayerId) -> Team {
+        if !self.is_enabled() {
+            return NO_TEAM;
+        }
+        if let Some(team) = self.members.get(id) {
+            return *team;
+        }
+        let team = self.smallest();
+        self.members.insert(id.clone(), team);
+        team
+    }
+
+    pub(crate) fn remove(&mut self, id: &PlayerId) {
+        self.members.remove(id);
+    }
+
+    pub(crate) fn team(&self, id: &PlayerId) -> Team {
+        self.members.get(id).copied().unwrap_or(NO_TEAM)
+    }
+
+    ///
+    /// Moves player to the requested team
+    ///
+    pub(crate) fn switch(&mut self, id: &PlayerId, team: Team) -> Result<(), TeamError> {
+        if !self.is_enabled() {
+            return Err(TeamError::Disabled);
+        }
+        if team == NO_TEAM || team > self.count {
+            return Err(TeamError::NoSuchTeam);
+        }
+        let current = self.team(id);
+        if current == team {
+            return Ok(());
+        }
+        let from = if current == NO_TEAM {
+            0
+        } else {
+            self.size(current) - 1
+        };
+        if self.balance && self.size(team) > from {
+            return Err(TeamError::Unbalanced);
+        }
+        self.members.insert(id.clone(), team);
+        Ok(())
+    }
+
+    ///
+    /// Moves players from bigger teams to smaller ones until sizes differ by one at most (after players left).
+    /// Returns moved players with their new teams.
+    ///
+    pub(crate) fn rebalance(&mut self) -> Vec<(PlayerId, Team)> {
+        let mut moved = Vec::new();
+        if !self.is_enabled() || !self.balance {
+            return moved;
+        }
+        while let (Some(big), Some(small)) = (
+            (1..=self.count).max_by_key(|t| (self.size(*t), Team::MAX - *t)),
+            (1..=self.count).min_by_key(|t| self.size(*t)),
+        ) {
+            if self.size(big) <= self.size(small) + 1 {
+                break;
+            }
+            // Move player with the "highest" id to be deterministic
+            let Some(id) = self
+                .members
+                .iter()
+                .filter(|(_, t)| **t == big)
+                .map(|(id, _)| id.clone())
+                .max()
+            else {
+                break;
+            };
+            self.members.insert(id.clone(), small);
+            moved.push((id, small));
+        }
+        moved
+    }
+
+    ///
+    /// Checks if both players are in the same team, always false without teams
+    ///
+    pub(crate) fn same_team(&self, id: &PlayerId, other: &PlayerId) -> bool {
+        let team = self.team(id);
+        team != NO_TEAM && team == self.team(other)
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use crate::server::sv_stats::PlayerId;
+
+    use super::{TeamError, Teams, NO_TEAM};
+
+    fn id(n: usize) -> PlayerId {
+        PlayerId::from_name(&format!("p{n}"))
+    }
+
+    #[test]
+    fn assign_and_switch() {
+        let mut t = Teams::new(2, true);
+        let teams: Vec<_> = (0..5).map(|i| t.assign(&id(i))).collect();
+        assert_eq!(vec![1, 2, 1, 2, 1], teams);
+        assert_eq!(1, t.assign(&id(0)));
+        assert!(t.same_team(&id(0), &id(2)));
+        assert!(!t.same_team(&id(0), &id(1)));
+
+        // 3 vs 2: moving to the smaller team is fine, to the bigger one is not
+        assert_eq!(Err(TeamError::Unbalanced), t.switch(&id(1), 1));
+        assert_eq!(Ok(()), t.switch(&id(0), 2));
+        assert_eq!(2, t.team(&id(0)));
+        assert_eq!(Err(TeamError::Unbalanced), t.switch(&id(2), 2));
+        assert_eq!(Err(TeamError::NoSuchTeam), t.switch(&id(0), 3));
+
+        let mut off = Teams::new(0, true);
+        assert_eq!(NO_TEAM, off.assign(&id(0)));
+        assert_eq!(Err(TeamError::Disabled), off.switch(&id(0), 1));
+        assert!(!off.same_team(&id(0), &id(0)));
+    }
+
+    #[test]
+    fn rebalance() {
+        let mut t = Teams::new(2, true);
+        for i in 0..6 {
+            t.assign(&id(i));
+        }
+        // Team 2 loses everyone
+        t.remove(&id(1));
+        t.remove(&id(3));
+        t.remove(&id(5));
+        let moved = t.rebalance();
+        assert_eq!(1, moved.len());
+        assert_eq!(2, moved[0].1);
+        assert!(t.rebalance().is_empty());
+
+        let mut free = Teams::new(2, false);
+        for i in 0..4 {
+            free.switch(&id(i), 1).unwrap();
+        }
+        assert!(free.rebalance().is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+///
+/// Handle of the scheduled timer, may be used to cancel it
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct TimerHandle(u64);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Timer<E> {
+    id: u64,
+    due: u64,
+    // Repeat interval in ticks
+    period: Option<u64>,
+    event: E,
+}
+
+///
+/// Hashed timer wheel aligned to server ticks. Instead of callbacks timers carry events of type `E`
+/// which are returned to the owner when due, so the whole state can be stored in save game.
+///
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Timers<E> {
+    tick_duration: Duration,
+    tick: u64,
+    next_id: u64,
+    slots: Vec<Vec<Timer<E>>>,
+    // Timer id -> due tick, to find timer slot on cancel
+    due: HashMap<u64, u64>,
+}
+
+impl<E: Clone> Timers<E> {
+    const SLOTS: usize = 256;
+
+    pub(crate) fn new(tick_duration: Duration) -> Self {
+        assert!(!tick_duration.is_zero());
+        Timers {
+            tick_duration,
+            tick: 0,
+            next_id: 0,
+            slots: (0..Self::SLOTS).map(|_| Vec::new()).collect(),
+            due: HashMap::new(),
+        }
+    }
+
+    ///
+    /// Number of the last processed tick
+    ///
+    pub(crate) fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.due.len()
+    }
+
+    ///
+    /// Converts duration to number of ticks rounding up, so timer never fires earlier than requested
+    ///
+    fn to_ticks(&self, duration: Duration) -> u64 {
+        let ticks = duration.as_nanos().div_ceil(self.tick_duration.as_nanos());
+        (ticks as u64).max(1)
+    }
+
+    fn insert(&mut self, due: u64, period: Option<u64>, event: E) -> TimerHandle {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.slots[due as usize % Self::SLOTS].push(Timer {
+            id,
+            due,
+            period,
+            event,
+        });
+        self.due.insert(id, due);
+        TimerHandle(id)
+    }
+
+    ///
+    /// Fires `event` once after `delay`
+    ///
+    #[cfg(test)]
+    pub(crate) fn schedule_in(&mut self, delay: Duration, event: E) -> TimerHandle {
+        let due = self.tick + self.to_ticks(delay);
+        self.insert(due, None, event)
+    }
+
+    ///
+    /// Fires `event` every `period` until canceled
+    ///
+    pub(crate) fn schedule_every(&mut self, period: Duration, event: E) -> TimerHandle {
+        let period = self.to_ticks(period);
+        self.insert(self.tick + period, Some(period), event)
+    }
+
+    ///
+    /// Returns false if timer has already fired (and was not repeating) or was canceled before
+    ///
+    #[cfg(test)]
+    pub(crate) fn cancel(&mut self, handle: TimerHandle) -> bool {
+        let Some(due) = self.due.remove(&handle.0) else {
+            return false;
+        };
+        let slot = &mut self.slots[due as usize % Self::SLOTS];
+        if let Some(index) = slot.iter().position(|t| t.id == handle.0) {
+            slot.swap_remove(index);
+        }
+        true
+    }
+
+    ///
+    /// Advances wheel by single tick and returns events of due timers in order of scheduling
+    ///
+    pub(crate) fn advance(&mut self) -> Vec<E> {
+        self.tick += 1;
+        let tick = self.tick;
+        let slot = &mut self.slots[tick as usize % Self::SLOTS];
+        // Timers more than one revolution ahead stay in slot
+        let (mut fired, rest): (Vec<_>, Vec<_>) = slot.drain(..).partition(|t| t.due == tick);
+        *slot = rest;
+        fired.sort_unstable_by_key(|t| t.id);
+        let mut result = Vec::with_capacity(fired.len());
+        for timer in fired {
+            match timer.period {
+                Some(period) => {
+                    let due = tick + period;
+                    result.push(timer.event.clone());
+                    self.due.insert(timer.id, due);
+                    self.slots[due as usize % Self::SLOTS].push(Timer { due, ..timer });
+                }
+                None => {
+                    self.due.remove(&timer.id);
+                    result.push(timer.event);
+                }
+            }
+        }
+        result
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Timers;
+
+    fn timers() -> Timers<&'static str> {
+        Timers::new(Duration::from_millis(10))
+    }
+
+    fn run(t: &mut Timers<&'static str>, ticks: u64) -> Vec<(u64, &'static str)> {
+        (0..ticks)
+            .flat_map(|_| {
+                let events = t.advance();
+                let tick = t.tick();
+                events.into_iter().map(move |e| (tick, e))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn once() {
+        let mut t = timers();
+        t.schedule_in(Duration::from_millis(30), "a");
+        // Rounded up to the next tick
+        t.schedule_in(Duration::from_millis(25), "b");
+        t.schedule_in(Duration::ZERO, "c");
+        assert_eq!(3, t.len());
+        assert_eq!(vec![(1, "c"), (3, "a"), (3, "b")], run(&mut t, 10));
+        assert_eq!(0, t.len());
+    }
+
+    #[test]
+    fn repeating_and_cancel() {
+        let mut t = timers();
+        let round = t.schedule_every(Duration::from_millis(20), "round");
+        let respawn = t.schedule_in(Duration::from_millis(50), "respawn");
+        assert!(t.cancel(respawn));
+        assert!(!t.cancel(respawn));
+        assert_eq!(
+            vec![(2, "round"), (4, "round"), (6, "round")],
+            run(&mut t, 7)
+        );
+        assert!(t.cancel(round));
+        assert!(run(&mut t, 10).is_empty());
+    }
+
+    #[test]
+    fn longer_than_wheel() {
+        let mut t = timers();
+        let ticks = 3 * Timers::<&str>::SLOTS as u64 + 5;
+        t.schedule_in(Duration::from_millis(10 * ticks), "late");
+        t.schedule_in(Duration::from_millis(50), "early");
+        assert_eq!(vec![(5, "early"), (ticks, "late")], run(&mut t, ticks + 1));
+    }
+
+    #[test]
+    fn serialization() {
+        let mut t = Timers::new(Duration::from_millis(10));
+        t.schedule_every(Duration::from_millis(30), "tick".to_string());
+        t.schedule_in(Duration::from_millis(50), "once".to_string());
+        t.advance();
+        t.advance();
+        let saved = serde_json::to_string(&t).unwrap();
+        let mut restored: Timers<String> = serde_json::from_str(&saved).unwrap();
+        for _ in 0..6 {
+            assert_eq!(t.advance(), restored.advance());
+        }
+        assert_eq!(t.tick(), restored.tick());
+        assert_eq!(t.len(), restored.len());
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use rg_common::config::VoteConfig;
+use rg_common::ClientId;
+
+///
+/// What is being voted for
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum VoteKind {
+    ///
+    /// Target is resolved by name once when vote is called, name is kept for display only
+    ///
+    Kick {
+        id: ClientId,
+        name: String,
+    },
+    Map(String),
+}
+
+impl VoteKind {
+    ///
+    /// Parses vote request, `find_client` maps player name to the id of connected client
+    ///
+    pub(crate) fn parse<F>(kind: &str, arg: &str, find_client: F) -> Option<Self>
+    where
+        F: FnOnce(&str) -> Option<ClientId>,
+    {
+        if arg.is_empty() {
+            return None;
+        }
+        match kind {
+            "kick" => find_client(arg).map(|id| VoteKind::Kick {
+                id,
+                name: arg.to_owned(),
+            }),
+            "map" => Some(VoteKind::Map(arg.to_owned())),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            VoteKind::Kick { .. } => "kick",
+            VoteKind::Map(_) => "map",
+        }
+    }
+
+    pub(crate) fn arg(&self) -> &str {
+        match self {
+            VoteKind::Kick { name: v, .. } | VoteKind::Map(v) => v,
+        }
+    }
+}
+
+impl Display for VoteKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.name(), self.arg())
+    }
+}
+
+///
+/// Vote related request received from client
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum VoteAction {
+    Call { kind: String, arg: String },
+    Cast(bool),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum VoteError {
+    AlreadyInProgress,
+    NotInProgress,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum VoteResult {
+    Passed(VoteKind),
+    Failed(VoteKind),
+}
+
+///
+/// Snapshot of the current vote for broadcasting to clients
+///
+#[derive(Debug, PartialEq)]
+pub(crate) struct VoteProgress<'a> {
+    pub kind: &'a VoteKind,
+    pub yes: u16,
+    pub no: u16,
+    pub time_left: Duration,
+}
+
+struct Vote<K> {
+    kind: VoteKind,
+    ends_at: Instant,
+    yes: HashSet<K>,
+    no: HashSet<K>,
+}
+
+///
+/// Single active vote with quorum and timeout rules
+///
+pub(crate) struct Votes<K> {
+    quorum: f64,
+    timeout: Duration,
+    current: Option<Vote<K>>,
+}
+
+impl<K: Eq + Hash> Votes<K> {
+    pub(crate) fn new(cfg: &VoteConfig) -> Self {
+        Votes {
+            quorum: cfg.quorum.clamp(0.0, 1.0),
+            timeout: Duration::from_secs_f64(cfg.timeout.max(0.0)),
+            current: None,
+        }
+    }
+
+    ///
+    /// Starts new vote, initiator votes "yes" automatically
+    ///
+    pub(crate) fn start(
+        &mut self,
+        initiator: K,
+        kind: VoteKind,
+        now: Instant,
+    ) -> Result<(), VoteError> {
+        if self.current.is_some() {
+            return Err(VoteError::AlreadyInProgress);
+        }
+        self.current = Some(Vote {
+            kind,
+            ends_at: now + self.timeout,
+            yes: HashSet::from([initiator]),
+            no: HashSet::new(),
+        });
+        Ok(())
+    }
+
+    ///
+    /// Registers client's choice. Client may change their mind while vote is in progress.
+    ///
+    pub(crate) fn cast(&mut self, voter: K, yes: bool) -> Result<(), VoteError> {
+        let vote = self.current.as_mut().ok_or(VoteError::NotInProgress)?;
+        if yes {
+            vote.no.remove(&voter);
+            vote.yes.insert(voter);
+        } else {
+            vote.yes.remove(&voter);
+            vote.no.insert(voter);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Forgets votes of disconnected client
+    ///
+    pub(crate) fn remove_voter(&mut self, voter: &K) {
+        if let Some(vote) = self.current.as_mut() {
+            vote.yes.remove(voter);
+            vote.no.remove(voter);
+        }
+    }
+
+    pub(crate) fn progress(&self, now: Instant) -> Option<VoteProgress<'_>> {
+        self.current.as_ref().map(|v| VoteProgress {
+            kind: &v.kind,
+            yes: v.yes.len() as u16,
+            no: v.no.len() as u16,
+            time_left: v.ends_at.saturating_duration_since(now),
+        })
+    }
+
+    ///
+    /// Checks if vote is decided. Vote passes once "yes" votes exceed quorum of all `voters`,
+    /// fails when that is no longer reachable or on timeout.
+    ///
+    pub(crate) fn update(&mut self, voters: usize, now: Instant) -> Option<VoteResult> {
+        let vote = self.current.as_ref()?;
+        let required = (self.quorum * voters as f64).floor() as usize + 1;
+        let result = if vote.yes.len() >= required {
+            Some(VoteResult::Passed(vote.kind.clone()))
+        } else if voters.saturating_sub(vote.no.len()) < required || now >= vote.ends_at {
+            Some(VoteResult::Failed(vote.kind.clone()))
+        } else {
+            None
+        };
+        if result.is_some() {
+            self.current = None;
+        }
+        result
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use rg_common::config::VoteConfig;
+    use rg_common::ClientId;
+
+    use super::{VoteError, VoteKind, VoteResult, Votes};
+
+    fn bob() -> VoteKind {
+        VoteKind::Kick {
+            id: ClientId::new(7),
+            name: "bob".to_string(),
+        }
+    }
+
+    fn votes() -> Votes<u32> {
+        Votes::new(&VoteConfig {
+            quorum: 0.5,
+            timeout: 30.0,
+        })
+    }
+
+    #[test]
+    fn parse_kind() {
+        let find = |name: &str| (name == "bob").then_some(ClientId::new(7));
+        assert_eq!(Some(bob()), VoteKind::parse("kick", "bob", find));
+        assert_eq!(None, VoteKind::parse("kick", "alice", find));
+        assert_eq!(
+            Some(VoteKind::Map("e1m1".to_string())),
+            VoteKind::parse("map", "e1m1", find)
+        );
+        assert_eq!(None, VoteKind::parse("map", "", find));
+        assert_eq!(None, VoteKind::parse("quit", "now", find));
+    }
+
+    #[test]
+    fn passes_on_quorum() {
+        let mut v = votes();
+        let now = Instant::now();
+        let kind = VoteKind::Map("e1m2".to_string());
+        v.start(1, kind.clone(), now).unwrap();
+        assert_eq!(
+            Err(VoteError::AlreadyInProgress),
+            v.start(2, kind.clone(), now)
+        );
+        v.cast(2, true).unwrap();
+        assert_eq!(None, v.update(5, now));
+        v.cast(3, true).unwrap();
+        assert_eq!(Some(VoteResult::Passed(kind)), v.update(5, now));
+        assert!(v.progress(now).is_none());
+        assert_eq!(Err(VoteError::NotInProgress), v.cast(1, true));
+    }
+
+    #[test]
+    fn fails_when_quorum_unreachable() {
+        let mut v = votes();
+        let now = Instant::now();
+        let kind = bob();
+        v.start(1, kind.clone(), now).unwrap();
+        v.cast(2, false).unwrap();
+        assert_eq!(None, v.update(4, now));
+        v.cast(1, false).unwrap();
+        assert_eq!(Some(VoteResult::Failed(kind)), v.update(4, now));
+    }
+
+    #[test]
+    fn fails_on_timeout() {
+        let mut v = votes();
+        let now = Instant::now();
+        let kind = bob();
+        v.start(1, kind.clone(), now).unwrap();
+        let progress = v.progress(now + Duration::from_secs(10)).unwrap();
+        assert_eq!((1, 0), (progress.yes, progress.no));
+        assert_eq!(Duration::from_secs(20), progress.time_left);
+        assert_eq!(None, v.update(3, now + Duration::from_secs(29)));
+        assert_eq!(
+            Some(VoteResult::Failed(kind)),
+            v.update(3, now + Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn single_player_passes_immediately() {
+        let mut v = votes();
+        let now = Instant::now();
+        let kind = VoteKind::Map("e1m1".to_string());
+        v.start(1, kind.clone(), now).unwrap();
+        assert_eq!(Some(VoteResult::Passed(kind)), v.update(1, now));
+    }
+}
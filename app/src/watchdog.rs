@@ -0,0 +1,260 @@
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use rg_common::config::WatchdogConfig;
+
+use crate::app::App;
+use crate::error::AppError;
+
+///
+/// Counter bumped by the monitored thread once per iteration of its loop. Thread is unregistered when heartbeat is dropped.
+///
+pub(crate) struct Heartbeat {
+    beats: Arc<AtomicU64>,
+}
+
+impl Heartbeat {
+    pub(crate) fn beat(&self) {
+        self.beats.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct Monitored {
+    name: String,
+    /// OS thread id, if known
+    tid: Option<u32>,
+    beats: Weak<AtomicU64>,
+    last_count: u64,
+    last_change: Instant,
+    stalled: bool,
+}
+
+///
+/// Thread which didn't beat for longer than threshold
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Stall {
+    pub name: String,
+    pub tid: Option<u32>,
+    pub silent_for: Duration,
+}
+
+///
+/// Detects hung threads by watching their heartbeat counters
+///
+pub(crate) struct Watchdog {
+    threshold: Duration,
+    threads: Mutex<Vec<Monitored>>,
+}
+
+impl Watchdog {
+    pub(crate) fn new(threshold: Duration) -> Self {
+        Watchdog {
+            threshold,
+            threads: Mutex::new(Vec::new()),
+        }
+    }
+
+    ///
+    /// Starts monitoring of the calling thread
+    ///
+    pub(crate) fn register(&self, name: &str) -> Heartbeat {
+        let beats = Arc::new(AtomicU64::new(0));
+        self.threads.lock().unwrap().push(Monitored {
+            name: name.to_string(),
+            tid: current_tid(),
+            beats: Arc::downgrade(&beats),
+            last_count: 0,
+            last_change: Instant::now(),
+            stalled: false,
+        });
+        Heartbeat { beats }
+    }
+
+    ///
+    /// Returns threads which became stalled since the last check
+    ///
+    pub(crate) fn check(&self, now: Instant) -> Vec<Stall> {
+        let mut result = Vec::new();
+        let mut guard = self.threads.lock().unwrap();
+        guard.retain(|t| t.beats.strong_count() > 0);
+        for t in guard.iter_mut() {
+            let count = t
+                .beats
+                .upgrade()
+                .map_or(t.last_count, |v| v.load(Ordering::Relaxed));
+            if count != t.last_count {
+                if t.stalled {
+                    info!("Thread \"{}\" recovered", t.name);
+                }
+                t.last_count = count;
+                t.last_change = now;
+                t.stalled = false;
+                continue;
+            }
+            let silent_for = now.saturating_duration_since(t.last_change);
+            if !t.stalled && silent_for > self.threshold {
+                t.stalled = true;
+                result.push(Stall {
+                    name: t.name.clone(),
+                    tid: t.tid,
+                    silent_for,
+                });
+            }
+        }
+        result
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn current_tid() -> Option<u32> {
+    // Link target looks like "<pid>/task/<tid>"
+    let link = std::fs::read_link("/proc/thread-self").ok()?;
+    link.file_name()?.to_str()?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn current_tid() -> Option<u32> {
+    None
+}
+
+///
+/// Scheduler state and kernel stack of the thread. Kernel stack is only readable with elevated privileges.
+///
+#[cfg(target_os = "linux")]
+fn thread_dump(tid: u32) -> Option<String> {
+    let dir = format!("/proc/self/task/{tid}");
+    let mut result = String::new();
+    let status = std::fs::read_to_string(format!("{dir}/status")).ok()?;
+    if let Some(state) = status.lines().find(|l| l.starts_with("State:")) {
+        let _ = writeln!(result, "  {state}");
+    }
+    if let Ok(wchan) = std::fs::read_to_string(format!("{dir}/wchan")) {
+        let _ = writeln!(result, "  Waiting in: {wchan}");
+    }
+    if let Ok(stack) = std::fs::read_to_string(format!("{dir}/stack")) {
+        for line in stack.lines() {
+            let _ = writeln!(result, "  {line}");
+        }
+    }
+    Some(result)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_dump(_tid: u32) -> Option<String> {
+    None
+}
+
+///
+/// Human-readable description of stalled threads
+///
+pub(crate) fn crash_report(stalls: &[Stall], uptime: Duration) -> String {
+    let mut result = String::new();
+    let _ = writeln!(
+        result,
+        "Watchdog report, uptime {:.1}s",
+        uptime.as_secs_f64()
+    );
+    for stall in stalls {
+        let _ = writeln!(
+            result,
+            "Thread \"{}\" (tid {}) is not responding for {:.1}s",
+            stall.name,
+            stall.tid.map_or_else(|| "?".to_string(), |v| v.to_string()),
+            stall.silent_for.as_secs_f64()
+        );
+        if let Some(dump) = stall.tid.and_then(thread_dump) {
+            result.push_str(&dump);
+        }
+    }
+    result
+}
+
+///
+/// Starts watchdog thread if enabled by config
+///
+pub(crate) fn watchdog_init(app: &Arc<App>) -> Result<Option<JoinHandle<()>>, AppError> {
+    let cfg: WatchdogConfig = app.config().lock().unwrap().watchdog.clone();
+    if !cfg.enabled {
+        return Ok(None);
+    }
+    let app = app.clone();
+    let exit = app.exit_token().child();
+    let period = app
+        .watchdog()
+        .threshold
+        .div_f64(4.0)
+        .max(Duration::from_millis(100));
+    let handle = thread::Builder::new()
+        .name("watchdog-thread".to_string())
+        .spawn(move || {
+            while !exit.wait_timeout(period) {
+                let stalls = app.watchdog().check(Instant::now());
+                if stalls.is_empty() {
+                    continue;
+                }
+                let report = crash_report(&stalls, app.elapsed());
+                warn!("{report}");
+                if cfg.abort {
+                    let path = app.user_path(&cfg.report_path);
+                    match std::fs::write(&path, &report) {
+                        Ok(_) => error!("Crash report saved to {:?}, aborting", path),
+                        Err(e) => error!("Unable to save crash report to {:?}: {e}", path),
+                    }
+                    log::logger().flush();
+                    std::process::abort();
+                }
+            }
+        })?;
+    Ok(Some(handle))
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{crash_report, Watchdog};
+
+    #[test]
+    fn stall_and_recovery() {
+        let watchdog = Watchdog::new(Duration::from_secs(1));
+        let main = watchdog.register("main");
+        let server = watchdog.register("server");
+        let start = Instant::now();
+        assert!(watchdog.check(start).is_empty());
+
+        main.beat();
+        let stalls = watchdog.check(start + Duration::from_secs(2));
+        assert_eq!(1, stalls.len());
+        assert_eq!("server", stalls[0].name);
+        // Reported once per stall
+        assert!(watchdog.check(start + Duration::from_secs(3)).is_empty());
+
+        server.beat();
+        main.beat();
+        assert!(watchdog.check(start + Duration::from_secs(4)).is_empty());
+
+        // Finished threads are not monitored
+        drop(server);
+        main.beat();
+        assert!(watchdog.check(start + Duration::from_secs(10)).is_empty());
+    }
+
+    #[test]
+    fn report() {
+        let watchdog = Watchdog::new(Duration::ZERO);
+        let _hb = watchdog.register("render");
+        let stalls = watchdog.check(Instant::now() + Duration::from_millis(1500));
+        let report = crash_report(&stalls, Duration::from_secs(60));
+        assert!(report.starts_with("Watchdog report, uptime 60.0s\n"));
+        assert!(report.contains("Thread \"render\""));
+        assert!(report.contains("is not responding for 1.5s"));
+    }
+}
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rg_common::cmd_parser::CmdParser;
+
+fuzz_target!(|data: &str| {
+    let mut parser = CmdParser::new(data);
+    // Each command consumes at least one char, so there can't be more commands than chars
+    let mut count = 0;
+    while parser.next().is_some() {
+        count += 1;
+        assert!(count <= data.len());
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rg_common::expr::{eval, expand};
+
+fn lookup(name: &str) -> Option<String> {
+    match name {
+        "a" => Some("1.5".to_string()),
+        "b" => Some("true".to_string()),
+        "c" => Some("text".to_string()),
+        _ => None,
+    }
+}
+
+fuzz_target!(|data: &str| {
+    let _ = eval(data, lookup);
+    let _ = expand(data, lookup);
+});
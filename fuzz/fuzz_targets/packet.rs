@@ -0,0 +1,18 @@
+#![no_main]
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+use libfuzzer_sys::fuzz_target;
+use rg_net::{check_header, PacketView};
+
+fuzz_target!(|data: &[u8]| {
+    // Same order as receiving endpoint: header first, then messages from the rest of datagram
+    let start = check_header(data).unwrap_or(0);
+    let mut view = PacketView::new(&data[start..], SocketAddr::from((Ipv4Addr::LOCALHOST, 1)));
+    // Each message takes at least one byte
+    let mut count = 0;
+    while view.read().is_some() {
+        count += 1;
+        assert!(count <= data.len());
+    }
+});
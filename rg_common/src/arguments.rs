@@ -1,9 +1,18 @@
 use std::env;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Arguments {
     dedicated: bool,
     windowed: bool,
+    bench_sim: bool,
+    bench_clients: usize,
+    bench_ticks: usize,
+    profile: Option<String>,
+    diff_snap: Option<(String, String)>,
+    bots: usize,
+    connect: Option<String>,
+    bot_rate: f64,
+    bot_secs: f64,
 }
 
 impl Arguments {
@@ -15,21 +24,119 @@ impl Arguments {
         self.windowed
     }
 
+    ///
+    /// Headless simulation benchmark mode
+    ///
+    pub fn bench_sim(&self) -> bool {
+        self.bench_sim
+    }
+
+    ///
+    /// Number of fake clients in benchmark mode
+    ///
+    pub fn bench_clients(&self) -> usize {
+        self.bench_clients
+    }
+
+    ///
+    /// Number of server ticks to measure in benchmark mode
+    ///
+    pub fn bench_ticks(&self) -> usize {
+        self.bench_ticks
+    }
+
+    ///
+    /// User profile directory override
+    ///
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    ///
+    /// World snapshot files to compare in diff mode
+    ///
+    pub fn diff_snap(&self) -> Option<(&str, &str)> {
+        self.diff_snap
+            .as_ref()
+            .map(|(a, b)| (a.as_str(), b.as_str()))
+    }
+
+    ///
+    /// Number of load testing bots, zero if not in bot mode
+    ///
+    pub fn bots(&self) -> usize {
+        self.bots
+    }
+
+    ///
+    /// Address of the external server for bots, in-process server is started if not set
+    ///
+    pub fn connect(&self) -> Option<&str> {
+        self.connect.as_deref()
+    }
+
+    ///
+    /// Inputs per second sent by each bot
+    ///
+    pub fn bot_rate(&self) -> f64 {
+        self.bot_rate
+    }
+
+    ///
+    /// Duration of the bot run in seconds
+    ///
+    pub fn bot_secs(&self) -> f64 {
+        self.bot_secs
+    }
+
     fn has_option(v: &Vec<String>, opt: &str) -> bool {
         v.iter().any(|s| *s == opt)
     }
 
     fn get_value<'a>(v: &'a Vec<String>, opt: &str) -> Option<&'a String> {
-        v.iter().position(|v| v == opt).map(|idx| &v[idx + 1])
+        v.iter()
+            .position(|v| v == opt)
+            .and_then(|idx| v.get(idx + 1))
     }
 
     pub fn parse() -> Self {
         let args: Vec<String> = env::args().collect();
         let dedicated = Self::has_option(&args, "--dedicated") || Self::has_option(&args, "-D");
         let windowed = Self::has_option(&args, "--windowed") || Self::has_option(&args, "-W");
+        let bench_sim = Self::has_option(&args, "--bench-sim");
+        let bench_clients = Self::get_value(&args, "--bench-clients")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(4);
+        let bench_ticks = Self::get_value(&args, "--bench-ticks")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1000);
+        let profile = Self::get_value(&args, "--profile").cloned();
+        let diff_snap = args
+            .iter()
+            .position(|v| v == "--diff-snap")
+            .and_then(|idx| Some((args.get(idx + 1)?.clone(), args.get(idx + 2)?.clone())));
+        let bots = Self::get_value(&args, "--bots")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let connect = Self::get_value(&args, "--connect").cloned();
+        let bot_rate = Self::get_value(&args, "--bot-rate")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30.0);
+        let bot_secs = Self::get_value(&args, "--bot-secs")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60.0);
         Arguments {
             dedicated,
             windowed,
+            bench_sim,
+            bench_clients,
+            bench_ticks,
+            profile,
+            diff_snap,
+            bots,
+            connect,
+            bot_rate,
+            bot_secs,
         }
     }
 }
@@ -0,0 +1,256 @@
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+///
+/// Error returned by [`CancellationToken::check`]
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Operation cancelled!")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+///
+/// Id of the callback registered by [`CancellationToken::on_cancel`]
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallbackId(u64);
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    callbacks: Vec<(u64, Callback)>,
+    children: Vec<Weak<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    state: Mutex<State>,
+    signal: Condvar,
+}
+
+impl Inner {
+    fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        // Run callbacks without lock held, so they are free to use the token
+        let (callbacks, children) = {
+            let mut state = self.state.lock().unwrap();
+            (
+                std::mem::take(&mut state.callbacks),
+                std::mem::take(&mut state.children),
+            )
+        };
+        self.signal.notify_all();
+        for (_, callback) in callbacks {
+            callback();
+        }
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+///
+/// Cooperative cancellation flag shared by the owner of the operation and the code doing the work.
+/// Long-running work is expected to poll [`CancellationToken::is_cancelled`] (or wait on the token instead of sleeping)
+/// and stop promptly. Cancelling the token also cancels all of its children, but not the other way around.
+///
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Creates token which is cancelled together with this one but may also be cancelled on its own
+    ///
+    pub fn child(&self) -> CancellationToken {
+        let child = CancellationToken::new();
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            if !self.is_cancelled() {
+                state.children.retain(|c| c.strong_count() > 0);
+                state.children.push(Arc::downgrade(&child.inner));
+                return child;
+            }
+        }
+        child.cancel();
+        child
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    ///
+    /// Convenience method for use with `?`
+    ///
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    ///
+    /// Registers callback invoked once on cancellation (on the thread calling [`CancellationToken::cancel`]).
+    /// If token is already cancelled callback is invoked immediately.
+    ///
+    pub fn on_cancel<F>(&self, callback: F) -> CallbackId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let id = {
+            let mut state = self.inner.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            if !self.is_cancelled() {
+                state.callbacks.push((id, Box::new(callback)));
+                return CallbackId(id);
+            }
+            id
+        };
+        callback();
+        CallbackId(id)
+    }
+
+    ///
+    /// Unregisters callback, returns false if it was already invoked or removed
+    ///
+    pub fn remove_callback(&self, id: CallbackId) -> bool {
+        let mut state = self.inner.state.lock().unwrap();
+        let before = state.callbacks.len();
+        state.callbacks.retain(|(i, _)| *i != id.0);
+        before != state.callbacks.len()
+    }
+
+    ///
+    /// Blocks for `timeout` or until token is cancelled, whichever comes first.
+    /// Returns true if token is cancelled. Use it instead of `thread::sleep` in worker loops.
+    ///
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.inner.state.lock().unwrap();
+        while !self.is_cancelled() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            state = self.inner.signal.wait_timeout(state, left).unwrap().0;
+        }
+        self.is_cancelled()
+    }
+}
+
+impl Debug for CancellationToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{CancellationToken, Cancelled};
+
+    #[test]
+    fn children() {
+        let root = CancellationToken::new();
+        let child = root.child();
+        let grand_child = child.child();
+        let other = root.child();
+
+        child.cancel();
+        assert!(!root.is_cancelled());
+        assert!(!other.is_cancelled());
+        assert!(grand_child.is_cancelled());
+        assert_eq!(Err(Cancelled), grand_child.check());
+
+        root.cancel();
+        assert!(other.is_cancelled());
+        assert!(root.child().is_cancelled());
+    }
+
+    #[test]
+    fn callbacks() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = || {
+            let calls = Arc::clone(&calls);
+            move || {
+                calls.fetch_add(1, Ordering::Relaxed);
+            }
+        };
+        let token = CancellationToken::new();
+        token.on_cancel(counter());
+        let id = token.on_cancel(counter());
+        let child = token.child();
+        child.on_cancel(counter());
+        // Dropped child is not cancelled, its callbacks are discarded
+        token.child().on_cancel(counter());
+        assert!(token.remove_callback(id));
+        assert!(!token.remove_callback(id));
+
+        token.cancel();
+        token.cancel();
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+        // Late registration is called at once
+        token.on_cancel(counter());
+        assert_eq!(3, calls.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn wait() {
+        let token = CancellationToken::new();
+        assert!(!token.wait_timeout(Duration::from_millis(1)));
+
+        let worker = {
+            let token = token.child();
+            thread::spawn(move || {
+                let started = Instant::now();
+                while !token.wait_timeout(Duration::from_secs(10)) {}
+                started.elapsed()
+            })
+        };
+        thread::sleep(Duration::from_millis(10));
+        token.cancel();
+        assert!(worker.join().unwrap() < Duration::from_secs(5));
+    }
+}
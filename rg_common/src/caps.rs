@@ -0,0 +1,110 @@
+use std::{collections::BTreeMap, fmt::Display, sync::RwLock};
+
+///
+/// State of the optional subsystem
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capability {
+    pub name: String,
+    pub available: bool,
+    /// Version, device name or the reason why subsystem is not available
+    pub detail: String,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            self.name,
+            if self.available { "yes" } else { "no" }
+        )?;
+        if !self.detail.is_empty() {
+            write!(f, " ({})", self.detail)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Registry of optional subsystems. Subsystems register themselves on startup,
+/// other code checks [`Capabilities::is_available`] instead of assuming the subsystem is there.
+/// Unknown capabilities are reported as not available.
+///
+#[derive(Debug, Default)]
+pub struct Capabilities {
+    data: RwLock<BTreeMap<String, Capability>>,
+}
+
+impl Capabilities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Registers or updates capability
+    ///
+    pub fn set<D: Into<String>>(&self, name: &str, available: bool, detail: D) {
+        self.data.write().unwrap().insert(
+            name.to_owned(),
+            Capability {
+                name: name.to_owned(),
+                available,
+                detail: detail.into(),
+            },
+        );
+    }
+
+    pub fn is_available(&self, name: &str) -> bool {
+        self.data
+            .read()
+            .unwrap()
+            .get(name)
+            .is_some_and(|c| c.available)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Capability> {
+        self.data.read().unwrap().get(name).cloned()
+    }
+
+    ///
+    /// Returns all registered capabilities sorted by name
+    ///
+    pub fn list(&self) -> Vec<Capability> {
+        self.data.read().unwrap().values().cloned().collect()
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use super::Capabilities;
+
+    #[test]
+    fn registry() {
+        let caps = Capabilities::new();
+        assert!(!caps.is_available("audio"));
+        assert!(caps.get("audio").is_none());
+
+        caps.set("gamepad", true, "Xbox controller");
+        caps.set("audio", false, "no output device");
+        assert!(caps.is_available("gamepad"));
+        assert!(!caps.is_available("audio"));
+        assert_eq!(
+            vec![
+                "audio: no (no output device)",
+                "gamepad: yes (Xbox controller)"
+            ],
+            caps.list()
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        caps.set("audio", true, "");
+        assert!(caps.is_available("audio"));
+        assert_eq!("audio: yes", caps.get("audio").unwrap().to_string());
+    }
+}
@@ -5,11 +5,18 @@ use std::{
     sync::{Arc, Mutex, PoisonError, Weak},
 };
 
+use crate::{ExecContext, Symbol};
+
 ///
 ///
 ///
 
-type CmdMap = HashMap<String, Weak<dyn CommandWrapper>>;
+struct CmdEntry {
+    wrapper: Weak<dyn CommandWrapper>,
+    access: ExecContext,
+}
+
+type CmdMap = HashMap<Symbol, CmdEntry>;
 
 #[derive(Default)]
 pub struct CommandRegistry {
@@ -18,22 +25,52 @@ pub struct CommandRegistry {
 
 impl CommandRegistry {
     pub fn register(&self, name: &str, wrapper: Weak<dyn CommandWrapper>) -> Result<(), CmdError> {
+        self.register_with_access(name, wrapper, ExecContext::Local)
+    }
+
+    ///
+    /// Registers command which may be invoked from `access` or more trusted context
+    ///
+    pub fn register_with_access(
+        &self,
+        name: &str,
+        wrapper: Weak<dyn CommandWrapper>,
+        access: ExecContext,
+    ) -> Result<(), CmdError> {
+        let name = Symbol::intern(name);
         let mut guard = self.data.lock()?;
-        if let Some(v) = guard.get(name) {
-            if v.strong_count() > 0 {
+        if let Some(v) = guard.get(&name) {
+            if v.wrapper.strong_count() > 0 {
                 return Err(CmdError::AlreadyExists);
             }
         }
-        guard.insert(name.to_owned(), wrapper);
+        guard.insert(name, CmdEntry { wrapper, access });
         Ok(())
     }
 
     pub fn invoke(&self, args: Vec<String>) -> Result<(), CmdError> {
+        self.invoke_as(ExecContext::Local, args)
+    }
+
+    ///
+    /// Invokes command on behalf of `context`. Commands not allowed for that context are reported as denied.
+    ///
+    pub fn invoke_as(&self, context: ExecContext, args: Vec<String>) -> Result<(), CmdError> {
         if args.len() < 1 {
             return Err(CmdError::ArgNumberMismatch(1));
         }
+        // Unknown names are not interned, so typos don't grow the symbol table
+        let Some(name) = Symbol::lookup(&args[0]) else {
+            return Err(CmdError::NotFound);
+        };
         let guard = self.data.lock()?;
-        if let Some(wrapper) = guard.get(&args[0]).and_then(|weak| weak.upgrade()) {
+        let Some(entry) = guard.get(&name) else {
+            return Err(CmdError::NotFound);
+        };
+        if let Some(wrapper) = entry.wrapper.upgrade() {
+            if !context.allows(entry.access) {
+                return Err(CmdError::AccessDenied);
+            }
             drop(guard);
             return wrapper.invoke(&args[1..]);
         }
@@ -41,20 +78,22 @@ impl CommandRegistry {
     }
 }
 
-pub trait CommandWrapper {
+pub trait CommandWrapper: Send + Sync {
     fn invoke(&self, args: &[String]) -> Result<(), CmdError>;
 }
 
+type Handler = Box<dyn Fn(&[String]) -> Result<(), CmdError> + Send + Sync>;
+
 struct Holder {
-    handler: Box<dyn Fn(&[String]) -> Result<(), CmdError>>,
+    handler: Handler,
 }
 
 struct Holder1<A: FromStr + 'static> {
-    handler: Box<dyn Fn(A) -> Result<(), CmdError>>,
+    handler: Box<dyn Fn(A) -> Result<(), CmdError> + Send + Sync>,
 }
 
 struct Holder2<A: FromStr, B: FromStr> {
-    handler: Box<dyn Fn(A, B) -> Result<(), CmdError>>,
+    handler: Box<dyn Fn(A, B) -> Result<(), CmdError> + Send + Sync>,
 }
 
 fn parse<T: FromStr>(value: &str) -> Result<T, CmdError> {
@@ -102,6 +141,8 @@ pub enum CmdError {
     ArgNumberMismatch(i8),
     NotFound,
     LockPoisoned,
+    AccessDenied,
+    Failed(String),
 }
 
 impl std::error::Error for CmdError {}
@@ -124,6 +165,12 @@ impl Display for CmdError {
             CmdError::LockPoisoned => {
                 write!(f, "Lock poisoned!")
             }
+            CmdError::AccessDenied => {
+                write!(f, "Access denied!")
+            }
+            CmdError::Failed(s) => {
+                write!(f, "Command failed: {s}")
+            }
         }
     }
 }
@@ -140,6 +187,7 @@ impl<T> From<PoisonError<T>> for CmdError {
 pub struct CommandBuilder<'a> {
     registry: &'a CommandRegistry,
     handlers: Vec<Arc<dyn CommandWrapper>>,
+    access: ExecContext,
 }
 
 pub struct CommandOwner {
@@ -151,32 +199,42 @@ impl CommandBuilder<'_> {
         CommandBuilder {
             registry,
             handlers: Vec::new(),
+            access: ExecContext::Local,
         }
     }
 
+    ///
+    /// Sets the least trusted context allowed to invoke commands added after this call
+    ///
+    pub fn access(&mut self, access: ExecContext) -> &mut Self {
+        self.access = access;
+        self
+    }
+
     pub fn add<F>(&mut self, name: &str, handler: F)
     where
-        F: Fn(&[String]) -> Result<(), CmdError> + 'static,
+        F: Fn(&[String]) -> Result<(), CmdError> + Send + Sync + 'static,
     {
         self.try_add(name, handler).unwrap();
     }
 
     pub fn try_add<F>(&mut self, name: &str, handler: F) -> Result<(), CmdError>
     where
-        F: Fn(&[String]) -> Result<(), CmdError> + 'static,
+        F: Fn(&[String]) -> Result<(), CmdError> + Send + Sync + 'static,
     {
         let h = Holder {
             handler: Box::new(handler),
         };
         let a = Arc::new(h);
-        self.registry.register(name, Arc::downgrade(&a) as _)?;
+        self.registry
+            .register_with_access(name, Arc::downgrade(&a) as _, self.access)?;
         self.handlers.push(a);
         Ok(())
     }
 
     pub fn add1<A, F>(&mut self, name: &str, handler: F)
     where
-        F: Fn(A) -> Result<(), CmdError> + 'static,
+        F: Fn(A) -> Result<(), CmdError> + Send + Sync + 'static,
         A: FromStr + 'static,
     {
         self.try_add1(name, handler).unwrap();
@@ -184,21 +242,22 @@ impl CommandBuilder<'_> {
 
     pub fn try_add1<A, F>(&mut self, name: &str, handler: F) -> Result<(), CmdError>
     where
-        F: Fn(A) -> Result<(), CmdError> + 'static,
+        F: Fn(A) -> Result<(), CmdError> + Send + Sync + 'static,
         A: FromStr + 'static,
     {
         let h = Holder1 {
             handler: Box::new(handler),
         };
         let a = Arc::new(h);
-        self.registry.register(name, Arc::downgrade(&a) as _)?;
+        self.registry
+            .register_with_access(name, Arc::downgrade(&a) as _, self.access)?;
         self.handlers.push(a);
         Ok(())
     }
 
     pub fn add2<A, B, F>(&mut self, name: &str, handler: F)
     where
-        F: Fn(A, B) -> Result<(), CmdError> + 'static,
+        F: Fn(A, B) -> Result<(), CmdError> + Send + Sync + 'static,
         A: FromStr + 'static,
         B: FromStr + 'static,
     {
@@ -207,7 +266,7 @@ impl CommandBuilder<'_> {
 
     pub fn try_add2<A, B, F>(&mut self, name: &str, handler: F) -> Result<(), CmdError>
     where
-        F: Fn(A, B) -> Result<(), CmdError> + 'static,
+        F: Fn(A, B) -> Result<(), CmdError> + Send + Sync + 'static,
         A: FromStr + 'static,
         B: FromStr + 'static,
     {
@@ -215,7 +274,8 @@ impl CommandBuilder<'_> {
             handler: Box::new(handler),
         };
         let a = Arc::new(h);
-        self.registry.register(name, Arc::downgrade(&a) as _)?;
+        self.registry
+            .register_with_access(name, Arc::downgrade(&a) as _, self.access)?;
         self.handlers.push(a);
         Ok(())
     }
@@ -237,7 +297,7 @@ mod test {
         Arc,
     };
 
-    use crate::{commands::CmdError, CommandRegistry};
+    use crate::{commands::CmdError, CommandRegistry, ExecContext};
 
     use super::CommandBuilder;
 
@@ -309,6 +369,40 @@ mod test {
         ));
     }
 
+    #[test]
+    fn access() {
+        let reg = CommandRegistry::default();
+        let mut b = CommandBuilder::new(&reg);
+        b.add("quit", |_: &[String]| Ok(()));
+        b.access(ExecContext::Rcon).add1("kick", |_: String| Ok(()));
+        b.access(ExecContext::Remote)
+            .add("say", |_: &[String]| Ok(()));
+        let _cmds = b.build();
+
+        let invoke_as =
+            |ctx, args: &[&str]| reg.invoke_as(ctx, args.iter().map(|v| v.to_string()).collect());
+        invoke_as(ExecContext::Local, &["quit"]).unwrap();
+        invoke_as(ExecContext::Local, &["kick", "bob"]).unwrap();
+        invoke_as(ExecContext::Rcon, &["kick", "bob"]).unwrap();
+        invoke_as(ExecContext::Remote, &["say", "hi"]).unwrap();
+        assert!(matches!(
+            invoke_as(ExecContext::Remote, &["quit"]),
+            Err(CmdError::AccessDenied)
+        ));
+        assert!(matches!(
+            invoke_as(ExecContext::Rcon, &["quit"]),
+            Err(CmdError::AccessDenied)
+        ));
+        assert!(matches!(
+            invoke_as(ExecContext::Remote, &["kick", "bob"]),
+            Err(CmdError::AccessDenied)
+        ));
+        assert!(matches!(
+            invoke_as(ExecContext::Remote, &["nope"]),
+            Err(CmdError::NotFound)
+        ));
+    }
+
     #[test]
     fn recusrive() {
         let reg = Arc::new(CommandRegistry::default());
@@ -1,15 +1,42 @@
 use std::io::Read;
+use std::{env, fs};
 
+use log::info;
 use serde::{Deserialize, Serialize};
 
 use rg_common::files;
 use rg_common::files::Files;
+use rg_common::{Context, ErrorKind, ErrorReport};
 use rg_macros::VarBag;
 
-#[derive(Debug, Serialize, Deserialize, VarBag)]
+#[derive(Debug, Default, Serialize, Deserialize, VarBag)]
 pub struct Config {
     pub server: ServerConfig,
     pub client: ClientConfig,
+    #[serde(default)]
+    pub watchdog: WatchdogConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct WatchdogConfig {
+    pub enabled: bool,
+    /// Seconds without heartbeat after which thread is considered hung
+    pub threshold: f64,
+    /// Write crash report and abort the process on stall
+    pub abort: bool,
+    /// Crash report file relative to profile dir
+    pub report_path: String,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            enabled: true,
+            threshold: 10.0,
+            abort: false,
+            report_path: "crash_report.txt".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, VarBag)]
@@ -19,18 +46,451 @@ pub struct ServerConfig {
     pub bound_to: Option<String>,
     pub key_bits: usize,
     pub password: Option<String>,
+    /// Outgoing bytes per second limit for each client, 0 - no limit
+    #[serde(default)]
+    pub max_rate: u32,
+    /// Add CRC32C of the payload to outgoing datagrams
+    #[serde(default)]
+    pub checksum: bool,
+    #[serde(default)]
+    pub vote: VoteConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// "sandbox" or "deathmatch"
+    #[serde(default = "default_game_mode")]
+    pub game_mode: String,
+    #[serde(default)]
+    pub deathmatch: DeathmatchConfig,
+    #[serde(default)]
+    pub teams: TeamsConfig,
+    #[serde(default)]
+    pub autosave: AutosaveConfig,
+    #[serde(default)]
+    pub congestion: CongestionConfig,
+    #[serde(default)]
+    pub match_log: MatchLogConfig,
+    #[serde(default)]
+    pub rotation: RotationConfig,
+}
+
+fn default_game_mode() -> String {
+    "sandbox".to_string()
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: "127.0.0.1:0".to_string(),
+            bound_to: None,
+            key_bits: 512,
+            password: None,
+            max_rate: 0,
+            checksum: false,
+            vote: VoteConfig::default(),
+            metrics: MetricsConfig::default(),
+            stats: StatsConfig::default(),
+            auth: AuthConfig::default(),
+            game_mode: default_game_mode(),
+            deathmatch: DeathmatchConfig::default(),
+            teams: TeamsConfig::default(),
+            autosave: AutosaveConfig::default(),
+            congestion: CongestionConfig::default(),
+            match_log: MatchLogConfig::default(),
+            rotation: RotationConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, VarBag)]
+pub struct VoteConfig {
+    /// Fraction of connected clients which must vote "yes" (strictly more than that)
+    pub quorum: f64,
+    /// Vote duration in seconds
+    pub timeout: f64,
+}
+
+impl Default for VoteConfig {
+    fn default() -> Self {
+        VoteConfig {
+            quorum: 0.5,
+            timeout: 30.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    /// Dump interval in seconds
+    pub interval: f64,
+    /// Output file relative to profile dir, "csv" or "json" (one object per line) format is chosen by extension
+    pub path: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: false,
+            interval: 10.0,
+            path: "server_metrics.csv".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct StatsConfig {
+    /// Save player statistics to file, off by default. Otherwise they are kept in memory until server stops.
+    pub enabled: bool,
+    /// Player statistics file relative to profile dir
+    pub path: String,
+    /// Interval in seconds between saves
+    pub save_interval: f64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        StatsConfig {
+            enabled: false,
+            path: "player_stats.json".to_string(),
+            save_interval: 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct DeathmatchConfig {
+    /// Frags to win, 0 - no limit
+    pub frag_limit: u32,
+    /// Match duration in seconds, 0 - no limit
+    pub time_limit: f64,
+}
+
+impl Default for DeathmatchConfig {
+    fn default() -> Self {
+        DeathmatchConfig {
+            frag_limit: 20,
+            time_limit: 600.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct TeamsConfig {
+    /// Number of teams, 0 - no teams
+    pub count: u32,
+    /// Keep team sizes within one player of each other
+    pub balance: bool,
+}
+
+impl Default for TeamsConfig {
+    fn default() -> Self {
+        TeamsConfig {
+            count: 0,
+            balance: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct CongestionConfig {
+    /// Adapt send rate of each client to packet loss and round trip
+    pub enabled: bool,
+    /// Bytes per second the rate is never reduced below
+    pub min_rate: u32,
+    /// Bytes per second for connections without rate limit, 0 - don't adapt such connections
+    pub max_rate: u32,
+}
+
+impl Default for CongestionConfig {
+    fn default() -> Self {
+        CongestionConfig {
+            enabled: true,
+            min_rate: 4000,
+            max_rate: 131072,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct AutosaveConfig {
+    pub enabled: bool,
+    /// Interval in seconds between saves
+    pub interval: f64,
+    /// Save file name prefix relative to profile dir, slot number and extension are appended
+    pub path: String,
+    /// Number of saves to keep, the oldest one is removed
+    pub keep: u32,
+    /// Restore the latest save on startup
+    pub restore: bool,
+}
+
+impl Default for AutosaveConfig {
+    fn default() -> Self {
+        AutosaveConfig {
+            enabled: true,
+            interval: 300.0,
+            path: "autosave".to_string(),
+            keep: 3,
+            restore: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct MatchLogConfig {
+    /// Write gameplay events (joins, leaves, chat) to JSON lines file, new file for each match
+    pub enabled: bool,
+    /// Directory relative to profile dir
+    pub path: String,
+    /// Number of match logs to keep, the oldest one is removed
+    pub keep: u32,
+}
+
+impl Default for MatchLogConfig {
+    fn default() -> Self {
+        MatchLogConfig {
+            enabled: false,
+            path: "match_logs".to_string(),
+            keep: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct RotationConfig {
+    /// Map names separated by commas, played in order and then from the start. Empty - no rotation.
+    pub maps: String,
+    /// Seconds between the end of the match and the next map
+    pub intermission: f64,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        RotationConfig {
+            maps: String::new(),
+            intermission: 10.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct AuthConfig {
+    /// "offline" - name and server password, "ticket" - ticket signed by external auth service
+    pub mode: String,
+    /// Secret shared with auth service to check ticket signatures
+    pub ticket_secret: Option<String>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            mode: "offline".to_string(),
+            ticket_secret: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, VarBag)]
-pub struct ClientConfig {}
+pub struct ClientConfig {
+    /// Player name shown to others
+    #[serde(default = "default_player_name")]
+    pub name: String,
+    /// Outgoing bytes per second limit requested by client (applies both ways), 0 - no limit
+    #[serde(default)]
+    pub rate: u32,
+    /// Add CRC32C of the payload to outgoing datagrams
+    #[serde(default)]
+    pub checksum: bool,
+    /// Hex encoded ticket from auth service, required by servers in "ticket" auth mode
+    #[serde(default)]
+    pub ticket: Option<String>,
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+}
+
+fn default_player_name() -> String {
+    "Player".to_string()
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            name: default_player_name(),
+            rate: 0,
+            checksum: false,
+            ticket: None,
+            reconnect: ReconnectConfig::default(),
+        }
+    }
+}
+
+///
+/// Makes player name from OS user name: only letters, digits, `_`, `-` and `.` are kept, up to 16 characters
+///
+fn player_name(user: &str) -> Option<String> {
+    let name: String = user
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .take(16)
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Comments written above tables of the generated config
+const SECTION_DOCS: &[(&str, &str)] = &[
+    ("server", "Server, used by both dedicated and listen server"),
+    ("server.vote", "Player votes"),
+    (
+        "server.metrics",
+        "Periodic dump of server performance counters",
+    ),
+    ("server.stats", "Persistent player statistics"),
+    ("server.auth", "Client authentication"),
+    ("server.deathmatch", "Deathmatch game mode"),
+    ("server.teams", "Teams"),
+    ("server.autosave", "Periodic save of the server state"),
+    (
+        "server.congestion",
+        "Adaptation of send rate to the link quality",
+    ),
+    (
+        "server.match_log",
+        "Log of gameplay events, one file per match",
+    ),
+    ("server.rotation", "Map rotation"),
+    ("client", "Client"),
+    ("client.reconnect", "Reconnection after connection loss"),
+    ("watchdog", "Detection of hung threads"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, VarBag)]
+pub struct ReconnectConfig {
+    /// Reconnect automatically if connection to server is lost
+    pub enabled: bool,
+    /// Seconds to try resuming the session with token before falling back to full handshake
+    pub window: f64,
+    /// Max number of full handshake attempts, 0 - unlimited
+    pub attempts: u32,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        ReconnectConfig {
+            enabled: true,
+            window: 10.0,
+            attempts: 5,
+        }
+    }
+}
 
 impl Config {
+    ///
+    /// Defaults for the new profile, player name is taken from OS user
+    ///
+    pub fn detect() -> Self {
+        let mut cfg = Config::default();
+        if let Some(name) = ["USER", "USERNAME"]
+            .iter()
+            .find_map(|v| env::var(v).ok())
+            .and_then(|v| player_name(&v))
+        {
+            cfg.client.name = name;
+        }
+        cfg
+    }
+
+    ///
+    /// Serializes config with comment above each table
+    ///
+    pub fn to_commented_toml(&self) -> Result<String, ErrorReport> {
+        let text = toml::to_string(self).map_err(|e| ErrorReport::new(ErrorKind::Config, e))?;
+        let mut result = String::from(
+            "# Generated on the first run. Missing keys take built-in defaults,\n\
+             # most values may also be changed in console.\n",
+        );
+        for line in text.lines() {
+            let table = line.strip_prefix('[').and_then(|v| v.strip_suffix(']'));
+            if let Some((_, doc)) = table.and_then(|t| SECTION_DOCS.iter().find(|(n, _)| *n == t)) {
+                result.push_str("\n# ");
+                result.push_str(doc);
+                result.push('\n');
+            }
+            result.push_str(line);
+            result.push('\n');
+        }
+        Ok(result)
+    }
+
+    ///
+    /// Loads config. If it's not found anywhere (the first run), default one is written to the profile dir.
+    ///
+    pub fn load_or_create(name: &str, files: &mut files::AppFiles) -> Result<Self, ErrorReport> {
+        if files.open(name).is_some() {
+            return Self::try_load(name, files);
+        }
+        let path = files.user_path(name);
+        info!("Config not found, writing defaults to {path:?}");
+        let cfg = Self::detect();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {dir:?}"))?;
+        }
+        fs::write(&path, cfg.to_commented_toml()?).with_context(|| format!("writing {path:?}"))?;
+        Ok(cfg)
+    }
+
     pub fn load(name: &str, files: &mut files::AppFiles) -> Self {
-        let mut cfg = files.open(name).expect("Unable to load config!");
+        Self::try_load(name, files).unwrap_or_else(|e| panic!("Unable to load config: {e:?}"))
+    }
+
+    pub fn try_load(name: &str, files: &mut files::AppFiles) -> Result<Self, ErrorReport> {
+        let mut cfg = files
+            .open(name)
+            .ok_or_else(|| ErrorReport::msg(ErrorKind::Config, "file not found"))
+            .with_context(|| format!("opening \"{name}\""))?;
         let mut tmp = String::new();
-        let read = cfg
-            .read_to_string(&mut tmp)
-            .expect("Unable to read from file!");
-        toml::from_str(&tmp).expect("Unable to deserialize!")
+        cfg.read_to_string(&mut tmp)
+            .with_context(|| format!("reading \"{name}\""))?;
+        toml::from_str(&tmp)
+            .map_err(|e| ErrorReport::new(ErrorKind::Config, e))
+            .with_context(|| format!("parsing \"{name}\""))
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use super::{player_name, Config};
+
+    #[test]
+    fn names() {
+        assert_eq!(Some("john.doe".to_string()), player_name("john.doe"));
+        assert_eq!(Some("AliceB".to_string()), player_name("Alice B"));
+        assert_eq!(
+            Some("abcdefghijklmnop".to_string()),
+            player_name("abcdefghijklmnopqrst")
+        );
+        assert_eq!(None, player_name(" /"));
+    }
+
+    #[test]
+    fn generated() {
+        let mut cfg = Config::default();
+        cfg.client.name = "bob".to_string();
+        let text = cfg.to_commented_toml().unwrap();
+        // Every table is documented
+        let lines: Vec<_> = text.lines().collect();
+        for (i, line) in lines.iter().enumerate().filter(|(_, v)| v.starts_with('[')) {
+            assert!(lines[i - 1].starts_with("# "), "No comment for {line}");
+        }
+        let parsed: Config = toml::from_str(&text).unwrap();
+        assert_eq!("bob", parsed.client.name);
+        assert_eq!(None, parsed.server.password);
+        assert_eq!(text, parsed.to_commented_toml().unwrap());
     }
 }
@@ -0,0 +1,43 @@
+///
+/// Origin of the command or variable change, ordered from the least trusted to the most trusted.
+/// Commands and variables declare the least trusted context allowed to use them,
+/// by default that is [`ExecContext::Local`].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ExecContext {
+    /// Request from networked client
+    Remote,
+    /// Server remote console
+    Rcon,
+    /// Local console, config files and the code itself
+    #[default]
+    Local,
+}
+
+impl ExecContext {
+    ///
+    /// Checks if this context may use something requiring `required` level
+    ///
+    #[inline]
+    pub fn allows(&self, required: ExecContext) -> bool {
+        *self >= required
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use super::ExecContext;
+
+    #[test]
+    fn allows() {
+        assert!(ExecContext::Local.allows(ExecContext::Local));
+        assert!(ExecContext::Local.allows(ExecContext::Remote));
+        assert!(ExecContext::Rcon.allows(ExecContext::Remote));
+        assert!(!ExecContext::Rcon.allows(ExecContext::Local));
+        assert!(!ExecContext::Remote.allows(ExecContext::Rcon));
+        assert_eq!(ExecContext::Local, ExecContext::default());
+    }
+}
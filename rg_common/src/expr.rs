@@ -0,0 +1,423 @@
+use std::{error::Error, fmt::Display, iter::Peekable, str::Chars};
+
+///
+/// Result of the expression
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExprValue {
+    Number(f64),
+    Bool(bool),
+}
+
+impl ExprValue {
+    fn number(self) -> Result<f64, ExprError> {
+        match self {
+            ExprValue::Number(v) => Ok(v),
+            ExprValue::Bool(_) => Err(ExprError::TypeMismatch),
+        }
+    }
+
+    fn bool(self) -> Result<bool, ExprError> {
+        match self {
+            ExprValue::Bool(v) => Ok(v),
+            ExprValue::Number(_) => Err(ExprError::TypeMismatch),
+        }
+    }
+}
+
+impl Display for ExprValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // Integral values are printed without fraction so they can be assigned to integer variables
+            ExprValue::Number(v) if v.fract() == 0.0 && v.abs() < 1e15 => {
+                write!(f, "{}", *v as i64)
+            }
+            ExprValue::Number(v) => write!(f, "{v}"),
+            ExprValue::Bool(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprError {
+    UnexpectedEnd,
+    UnexpectedChar(char),
+    UnknownVariable(String),
+    NotANumber(String),
+    TypeMismatch,
+    DivisionByZero,
+    Unclosed,
+    TooDeep,
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExprError::UnexpectedEnd => write!(f, "Unexpected end of expression"),
+            ExprError::UnexpectedChar(ch) => write!(f, "Unexpected character: {ch}"),
+            ExprError::UnknownVariable(name) => write!(f, "Unknown variable: {name}"),
+            ExprError::NotANumber(name) => write!(f, "Variable is not a number: {name}"),
+            ExprError::TypeMismatch => write!(f, "Type mismatch"),
+            ExprError::DivisionByZero => write!(f, "Division by zero"),
+            ExprError::Unclosed => write!(f, "Unclosed $( in command line"),
+            ExprError::TooDeep => write!(f, "Expression is nested too deep"),
+        }
+    }
+}
+
+impl Error for ExprError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: [&str; 16] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, ExprError> {
+    let mut result = Vec::new();
+    let mut chars: Peekable<Chars> = expr.chars().peekable();
+    while let Some(&ch) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else if ch.is_ascii_digit() || ch == '.' {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                s.push(c);
+                chars.next();
+            }
+            let v = s.parse().map_err(|_| ExprError::UnexpectedChar(ch))?;
+            result.push(Token::Number(v));
+        } else if ch.is_alphabetic() || ch == '_' {
+            let mut s = String::new();
+            while let Some(&c) = chars
+                .peek()
+                .filter(|c| c.is_alphanumeric() || **c == '_' || **c == ':')
+            {
+                s.push(c);
+                chars.next();
+            }
+            result.push(Token::Ident(s));
+        } else {
+            chars.next();
+            let next = chars.peek().copied();
+            let op = OPERATORS
+                .iter()
+                .find(|op| {
+                    let mut it = op.chars();
+                    it.next() == Some(ch) && it.next().is_none_or(|c| Some(c) == next)
+                })
+                .ok_or(ExprError::UnexpectedChar(ch))?;
+            if op.len() == 2 {
+                chars.next();
+            }
+            result.push(match *op {
+                "(" => Token::Open,
+                ")" => Token::Close,
+                op => Token::Op(op),
+            });
+        }
+    }
+    Ok(result)
+}
+
+///
+/// Recursive descent parser evaluating expression as it goes
+///
+struct Evaluator<'a, F> {
+    tokens: &'a [Token],
+    pos: usize,
+    depth: usize,
+    lookup: F,
+}
+
+impl<F> Evaluator<'_, F>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    ///
+    /// Limits recursion, so untrusted input can't overflow the stack
+    ///
+    const MAX_DEPTH: usize = 64;
+
+    fn enter(&mut self) -> Result<(), ExprError> {
+        self.depth += 1;
+        if self.depth > Self::MAX_DEPTH {
+            return Err(ExprError::TooDeep);
+        }
+        Ok(())
+    }
+
+    fn peek_op(&self, ops: &[&str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn or(&mut self) -> Result<ExprValue, ExprError> {
+        let mut left = self.and()?;
+        while self.peek_op(&["||"]).is_some() {
+            self.pos += 1;
+            let right = self.and()?;
+            left = ExprValue::Bool(left.bool()? || right.bool()?);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<ExprValue, ExprError> {
+        let mut left = self.comparison()?;
+        while self.peek_op(&["&&"]).is_some() {
+            self.pos += 1;
+            let right = self.comparison()?;
+            left = ExprValue::Bool(left.bool()? && right.bool()?);
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<ExprValue, ExprError> {
+        let left = self.sum()?;
+        let Some(op) = self.peek_op(&["==", "!=", "<", "<=", ">", ">="]) else {
+            return Ok(left);
+        };
+        self.pos += 1;
+        let right = self.sum()?;
+        let result = match (op, left, right) {
+            ("==", l, r) => l == r,
+            ("!=", l, r) => l != r,
+            ("<", l, r) => l.number()? < r.number()?,
+            ("<=", l, r) => l.number()? <= r.number()?,
+            (">", l, r) => l.number()? > r.number()?,
+            (_, l, r) => l.number()? >= r.number()?,
+        };
+        Ok(ExprValue::Bool(result))
+    }
+
+    fn sum(&mut self) -> Result<ExprValue, ExprError> {
+        let mut left = self.product()?;
+        while let Some(op) = self.peek_op(&["+", "-"]) {
+            self.pos += 1;
+            let (l, r) = (left.number()?, self.product()?.number()?);
+            left = ExprValue::Number(if op == "+" { l + r } else { l - r });
+        }
+        Ok(left)
+    }
+
+    fn product(&mut self) -> Result<ExprValue, ExprError> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek_op(&["*", "/", "%"]) {
+            self.pos += 1;
+            let (l, r) = (left.number()?, self.unary()?.number()?);
+            if op != "*" && r == 0.0 {
+                return Err(ExprError::DivisionByZero);
+            }
+            left = ExprValue::Number(match op {
+                "*" => l * r,
+                "/" => l / r,
+                _ => l % r,
+            });
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<ExprValue, ExprError> {
+        self.enter()?;
+        let result = self.unary_inner();
+        self.depth -= 1;
+        result
+    }
+
+    fn unary_inner(&mut self) -> Result<ExprValue, ExprError> {
+        match self.peek_op(&["-", "!"]) {
+            Some("-") => {
+                self.pos += 1;
+                Ok(ExprValue::Number(-self.unary()?.number()?))
+            }
+            Some(_) => {
+                self.pos += 1;
+                Ok(ExprValue::Bool(!self.unary()?.bool()?))
+            }
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<ExprValue, ExprError> {
+        let token = self.tokens.get(self.pos).ok_or(ExprError::UnexpectedEnd)?;
+        self.pos += 1;
+        match token {
+            Token::Number(v) => Ok(ExprValue::Number(*v)),
+            Token::Ident(name) if name == "true" => Ok(ExprValue::Bool(true)),
+            Token::Ident(name) if name == "false" => Ok(ExprValue::Bool(false)),
+            Token::Ident(name) => {
+                let value =
+                    (self.lookup)(name).ok_or_else(|| ExprError::UnknownVariable(name.clone()))?;
+                match value.as_str() {
+                    "true" => Ok(ExprValue::Bool(true)),
+                    "false" => Ok(ExprValue::Bool(false)),
+                    v => v
+                        .parse()
+                        .map(ExprValue::Number)
+                        .map_err(|_| ExprError::NotANumber(name.clone())),
+                }
+            }
+            Token::Open => {
+                let result = self.or()?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Close) => {
+                        self.pos += 1;
+                        Ok(result)
+                    }
+                    Some(_) => Err(ExprError::UnexpectedChar(')')),
+                    None => Err(ExprError::UnexpectedEnd),
+                }
+            }
+            Token::Close => Err(ExprError::UnexpectedChar(')')),
+            Token::Op(op) => Err(ExprError::UnexpectedChar(
+                op.chars().next().unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+///
+/// Evaluates arithmetic (`+ - * / %`), comparison (`== != < <= > >=`) and logical (`&& || !`) expression.
+/// Identifiers are resolved with `lookup`, which is normally [`crate::VarRegistry::try_get_value`].
+///
+pub fn eval<F>(expr: &str, lookup: F) -> Result<ExprValue, ExprError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let tokens = tokenize(expr)?;
+    let mut evaluator = Evaluator {
+        tokens: &tokens,
+        pos: 0,
+        depth: 0,
+        lookup,
+    };
+    let result = evaluator.or()?;
+    match tokens.get(evaluator.pos) {
+        None => Ok(result),
+        Some(Token::Close) => Err(ExprError::UnexpectedChar(')')),
+        Some(_) => Err(ExprError::UnexpectedChar(
+            expr.chars().last().unwrap_or_default(),
+        )),
+    }
+}
+
+///
+/// Replaces each `$(expr)` in command line with the value of expression
+///
+pub fn expand<F>(line: &str, lookup: F) -> Result<String, ExprError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find("$(") {
+        result.push_str(&rest[..start]);
+        let body = &rest[start + 2..];
+        let mut depth = 1;
+        let end = body
+            .char_indices()
+            .find(|(_, ch)| {
+                match ch {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map(|(i, _)| i)
+            .ok_or(ExprError::Unclosed)?;
+        result.push_str(&eval(&body[..end], &lookup)?.to_string());
+        rest = &body[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use super::{eval, expand, ExprError, ExprValue};
+
+    fn vars(name: &str) -> Option<String> {
+        match name {
+            "cl::base_fov" => Some("90".to_string()),
+            "speed" => Some("2.5".to_string()),
+            "flag" => Some("true".to_string()),
+            "name" => Some("player".to_string()),
+            _ => None,
+        }
+    }
+
+    fn num(expr: &str) -> f64 {
+        match eval(expr, vars).unwrap() {
+            ExprValue::Number(v) => v,
+            v => panic!("Not a number: {v}"),
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(7.0, num("1 + 2 * 3"));
+        assert_eq!(9.0, num("(1 + 2) * 3"));
+        assert_eq!(-1.0, num("-(4 - 3)"));
+        assert_eq!(1.0, num("7 % 3"));
+        assert_eq!(100.0, num("cl::base_fov + 10"));
+        assert_eq!(5.0, num("speed*2"));
+        assert_eq!(Err(ExprError::DivisionByZero), eval("1 / 0", vars));
+    }
+
+    #[test]
+    fn comparison() {
+        assert_eq!(Ok(ExprValue::Bool(true)), eval("cl::base_fov >= 90", vars));
+        assert_eq!(Ok(ExprValue::Bool(false)), eval("1 + 1 != 2", vars));
+        assert_eq!(
+            Ok(ExprValue::Bool(true)),
+            eval("flag && !(speed < 1) || false", vars)
+        );
+        assert_eq!(Err(ExprError::TypeMismatch), eval("flag + 1", vars));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(ExprError::UnknownVariable("fov".to_string())),
+            eval("fov + 1", vars)
+        );
+        assert_eq!(
+            Err(ExprError::NotANumber("name".to_string())),
+            eval("name * 2", vars)
+        );
+        assert_eq!(Err(ExprError::UnexpectedEnd), eval("(1 + 2", vars));
+        assert_eq!(Err(ExprError::UnexpectedChar(')')), eval("1 + 2)", vars));
+        assert_eq!(Err(ExprError::UnexpectedChar('#')), eval("1 # 2", vars));
+        let deep = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+        assert_eq!(Err(ExprError::TooDeep), eval(&deep, vars));
+        assert_eq!(Err(ExprError::TooDeep), eval(&"-".repeat(10_000), vars));
+        assert_eq!(Ok(ExprValue::Number(1.0)), eval("((((1))))", vars));
+    }
+
+    #[test]
+    fn expand_line() {
+        assert_eq!(
+            "set fov 100",
+            expand("set fov $(cl::base_fov + 10)", vars).unwrap()
+        );
+        assert_eq!(
+            "a 1.25 b true",
+            expand("a $((speed) / 2) b $(1 < 2)", vars).unwrap()
+        );
+        assert_eq!("no exprs", expand("no exprs", vars).unwrap());
+        assert_eq!(Err(ExprError::Unclosed), expand("set fov $(1 + (2)", vars));
+    }
+}
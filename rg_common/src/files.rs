@@ -1,6 +1,6 @@
 use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 use std::path::{Path, PathBuf};
 use std::{env, fs};
 
@@ -55,23 +55,102 @@ impl Display for FileRoot {
     }
 }
 
+const APP_NAME: &str = "rustground";
+
+///
+/// Per-user writable directory for configs, saves, logs and screenshots.
+/// Uses `--profile` argument if passed, otherwise platform data dir
+/// (`$XDG_DATA_HOME` on Linux, `%APPDATA%` on Windows, `~/Library/Application Support` on macOS).
+/// Directory is created if missing.
+///
+pub fn profile_dir(args: &Arguments) -> PathBuf {
+    let path = match args.profile() {
+        Some(path) => PathBuf::from(path),
+        None => dirs::data_dir()
+            .map(|dir| dir.join(APP_NAME))
+            .unwrap_or_else(|| PathBuf::from(".")),
+    };
+    if let Err(e) = fs::create_dir_all(&path) {
+        error!("Unable to create profile dir: {:?}: {:?}", &path, e);
+    }
+    path
+}
+
+///
+/// Moves files from legacy location to profile. Files already present in profile are left intact.
+/// Returns number of moved entries.
+///
+fn migrate(from: &Path, to: &Path) -> Result<usize, Error> {
+    if !from.is_dir() || same_dir(from, to) {
+        return Ok(0);
+    }
+    let mut moved = 0;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if target.exists() {
+            debug!("Skipping migration of {:?}: already exists", entry.path());
+            continue;
+        }
+        if fs::rename(entry.path(), &target).is_err() {
+            // Different file system, fall back to copy
+            if !entry.file_type()?.is_file() {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    format!("Unable to move {:?}", entry.path()),
+                ));
+            }
+            fs::copy(entry.path(), &target)?;
+            fs::remove_file(entry.path())?;
+        }
+        moved += 1;
+    }
+    // Remove legacy folder only if everything is moved
+    if fs::read_dir(from)?.next().is_none() {
+        fs::remove_dir(from)?;
+    }
+    Ok(moved)
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
 pub struct AppFiles {
+    profile: PathBuf,
     roots: Vec<FileRoot>,
 }
 
 impl AppFiles {
     pub fn new(args: &Arguments) -> Self {
-        let current_dir = env::current_dir().unwrap_or(PathBuf::from("."));
-        let mut folders: Vec<PathBuf> = Vec::new();
+        let profile = profile_dir(args);
         if let Some(home) = dirs::home_dir() {
-            let app_home = home.join(".rustground");
-            if let Err(e) = fs::create_dir_all(&app_home) {
-                error!("Unable to create app home: {:?}: {:?}", &app_home, e);
+            let legacy = home.join(".rustground");
+            match migrate(&legacy, &profile) {
+                Ok(0) => {}
+                Ok(n) => info!("Moved {n} file(s) from {:?} to {:?}", legacy, profile),
+                Err(e) => warn!("Failed to migrate {:?}: {e}", legacy),
             }
-            folders.push(app_home);
         }
-        folders.push(current_dir.join("base"));
-        folders.push(current_dir.join("base/resources"));
+        Self::with_profile(profile)
+    }
+
+    ///
+    /// Uses passed directory as profile as is, legacy files are not migrated.
+    ///
+    pub fn with_profile(profile: PathBuf) -> Self {
+        let current_dir = env::current_dir().unwrap_or(PathBuf::from("."));
+        if let Err(e) = fs::create_dir_all(&profile) {
+            error!("Unable to create profile dir: {:?}: {:?}", &profile, e);
+        }
+        let folders = [
+            profile.clone(),
+            current_dir.join("base"),
+            current_dir.join("base/resources"),
+        ];
         let roots = folders
             .iter()
             .map(|path| {
@@ -90,7 +169,18 @@ impl AppFiles {
             .map(Result::unwrap)
             .collect();
 
-        AppFiles { roots }
+        AppFiles { profile, roots }
+    }
+
+    pub fn profile_dir(&self) -> &Path {
+        &self.profile
+    }
+
+    ///
+    /// Resolves path of the writable user file. Relative paths are resolved against profile dir.
+    ///
+    pub fn user_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.profile.join(path)
     }
 }
 
@@ -99,3 +189,42 @@ impl Files for AppFiles {
         self.roots.iter_mut().find_map(|r| r.open(path.as_ref()))
     }
 }
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use super::migrate;
+
+    #[test]
+    fn migrate_legacy() {
+        let root = env::temp_dir().join(format!("rg_files_test_{}", process::id()));
+        let legacy = root.join("legacy");
+        let profile = root.join("profile");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::create_dir_all(&profile).unwrap();
+        fs::write(legacy.join("config.toml"), "old").unwrap();
+        fs::write(legacy.join("save1.dat"), "save").unwrap();
+        fs::write(profile.join("config.toml"), "new").unwrap();
+
+        assert_eq!(1, migrate(&legacy, &profile).unwrap());
+        assert_eq!(
+            "new",
+            fs::read_to_string(profile.join("config.toml")).unwrap()
+        );
+        assert_eq!(
+            "save",
+            fs::read_to_string(profile.join("save1.dat")).unwrap()
+        );
+        assert!(legacy.join("config.toml").exists());
+        // Nothing to do second time or for the same folder
+        assert_eq!(0, migrate(&legacy, &profile).unwrap());
+        assert_eq!(0, migrate(&profile, &profile).unwrap());
+        assert_eq!(0, migrate(&root.join("missing"), &profile).unwrap());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
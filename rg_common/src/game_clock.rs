@@ -0,0 +1,280 @@
+use std::{
+    ops::{Add, Sub},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Tick;
+
+///
+/// Point in time measured from the start of the clock. Unlike [`Instant`] can be stored in save games
+/// and replays. Microsecond precision keeps it exact and platform independent.
+///
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct GameTime(u64);
+
+impl GameTime {
+    pub const ZERO: GameTime = GameTime(0);
+
+    pub fn from_duration(value: Duration) -> Self {
+        GameTime(value.as_micros().min(u64::MAX as u128) as u64)
+    }
+
+    pub fn from_secs_f64(secs: f64) -> Self {
+        Self::from_duration(Duration::from_secs_f64(secs.max(0.0)))
+    }
+
+    pub fn as_micros(&self) -> u64 {
+        self.0
+    }
+
+    pub fn as_secs_f64(&self) -> f64 {
+        self.0 as f64 / 1_000_000.0
+    }
+
+    ///
+    /// Time since the start of the clock
+    ///
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_micros(self.0)
+    }
+
+    ///
+    /// Time passed since `earlier`, zero if `earlier` is actually later
+    ///
+    pub fn since(&self, earlier: GameTime) -> Duration {
+        Duration::from_micros(self.0.saturating_sub(earlier.0))
+    }
+}
+
+impl Add<Duration> for GameTime {
+    type Output = GameTime;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        GameTime(self.0.saturating_add(GameTime::from_duration(rhs).0))
+    }
+}
+
+impl Sub for GameTime {
+    type Output = Duration;
+
+    fn sub(self, rhs: GameTime) -> Self::Output {
+        self.since(rhs)
+    }
+}
+
+///
+/// Serializable part of the [`GameClock`]
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GameClockState {
+    pub time: GameTime,
+    pub tick: Tick,
+    pub timescale: f64,
+    pub paused: bool,
+}
+
+///
+/// Single source of time for the game logic. Game time follows wall time scaled by `timescale` and stops
+/// while paused, fixed ticks are counted off game time so simulation stays deterministic.
+///
+#[derive(Debug)]
+pub struct GameClock {
+    tick_interval: Duration,
+    timescale: f64,
+    paused: bool,
+    time: GameTime,
+    tick: Tick,
+    /// Game time not yet consumed by fixed ticks
+    accumulated: Duration,
+    delta: Duration,
+    started_at: Instant,
+    updated_at: Instant,
+}
+
+impl GameClock {
+    pub fn new(tick_interval: Duration) -> Self {
+        let now = Instant::now();
+        GameClock {
+            tick_interval,
+            timescale: 1.0,
+            paused: false,
+            time: GameTime::ZERO,
+            tick: Tick::default(),
+            accumulated: Duration::ZERO,
+            delta: Duration::ZERO,
+            started_at: now,
+            updated_at: now,
+        }
+    }
+
+    ///
+    /// Advances clock to `now`, returns number of fixed ticks due
+    ///
+    pub fn update(&mut self, now: Instant) -> u32 {
+        let wall = now.saturating_duration_since(self.updated_at);
+        self.updated_at = now;
+        let dt = if self.paused {
+            Duration::ZERO
+        } else {
+            wall.mul_f64(self.timescale)
+        };
+        self.advance(dt)
+    }
+
+    ///
+    /// Advances game time by `dt` regardless of pause and timescale (used by replays), returns number of fixed ticks due
+    ///
+    pub fn advance(&mut self, dt: Duration) -> u32 {
+        self.delta = dt;
+        self.time = self.time + dt;
+        if self.tick_interval.is_zero() {
+            return 0;
+        }
+        self.accumulated += dt;
+        let mut ticks = 0;
+        while self.accumulated >= self.tick_interval {
+            self.accumulated -= self.tick_interval;
+            ticks += 1;
+        }
+        self.tick = self.tick + ticks as u64;
+        ticks
+    }
+
+    ///
+    /// Game time passed by the last update
+    ///
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn time(&self) -> GameTime {
+        self.time
+    }
+
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    pub fn tick_interval(&self) -> Duration {
+        self.tick_interval
+    }
+
+    ///
+    /// Real time since the clock was created, not affected by pause and timescale
+    ///
+    pub fn wall_time(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn timescale(&self) -> f64 {
+        self.timescale
+    }
+
+    ///
+    /// Sets game time speed, negative or invalid values are treated as zero
+    ///
+    pub fn set_timescale(&mut self, value: f64) {
+        self.timescale = if value.is_finite() {
+            value.max(0.0)
+        } else {
+            0.0
+        };
+    }
+
+    pub fn state(&self) -> GameClockState {
+        GameClockState {
+            time: self.time,
+            tick: self.tick,
+            timescale: self.timescale,
+            paused: self.paused,
+        }
+    }
+
+    ///
+    /// Restores game time from the save game, wall time is not affected
+    ///
+    pub fn restore(&mut self, state: &GameClockState) {
+        self.time = state.time;
+        self.tick = state.tick;
+        self.set_timescale(state.timescale);
+        self.paused = state.paused;
+        self.accumulated = Duration::ZERO;
+        self.delta = Duration::ZERO;
+        self.updated_at = Instant::now();
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::Tick;
+
+    use super::{GameClock, GameTime};
+
+    #[test]
+    fn game_time() {
+        let t = GameTime::from_secs_f64(1.5);
+        assert_eq!(1_500_000, t.as_micros());
+        assert_eq!(Duration::from_millis(500), t - GameTime::from_secs_f64(1.0));
+        assert_eq!(Duration::ZERO, GameTime::ZERO - t);
+        assert_eq!(2.0, (t + Duration::from_millis(500)).as_secs_f64());
+        let text = toml::to_string(&super::GameClockState {
+            time: t,
+            tick: Tick::new(3),
+            timescale: 1.0,
+            paused: false,
+        })
+        .unwrap();
+        assert!(text.contains("time = 1500000"));
+    }
+
+    #[test]
+    fn fixed_ticks() {
+        let mut clock = GameClock::new(Duration::from_millis(10));
+        assert_eq!(0, clock.advance(Duration::from_millis(5)));
+        assert_eq!(1, clock.advance(Duration::from_millis(5)));
+        assert_eq!(3, clock.advance(Duration::from_millis(35)));
+        assert_eq!(Tick::new(4), clock.tick());
+        assert_eq!(Duration::from_millis(45), clock.time().as_duration());
+    }
+
+    #[test]
+    fn pause_and_timescale() {
+        let mut clock = GameClock::new(Duration::from_millis(10));
+        let start = Instant::now();
+        clock.restore(&clock.state());
+        clock.updated_at = start;
+        clock.set_timescale(0.5);
+        assert_eq!(5, clock.update(start + Duration::from_millis(100)));
+        assert_eq!(Duration::from_millis(50), clock.delta());
+
+        clock.set_paused(true);
+        assert_eq!(0, clock.update(start + Duration::from_millis(200)));
+        assert_eq!(Duration::from_millis(50), clock.time().as_duration());
+
+        let saved = clock.state();
+        clock.set_paused(false);
+        clock.set_timescale(-1.0);
+        assert_eq!(0.0, clock.timescale());
+        clock.advance(Duration::from_secs(1));
+        clock.restore(&saved);
+        assert_eq!(saved, clock.state());
+    }
+}
@@ -1,19 +1,53 @@
 extern crate self as rg_common;
 
 pub use arguments::Arguments;
+pub use cancel::CancellationToken;
+pub use caps::Capabilities;
 pub use commands::CommandRegistry;
+pub use context::ExecContext;
+pub use expr::ExprError;
+pub use expr::ExprValue;
 pub use files::AppFiles;
+pub use game_clock::GameClock;
+pub use game_clock::GameClockState;
+pub use game_clock::GameTime;
+pub use net_ids::ClientId;
+pub use net_ids::SessionId;
+pub use net_ids::Tick;
+pub use progress::Progress;
+pub use report::Context;
+pub use report::ErrorKind;
+pub use report::ErrorReport;
+pub use stopwatch::FrameTimer;
+pub use stopwatch::Stopwatch;
+pub use symbol::Symbol;
+pub use ttl_cache::Memoized;
+pub use ttl_cache::TtlCache;
 pub use vars::FromStrMutator;
+pub use vars::PendingChange;
 pub use vars::VarBag;
 pub use vars::VarRegistry;
+pub use vars::VarSnapshot;
+pub use vars::VarTransaction;
 pub use vars::Variable;
 pub use vars::VariableError;
 
 pub mod arguments;
+pub mod cancel;
+pub mod caps;
 pub mod cmd_parser;
 pub mod commands;
 pub mod config;
+pub mod context;
+pub mod expr;
 pub mod files;
+pub mod game_clock;
+pub mod net_ids;
+pub mod progress;
+pub mod report;
+pub mod stopwatch;
+pub mod symbol;
+pub mod ttl_cache;
 mod v_from;
 mod v_from_str;
 mod vars;
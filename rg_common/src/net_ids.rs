@@ -0,0 +1,125 @@
+use std::{
+    fmt::Display,
+    ops::{Add, Sub},
+};
+
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+macro_rules! id_type {
+    ($(#[$meta:meta])* $name:ident($ty:ty)) => {
+        $(#[$meta])*
+        #[derive(
+            Debug,
+            Default,
+            Clone,
+            Copy,
+            PartialEq,
+            Eq,
+            PartialOrd,
+            Ord,
+            Hash,
+            Serialize,
+            Deserialize,
+            Encode,
+            Decode,
+        )]
+        #[serde(transparent)]
+        pub struct $name($ty);
+
+        impl $name {
+            pub const fn new(value: $ty) -> Self {
+                $name(value)
+            }
+
+            pub const fn get(&self) -> $ty {
+                self.0
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                Display::fmt(&self.0, f)
+            }
+        }
+    };
+}
+
+id_type!(
+    ///
+    /// Short number of the connected client, unique while server is running. Used in console commands.
+    ///
+    ClientId(u32)
+);
+
+id_type!(
+    ///
+    /// Random secret issued by server, client presents it to resume the session
+    ///
+    SessionId(u64)
+);
+
+id_type!(
+    ///
+    /// Number of the fixed simulation step
+    ///
+    Tick(u64)
+);
+
+impl ClientId {
+    ///
+    /// Returns this id and advances it to the next one
+    ///
+    pub fn take_next(&mut self) -> ClientId {
+        let result = *self;
+        self.0 += 1;
+        result
+    }
+}
+
+impl Add<u64> for Tick {
+    type Output = Tick;
+
+    fn add(self, rhs: u64) -> Self::Output {
+        Tick(self.0.saturating_add(rhs))
+    }
+}
+
+impl Sub for Tick {
+    type Output = u64;
+
+    ///
+    /// Number of ticks between two, zero if `rhs` is later
+    ///
+    fn sub(self, rhs: Tick) -> Self::Output {
+        self.0.saturating_sub(rhs.0)
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use super::{ClientId, SessionId, Tick};
+
+    #[test]
+    fn ids() {
+        let mut next = ClientId::new(1);
+        assert_eq!(ClientId::new(1), next.take_next());
+        assert_eq!(ClientId::new(2), next);
+        assert_eq!("2", next.to_string());
+
+        assert_eq!(Tick::new(12), Tick::new(10) + 2);
+        assert_eq!(2, Tick::new(12) - Tick::new(10));
+        assert_eq!(0, Tick::new(10) - Tick::new(12));
+
+        let token = SessionId::new(0xdead_beef);
+        let bytes = bitcode::encode(&token);
+        assert_eq!(token, bitcode::decode(&bytes).unwrap());
+        assert_eq!(
+            "3735928559",
+            toml::Value::try_from(token).unwrap().to_string()
+        );
+    }
+}
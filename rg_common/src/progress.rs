@@ -0,0 +1,242 @@
+use std::{
+    fmt::{Debug, Formatter},
+    sync::{Arc, Mutex},
+};
+
+use crate::cancel::{CancellationToken, Cancelled};
+
+/// (weight, stage)
+type Stages = Vec<(f32, Arc<Mutex<State>>)>;
+
+#[derive(Default)]
+struct State {
+    ratio: f32,
+    stage: String,
+    done: bool,
+    children: Stages,
+}
+
+///
+/// Progress of the long operation: completed ratio, label of the current stage and cancellation token.
+/// Worker updates it, UI polls [`Progress::ratio`] and [`Progress::stage`] to show loading bar.
+/// Multi-stage operation creates sub-progress for each stage with [`Progress::stage_of`],
+/// then its ratio is the weighted sum of stages.
+///
+#[derive(Clone, Default)]
+pub struct Progress {
+    state: Arc<Mutex<State>>,
+    token: CancellationToken,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Creates progress of the operation cancelled with the given token (or its parent)
+    ///
+    pub fn with_token(token: CancellationToken) -> Self {
+        Progress {
+            state: Arc::default(),
+            token,
+        }
+    }
+
+    ///
+    /// Adds stage taking `weight` share of the whole operation relative to other stages
+    ///
+    pub fn stage_of(&self, weight: f32, label: &str) -> Progress {
+        let child = Progress::with_token(self.token.child());
+        child.set_stage(label);
+        self.state
+            .lock()
+            .unwrap()
+            .children
+            .push((weight.max(0.0), Arc::clone(&child.state)));
+        child
+    }
+
+    ///
+    /// Sets completed ratio, it's clamped to 0..1. Ignored for progress with stages.
+    ///
+    pub fn set(&self, ratio: f32) {
+        self.state.lock().unwrap().ratio = ratio.clamp(0.0, 1.0);
+    }
+
+    ///
+    /// Sets ratio from the number of processed items
+    ///
+    pub fn set_count(&self, done: usize, total: usize) {
+        self.set(if total == 0 {
+            1.0
+        } else {
+            done as f32 / total as f32
+        });
+    }
+
+    pub fn set_stage(&self, label: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.stage.clear();
+        state.stage.push_str(label);
+    }
+
+    ///
+    /// Marks operation (or stage) completed, its ratio becomes 1
+    ///
+    pub fn finish(&self) {
+        self.state.lock().unwrap().done = true;
+    }
+
+    pub fn is_done(&self) -> bool {
+        Self::is_node_done(&self.state)
+    }
+
+    fn is_node_done(state: &Mutex<State>) -> bool {
+        let (done, children) = Self::read(state);
+        done || (!children.is_empty() && children.iter().all(|(_, c)| Self::is_node_done(c)))
+    }
+
+    // Children are copied out, so child lock is never taken while parent one is held
+    fn read(state: &Mutex<State>) -> (bool, Stages) {
+        let guard = state.lock().unwrap();
+        (guard.done, guard.children.clone())
+    }
+
+    pub fn ratio(&self) -> f32 {
+        Self::node_ratio(&self.state)
+    }
+
+    fn node_ratio(state: &Mutex<State>) -> f32 {
+        let (done, children) = Self::read(state);
+        if done {
+            return 1.0;
+        }
+        let total: f32 = children.iter().map(|(w, _)| w).sum();
+        if total <= 0.0 {
+            return state.lock().unwrap().ratio;
+        }
+        children
+            .iter()
+            .map(|(w, c)| w * Self::node_ratio(c))
+            .sum::<f32>()
+            / total
+    }
+
+    ///
+    /// Labels from this progress down to the first unfinished stage, e.g. "Loading level: textures"
+    ///
+    pub fn stage(&self) -> String {
+        let mut labels = Vec::new();
+        let mut next = Some(Arc::clone(&self.state));
+        while let Some(state) = next.take() {
+            let stage = state.lock().unwrap().stage.clone();
+            if !stage.is_empty() {
+                labels.push(stage);
+            }
+            let (done, children) = Self::read(&state);
+            if !done {
+                next = children
+                    .into_iter()
+                    .find(|(_, c)| !Self::is_node_done(c))
+                    .map(|(_, c)| c);
+            }
+        }
+        labels.join(": ")
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    ///
+    /// Requests cancellation of the operation including all stages
+    ///
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    ///
+    /// Convenience method for use with `?` in the worker
+    ///
+    pub fn check(&self) -> Result<(), Cancelled> {
+        self.token.check()
+    }
+}
+
+impl Debug for Progress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("ratio", &self.ratio())
+            .field("stage", &self.stage())
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::Progress;
+    use crate::cancel::Cancelled;
+
+    #[test]
+    fn single() {
+        let p = Progress::new();
+        assert_eq!(0.0, p.ratio());
+        p.set(1.5);
+        assert_eq!(1.0, p.ratio());
+        p.set_count(1, 4);
+        assert_eq!(0.25, p.ratio());
+        p.set_stage("Downloading");
+        assert_eq!("Downloading", p.stage());
+        assert!(!p.is_done());
+        p.finish();
+        assert!(p.is_done());
+        assert_eq!(1.0, p.ratio());
+    }
+
+    #[test]
+    fn stages() {
+        let p = Progress::new();
+        p.set_stage("Loading level");
+        let geometry = p.stage_of(1.0, "geometry");
+        let textures = p.stage_of(3.0, "textures");
+        assert_eq!("Loading level: geometry", p.stage());
+
+        geometry.set(0.5);
+        assert_eq!(0.125, p.ratio());
+        geometry.finish();
+        assert_eq!("Loading level: textures", p.stage());
+        let worker = {
+            let textures = textures.clone();
+            thread::spawn(move || textures.set_count(1, 3))
+        };
+        worker.join().unwrap();
+        assert_eq!(0.5, p.ratio());
+        assert!(!p.is_done());
+
+        textures.finish();
+        assert!(p.is_done());
+        assert_eq!(1.0, p.ratio());
+        assert_eq!("Loading level", p.stage());
+    }
+
+    #[test]
+    fn cancel() {
+        let p = Progress::new();
+        let stage = p.stage_of(1.0, "baking");
+        assert_eq!(Ok(()), stage.check());
+        p.cancel();
+        assert_eq!(Err(Cancelled), stage.check());
+        assert!(p.stage_of(1.0, "late").is_cancelled());
+    }
+}
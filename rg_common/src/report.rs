@@ -0,0 +1,223 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+
+use crate::commands::CmdError;
+use crate::vars::{VarRegistryError, VariableError};
+
+///
+/// Subsystem the error originated from
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Io,
+    Config,
+    Command,
+    Variable,
+    Network,
+    Render,
+    Other,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+///
+/// Error with kind, chain of context messages and backtrace (captured only if enabled by `RUST_BACKTRACE`).
+/// Any error type converts into report with `?`, more context is added by [`Context`] methods.
+///
+pub struct ErrorReport {
+    kind: ErrorKind,
+    source: Box<dyn Error + Send + Sync + 'static>,
+    // Innermost context first
+    context: Vec<String>,
+    backtrace: Backtrace,
+}
+
+impl ErrorReport {
+    pub fn new<E>(kind: ErrorKind, error: E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync + 'static>>,
+    {
+        ErrorReport {
+            kind,
+            source: error.into(),
+            context: Vec::new(),
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    ///
+    /// Creates report from plain message
+    ///
+    pub fn msg<M: Display>(kind: ErrorKind, message: M) -> Self {
+        Self::new(kind, message.to_string())
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    ///
+    /// Overrides kind guessed on conversion
+    ///
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn context(&self) -> &[String] {
+        &self.context
+    }
+
+    pub fn source(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self.source.as_ref()
+    }
+
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self.backtrace.status() {
+            BacktraceStatus::Captured => Some(&self.backtrace),
+            _ => None,
+        }
+    }
+
+    fn push_context(mut self, context: String) -> Self {
+        self.context.push(context);
+        self
+    }
+}
+
+///
+/// Guesses kind of error by its type
+///
+fn classify(error: &(dyn Error + 'static)) -> ErrorKind {
+    if error.is::<io::Error>() {
+        ErrorKind::Io
+    } else if error.is::<CmdError>() {
+        ErrorKind::Command
+    } else if error.is::<VarRegistryError>() || error.is::<VariableError>() {
+        ErrorKind::Variable
+    } else {
+        ErrorKind::Other
+    }
+}
+
+impl<E> From<E> for ErrorReport
+where
+    E: Error + Send + Sync + 'static,
+{
+    fn from(error: E) -> Self {
+        let kind = classify(&error);
+        Self::new(kind, error)
+    }
+}
+
+impl Display for ErrorReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} error: {}", self.kind, self.source)?;
+        let mut source = self.source.source();
+        while let Some(cause) = source {
+            write!(f, "\n  caused by: {cause}")?;
+            source = cause.source();
+        }
+        for context in self.context.iter() {
+            write!(f, "\n  while {context}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for ErrorReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)?;
+        if let Some(backtrace) = self.backtrace() {
+            write!(f, "\n\nBacktrace:\n{backtrace}")?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Adds context to errors of any result
+///
+pub trait Context<T> {
+    fn context<C: Display>(self, context: C) -> Result<T, ErrorReport>;
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T, ErrorReport>;
+}
+
+impl<T, E> Context<T> for Result<T, E>
+where
+    E: Error + Send + Sync + 'static,
+{
+    fn context<C: Display>(self, context: C) -> Result<T, ErrorReport> {
+        self.map_err(|e| ErrorReport::from(e).push_context(context.to_string()))
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T, ErrorReport> {
+        self.map_err(|e| ErrorReport::from(e).push_context(f().to_string()))
+    }
+}
+
+impl<T> Context<T> for Result<T, ErrorReport> {
+    fn context<C: Display>(self, context: C) -> Result<T, ErrorReport> {
+        self.map_err(|e| e.push_context(context.to_string()))
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T, ErrorReport> {
+        self.map_err(|e| e.push_context(f().to_string()))
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use crate::commands::CmdError;
+
+    use super::{Context, ErrorKind, ErrorReport};
+
+    fn open(name: &str) -> Result<(), ErrorReport> {
+        Err(io::Error::new(io::ErrorKind::NotFound, "file not found"))
+            .with_context(|| format!("opening \"{name}\""))
+    }
+
+    fn load() -> Result<(), ErrorReport> {
+        open("config.toml").context("loading config")
+    }
+
+    #[test]
+    fn context_chain() {
+        let e = load().unwrap_err();
+        assert_eq!(ErrorKind::Io, e.kind());
+        assert_eq!(["opening \"config.toml\"", "loading config"], e.context());
+        assert_eq!(
+            "Io error: file not found\n  while opening \"config.toml\"\n  while loading config",
+            e.to_string()
+        );
+        let e = e.with_kind(ErrorKind::Config);
+        assert!(e.to_string().starts_with("Config error: file not found"));
+    }
+
+    #[test]
+    fn conversions() {
+        fn invoke() -> Result<(), ErrorReport> {
+            Err(CmdError::NotFound)?;
+            Ok(())
+        }
+        let e = invoke().unwrap_err();
+        assert_eq!(ErrorKind::Command, e.kind());
+        assert!(e.context().is_empty());
+        assert!(e.source().is::<CmdError>());
+
+        let e = ErrorReport::msg(ErrorKind::Network, "bad packet");
+        assert_eq!("Network error: bad packet", e.to_string());
+    }
+}
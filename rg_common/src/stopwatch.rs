@@ -0,0 +1,255 @@
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+///
+/// Simple stopwatch
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    started_at: Instant,
+    lap_at: Instant,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Stopwatch {
+            started_at: now,
+            lap_at: now,
+        }
+    }
+
+    ///
+    /// Returns time since start
+    ///
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    ///
+    /// Returns time since previous lap (or start)
+    ///
+    pub fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let result = now - self.lap_at;
+        self.lap_at = now;
+        result
+    }
+
+    ///
+    /// Returns time since start and starts again
+    ///
+    pub fn restart(&mut self) -> Duration {
+        let result = self.elapsed();
+        *self = Self::start();
+        result
+    }
+}
+
+impl Default for Stopwatch {
+    fn default() -> Self {
+        Self::start()
+    }
+}
+
+///
+/// Flattened node of the timing tree
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingRecord {
+    pub name: &'static str,
+    pub depth: usize,
+    pub calls: u32,
+    /// Total time including nested scopes
+    pub inclusive: Duration,
+    /// Time spent in this scope itself
+    pub exclusive: Duration,
+}
+
+struct TimingNode {
+    name: &'static str,
+    calls: u32,
+    inclusive: Duration,
+    children: Vec<usize>,
+}
+
+#[derive(Default)]
+struct TimingTree {
+    nodes: Vec<TimingNode>,
+    roots: Vec<usize>,
+    // Indices of currently open scopes
+    stack: Vec<usize>,
+}
+
+impl TimingTree {
+    fn enter(&mut self, name: &'static str) {
+        let siblings = match self.stack.last() {
+            Some(parent) => &self.nodes[*parent].children,
+            None => &self.roots,
+        };
+        let index = match siblings.iter().find(|i| self.nodes[**i].name == name) {
+            Some(index) => *index,
+            None => {
+                let index = self.nodes.len();
+                self.nodes.push(TimingNode {
+                    name,
+                    calls: 0,
+                    inclusive: Duration::ZERO,
+                    children: Vec::new(),
+                });
+                match self.stack.last() {
+                    Some(parent) => self.nodes[*parent].children.push(index),
+                    None => self.roots.push(index),
+                }
+                index
+            }
+        };
+        self.stack.push(index);
+    }
+
+    fn leave(&mut self, elapsed: Duration) {
+        if let Some(index) = self.stack.pop() {
+            let node = &mut self.nodes[index];
+            node.calls += 1;
+            node.inclusive += elapsed;
+        }
+    }
+
+    fn flatten(&self, index: usize, depth: usize, result: &mut Vec<TimingRecord>) {
+        let node = &self.nodes[index];
+        let nested: Duration = node.children.iter().map(|i| self.nodes[*i].inclusive).sum();
+        result.push(TimingRecord {
+            name: node.name,
+            depth,
+            calls: node.calls,
+            inclusive: node.inclusive,
+            exclusive: node.inclusive.saturating_sub(nested),
+        });
+        for child in node.children.iter() {
+            self.flatten(*child, depth + 1, result);
+        }
+    }
+}
+
+///
+/// Per-frame hierarchical timer. Scopes with the same name under the same parent are merged.
+/// It's not `Sync`, each thread running a frame loop owns its own instance.
+///
+#[derive(Default)]
+pub struct FrameTimer {
+    tree: RefCell<TimingTree>,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Clears timings of the previous frame
+    ///
+    pub fn begin_frame(&mut self) {
+        let tree = self.tree.get_mut();
+        debug_assert!(tree.stack.is_empty(), "Scope is still open!");
+        tree.nodes.clear();
+        tree.roots.clear();
+        tree.stack.clear();
+    }
+
+    ///
+    /// Opens new scope which is closed when returned guard is dropped
+    ///
+    pub fn scope(&self, name: &'static str) -> ScopedTimer<'_> {
+        self.tree.borrow_mut().enter(name);
+        ScopedTimer {
+            owner: self,
+            started_at: Instant::now(),
+        }
+    }
+
+    ///
+    /// Returns timing tree flattened in depth-first order
+    ///
+    pub fn report(&self) -> Vec<TimingRecord> {
+        let tree = self.tree.borrow();
+        let mut result = Vec::with_capacity(tree.nodes.len());
+        for root in tree.roots.iter() {
+            tree.flatten(*root, 0, &mut result);
+        }
+        result
+    }
+}
+
+///
+/// RAII guard of the [`FrameTimer`] scope
+///
+pub struct ScopedTimer<'a> {
+    owner: &'a FrameTimer,
+    started_at: Instant,
+}
+
+impl Drop for ScopedTimer<'_> {
+    fn drop(&mut self) {
+        self.owner
+            .tree
+            .borrow_mut()
+            .leave(self.started_at.elapsed());
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use super::{FrameTimer, Stopwatch};
+
+    #[test]
+    fn stopwatch() {
+        let mut sw = Stopwatch::start();
+        thread::sleep(Duration::from_millis(2));
+        let lap = sw.lap();
+        assert!(lap >= Duration::from_millis(2));
+        assert!(sw.lap() < lap);
+        assert!(sw.restart() >= lap);
+        assert!(sw.elapsed() < lap);
+    }
+
+    #[test]
+    fn hierarchy() {
+        let mut timer = FrameTimer::new();
+        for _ in 0..2 {
+            timer.begin_frame();
+            {
+                let _frame = timer.scope("frame");
+                for _ in 0..3 {
+                    let _update = timer.scope("update");
+                    thread::sleep(Duration::from_millis(1));
+                }
+                let _render = timer.scope("render");
+                let _upload = timer.scope("upload");
+            }
+        }
+        let report = timer.report();
+        let names: Vec<_> = report.iter().map(|r| (r.name, r.depth, r.calls)).collect();
+        assert_eq!(
+            vec![
+                ("frame", 0, 1),
+                ("update", 1, 3),
+                ("render", 1, 1),
+                ("upload", 2, 1)
+            ],
+            names
+        );
+        let frame = &report[0];
+        let update = &report[1];
+        assert!(update.inclusive >= Duration::from_millis(3));
+        assert_eq!(update.inclusive, update.exclusive);
+        assert!(frame.inclusive >= update.inclusive);
+        assert!(frame.exclusive < frame.inclusive);
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    sync::{OnceLock, RwLock},
+};
+
+///
+/// Interned string. Copying and comparing symbols is O(1), the text is stored once for the lifetime
+/// of the process, so only names from code and data files should be interned, never untrusted input.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+fn interner() -> &'static RwLock<Interner> {
+    static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(Default::default)
+}
+
+impl Symbol {
+    ///
+    /// Returns symbol of the string, adding it to the table if needed
+    ///
+    pub fn intern(value: &str) -> Self {
+        if let Some(symbol) = Self::lookup(value) {
+            return symbol;
+        }
+        let mut guard = interner().write().unwrap_or_else(|e| e.into_inner());
+        if let Some(symbol) = guard.ids.get(value) {
+            return *symbol;
+        }
+        let name: &'static str = Box::leak(value.to_owned().into_boxed_str());
+        let symbol = Symbol(guard.names.len() as u32);
+        guard.names.push(name);
+        guard.ids.insert(name, symbol);
+        symbol
+    }
+
+    ///
+    /// Returns symbol of already interned string. Doesn't allocate, so it's safe for user input.
+    ///
+    pub fn lookup(value: &str) -> Option<Self> {
+        interner()
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .ids
+            .get(value)
+            .copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        interner().read().unwrap_or_else(|e| e.into_inner()).names[self.0 as usize]
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Self {
+        Symbol::intern(value)
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Symbol({:?})", self.as_str())
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use super::Symbol;
+
+    #[test]
+    fn intern() {
+        let a = Symbol::intern("sym_test::a");
+        let b = Symbol::intern("sym_test::b");
+        assert_ne!(a, b);
+        let owned = String::from("sym_test::a");
+        assert_eq!(a, Symbol::intern(&owned));
+        assert_eq!(Some(b), Symbol::lookup("sym_test::b"));
+        assert_eq!(None, Symbol::lookup("sym_test::never_interned"));
+        assert_eq!("sym_test::a", a.as_str());
+        assert_eq!("sym_test::b", b.to_string());
+        assert_eq!("Symbol(\"sym_test::a\")", format!("{a:?}"));
+    }
+}
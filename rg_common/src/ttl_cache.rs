@@ -0,0 +1,302 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+///
+/// Thread-safe cache with time-to-live expiry and least recently used eviction
+///
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    max_entries: usize,
+    data: Mutex<CacheData<K, V>>,
+}
+
+struct CacheEntry<V> {
+    value: V,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+struct CacheData<K, V> {
+    entries: HashMap<K, CacheEntry<V>>,
+    // last_used -> key, oldest first
+    order: BTreeMap<u64, K>,
+    seq: u64,
+}
+
+impl<K, V> CacheData<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn remove(&mut self, key: &K) -> Option<CacheEntry<V>> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.last_used);
+        Some(entry)
+    }
+
+    fn touch(&mut self, key: &K) {
+        let seq = self.next_seq();
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.order.remove(&entry.last_used);
+            entry.last_used = seq;
+            self.order.insert(seq, key.clone());
+        }
+    }
+
+    fn purge_expired(&mut self, now: Instant) {
+        self.entries.retain(|_, e| {
+            let alive = e.expires_at > now;
+            if !alive {
+                self.order.remove(&e.last_used);
+            }
+            alive
+        });
+    }
+
+    fn evict_lru(&mut self) {
+        if let Some((_, key)) = self.order.pop_first() {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    ///
+    /// Creates new cache.
+    /// # Arguments:
+    /// * `ttl` - how long inserted value stays valid
+    /// * `max_entries` - maximum number of entries, least recently used ones are evicted first
+    ///
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        assert!(max_entries > 0);
+        TtlCache {
+            ttl,
+            max_entries,
+            data: Mutex::new(CacheData {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                seq: 0,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheData<K, V>> {
+        // Cache contents are always consistent between calls, so poisoning is harmless
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    ///
+    /// Returns copy of the cached value if it's still alive
+    ///
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_at(key, Instant::now())
+    }
+
+    fn get_at(&self, key: &K, now: Instant) -> Option<V> {
+        let mut guard = self.lock();
+        if guard.entries.get(key)?.expires_at <= now {
+            guard.remove(key);
+            return None;
+        }
+        guard.touch(key);
+        guard.entries.get(key).map(|e| e.value.clone())
+    }
+
+    ///
+    /// Inserts or replaces value, evicting expired and then least recently used entries if cache is full
+    ///
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_at(key, value, Instant::now())
+    }
+
+    fn insert_at(&self, key: K, value: V, now: Instant) {
+        let mut guard = self.lock();
+        guard.remove(&key);
+        if guard.entries.len() >= self.max_entries {
+            guard.purge_expired(now);
+        }
+        while guard.entries.len() >= self.max_entries {
+            guard.evict_lru();
+        }
+        let last_used = guard.next_seq();
+        guard.order.insert(last_used, key.clone());
+        guard.entries.insert(
+            key,
+            CacheEntry {
+                value,
+                expires_at: now + self.ttl,
+                last_used,
+            },
+        );
+    }
+
+    ///
+    /// Returns cached value or computes and caches new one.
+    /// Lock is not held while `f` is running, so concurrent callers may compute the same value more than once.
+    ///
+    pub fn get_or_insert_with<F>(&self, key: &K, f: F) -> V
+    where
+        F: FnOnce() -> V,
+    {
+        if let Some(value) = self.get(key) {
+            return value;
+        }
+        let value = f();
+        self.insert(key.clone(), value.clone());
+        value
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.lock().remove(key).map(|e| e.value)
+    }
+
+    ///
+    /// Removes all expired entries
+    ///
+    pub fn purge_expired(&self) {
+        self.lock().purge_expired(Instant::now());
+    }
+
+    pub fn clear(&self) {
+        let mut guard = self.lock();
+        guard.entries.clear();
+        guard.order.clear();
+    }
+
+    ///
+    /// Returns number of entries (including expired ones which are not purged yet)
+    ///
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+///
+/// Memoized function backed by [`TtlCache`]
+///
+pub struct Memoized<K, V, F> {
+    cache: TtlCache<K, V>,
+    func: F,
+}
+
+impl<K, V, F> Memoized<K, V, F>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(&K) -> V,
+{
+    pub fn new(ttl: Duration, max_entries: usize, func: F) -> Self {
+        Memoized {
+            cache: TtlCache::new(ttl, max_entries),
+            func,
+        }
+    }
+
+    pub fn get(&self, key: &K) -> V {
+        self.cache.get_or_insert_with(key, || (self.func)(key))
+    }
+
+    pub fn cache(&self) -> &TtlCache<K, V> {
+        &self.cache
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use super::{Memoized, TtlCache};
+
+    #[test]
+    fn expiry() {
+        let cache = TtlCache::new(Duration::from_secs(10), 10);
+        let now = Instant::now();
+        cache.insert_at(1, "one", now);
+        assert_eq!(Some("one"), cache.get_at(&1, now + Duration::from_secs(9)));
+        assert_eq!(None, cache.get_at(&1, now + Duration::from_secs(10)));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let cache = TtlCache::new(Duration::from_secs(10), 3);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        cache.insert(3, 3);
+        // 1 becomes most recently used, so 2 goes first
+        assert_eq!(Some(1), cache.get(&1));
+        cache.insert(4, 4);
+        assert_eq!(3, cache.len());
+        assert_eq!(None, cache.get(&2));
+        assert_eq!(Some(1), cache.get(&1));
+        assert_eq!(Some(3), cache.get(&3));
+        assert_eq!(Some(4), cache.get(&4));
+    }
+
+    #[test]
+    fn expired_evicted_before_lru() {
+        let cache = TtlCache::new(Duration::from_secs(10), 2);
+        let now = Instant::now();
+        cache.insert_at(1, 1, now);
+        cache.insert_at(2, 2, now + Duration::from_secs(5));
+        assert_eq!(Some(1), cache.get_at(&1, now + Duration::from_secs(6)));
+        // 1 is recently used but expired by now
+        cache.insert_at(3, 3, now + Duration::from_secs(11));
+        assert_eq!(None, cache.get_at(&1, now + Duration::from_secs(11)));
+        assert_eq!(Some(2), cache.get_at(&2, now + Duration::from_secs(11)));
+        assert_eq!(Some(3), cache.get_at(&3, now + Duration::from_secs(11)));
+    }
+
+    #[test]
+    fn replace_and_remove() {
+        let cache = TtlCache::new(Duration::from_secs(10), 2);
+        cache.insert("a", 1);
+        cache.insert("a", 2);
+        assert_eq!(1, cache.len());
+        assert_eq!(Some(2), cache.remove(&"a"));
+        assert_eq!(None, cache.remove(&"a"));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn memoized() {
+        let calls = AtomicUsize::new(0);
+        let m = Memoized::new(Duration::from_secs(10), 10, |v: &i32| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            v * 2
+        });
+        assert_eq!(4, m.get(&2));
+        assert_eq!(4, m.get(&2));
+        assert_eq!(6, m.get(&3));
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+        m.cache().clear();
+        assert_eq!(4, m.get(&2));
+        assert_eq!(3, calls.load(Ordering::Relaxed));
+    }
+}
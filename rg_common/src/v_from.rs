@@ -53,6 +53,18 @@ impl From<&mut usize> for Variable<'_> {
     }
 }
 
+impl From<&u32> for Variable<'_> {
+    fn from(value: &u32) -> Self {
+        Variable::Integer(*value as i64)
+    }
+}
+
+impl From<&mut u32> for Variable<'_> {
+    fn from(value: &mut u32) -> Self {
+        Variable::Integer(*value as i64)
+    }
+}
+
 impl From<&i64> for Variable<'_> {
     fn from(value: &i64) -> Self {
         Variable::Integer(*value)
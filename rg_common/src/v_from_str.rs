@@ -46,6 +46,14 @@ impl FromStrMutator for i64 {
     }
 }
 
+impl FromStrMutator for u32 {
+    fn set_from_str(&mut self, sp: &mut Split<&str>, value: &str) -> Result<(), VariableError> {
+        assert!(sp.next().is_none());
+        *self = value.parse::<u32>()?;
+        Ok(())
+    }
+}
+
 impl FromStrMutator for usize {
     fn set_from_str(&mut self, sp: &mut Split<&str>, value: &str) -> Result<(), VariableError> {
         assert!(sp.next().is_none());
@@ -1,13 +1,19 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::iter::Peekable;
 use std::ops::Deref;
 use std::str::Split;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
+use log::error;
+
+use crate::expr::{self, ExprError, ExprValue};
 use crate::vars::VarRegistryError::VarError;
 use crate::VariableError::NotFound;
+use crate::{ExecContext, Symbol};
 
 pub enum Variable<'a> {
     VarBag(&'a dyn VarBag),
@@ -36,13 +42,50 @@ where
     T: VarBag,
 {
     data: Option<Arc<Mutex<T>>>,
+    // Variable or group name -> the least trusted context allowed to change it
+    access: HashMap<Symbol, ExecContext>,
 }
 
 impl<T: VarBag> VarRegistry<T> {
     pub const DELIMITER: &'static str = "::";
 
     pub fn new(data: Arc<Mutex<T>>) -> Self {
-        VarRegistry { data: Some(data) }
+        VarRegistry {
+            data: Some(data),
+            access: HashMap::new(),
+        }
+    }
+
+    ///
+    /// Allows changing variable (or all variables of the group) from `access` or more trusted context.
+    /// Variables without explicit access level can be changed only locally.
+    ///
+    pub fn set_access(&mut self, name: &str, access: ExecContext) {
+        self.access.insert(Symbol::intern(name), access);
+    }
+
+    ///
+    /// Returns access level of variable, inherited from the closest group if not set explicitly
+    ///
+    pub fn access(&self, name: &str) -> ExecContext {
+        let mut name = name;
+        loop {
+            if let Some(access) = Symbol::lookup(name).and_then(|s| self.access.get(&s)) {
+                return *access;
+            }
+            match name.rfind(Self::DELIMITER) {
+                Some(idx) => name = &name[..idx],
+                None => return ExecContext::Local,
+            }
+        }
+    }
+
+    fn check_access(&self, context: ExecContext, name: &str) -> Result<(), VariableError> {
+        if context.allows(self.access(name)) {
+            Ok(())
+        } else {
+            Err(VariableError::AccessDenied)
+        }
     }
 
     pub fn set_data(&mut self, config: Arc<Mutex<T>>) {
@@ -55,7 +98,11 @@ impl<T: VarBag> VarRegistry<T> {
 
     pub fn try_get_value(&self, name: &str) -> Option<String> {
         let guard = self.lock_data()?;
-        let mut v = Variable::from(guard.deref());
+        Self::read_value(guard.deref(), name)
+    }
+
+    fn read_value(data: &T, name: &str) -> Option<String> {
+        let mut v = Variable::from(data);
         let mut sp = name.split(Self::DELIMITER);
         loop {
             match v {
@@ -102,12 +149,70 @@ impl<T: VarBag> VarRegistry<T> {
     }
 
     pub fn try_set_value(&self, name: &str, value: &str) -> Result<(), VarRegistryError> {
+        self.try_set_value_as(ExecContext::Local, name, value)
+    }
+
+    ///
+    /// Sets variable on behalf of `context`
+    ///
+    pub fn try_set_value_as(
+        &self,
+        context: ExecContext,
+        name: &str,
+        value: &str,
+    ) -> Result<(), VarRegistryError> {
+        self.check_access(context, name)?;
         let mut sp = name.split(Self::DELIMITER);
         let mut guard = self.lock_data().ok_or(VarRegistryError::LockFailed)?;
         guard.try_set_var(&mut sp, value)?;
         Ok(())
     }
 
+    ///
+    /// Starts new transaction. Staged values are applied all at once by [`VarTransaction::commit`].
+    ///
+    pub fn transaction(&self) -> VarTransaction<'_, T> {
+        self.transaction_as(ExecContext::Local)
+    }
+
+    ///
+    /// Starts new transaction on behalf of `context`
+    ///
+    pub fn transaction_as(&self, context: ExecContext) -> VarTransaction<'_, T> {
+        VarTransaction {
+            registry: self,
+            context,
+            staged: Vec::new(),
+        }
+    }
+
+    ///
+    /// Captures current values of passed variables
+    ///
+    pub fn snapshot(&self, names: &[&str]) -> Result<VarSnapshot, VarRegistryError> {
+        let guard = self.lock_data().ok_or(VarRegistryError::LockFailed)?;
+        let values = names
+            .iter()
+            .map(|name| {
+                Self::read_value(guard.deref(), name)
+                    .map(|v| (name.to_string(), v))
+                    .ok_or(VarError(NotFound))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(VarSnapshot { values })
+    }
+
+    ///
+    /// Sets all variables back to the captured values in single transaction
+    ///
+    pub fn restore(&self, snapshot: &VarSnapshot) -> Result<Vec<String>, VarRegistryError> {
+        let mut tx = self.transaction();
+        for (name, value) in snapshot.values.iter() {
+            tx.set(name, value);
+        }
+        tx.commit()
+    }
+
     fn filter_names(
         owner: &dyn VarBag,
         sp: &mut Peekable<Split<&str>>,
@@ -150,12 +255,180 @@ impl<T: VarBag> VarRegistry<T> {
             result
         })
     }
+
+    ///
+    /// Evaluates expression over current variable values, see [`expr::eval`]
+    ///
+    pub fn eval(&self, expr: &str) -> Result<ExprValue, ExprError> {
+        expr::eval(expr, |name| self.try_get_value(name))
+    }
+
+    ///
+    /// Replaces `$(expr)` in command line with evaluated values, see [`expr::expand`]
+    ///
+    pub fn expand(&self, line: &str) -> Result<String, ExprError> {
+        expr::expand(line, |name| self.try_get_value(name))
+    }
+}
+
+///
+/// Set of variable changes applied atomically: either all staged values are set or none.
+/// Dropping transaction without commit discards staged values.
+///
+pub struct VarTransaction<'a, T: VarBag> {
+    registry: &'a VarRegistry<T>,
+    context: ExecContext,
+    staged: Vec<(String, String)>,
+}
+
+impl<T: VarBag> VarTransaction<'_, T> {
+    ///
+    /// Stages new value for variable. The last staged value wins.
+    ///
+    pub fn set(&mut self, name: &str, value: &str) -> &mut Self {
+        self.staged.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    ///
+    /// Applies staged values while holding the lock, so nobody observes half-applied state.
+    /// On the first failure already applied values are restored.
+    /// Returns names of changed variables in order of staging.
+    ///
+    pub fn commit(self) -> Result<Vec<String>, VarRegistryError> {
+        self.commit_with_snapshot().map(|(changed, _)| changed)
+    }
+
+    ///
+    /// Same as [`VarTransaction::commit`] but also returns values changed variables had before commit
+    ///
+    pub fn commit_with_snapshot(self) -> Result<(Vec<String>, VarSnapshot), VarRegistryError> {
+        let mut guard = self
+            .registry
+            .lock_data()
+            .ok_or(VarRegistryError::LockFailed)?;
+        let mut applied: Vec<(&str, String)> = Vec::with_capacity(self.staged.len());
+        for (name, value) in self.staged.iter() {
+            let result = self
+                .registry
+                .check_access(self.context, name)
+                .and_then(|_| VarRegistry::read_value(guard.deref(), name).ok_or(NotFound))
+                .and_then(|old| {
+                    guard.try_set_var(&mut name.split(VarRegistry::<T>::DELIMITER), value)?;
+                    Ok(old)
+                });
+            match result {
+                Ok(old) => applied.push((name, old)),
+                Err(error) => {
+                    for (name, old) in applied.into_iter().rev() {
+                        // Value read back as text may not parse the same way, variable keeps the new value then
+                        if let Err(e) =
+                            guard.try_set_var(&mut name.split(VarRegistry::<T>::DELIMITER), &old)
+                        {
+                            error!("Unable to restore \"{name}\" to \"{old}\": {e}");
+                        }
+                    }
+                    return Err(VarRegistryError::TransactionFailed {
+                        name: name.clone(),
+                        error,
+                    });
+                }
+            }
+        }
+        let mut changed = Vec::with_capacity(applied.len());
+        let mut values = Vec::with_capacity(applied.len());
+        for (name, old) in applied {
+            // The first staged write of variable has seen its original value
+            if !changed.iter().any(|v: &String| v == name) {
+                changed.push(name.to_string());
+                values.push((name.to_string(), old));
+            }
+        }
+        Ok((changed, VarSnapshot { values }))
+    }
+
+    ///
+    /// Commits transaction which has to be confirmed within `timeout`, see [`PendingChange`]
+    ///
+    pub fn commit_pending(self, timeout: Duration) -> Result<PendingChange, VarRegistryError> {
+        let (changed, snapshot) = self.commit_with_snapshot()?;
+        Ok(PendingChange {
+            changed,
+            snapshot,
+            deadline: Instant::now() + timeout,
+        })
+    }
+}
+
+///
+/// Values of variables captured at some point
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarSnapshot {
+    values: Vec<(String, String)>,
+}
+
+impl VarSnapshot {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+///
+/// Applied change waiting for user confirmation, e.g. new video mode.
+/// Owner should call [`PendingChange::revert`] once [`PendingChange::is_expired`] returns true,
+/// so unconfirmed (possibly broken) settings don't stick.
+///
+#[derive(Debug)]
+pub struct PendingChange {
+    changed: Vec<String>,
+    snapshot: VarSnapshot,
+    deadline: Instant,
+}
+
+impl PendingChange {
+    pub fn changed(&self) -> &[String] {
+        &self.changed
+    }
+
+    pub fn time_left(&self, now: Instant) -> Duration {
+        self.deadline.saturating_duration_since(now)
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now >= self.deadline
+    }
+
+    ///
+    /// Keeps new values
+    ///
+    pub fn confirm(self) -> Vec<String> {
+        self.changed
+    }
+
+    ///
+    /// Restores values variables had before the change
+    ///
+    pub fn revert<T: VarBag>(
+        self,
+        registry: &VarRegistry<T>,
+    ) -> Result<Vec<String>, VarRegistryError> {
+        registry.restore(&self.snapshot)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum VarRegistryError {
     VarError(VariableError),
     LockFailed,
+    TransactionFailed { name: String, error: VariableError },
 }
 
 impl Display for VarRegistryError {
@@ -167,6 +440,9 @@ impl Display for VarRegistryError {
             VarRegistryError::LockFailed => {
                 write!(f, "Lock failed!")
             }
+            VarRegistryError::TransactionFailed { name, error } => {
+                write!(f, "Unable to set \"{name}\": {error}")
+            }
         }
     }
 }
@@ -183,6 +459,7 @@ impl From<VariableError> for VarRegistryError {
 pub enum VariableError {
     ParsingError,
     NotFound,
+    AccessDenied,
 }
 
 impl Display for VariableError {
@@ -194,6 +471,9 @@ impl Display for VariableError {
             NotFound => {
                 write!(f, "No such variable!")
             }
+            VariableError::AccessDenied => {
+                write!(f, "Access denied!")
+            }
         }
     }
 }
@@ -206,10 +486,12 @@ mod test {
     use std::fmt::Debug;
     use std::str::Split;
     use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
 
     use rg_macros::VarBag;
 
-    use crate::vars::{FromStrMutator, VarBag, VarRegistry, Variable};
+    use crate::vars::{FromStrMutator, VarBag, VarRegistry, VarRegistryError, Variable};
+    use crate::{ExecContext, VariableError};
 
     #[derive(VarBag, Default)]
     struct TestVars {
@@ -281,6 +563,110 @@ mod test {
         assert_eq!(v, ["sub::speed"]);
     }
 
+    #[test]
+    fn expressions() {
+        let reg = VarRegistry::new(Arc::new(Mutex::new(TestVars {
+            counter: 3,
+            sub: MoreTestVars { speed: 1.5 },
+            ..Default::default()
+        })));
+        let line = reg.expand("counter $(sub::speed * 2 + counter)").unwrap();
+        assert_eq!("counter 6", line);
+        reg.try_set_value("counter", line.split(' ').nth(1).unwrap())
+            .unwrap();
+        assert_eq!(
+            "true",
+            reg.eval("counter > 5 && !flag").unwrap().to_string()
+        );
+        assert!(reg.eval("name + 1").is_err());
+    }
+
+    #[test]
+    fn transaction() {
+        let reg = VarRegistry::new(Arc::new(Mutex::new(TestVars {
+            counter: 1,
+            name: "old".to_string(),
+            ..Default::default()
+        })));
+
+        let mut tx = reg.transaction();
+        tx.set("counter", "2")
+            .set("name", "new")
+            .set("sub::speed", "abc")
+            .set("flag", "true");
+        assert_eq!(
+            Err(VarRegistryError::TransactionFailed {
+                name: "sub::speed".to_string(),
+                error: VariableError::ParsingError
+            }),
+            tx.commit()
+        );
+        // Nothing is applied
+        assert_eq!("1", reg.try_get_value("counter").unwrap());
+        assert_eq!("old", reg.try_get_value("name").unwrap());
+        assert_eq!("false", reg.try_get_value("flag").unwrap());
+
+        let mut tx = reg.transaction();
+        tx.set("counter", "2").set("unknown", "1");
+        assert!(matches!(
+            tx.commit(),
+            Err(VarRegistryError::TransactionFailed {
+                error: VariableError::NotFound,
+                ..
+            })
+        ));
+        assert_eq!("1", reg.try_get_value("counter").unwrap());
+
+        let mut tx = reg.transaction();
+        tx.set("counter", "2")
+            .set("sub::speed", "1.5")
+            .set("counter", "3");
+        drop(tx);
+        assert_eq!("1", reg.try_get_value("counter").unwrap());
+
+        let mut tx = reg.transaction();
+        tx.set("counter", "2")
+            .set("sub::speed", "1.5")
+            .set("counter", "3");
+        assert_eq!(vec!["counter", "sub::speed"], tx.commit().unwrap());
+        assert_eq!("3", reg.try_get_value("counter").unwrap());
+        assert_eq!("1.5", reg.try_get_value("sub::speed").unwrap());
+    }
+
+    #[test]
+    fn access() {
+        let mut reg = VarRegistry::new(Arc::new(Mutex::new(TestVars::default())));
+        reg.set_access("sub", ExecContext::Rcon);
+        reg.set_access("name", ExecContext::Remote);
+        assert_eq!(ExecContext::Local, reg.access("counter"));
+        assert_eq!(ExecContext::Rcon, reg.access("sub::speed"));
+
+        reg.try_set_value_as(ExecContext::Remote, "name", "player")
+            .unwrap();
+        reg.try_set_value_as(ExecContext::Rcon, "sub::speed", "2")
+            .unwrap();
+        assert_eq!(
+            Err(VarRegistryError::VarError(VariableError::AccessDenied)),
+            reg.try_set_value_as(ExecContext::Remote, "sub::speed", "3")
+        );
+        assert_eq!(
+            Err(VarRegistryError::VarError(VariableError::AccessDenied)),
+            reg.try_set_value_as(ExecContext::Rcon, "counter", "3")
+        );
+        assert_eq!("2", reg.try_get_value("sub::speed").unwrap());
+
+        let mut tx = reg.transaction_as(ExecContext::Remote);
+        tx.set("name", "other").set("flag", "true");
+        assert_eq!(
+            Err(VarRegistryError::TransactionFailed {
+                name: "flag".to_string(),
+                error: VariableError::AccessDenied
+            }),
+            tx.commit()
+        );
+        assert_eq!("player", reg.try_get_value("name").unwrap());
+    }
+
     #[derive(Debug, VarBag)]
     struct Sub {
         name: String,
@@ -331,4 +717,54 @@ mod test {
         c.speed.set_from_str(&mut empty_split(), "3.33").unwrap();
         assert_eq!(c.speed, 3.33);
     }
+
+    #[test]
+    fn snapshot() {
+        let reg = VarRegistry::new(Arc::new(Mutex::new(TestVars {
+            counter: 1,
+            name: "old".to_string(),
+            ..Default::default()
+        })));
+        let snapshot = reg.snapshot(&["counter", "name"]).unwrap();
+        assert_eq!(Some("1"), snapshot.get("counter"));
+        assert_eq!(
+            Err(VarRegistryError::VarError(VariableError::NotFound)),
+            reg.snapshot(&["counter", "unknown"])
+        );
+        reg.try_set_value("counter", "5").unwrap();
+        reg.try_set_value("name", "new").unwrap();
+        assert_eq!(vec!["counter", "name"], reg.restore(&snapshot).unwrap());
+        assert_eq!("1", reg.try_get_value("counter").unwrap());
+        assert_eq!("old", reg.try_get_value("name").unwrap());
+    }
+
+    #[test]
+    fn pending_change() {
+        let reg = VarRegistry::new(Arc::new(Mutex::new(TestVars {
+            counter: 1,
+            ..Default::default()
+        })));
+        let mut tx = reg.transaction();
+        tx.set("counter", "2")
+            .set("flag", "true")
+            .set("counter", "3");
+        let pending = tx.commit_pending(Duration::from_secs(10)).unwrap();
+        let now = Instant::now();
+        assert_eq!(vec!["counter", "flag"], pending.changed());
+        assert!(!pending.is_expired(now));
+        assert!(pending.is_expired(now + Duration::from_secs(10)));
+        assert!(pending.time_left(now) <= Duration::from_secs(10));
+        assert_eq!("3", reg.try_get_value("counter").unwrap());
+
+        // Not confirmed in time
+        pending.revert(&reg).unwrap();
+        assert_eq!("1", reg.try_get_value("counter").unwrap());
+        assert_eq!("false", reg.try_get_value("flag").unwrap());
+
+        let mut tx = reg.transaction();
+        tx.set("counter", "4");
+        let pending = tx.commit_pending(Duration::from_secs(10)).unwrap();
+        assert_eq!(vec!["counter"], pending.confirm());
+        assert_eq!("4", reg.try_get_value("counter").unwrap());
+    }
 }
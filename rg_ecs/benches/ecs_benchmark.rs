@@ -15,6 +15,10 @@ struct Velocity(f32, f32, f32);
 struct Direction(f32, f32, f32);
 #[derive(Default)]
 struct Name(String);
+#[derive(Default)]
+struct Frozen;
+#[derive(Default)]
+struct FrozenFlag(u64);
 
 fn init_storage(chunk_size: usize, count: Option<usize>) -> (Entities, ArchetypeId, ArchetypeId) {
     let entities = Entities::new(chunk_size);
@@ -70,6 +74,39 @@ fn ecs_benchmark(c: &mut Criterion) {
             criterion::BatchSize::SmallInput,
         )
     });
+    // Zero-sized tag vs the same marker with payload
+    c.bench_function("ecs tag 1000", |b| {
+        b.iter_batched(
+            || {
+                (0..1000)
+                    .map(|_| entities.add(Some(arch_id2)).unwrap())
+                    .collect::<Vec<_>>()
+            },
+            |batch| {
+                batch
+                    .iter()
+                    .map(|ent_id| entities.set(*ent_id, Frozen))
+                    .count()
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    c.bench_function("ecs marker 1000", |b| {
+        b.iter_batched(
+            || {
+                (0..1000)
+                    .map(|_| entities.add(Some(arch_id2)).unwrap())
+                    .collect::<Vec<_>>()
+            },
+            |batch| {
+                batch
+                    .iter()
+                    .map(|ent_id| entities.set(*ent_id, FrozenFlag(1)))
+                    .count()
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
     let columns1 = HashSet::from([ComponentId::new::<EntityId>(), ComponentId::new::<String>()]);
     let columns2 = HashSet::from([
         ComponentId::new::<Location>(),
@@ -5,7 +5,7 @@ use std::{
     marker::PhantomData,
     slice::Iter,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
         Arc, RwLock,
     },
 };
@@ -15,8 +15,11 @@ use itertools::Itertools;
 use once_cell::sync::{self, Lazy};
 
 use crate::{
-    component::{cast, cast_mut, ComponentId, ComponentStorage, TypedComponentStorage},
-    entity::EntityId,
+    component::{
+        cast, cast_mut, ColumnCodecs, ComponentId, ComponentStorage, DropHooks, RemapHooks,
+        TypedComponentStorage,
+    },
+    entity::{EntityId, EntityMap},
     error::EntityError,
 };
 ///
@@ -25,6 +28,9 @@ use crate::{
 pub(crate) static COLUMN_ENTITY_ID: Lazy<ComponentId> =
     sync::Lazy::new(|| ComponentId::new::<EntityId>());
 
+/// Deflate level of frozen columns, favours speed as archetype is thawed on the first access
+const COMPRESSION_LEVEL: u8 = 3;
+
 ///
 /// ArchetypeId
 ///
@@ -80,22 +86,25 @@ impl ArchetypeRef {
 /// ColumnFactory
 ///
 //type ColumnFactory = dyn Fn(usize) -> Box<dyn ComponentStorage + 'static>;
-trait ColumnFactory {
+trait ColumnFactory: Send + Sync {
     fn create(&self, capacity: usize) -> Box<dyn ComponentStorage + 'static>;
     fn item_size(&self) -> usize;
+    fn is_tag(&self) -> bool {
+        self.item_size() == 0
+    }
 }
 
 #[derive(Default)]
 struct TypedColumnFactory<T>
 where
-    T: Default + 'static,
+    T: Default + Send + Sync + 'static,
 {
-    _data: PhantomData<T>,
+    _data: PhantomData<fn() -> T>,
 }
 
 impl<T> ColumnFactory for TypedColumnFactory<T>
 where
-    T: Default + 'static,
+    T: Default + Send + Sync + 'static,
 {
     fn create(&self, capacity: usize) -> Box<dyn ComponentStorage + 'static> {
         Box::new(TypedComponentStorage::<T>::with_capacity(capacity))
@@ -121,7 +130,7 @@ impl ArchetypeBuilder {
         .add::<EntityId>()
     }
 
-    pub fn add<T: Default + 'static>(mut self) -> Self {
+    pub fn add<T: Default + Send + Sync + 'static>(mut self) -> Self {
         let comp_id = ComponentId::new::<T>();
         self.factories
             .insert(comp_id, Arc::new(TypedColumnFactory::<T>::default()));
@@ -174,6 +183,14 @@ impl Archetype {
         self.factories.contains_key(comp_id)
     }
 
+    ///
+    /// Checks if component is a zero-sized tag. Tag columns never allocate, so presence of the tag
+    /// is tracked by the archetype only.
+    ///
+    pub fn is_tag(&self, comp_id: &ComponentId) -> bool {
+        self.factories.get(comp_id).is_some_and(|f| f.is_tag())
+    }
+
     pub fn row_bytes(&self) -> usize {
         self.factories.iter().map(|(_, f)| f.item_size()).sum()
     }
@@ -212,6 +229,8 @@ type ColumnMap = HashMap<ComponentId, RwLock<Box<dyn ComponentStorage>>>;
 pub struct Chunk {
     columns: ColumnMap,
     available_rows: AtomicU32,
+    // Rows of entities waiting for deferred despawn, indexed same as columns
+    tombstones: Box<[AtomicBool]>,
 }
 
 impl Chunk {
@@ -224,13 +243,52 @@ impl Chunk {
         Chunk {
             columns,
             available_rows: AtomicU32::new(capacity as u32),
+            tombstones: (0..capacity).map(|_| AtomicBool::new(false)).collect(),
         }
     }
 
+    ///
+    /// Checks if row is marked for deferred despawn. Such rows should be skipped by queries.
+    ///
+    #[inline]
+    pub fn is_dead(&self, index: usize) -> bool {
+        self.tombstones
+            .get(index)
+            .is_some_and(|v| v.load(Ordering::Acquire))
+    }
+
+    ///
+    /// Returns false if row was already marked
+    ///
+    fn mark_dead(&self, index: usize) -> bool {
+        self.tombstones
+            .get(index)
+            .is_some_and(|v| !v.swap(true, Ordering::AcqRel))
+    }
+
+    ///
+    /// Mirrors swap remove of the row in columns
+    ///
+    fn remove_tombstone(&self, index: usize, last: usize) {
+        let value = if index < last {
+            self.tombstones[last].swap(false, Ordering::AcqRel)
+        } else {
+            false
+        };
+        self.tombstones[index].store(value, Ordering::Release);
+    }
+
     fn available(&self) -> u32 {
         self.available_rows.load(Ordering::Acquire)
     }
 
+    ///
+    /// Number of occupied rows. Unlike [`Chunk::row_count`] doesn't lock any column.
+    ///
+    pub(crate) fn len(&self) -> usize {
+        self.tombstones.len() - self.available() as usize
+    }
+
     ///
     /// Adds new row for passed entity to this storage and returns local index
     ///
@@ -244,6 +302,7 @@ impl Chunk {
             // Cell already added in above loop, now set value
             cast_mut::<EntityId>(column.write().unwrap().as_mut())[index] = ent_id;
         }
+        self.tombstones[index].store(false, Ordering::Release);
         self.available_rows.fetch_sub(1, Ordering::Relaxed);
         index
     }
@@ -256,11 +315,18 @@ impl Chunk {
     }
 
     ///
-    /// Removes row from this chunk
+    /// Removes row from this chunk, hooks are called before values are dropped
     ///
-    fn remove(&self, index: usize) -> Option<EntityId> {
-        for column in self.columns.values() {
-            column.write().unwrap().remove(index);
+    fn remove(&self, index: usize, hooks: &DropHooks) -> Option<EntityId> {
+        let last = self.row_count().saturating_sub(1);
+        let removed = self.get_entity_id(index).unwrap_or_default();
+        self.remove_tombstone(index, last);
+        for (comp_id, column) in self.columns.iter() {
+            let mut guard = column.write().unwrap();
+            if let Some(hook) = hooks.get(comp_id) {
+                hook.on_drop(removed, guard.as_mut(), index);
+            }
+            guard.remove(index);
         }
         self.available_rows.fetch_add(1, Ordering::Relaxed);
         self.get_entity_id(index)
@@ -273,6 +339,8 @@ impl Chunk {
     where
         T: Default + 'static,
     {
+        let last = self.row_count().saturating_sub(1);
+        self.remove_tombstone(index, last);
         for (comp_id, column) in self.columns.iter() {
             let lock = dest.get_column(*comp_id).unwrap();
             let mut guard = lock.write().unwrap();
@@ -286,11 +354,58 @@ impl Chunk {
             typed_col.push(value);
             idx
         };
+        dest.tombstones[idx].store(false, Ordering::Release);
         dest.available_rows.fetch_sub(1, Ordering::Relaxed);
         self.available_rows.fetch_add(1, Ordering::Relaxed);
         (idx, self.get_entity_id(index))
     }
 
+    ///
+    /// Calls hooks for every row, values are dropped with the chunk itself
+    ///
+    fn call_hooks(&self, hooks: &DropHooks) {
+        let entities = match self.columns.get(&COLUMN_ENTITY_ID) {
+            Some(column) => cast::<EntityId>(column.read().unwrap().as_ref()).clone(),
+            None => Vec::new(),
+        };
+        for (comp_id, column) in self.columns.iter() {
+            let Some(hook) = hooks.get(comp_id) else {
+                continue;
+            };
+            let mut guard = column.write().unwrap();
+            for index in 0..guard.row_count() {
+                let entity = entities.get(index).copied().unwrap_or_default();
+                hook.on_drop(entity, guard.as_mut(), index);
+            }
+        }
+    }
+
+    ///
+    /// Replaces ids of entities and references to them in components. Chunk must not be shared yet.
+    ///
+    fn remap(&mut self, map: &EntityMap, hooks: &RemapHooks) {
+        for (comp_id, column) in self.columns.iter_mut() {
+            let column = column.get_mut().unwrap();
+            if *comp_id == *COLUMN_ENTITY_ID {
+                for id in cast_mut::<EntityId>(column.as_mut()).iter_mut() {
+                    *id = map.get(*id).unwrap_or(*id);
+                }
+            } else if let Some(hook) = hooks.get(comp_id) {
+                hook.remap(column.as_mut(), map);
+            }
+        }
+    }
+
+    ///
+    /// Returns ids of entities in this chunk (in row order)
+    ///
+    fn entity_ids(&self) -> Vec<EntityId> {
+        self.columns
+            .get(&COLUMN_ENTITY_ID)
+            .map(|c| cast::<EntityId>(c.read().unwrap().as_ref()).clone())
+            .unwrap_or_default()
+    }
+
     #[inline(always)]
     pub(crate) fn get_column(
         &self,
@@ -313,6 +428,70 @@ impl Chunk {
         }
         0
     }
+
+    fn has_dead_rows(&self) -> bool {
+        (0..self.len()).any(|index| self.is_dead(index))
+    }
+
+    ///
+    /// Encodes and compresses columns, values are dropped with the chunk. Tags without codec are not stored,
+    /// only the number of rows is kept for them.
+    ///
+    fn freeze(self, codecs: &ColumnCodecs) -> FrozenChunk {
+        let rows = self.len();
+        let columns = self
+            .columns
+            .into_iter()
+            .filter_map(|(comp_id, column)| {
+                let codec = codecs.get(&comp_id)?;
+                let bytes = codec.encode(column.into_inner().unwrap().as_ref());
+                Some((
+                    comp_id,
+                    miniz_oxide::deflate::compress_to_vec(&bytes, COMPRESSION_LEVEL),
+                ))
+            })
+            .collect();
+        FrozenChunk { rows, columns }
+    }
+}
+
+///
+/// Compressed columns of the chunk, see [`ArchetypeStorage::freeze`]
+///
+struct FrozenChunk {
+    rows: usize,
+    columns: HashMap<ComponentId, Vec<u8>>,
+}
+
+impl FrozenChunk {
+    ///
+    /// Restores rows into the empty `chunk`
+    ///
+    fn thaw(self, mut chunk: Chunk, codecs: &ColumnCodecs) -> Chunk {
+        for (comp_id, column) in chunk.columns.iter_mut() {
+            let column = column.get_mut().unwrap();
+            match self.columns.get(comp_id) {
+                Some(data) => {
+                    let bytes = miniz_oxide::inflate::decompress_to_vec(data)
+                        .expect("Frozen column is corrupted!");
+                    codecs[comp_id]
+                        .decode(&bytes, column.as_mut())
+                        .expect("Frozen column is corrupted!");
+                }
+                None => {
+                    for _ in 0..self.rows {
+                        column.add();
+                    }
+                }
+            }
+        }
+        *chunk.available_rows.get_mut() -= self.rows as u32;
+        chunk
+    }
+
+    fn size(&self) -> usize {
+        self.columns.values().map(Vec::len).sum()
+    }
 }
 
 ///
@@ -322,6 +501,10 @@ pub(crate) struct ArchetypeStorage {
     pub(crate) archetype: Archetype,
     chunk_size: usize,
     chunks: Vec<Chunk>,
+    // Chunks of the cold archetype, `chunks` is empty while it's set
+    frozen: Option<Vec<FrozenChunk>>,
+    // Frame of the last access, see [`ArchetypeStorage::touch`]
+    last_access: AtomicU64,
 }
 
 impl ArchetypeStorage {
@@ -331,6 +514,8 @@ impl ArchetypeStorage {
             archetype,
             chunk_size,
             chunks: vec![],
+            frozen: None,
+            last_access: AtomicU64::new(0),
         }
     }
 
@@ -428,10 +613,30 @@ impl ArchetypeStorage {
     ///
     /// Removes row from this storage. Returns id of moved enity (in case of swap remove)
     ///
-    pub(crate) fn remove(&self, arch_ref: &ArchetypeRef) -> Option<EntityId> {
+    pub(crate) fn remove(&self, arch_ref: &ArchetypeRef, hooks: &DropHooks) -> Option<EntityId> {
+        self.chunks
+            .get(arch_ref.chunk_index())
+            .and_then(|ch| ch.remove(arch_ref.local_index(), hooks))
+    }
+
+    ///
+    /// Marks row as dead, it will be skipped by queries until removed. Returns false if row was already marked.
+    ///
+    pub(crate) fn mark_dead(&self, arch_ref: &ArchetypeRef) -> Result<bool, EntityError> {
+        Ok(self
+            .chunks
+            .get(arch_ref.chunk_index())
+            .ok_or(EntityError::OutOfBounds)?
+            .mark_dead(arch_ref.local_index()))
+    }
+
+    ///
+    /// Checks if row is waiting for deferred despawn
+    ///
+    pub(crate) fn is_dead(&self, arch_ref: &ArchetypeRef) -> bool {
         self.chunks
             .get(arch_ref.chunk_index())
-            .and_then(|ch| ch.remove(arch_ref.local_index()))
+            .is_some_and(|chunk| chunk.is_dead(arch_ref.local_index()))
     }
 
     ///
@@ -444,8 +649,40 @@ impl ArchetypeStorage {
     ///
     /// Removes all rows from this storage
     ///
-    pub(crate) fn clear(&mut self) {
+    pub(crate) fn clear(&mut self, hooks: &DropHooks) {
+        if !hooks.is_empty() {
+            for chunk in self.chunks.iter() {
+                chunk.call_hooks(hooks);
+            }
+        }
         self.chunks.clear();
+        self.frozen = None;
+    }
+
+    ///
+    /// Remaps entity ids in all chunks, see [`Chunk::remap`]
+    ///
+    pub(crate) fn remap(&mut self, map: &EntityMap, hooks: &RemapHooks) {
+        for chunk in self.chunks.iter_mut() {
+            chunk.remap(map, hooks);
+        }
+    }
+
+    ///
+    /// Moves all chunks of `other` storage of the same archetype to this one.
+    /// Returns references to the moved rows along with their entity ids.
+    ///
+    pub(crate) fn append(&mut self, other: ArchetypeStorage) -> Vec<(EntityId, ArchetypeRef)> {
+        debug_assert_eq!(self.archetype.id, other.archetype.id);
+        let offset = self.chunks.len();
+        let mut result = Vec::new();
+        for (i, chunk) in other.chunks.into_iter().enumerate() {
+            for (local, id) in chunk.entity_ids().into_iter().enumerate() {
+                result.push((id, ArchetypeRef::new(offset + i, local)));
+            }
+            self.chunks.push(chunk);
+        }
+        result
     }
 
     ///
@@ -454,6 +691,70 @@ impl ArchetypeStorage {
     pub(crate) fn row_count(&self) -> usize {
         self.chunks.iter().map(|chunk| chunk.row_count()).sum()
     }
+
+    ///
+    /// Records access in the given frame, so archetype is not considered cold
+    ///
+    pub(crate) fn touch(&self, frame: u64) {
+        self.last_access.store(frame, Ordering::Relaxed);
+    }
+
+    ///
+    /// Number of frames passed since the last access
+    ///
+    pub(crate) fn idle_frames(&self, frame: u64) -> u64 {
+        frame.saturating_sub(self.last_access.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn is_frozen(&self) -> bool {
+        self.frozen.is_some()
+    }
+
+    ///
+    /// Size of compressed columns in bytes
+    ///
+    pub(crate) fn frozen_size(&self) -> usize {
+        self.frozen.iter().flatten().map(|chunk| chunk.size()).sum()
+    }
+
+    ///
+    /// Encodes and compresses all chunks. Rows keep their places, so references to them stay valid.
+    /// Refused (returns false) if storage is empty, some column has no codec (tags don't need one)
+    /// or there are rows waiting for deferred despawn.
+    ///
+    pub(crate) fn freeze(&mut self, codecs: &ColumnCodecs) -> bool {
+        if self.is_frozen()
+            || self.chunks.iter().all(|chunk| chunk.len() == 0)
+            || self.chunks.iter().any(|chunk| chunk.has_dead_rows())
+            || !self
+                .archetype
+                .factories
+                .iter()
+                .all(|(comp_id, f)| f.is_tag() || codecs.contains_key(comp_id))
+        {
+            return false;
+        }
+        self.frozen = Some(
+            self.chunks
+                .drain(..)
+                .map(|chunk| chunk.freeze(codecs))
+                .collect(),
+        );
+        true
+    }
+
+    ///
+    /// Decodes chunks of the frozen storage
+    ///
+    pub(crate) fn thaw(&mut self, codecs: &ColumnCodecs) {
+        let Some(frozen) = self.frozen.take() else {
+            return;
+        };
+        self.chunks = frozen
+            .into_iter()
+            .map(|chunk| chunk.thaw(self.archetype.new_chunk(self.chunk_size), codecs))
+            .collect();
+    }
 }
 
 ///
@@ -479,7 +780,7 @@ mod test {
 
     use crate::{
         archetype::{ArchetypeRef, ArchetypeStorage},
-        component::ComponentStorage,
+        component::DropHooks,
         entity::EntityId,
     };
 
@@ -502,9 +803,9 @@ mod test {
         assert_eq!(ArchetypeRef::new(0, 1), storage.add(EntityId::new(2)));
         assert_eq!(ArchetypeRef::new(0, 2), storage.add(EntityId::new(3)));
 
-        storage.remove(&ArchetypeRef::new(0, 0));
-        storage.remove(&ArchetypeRef::new(0, 0));
-        storage.remove(&ArchetypeRef::new(0, 0));
+        storage.remove(&ArchetypeRef::new(0, 0), &DropHooks::new());
+        storage.remove(&ArchetypeRef::new(0, 0), &DropHooks::new());
+        storage.remove(&ArchetypeRef::new(0, 0), &DropHooks::new());
 
         assert_eq!(ArchetypeRef::new(0, 0), storage.add(EntityId::new(4)));
 
@@ -517,7 +818,7 @@ mod test {
         assert!(storage.get_by_type::<i8>().is_none());
 
         assert!(storage.row_count() > 0);
-        storage.clear();
+        storage.clear(&DropHooks::new());
         assert_eq!(0, storage.row_count());
     }
 
@@ -1,9 +1,16 @@
 use std::{
     any::{Any, TypeId},
+    collections::HashMap,
     fmt::Debug,
     hash::Hash,
+    marker::PhantomData,
+    sync::Arc,
 };
 
+use bitcode::{DecodeOwned, Encode};
+
+use crate::entity::{EntityId, EntityMap};
+
 ///
 /// ComponentId
 ///
@@ -23,7 +30,7 @@ impl ComponentId {
 ///
 /// CoponentStorage trait
 ///
-pub trait ComponentStorage {
+pub trait ComponentStorage: Send + Sync {
     fn row_count(&self) -> usize;
 
     fn as_any(&self) -> &dyn Any;
@@ -37,6 +44,141 @@ pub trait ComponentStorage {
     fn move_to(&mut self, index: usize, dest: &mut dyn ComponentStorage);
 }
 
+///
+/// Callback invoked right before component value is dropped
+///
+pub(crate) trait DropHook: Send + Sync {
+    fn on_drop(&self, entity: EntityId, column: &mut dyn ComponentStorage, index: usize);
+}
+
+struct TypedDropHook<T, F> {
+    hook: F,
+    _data: PhantomData<fn(&mut T)>,
+}
+
+impl<T, F> DropHook for TypedDropHook<T, F>
+where
+    T: Default + 'static,
+    F: Fn(EntityId, &mut T) + Send + Sync,
+{
+    fn on_drop(&self, entity: EntityId, column: &mut dyn ComponentStorage, index: usize) {
+        if let Some(value) = cast_mut::<T>(column).get_mut(index) {
+            (self.hook)(entity, value);
+        }
+    }
+}
+
+pub(crate) fn drop_hook<T, F>(hook: F) -> Box<dyn DropHook>
+where
+    T: Default + 'static,
+    F: Fn(EntityId, &mut T) + Send + Sync + 'static,
+{
+    Box::new(TypedDropHook {
+        hook,
+        _data: PhantomData,
+    })
+}
+
+pub(crate) type DropHooks = HashMap<ComponentId, Box<dyn DropHook>>;
+
+///
+/// Callback fixing entity references stored in components when entities get new ids
+///
+pub(crate) trait RemapHook: Send + Sync {
+    fn remap(&self, column: &mut dyn ComponentStorage, map: &EntityMap);
+}
+
+struct TypedRemapHook<T, F> {
+    hook: F,
+    _data: PhantomData<fn(&mut T)>,
+}
+
+impl<T, F> RemapHook for TypedRemapHook<T, F>
+where
+    T: Default + 'static,
+    F: Fn(&mut T, &EntityMap) + Send + Sync,
+{
+    fn remap(&self, column: &mut dyn ComponentStorage, map: &EntityMap) {
+        for value in cast_mut::<T>(column).iter_mut() {
+            (self.hook)(value, map);
+        }
+    }
+}
+
+pub(crate) fn remap_hook<T, F>(hook: F) -> Arc<dyn RemapHook>
+where
+    T: Default + 'static,
+    F: Fn(&mut T, &EntityMap) + Send + Sync + 'static,
+{
+    Arc::new(TypedRemapHook {
+        hook,
+        _data: PhantomData,
+    })
+}
+
+///
+/// Runs both hooks, so several entity reference fields of the same component are remapped
+///
+struct ChainedRemapHook(Arc<dyn RemapHook>, Arc<dyn RemapHook>);
+
+impl RemapHook for ChainedRemapHook {
+    fn remap(&self, column: &mut dyn ComponentStorage, map: &EntityMap) {
+        self.0.remap(column, map);
+        self.1.remap(column, map);
+    }
+}
+
+pub(crate) fn chain_remap_hooks(
+    first: Arc<dyn RemapHook>,
+    second: Arc<dyn RemapHook>,
+) -> Arc<dyn RemapHook> {
+    Arc::new(ChainedRemapHook(first, second))
+}
+
+pub(crate) type RemapHooks = HashMap<ComponentId, Arc<dyn RemapHook>>;
+
+///
+/// Converts column values to bytes and back, so columns of cold archetypes may be kept compressed
+///
+pub(crate) trait ColumnCodec: Send + Sync {
+    fn encode(&self, column: &dyn ComponentStorage) -> Vec<u8>;
+
+    ///
+    /// Appends decoded values to the column
+    ///
+    fn decode(&self, bytes: &[u8], column: &mut dyn ComponentStorage)
+        -> Result<(), bitcode::Error>;
+}
+
+struct TypedColumnCodec<T>(PhantomData<fn(&T) -> T>);
+
+impl<T> ColumnCodec for TypedColumnCodec<T>
+where
+    T: Default + Encode + DecodeOwned + 'static,
+{
+    fn encode(&self, column: &dyn ComponentStorage) -> Vec<u8> {
+        bitcode::encode(cast::<T>(column))
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        column: &mut dyn ComponentStorage,
+    ) -> Result<(), bitcode::Error> {
+        cast_mut::<T>(column).extend(bitcode::decode::<Vec<T>>(bytes)?);
+        Ok(())
+    }
+}
+
+pub(crate) fn column_codec<T>() -> Arc<dyn ColumnCodec>
+where
+    T: Default + Encode + DecodeOwned + 'static,
+{
+    Arc::new(TypedColumnCodec::<T>(PhantomData))
+}
+
+pub(crate) type ColumnCodecs = HashMap<ComponentId, Arc<dyn ColumnCodec>>;
+
 ///
 /// Helper functions
 ///
@@ -75,7 +217,7 @@ pub(crate) fn cast_mut<'a, T: 'static>(
 ///
 pub(crate) type TypedComponentStorage<T> = Vec<T>;
 
-impl<T: Any + Default + 'static> ComponentStorage for TypedComponentStorage<T> {
+impl<T: Any + Default + Send + Sync + 'static> ComponentStorage for TypedComponentStorage<T> {
     fn as_any(&self) -> &dyn Any {
         self
     }
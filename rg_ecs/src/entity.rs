@@ -1,30 +1,132 @@
 use std::{
-    collections::{hash_map::Values, HashMap, HashSet},
+    collections::{hash_map::Entry, HashMap, HashSet},
     fmt::Debug,
     sync::{
         atomic::{AtomicU32, Ordering},
-        RwLock, RwLockReadGuard,
+        Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
     },
 };
 
+use bitcode::{Decode, DecodeOwned, Encode};
+
 use crate::{
-    archetype::{Archetype, ArchetypeId, ArchetypeRef, ArchetypeStorage, Chunk},
+    archetype::{
+        Archetype, ArchetypeBuilder, ArchetypeId, ArchetypeRef, ArchetypeStorage, Chunk,
+        COLUMN_ENTITY_ID,
+    },
     build_archetype,
-    component::{cast, cast_mut, ComponentId, ComponentStorage},
+    component::{
+        cast, cast_mut, chain_remap_hooks, column_codec, drop_hook, remap_hook, ColumnCodec,
+        ColumnCodecs, ComponentId, ComponentStorage, DropHook, DropHooks, RemapHook, RemapHooks,
+    },
     error::EntityError,
+    visitor::{Locker, Visitor1, Visitor2},
 };
 
 ///
 /// EntityId
 ///
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug, Encode, Decode)]
 #[repr(transparent)]
 pub struct EntityId(u32);
 
 impl EntityId {
+    ///
+    /// Marks ids of client-only entities, so they never collide with ids of replicated ones
+    ///
+    const LOCAL_BIT: u32 = 1 << 31;
+
     pub fn new(id: u32) -> Self {
         EntityId(id)
     }
+
+    pub fn id(&self) -> u32 {
+        self.0
+    }
+
+    ///
+    /// Checks if entity is client-only (particles, decals, UI widgets). Such entities are never
+    /// replicated or stored in snapshots and are removed by [`Entities::remove_local`].
+    ///
+    pub fn is_local(&self) -> bool {
+        self.0 & Self::LOCAL_BIT != 0
+    }
+}
+
+///
+/// Old to new entity id mapping produced by [`Entities::append`] or built by snapshot loader
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EntityMap(HashMap<EntityId, EntityId>);
+
+impl EntityMap {
+    ///
+    /// Returns new id of the appended entity or None if entity wasn't appended
+    ///
+    pub fn get(&self, id: EntityId) -> Option<EntityId> {
+        self.0.get(&id).copied()
+    }
+
+    ///
+    /// Returns new id of the entity or the same id if it's not in the map
+    ///
+    pub fn translate(&self, id: EntityId) -> EntityId {
+        self.get(id).unwrap_or(id)
+    }
+
+    pub fn insert(&mut self, old: EntityId, new: EntityId) {
+        self.0.insert(old, new);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, EntityId)> + '_ {
+        self.0.iter().map(|(k, v)| (*k, *v))
+    }
+}
+
+impl FromIterator<(EntityId, EntityId)> for EntityMap {
+    fn from_iter<I: IntoIterator<Item = (EntityId, EntityId)>>(iter: I) -> Self {
+        EntityMap(iter.into_iter().collect())
+    }
+}
+
+///
+/// Component field holding references to other entities, see [`Entities::on_entity_ref`]
+///
+pub trait EntityRefs {
+    ///
+    /// Replaces ids found in the map, others are kept as they may refer to entities which are not moved
+    ///
+    fn remap(&mut self, map: &EntityMap);
+}
+
+impl EntityRefs for EntityId {
+    fn remap(&mut self, map: &EntityMap) {
+        *self = map.translate(*self);
+    }
+}
+
+impl EntityRefs for Option<EntityId> {
+    fn remap(&mut self, map: &EntityMap) {
+        if let Some(id) = self {
+            id.remap(map);
+        }
+    }
+}
+
+impl EntityRefs for Vec<EntityId> {
+    fn remap(&mut self, map: &EntityMap) {
+        for id in self.iter_mut() {
+            id.remap(map);
+        }
+    }
 }
 
 ///
@@ -52,12 +154,140 @@ impl EntityRef {
 type EntityRefMap = HashMap<EntityId, EntityRef>;
 type ArchetypeMap = HashMap<ArchetypeId, RwLock<ArchetypeStorage>>;
 
+///
+/// Archetypes matching every set of columns visited so far. Kept up to date as archetypes are added,
+/// so visiting costs proportionally to the number of matching archetypes, not all of them.
+///
+#[derive(Default)]
+struct QueryCache(RwLock<HashMap<Vec<ComponentId>, Arc<Vec<ArchetypeId>>>>);
+
+impl QueryCache {
+    fn signature(columns: &HashSet<ComponentId>) -> Vec<ComponentId> {
+        let mut result: Vec<_> = columns.iter().copied().collect();
+        result.sort_unstable();
+        result
+    }
+
+    fn matching<F>(&self, columns: &HashSet<ComponentId>, find: F) -> Arc<Vec<ArchetypeId>>
+    where
+        F: FnOnce() -> Vec<ArchetypeId>,
+    {
+        let signature = Self::signature(columns);
+        if let Some(ids) = self.0.read().unwrap().get(&signature) {
+            return Arc::clone(ids);
+        }
+        Arc::clone(
+            self.0
+                .write()
+                .unwrap()
+                .entry(signature)
+                .or_insert_with(|| Arc::new(find())),
+        )
+    }
+
+    fn add(&mut self, archetype: &Archetype) {
+        for (signature, ids) in self.0.get_mut().unwrap().iter_mut() {
+            if signature.iter().all(|c| archetype.has_component(c)) {
+                Arc::make_mut(ids).push(archetype.id);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.0.get_mut().unwrap().clear();
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.0.read().unwrap().len()
+    }
+}
+
+///
+/// Compression of archetypes which were not accessed for a while, see [`Entities::compact`].
+/// Storage locks are taken through it, so frozen archetype is thawed on the first access.
+///
+struct Compaction {
+    codecs: ColumnCodecs,
+    // Number of compaction passes, serves as a clock for access stamps
+    frame: u64,
+}
+
+impl Compaction {
+    fn new() -> Self {
+        Compaction {
+            codecs: HashMap::from([(*COLUMN_ENTITY_ID, column_codec::<EntityId>())]),
+            frame: 0,
+        }
+    }
+
+    fn read<'a>(
+        &self,
+        lock: &'a RwLock<ArchetypeStorage>,
+    ) -> Result<RwLockReadGuard<'a, ArchetypeStorage>, EntityError> {
+        loop {
+            let guard = lock.read()?;
+            if !guard.is_frozen() {
+                guard.touch(self.frame);
+                return Ok(guard);
+            }
+            drop(guard);
+            // Blocking here may deadlock with the thread holding read lock and waiting for another one
+            match lock.try_write() {
+                Ok(mut guard) => guard.thaw(&self.codecs),
+                Err(TryLockError::WouldBlock) => std::thread::yield_now(),
+                Err(TryLockError::Poisoned(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn write<'a>(
+        &self,
+        lock: &'a RwLock<ArchetypeStorage>,
+    ) -> Result<RwLockWriteGuard<'a, ArchetypeStorage>, EntityError> {
+        let mut guard = lock.write()?;
+        self.thaw(&mut guard);
+        Ok(guard)
+    }
+
+    fn thaw(&self, storage: &mut ArchetypeStorage) {
+        storage.thaw(&self.codecs);
+        storage.touch(self.frame);
+    }
+
+    ///
+    /// Freezes archetypes which were not accessed for `idle_frames`, returns number of frozen ones
+    ///
+    fn run(
+        &mut self,
+        archetypes: &mut ArchetypeMap,
+        idle_frames: u64,
+    ) -> Result<usize, EntityError> {
+        self.frame += 1;
+        let mut count = 0;
+        for lock in archetypes.values_mut() {
+            let storage = lock.get_mut()?;
+            if storage.idle_frames(self.frame) >= idle_frames && storage.freeze(&self.codecs) {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
 pub(crate) struct EntityStorage {
     def_arch_id: ArchetypeId,
     chunk_size_in_bytes: usize,
     entity_seq: AtomicU32,
+    local_seq: AtomicU32,
     entities: EntityRefMap,
     archetypes: ArchetypeMap,
+    queries: QueryCache,
+    // Entities to remove by flush, dead rows are marked by chunk tombstones
+    despawned: Mutex<Vec<EntityId>>,
+    hooks: DropHooks,
+    remap_hooks: RemapHooks,
+    compaction: Compaction,
 }
 
 impl EntityStorage {
@@ -71,27 +301,64 @@ impl EntityStorage {
             def_arch_id,
             chunk_size_in_bytes,
             entity_seq: AtomicU32::new(0),
+            local_seq: AtomicU32::new(0),
             entities: HashMap::with_capacity(chunk_size_in_bytes),
             archetypes,
+            queries: QueryCache::default(),
+            despawned: Mutex::new(Vec::new()),
+            hooks: DropHooks::new(),
+            remap_hooks: RemapHooks::new(),
+            compaction: Compaction::new(),
         }
     }
 
     fn add_archetype(&mut self, archetype: Archetype) -> ArchetypeId {
         let arc_id = archetype.id;
-        let arc_storage = ArchetypeStorage::new(archetype, self.chunk_size_in_bytes);
-        self.archetypes.insert(arc_id, RwLock::new(arc_storage));
+        if let Entry::Vacant(entry) = self.archetypes.entry(arc_id) {
+            self.queries.add(&archetype);
+            entry.insert(RwLock::new(ArchetypeStorage::new(
+                archetype,
+                self.chunk_size_in_bytes,
+            )));
+        }
         arc_id
     }
 
-    fn add(&mut self, archetype: Option<ArchetypeId>) -> Result<EntityId, EntityError> {
+    ///
+    /// Reserves `count` consecutive ids in the regular or local domain, returns the first one
+    ///
+    fn reserve_ids(&self, count: u32, local: bool) -> Result<u32, EntityError> {
+        let seq = if local {
+            &self.local_seq
+        } else {
+            &self.entity_seq
+        };
+        let base = seq.fetch_add(count, Ordering::Relaxed);
+        if base
+            .checked_add(count)
+            .is_none_or(|v| v > EntityId::LOCAL_BIT)
+        {
+            return Err(EntityError::OutOfIds);
+        }
+        Ok(if local {
+            base | EntityId::LOCAL_BIT
+        } else {
+            base
+        })
+    }
+
+    fn add(
+        &mut self,
+        archetype: Option<ArchetypeId>,
+        local: bool,
+    ) -> Result<EntityId, EntityError> {
         let arch_id = archetype.unwrap_or(self.def_arch_id);
-        let seq = self.entity_seq.fetch_add(1, Ordering::Relaxed);
-        let ent_id = EntityId(seq);
-        let mut storage = self
-            .archetypes
-            .get(&arch_id)
-            .ok_or(EntityError::NoSuchArchetype)?
-            .write()?;
+        let ent_id = EntityId(self.reserve_ids(1, local)?);
+        let mut storage = self.compaction.write(
+            self.archetypes
+                .get(&arch_id)
+                .ok_or(EntityError::NoSuchArchetype)?,
+        )?;
         let arch_ref = storage.add(ent_id);
         let ent_ref = EntityRef {
             archetype: arch_id,
@@ -101,6 +368,31 @@ impl EntityStorage {
         Ok(ent_id)
     }
 
+    ///
+    /// Adds entity straight into archetype built from supplied values, so it's never moved
+    ///
+    fn spawn(
+        &mut self,
+        archetype: Archetype,
+        values: Vec<Box<dyn SpawnValue>>,
+        local: bool,
+    ) -> Result<EntityId, EntityError> {
+        let arch_id = self.add_archetype(archetype);
+        let ent_id = EntityId(self.reserve_ids(1, local)?);
+        let mut storage = self.compaction.write(&self.archetypes[&arch_id])?;
+        let arch_ref = storage.add(ent_id);
+        for value in values {
+            let column = storage
+                .get_at(value.component_id(), arch_ref.chunk_index())
+                .ok_or(EntityError::NoSuchArchetype)?;
+            value.write(column.write()?.as_mut(), arch_ref.local_index());
+        }
+        drop(storage);
+        self.entities
+            .insert(ent_id, EntityRef::new(arch_id, arch_ref));
+        Ok(ent_id)
+    }
+
     fn get<T, F, R>(&self, entity: EntityId, consumer: F) -> Option<R>
     where
         T: Default + 'static,
@@ -108,7 +400,13 @@ impl EntityStorage {
         F: FnOnce(Option<&T>) -> R,
     {
         let e_ref = self.entities.get(&entity)?;
-        let storage = self.archetypes.get(&e_ref.archetype)?.read().ok()?;
+        let storage = self
+            .compaction
+            .read(self.archetypes.get(&e_ref.archetype)?)
+            .ok()?;
+        if storage.is_dead(&e_ref.arch_ref) {
+            return None;
+        }
         let column = storage.get_at(ComponentId::new::<T>(), e_ref.arch_ref.chunk_index())?;
         let guard = column.read().unwrap();
         Some(consumer(
@@ -116,6 +414,31 @@ impl EntityStorage {
         ))
     }
 
+    fn has(&self, entity: EntityId, comp_id: &ComponentId) -> bool {
+        self.with_live_storage(entity, |storage| storage.archetype.has_component(comp_id))
+    }
+
+    fn is_alive(&self, entity: EntityId) -> bool {
+        self.with_live_storage(entity, |_| true)
+    }
+
+    ///
+    /// Calls `f` with storage of entity unless it doesn't exist or waits for despawn.
+    /// Frozen storage is not thawed as it never has dead rows.
+    ///
+    fn with_live_storage<F>(&self, entity: EntityId, f: F) -> bool
+    where
+        F: FnOnce(&ArchetypeStorage) -> bool,
+    {
+        let Some(e_ref) = self.entities.get(&entity) else {
+            return false;
+        };
+        self.archetypes
+            .get(&e_ref.archetype)
+            .and_then(|storage| storage.read().ok())
+            .is_some_and(|storage| !storage.is_dead(&e_ref.arch_ref) && f(&storage))
+    }
+
     fn move_and_set<T>(
         &mut self,
         entity: EntityId,
@@ -126,12 +449,9 @@ impl EntityStorage {
     where
         T: Default + 'static,
     {
-        let dest_arch_id = dest_arch.id;
-        self.archetypes.entry(dest_arch_id).or_insert_with(|| {
-            RwLock::new(ArchetypeStorage::new(dest_arch, self.chunk_size_in_bytes))
-        });
-        let mut dest = self.archetypes[&dest_arch_id].write()?;
-        let base = self.archetypes[&ent_ref.archetype].read()?;
+        let dest_arch_id = self.add_archetype(dest_arch);
+        let mut dest = self.compaction.write(&self.archetypes[&dest_arch_id])?;
+        let base = self.compaction.read(&self.archetypes[&ent_ref.archetype])?;
         let (arch_ref, swapped_ent_id) = base.move_to(&mut dest, &ent_ref.arch_ref, value)?;
         self.entities
             .insert(entity, EntityRef::new(dest_arch_id, arch_ref));
@@ -144,22 +464,29 @@ impl EntityStorage {
 
     fn set<T>(&mut self, entity: EntityId, value: T) -> Result<(), EntityError>
     where
-        T: Default + 'static,
+        T: Default + Send + Sync + 'static,
     {
         let comp_id = ComponentId::new::<T>();
         let ent_ref = self
             .entities
             .get(&entity)
-            .ok_or_else(|| EntityError::NotFound)?
+            .ok_or(EntityError::NotFound)?
             .clone();
-        let base = self
-            .archetypes
-            .get(&ent_ref.archetype)
-            .ok_or_else(|| EntityError::NotFound)?
-            .read()?;
+        let base = self.compaction.read(
+            self.archetypes
+                .get(&ent_ref.archetype)
+                .ok_or(EntityError::NotFound)?,
+        )?;
+        if base.is_dead(&ent_ref.arch_ref) {
+            return Err(EntityError::NotFound);
+        }
         if let Some(column) = base.get_at(comp_id, ent_ref.arch_ref.chunk_index()) {
             let mut guard = column.write()?;
-            cast_mut::<T>(guard.as_mut())[ent_ref.arch_ref.local_index()] = value;
+            let index = ent_ref.arch_ref.local_index();
+            if let Some(hook) = self.hooks.get(&comp_id) {
+                hook.on_drop(entity, guard.as_mut(), index);
+            }
+            cast_mut::<T>(guard.as_mut())[index] = value;
             Ok(())
         } else {
             let dest_arch = base.archetype.to_builder().add::<T>().build();
@@ -176,13 +503,43 @@ impl EntityStorage {
             .get(&ent_ref.archetype)
             .ok_or(EntityError::NoSuchArchetype)?;
         // Remove entitie's row from storage
-        if let Some(swapped_ent_id) = storage.read().unwrap().remove(&ent_ref.arch_ref) {
+        if let Some(swapped_ent_id) = self
+            .compaction
+            .read(storage)?
+            .remove(&ent_ref.arch_ref, &self.hooks)
+        {
             // Fix swapped entity reference
             self.entities.insert(swapped_ent_id, ent_ref);
         }
         Ok(())
     }
 
+    fn despawn_deferred(&self, entity: EntityId) -> Result<(), EntityError> {
+        let ent_ref = self.entities.get(&entity).ok_or(EntityError::NotFound)?;
+        let storage = self.compaction.read(
+            self.archetypes
+                .get(&ent_ref.archetype)
+                .ok_or(EntityError::NoSuchArchetype)?,
+        )?;
+        if storage.mark_dead(&ent_ref.arch_ref)? {
+            self.despawned.lock()?.push(entity);
+        }
+        Ok(())
+    }
+
+    fn flush_despawns(&mut self) -> Result<usize, EntityError> {
+        let despawned = std::mem::take(self.despawned.get_mut()?);
+        let mut count = 0;
+        for entity in despawned {
+            // May be removed directly after it was marked
+            if self.entities.contains_key(&entity) {
+                self.remove(entity)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
     fn visit<H>(&self, columns: &HashSet<ComponentId>, handler: H) -> (usize, usize, usize)
     where
         H: Fn(&Chunk) -> usize,
@@ -190,11 +547,21 @@ impl EntityStorage {
         let mut arch_count: usize = 0;
         let mut chunk_count: usize = 0;
         let mut row_count: usize = 0;
-        for v in self.archetypes.values() {
-            let guard = v.read().unwrap();
-            if !columns.iter().all(|c| guard.archetype.has_component(c)) {
+        let matching = self.queries.matching(columns, || {
+            self.archetypes
+                .iter()
+                .filter(|(_, v)| {
+                    let guard = v.read().unwrap();
+                    columns.iter().all(|c| guard.archetype.has_component(c))
+                })
+                .map(|(id, _)| *id)
+                .collect()
+        });
+        for id in matching.iter() {
+            let Some(v) = self.archetypes.get(id) else {
                 continue;
-            }
+            };
+            let guard = self.compaction.read(v).unwrap();
             for chunk in guard.iter() {
                 row_count += (handler)(chunk);
                 chunk_count += 1;
@@ -206,13 +573,109 @@ impl EntityStorage {
 
     fn clear(&mut self) {
         self.entities.clear();
-        for (_, lock) in self.archetypes.iter() {
-            lock.write().unwrap().clear();
+        self.despawned.get_mut().unwrap().clear();
+        for lock in self.archetypes.values_mut() {
+            let storage = lock.get_mut().unwrap();
+            // Hooks are called for every value, so values have to be decoded
+            if !self.hooks.is_empty() {
+                self.compaction.thaw(storage);
+            }
+            storage.clear(&self.hooks);
         }
     }
 
-    pub(crate) fn archetypes(&self) -> Values<'_, ArchetypeId, RwLock<ArchetypeStorage>> {
-        self.archetypes.values()
+    fn set_drop_hook(&mut self, comp_id: ComponentId, hook: Box<dyn DropHook>) {
+        self.hooks.insert(comp_id, hook);
+    }
+
+    fn set_remap_hook(&mut self, comp_id: ComponentId, hook: Arc<dyn RemapHook>) {
+        self.remap_hooks.insert(comp_id, hook);
+    }
+
+    fn add_remap_hook(&mut self, comp_id: ComponentId, hook: Arc<dyn RemapHook>) {
+        let hook = match self.remap_hooks.remove(&comp_id) {
+            Some(prev) => chain_remap_hooks(prev, hook),
+            None => hook,
+        };
+        self.remap_hooks.insert(comp_id, hook);
+    }
+
+    ///
+    /// Fixes entity references of all components in place
+    ///
+    fn remap(&mut self, map: &EntityMap) -> Result<(), EntityError> {
+        for storage in self.archetypes.values_mut() {
+            let storage = storage.get_mut()?;
+            self.compaction.thaw(storage);
+            storage.remap(map, &self.remap_hooks);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Takes out all archetype storages, entities are expected to be remapped already
+    ///
+    fn take_archetypes(&mut self) -> ArchetypeMap {
+        for storage in self.archetypes.values_mut() {
+            self.compaction.thaw(storage.get_mut().unwrap());
+        }
+        self.entities.clear();
+        self.queries.clear();
+        std::mem::take(&mut self.archetypes)
+    }
+
+    ///
+    /// Moves chunks of other world into this one
+    ///
+    fn append(&mut self, archetypes: ArchetypeMap) -> Result<(), EntityError> {
+        for (arch_id, storage) in archetypes {
+            let storage = storage.into_inner()?;
+            if !self.archetypes.contains_key(&arch_id) {
+                self.add_archetype(storage.archetype.clone());
+            }
+            let dest = self.archetypes.get_mut(&arch_id).unwrap().get_mut()?;
+            self.compaction.thaw(dest);
+            for (entity, arch_ref) in dest.append(storage) {
+                self.entities
+                    .insert(entity, EntityRef::new(arch_id, arch_ref));
+            }
+        }
+        Ok(())
+    }
+
+    fn set_codec(&mut self, comp_id: ComponentId, codec: Arc<dyn ColumnCodec>) {
+        self.compaction.codecs.insert(comp_id, codec);
+    }
+
+    fn compact(&mut self, idle_frames: u64) -> Result<usize, EntityError> {
+        self.compaction.run(&mut self.archetypes, idle_frames)
+    }
+
+    fn compressed_size(&self) -> usize {
+        self.archetypes
+            .values()
+            .map(|lock| lock.read().unwrap().frozen_size())
+            .sum()
+    }
+
+    ///
+    /// Returns read guards of all archetype storages, frozen storage is thawed when iterator reaches it
+    ///
+    pub(crate) fn archetypes(
+        &self,
+    ) -> impl Iterator<Item = Result<RwLockReadGuard<'_, ArchetypeStorage>, EntityError>> {
+        self.archetypes
+            .values()
+            .map(|lock| self.compaction.read(lock))
+    }
+}
+
+impl Drop for EntityStorage {
+    fn drop(&mut self) {
+        // Let hooks release resources of the remaining components
+        if !self.hooks.is_empty() {
+            self.clear();
+        }
     }
 }
 
@@ -234,7 +697,7 @@ impl Entities {
     }
 
     ///
-    /// Adds new archetype to this storage
+    /// Adds new archetype to this storage. Already known archetype is left intact.
     ///
     #[inline]
     pub fn add_archetype(&self, archetype: Archetype) -> ArchetypeId {
@@ -246,7 +709,43 @@ impl Entities {
     ///
     #[inline]
     pub fn add(&self, archetype: Option<ArchetypeId>) -> Result<EntityId, EntityError> {
-        self.storage.write().unwrap().add(archetype)
+        self.storage.write().unwrap().add(archetype, false)
+    }
+
+    ///
+    /// Adds new client-only entity, see [`EntityId::is_local`]
+    ///
+    #[inline]
+    pub fn add_local(&self, archetype: Option<ArchetypeId>) -> Result<EntityId, EntityError> {
+        self.storage.write().unwrap().add(archetype, true)
+    }
+
+    ///
+    /// Starts building new entity, see [`SpawnBuilder`]
+    ///
+    pub fn spawn(&self) -> SpawnBuilder<'_> {
+        SpawnBuilder {
+            entities: self,
+            values: Vec::new(),
+            local: false,
+        }
+    }
+
+    ///
+    /// Removes all client-only entities (when server world is reset), returns number of removed entities
+    ///
+    pub fn remove_local(&self) -> Result<usize, EntityError> {
+        let mut guard = self.storage.write()?;
+        let local: Vec<_> = guard
+            .entities
+            .keys()
+            .filter(|e| e.is_local())
+            .copied()
+            .collect();
+        for entity in local.iter() {
+            guard.remove(*entity)?;
+        }
+        Ok(local.len())
     }
 
     ///
@@ -256,7 +755,7 @@ impl Entities {
     #[inline]
     pub fn set<T>(&self, entity: EntityId, value: T) -> Result<(), EntityError>
     where
-        T: Default + 'static,
+        T: Default + Send + Sync + 'static,
     {
         self.storage.write().unwrap().set(entity, value)
     }
@@ -274,6 +773,20 @@ impl Entities {
         self.storage.read().unwrap().get(entity, consumer)
     }
 
+    ///
+    /// Checks if entity has component. Doesn't touch column data, so it's the way to test tags.
+    ///
+    #[inline]
+    pub fn has<T>(&self, entity: EntityId) -> bool
+    where
+        T: 'static,
+    {
+        self.storage
+            .read()
+            .unwrap()
+            .has(entity, &ComponentId::new::<T>())
+    }
+
     ///
     /// Removes entity from storage
     ///
@@ -282,6 +795,35 @@ impl Entities {
         self.storage.write().unwrap().remove(entity)
     }
 
+    ///
+    /// Marks entity as dead. It's not visible to queries anymore, but its storage is reclaimed
+    /// only by [`Entities::flush_despawns`], so it's safe to call this while iterating.
+    ///
+    #[inline]
+    pub fn despawn_deferred(&self, entity: EntityId) -> Result<(), EntityError> {
+        self.storage.read().unwrap().despawn_deferred(entity)
+    }
+
+    ///
+    /// Removes all entities marked by [`Entities::despawn_deferred`]. Expected to be called at the end of frame.
+    /// Returns number of removed entities.
+    ///
+    pub fn flush_despawns(&self) -> Result<usize, EntityError> {
+        self.storage.write().unwrap().flush_despawns()
+    }
+
+    ///
+    /// Checks if entity exists and is not marked for despawn
+    ///
+    pub fn is_alive(&self, entity: EntityId) -> bool {
+        let guard = self.storage.read().unwrap();
+        guard.is_alive(entity)
+    }
+
+    ///
+    /// Passes every chunk having all of the `columns` to `handler`.
+    /// Rows of entities waiting for despawn are still there, use [`Chunk::is_dead`] to skip them.
+    ///
     pub fn visit<H>(&self, columns: &HashSet<ComponentId>, handler: H) -> (usize, usize, usize)
     where
         H: Fn(&Chunk) -> usize,
@@ -289,6 +831,158 @@ impl Entities {
         self.storage.read().unwrap().visit(columns, handler)
     }
 
+    ///
+    /// Passes every live entity having argument's column to `handler`, returns number of visited entities.
+    /// Optional argument (`Option<&T>`) makes every entity match, `None` is passed if it has no such component.
+    ///
+    pub fn visit_1<A, H>(&self, handler: H) -> usize
+    where
+        A: Locker,
+        H: Fn(A::Item<'_>),
+    {
+        let visitor = Visitor1::<A, H>::new(handler);
+        let (_, _, rows) = self.visit(&visitor.columns(), |chunk| visitor.visit(chunk));
+        rows
+    }
+
+    ///
+    /// Same as [`Entities::visit_1`] for two arguments
+    ///
+    pub fn visit_2<A, B, H>(&self, handler: H) -> usize
+    where
+        A: Locker,
+        B: Locker,
+        H: Fn(A::Item<'_>, B::Item<'_>),
+    {
+        let visitor = Visitor2::<A, B, H>::new(handler);
+        let (_, _, rows) = self.visit(&visitor.columns(), |chunk| visitor.visit(chunk));
+        rows
+    }
+
+    ///
+    /// Registers callback invoked right before component value of type `T` is dropped: when entity is removed
+    /// (directly or by [`Entities::flush_despawns`]), storage is cleared or dropped, or value is replaced by
+    /// [`Entities::set`]. Moving entity between archetypes keeps the value, so hook is not called.
+    /// Replaces previously registered hook for the same type.
+    ///
+    pub fn on_drop<T, F>(&self, hook: F)
+    where
+        T: Default + 'static,
+        F: Fn(EntityId, &mut T) + Send + Sync + 'static,
+    {
+        self.storage
+            .write()
+            .unwrap()
+            .set_drop_hook(ComponentId::new::<T>(), drop_hook(hook));
+    }
+
+    ///
+    /// Registers callback fixing references to other entities stored in components of type `T`
+    /// (like parent of the entity) when entities are moved to this world by [`Entities::append`].
+    /// References to entities which are not appended are not in the map, hook decides what to do with them.
+    ///
+    pub fn on_remap<T, F>(&self, hook: F)
+    where
+        T: Default + 'static,
+        F: Fn(&mut T, &EntityMap) + Send + Sync + 'static,
+    {
+        self.storage
+            .write()
+            .unwrap()
+            .set_remap_hook(ComponentId::new::<T>(), remap_hook(hook));
+    }
+
+    ///
+    /// Declares field of component `T` referring to other entities (target, owner), so it's fixed
+    /// by [`Entities::append`] and [`Entities::remap`]. Unlike [`Entities::on_remap`] doesn't replace
+    /// hooks registered before, so each field of the component is declared separately.
+    ///
+    pub fn on_entity_ref<T, R>(&self, field: fn(&mut T) -> &mut R)
+    where
+        T: Default + 'static,
+        R: EntityRefs + 'static,
+    {
+        self.storage.write().unwrap().add_remap_hook(
+            ComponentId::new::<T>(),
+            remap_hook(move |value: &mut T, map| field(value).remap(map)),
+        );
+    }
+
+    ///
+    /// Allows columns of component `T` to be compressed when archetype gets cold, see [`Entities::compact`]
+    ///
+    pub fn allow_compression<T>(&self)
+    where
+        T: Default + Encode + DecodeOwned + 'static,
+    {
+        self.storage
+            .write()
+            .unwrap()
+            .set_codec(ComponentId::new::<T>(), column_codec::<T>());
+    }
+
+    ///
+    /// Compresses archetypes which were not accessed for `idle_frames` calls of this method
+    /// (like entities of far away regions which were streamed out), trading CPU for memory.
+    /// Expected to be called once per frame. Archetype is decompressed on the first access, so it's
+    /// transparent for callers except the time it takes. Only archetypes having all columns allowed
+    /// by [`Entities::allow_compression`] (tags excluded) and no rows waiting for despawn are compressed.
+    /// Returns number of compressed archetypes.
+    ///
+    pub fn compact(&self, idle_frames: u64) -> Result<usize, EntityError> {
+        self.storage.write()?.compact(idle_frames)
+    }
+
+    ///
+    /// Memory taken by compressed archetypes in bytes
+    ///
+    pub fn compressed_size(&self) -> usize {
+        self.storage.read().unwrap().compressed_size()
+    }
+
+    ///
+    /// Translates entity references stored in components of all entities, e.g. after loading
+    /// a snapshot where entities got new ids. Only ids of the references are changed, not ids of the entities.
+    ///
+    pub fn remap(&self, map: &EntityMap) -> Result<(), EntityError> {
+        self.storage.write()?.remap(map)
+    }
+
+    ///
+    /// Moves all entities of `other` world (built offline or on worker thread) to this one.
+    /// Entities get new ids, returned map may be used to translate ids kept elsewhere.
+    /// Entities waiting for deferred despawn in `other` are dropped.
+    /// Ids are reserved and components are remapped before taking the write lock which is held
+    /// only to move whole chunks, so the cost of locking doesn't depend on the number of entities.
+    ///
+    pub fn append(&self, other: Entities) -> Result<EntityMap, EntityError> {
+        let mut source = other.storage.into_inner()?;
+        source.flush_despawns()?;
+        let mut ids: Vec<_> = source.entities.keys().copied().collect();
+        ids.sort_unstable();
+        // Local entities stay local
+        let (local, regular): (Vec<_>, Vec<_>) = ids.into_iter().partition(|e| e.is_local());
+        let (map, hooks) = {
+            let guard = self.storage.read()?;
+            let mut map = HashMap::with_capacity(local.len() + regular.len());
+            for (ids, is_local) in [(regular, false), (local, true)] {
+                let base = guard.reserve_ids(ids.len() as u32, is_local)?;
+                map.extend(
+                    ids.into_iter()
+                        .enumerate()
+                        .map(|(i, id)| (id, EntityId(base + i as u32))),
+                );
+            }
+            (EntityMap(map), guard.remap_hooks.clone())
+        };
+        let mut archetypes = source.take_archetypes();
+        for storage in archetypes.values_mut() {
+            storage.get_mut()?.remap(&map, &hooks);
+        }
+        self.storage.write()?.append(archetypes)?;
+        Ok(map)
+    }
+
     ///
     /// Removes all entities from storage
     ///
@@ -296,21 +990,187 @@ impl Entities {
         self.storage.write().unwrap().clear();
     }
 
+    ///
+    /// Read-only view of entities which may be shared between threads, see [`WorldView`]
+    ///
+    pub fn view(&self) -> WorldView<'_> {
+        WorldView {
+            storage: self.storage.read().unwrap(),
+        }
+    }
+
     #[doc(hidden)]
     pub(crate) fn read(&self) -> RwLockReadGuard<'_, EntityStorage> {
         self.storage.read().unwrap()
     }
 }
 
+///
+/// Type-erased component value of the entity being spawned
+///
+trait SpawnValue: Send {
+    fn component_id(&self) -> ComponentId;
+
+    fn add_column(&self, builder: ArchetypeBuilder) -> ArchetypeBuilder;
+
+    fn write(self: Box<Self>, column: &mut dyn ComponentStorage, index: usize);
+}
+
+struct TypedSpawnValue<T>(T);
+
+impl<T> SpawnValue for TypedSpawnValue<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    fn component_id(&self) -> ComponentId {
+        ComponentId::new::<T>()
+    }
+
+    fn add_column(&self, builder: ArchetypeBuilder) -> ArchetypeBuilder {
+        builder.add::<T>()
+    }
+
+    fn write(self: Box<Self>, column: &mut dyn ComponentStorage, index: usize) {
+        cast_mut::<T>(column)[index] = self.0;
+    }
+}
+
+///
+/// Collects components of the new entity, so final archetype is computed once and all values are written
+/// into a single row. Calling [`Entities::set`] for each component instead moves entity between archetypes
+/// on every new component.
+///
+/// ```ignore
+/// let id = entities.spawn().with(Position(..)).with(Velocity(..)).finish()?;
+/// ```
+///
+#[must_use]
+pub struct SpawnBuilder<'a> {
+    entities: &'a Entities,
+    values: Vec<Box<dyn SpawnValue>>,
+    local: bool,
+}
+
+impl SpawnBuilder<'_> {
+    ///
+    /// Adds component value, the previous value of the same type is replaced
+    ///
+    pub fn with<T>(mut self, value: T) -> Self
+    where
+        T: Default + Send + Sync + 'static,
+    {
+        let comp_id = ComponentId::new::<T>();
+        self.values.retain(|v| v.component_id() != comp_id);
+        self.values.push(Box::new(TypedSpawnValue(value)));
+        self
+    }
+
+    ///
+    /// Makes client-only entity, see [`EntityId::is_local`]
+    ///
+    pub fn local(mut self) -> Self {
+        self.local = true;
+        self
+    }
+
+    pub fn finish(self) -> Result<EntityId, EntityError> {
+        let archetype = self
+            .values
+            .iter()
+            .fold(ArchetypeBuilder::new(), |b, v| v.add_column(b))
+            .build();
+        self.entities
+            .storage
+            .write()?
+            .spawn(archetype, self.values, self.local)
+    }
+}
+
+///
+/// Shared read access to entities. Any number of views may be used concurrently (AI, network extraction),
+/// each of them locks only the columns being read and only for reading.
+/// Entities can't be added, removed or moved between archetypes while any view is alive,
+/// so calling [`Entities::set`] from the thread holding a view deadlocks.
+///
+pub struct WorldView<'a> {
+    storage: RwLockReadGuard<'a, EntityStorage>,
+}
+
+impl WorldView<'_> {
+    pub fn get<T, F, R>(&self, entity: EntityId, consumer: F) -> Option<R>
+    where
+        T: Default + 'static,
+        R: 'static,
+        F: FnOnce(Option<&T>) -> R,
+    {
+        self.storage.get(entity, consumer)
+    }
+
+    pub fn has<T>(&self, entity: EntityId) -> bool
+    where
+        T: 'static,
+    {
+        self.storage.has(entity, &ComponentId::new::<T>())
+    }
+
+    pub fn is_alive(&self, entity: EntityId) -> bool {
+        self.storage.is_alive(entity)
+    }
+
+    ///
+    /// Passes every live entity having component `T` to `handler`, returns number of visited entities
+    ///
+    pub fn for_each<T, F>(&self, handler: F) -> usize
+    where
+        T: 'static,
+        F: Fn(EntityId, &T),
+    {
+        let columns = HashSet::from([ComponentId::new::<T>()]);
+        let (_, _, rows) = self.storage.visit(&columns, |chunk| {
+            let (Some(ids), Some(values)) = (
+                chunk.get_column(*COLUMN_ENTITY_ID),
+                chunk.get_column(ComponentId::new::<T>()),
+            ) else {
+                return 0;
+            };
+            let (ids, values) = (ids.read().unwrap(), values.read().unwrap());
+            let mut count = 0;
+            for (i, (id, value)) in cast::<EntityId>(ids.as_ref())
+                .iter()
+                .zip(cast::<T>(values.as_ref()))
+                .enumerate()
+            {
+                if !chunk.is_dead(i) {
+                    handler(*id, value);
+                    count += 1;
+                }
+            }
+            count
+        });
+        rows
+    }
+}
+
 ///
 /// Tests
 ///
 #[cfg(test)]
 mod test {
 
-    use std::collections::HashSet;
+    use std::{
+        collections::HashSet,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
 
-    use crate::{build_archetype, component::ComponentId, entity::EntityId};
+    use crate::{
+        build_archetype,
+        component::{cast, ComponentId},
+        entity::{EntityId, EntityMap},
+        visitor::Entity,
+    };
 
     use super::Entities;
 
@@ -367,4 +1227,588 @@ mod test {
         // let (ac, cc, rc) = entities.visit(&columns, v2);
         // println!("archs={}, chunks={}, rows={}", ac, cc, rc);
     }
+
+    #[test]
+    fn deferred_despawn() {
+        let entities = Entities::new(100);
+        let arch_id = entities.add_archetype(build_archetype! {i32});
+        let ids: Vec<_> = (0..5)
+            .map(|i| {
+                let e = entities.add(Some(arch_id)).unwrap();
+                entities.set(e, i).unwrap();
+                e
+            })
+            .collect();
+
+        let columns = HashSet::from([ComponentId::new::<i32>()]);
+        let alive_rows = || {
+            entities.visit(&columns, |chunk| {
+                let guard = chunk.get_column_for_type::<i32>().unwrap().read().unwrap();
+                (0..guard.row_count())
+                    .filter(|i| !chunk.is_dead(*i))
+                    .count()
+            })
+        };
+        // Despawn from inside of iteration
+        entities.visit(&columns, |chunk| {
+            let ids = chunk
+                .get_column_for_type::<EntityId>()
+                .unwrap()
+                .read()
+                .unwrap();
+            let values = chunk.get_column_for_type::<i32>().unwrap().read().unwrap();
+            let ids = cast::<EntityId>(ids.as_ref());
+            for (id, v) in ids.iter().zip(cast::<i32>(values.as_ref())) {
+                if v % 2 == 0 {
+                    entities.despawn_deferred(*id).unwrap();
+                }
+            }
+            0
+        });
+        assert!(!entities.is_alive(ids[0]));
+        assert!(entities.is_alive(ids[1]));
+        assert!(entities.get::<i32, _, _>(ids[2], |v| v.copied()).is_none());
+        assert!(entities.set(ids[4], 10).is_err());
+        assert_eq!(2, alive_rows().2);
+        // Marked once
+        entities.despawn_deferred(ids[0]).unwrap();
+
+        assert_eq!(3, entities.flush_despawns().unwrap());
+        assert_eq!(0, entities.flush_despawns().unwrap());
+        assert_eq!(2, alive_rows().2);
+        for (i, e) in ids.iter().enumerate() {
+            assert_eq!(i % 2 == 1, entities.is_alive(*e));
+        }
+        assert_eq!(
+            Some(Some(3)),
+            entities.get::<i32, _, _>(ids[3], |v| v.copied())
+        );
+        assert!(entities.despawn_deferred(ids[0]).is_err());
+
+        // Removed directly after it was marked
+        entities.despawn_deferred(ids[1]).unwrap();
+        entities.remove(ids[1]).unwrap();
+        assert_eq!(0, entities.flush_despawns().unwrap());
+        assert!(entities.is_alive(ids[3]));
+    }
+
+    ///
+    /// Counts drops of the component values which were actually set
+    ///
+    #[derive(Default)]
+    struct Tracked(Option<Arc<AtomicUsize>>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            if let Some(counter) = self.0.as_ref() {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[test]
+    fn drop_components() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let tracked = || Tracked(Some(Arc::clone(&drops)));
+        let dropped = || drops.load(Ordering::Relaxed);
+        // Small chunks to spread entities
+        let entities = Entities::new(64);
+        let arch_id = entities.add_archetype(build_archetype! {Tracked, i32});
+        let ids: Vec<_> = (0..6)
+            .map(|_| {
+                let e = entities.add(Some(arch_id)).unwrap();
+                entities.set(e, tracked()).unwrap();
+                e
+            })
+            .collect();
+        assert_eq!(0, dropped());
+
+        entities.remove(ids[0]).unwrap();
+        assert_eq!(1, dropped());
+        // Old value is dropped on overwrite
+        entities.set(ids[1], tracked()).unwrap();
+        assert_eq!(2, dropped());
+        // Moving to another archetype keeps the value
+        entities.set(ids[2], 1.5f32).unwrap();
+        assert_eq!(2, dropped());
+        entities.despawn_deferred(ids[2]).unwrap();
+        entities.flush_despawns().unwrap();
+        assert_eq!(3, dropped());
+
+        // Four values left, including the one which replaced overwritten value
+        entities.clear();
+        assert_eq!(7, dropped());
+        let e = entities.add(Some(arch_id)).unwrap();
+        entities.set(e, tracked()).unwrap();
+        drop(entities);
+        assert_eq!(8, dropped());
+    }
+
+    #[test]
+    fn drop_hooks() {
+        let released = Arc::new(Mutex::new(Vec::new()));
+        let take = || std::mem::take(&mut *released.lock().unwrap());
+        let entities = Entities::new(100);
+        let arch_id = entities.add_archetype(build_archetype! {i32});
+        {
+            let released = Arc::clone(&released);
+            entities.on_drop::<i32, _>(move |e, v| released.lock().unwrap().push((e, *v)));
+        }
+        let ids: Vec<_> = (0..4)
+            .map(|i| {
+                let e = entities.add(Some(arch_id)).unwrap();
+                entities.set(e, 10 * i).unwrap();
+                e
+            })
+            .collect();
+        // Default value being replaced
+        assert_eq!(
+            vec![(ids[0], 0), (ids[1], 0), (ids[2], 0), (ids[3], 0)],
+            take()
+        );
+
+        entities.remove(ids[1]).unwrap();
+        assert_eq!(vec![(ids[1], 10)], take());
+        // Not called on move
+        entities.set(ids[2], "name").unwrap();
+        assert!(released.lock().unwrap().is_empty());
+
+        drop(entities);
+        let mut rest = take();
+        rest.sort();
+        assert_eq!(vec![(ids[0], 0), (ids[2], 20), (ids[3], 30)], rest);
+    }
+
+    #[derive(Default, Clone, Copy, Debug, PartialEq)]
+    struct Parent(Option<EntityId>);
+
+    #[test]
+    fn append() {
+        let world = Entities::new(256);
+        let arch_id = world.add_archetype(build_archetype! {i32});
+        let existing = world.add(Some(arch_id)).unwrap();
+        world.set(existing, 1).unwrap();
+        world.on_remap::<Parent, _>(|p, map| {
+            if let Some(id) = p.0 {
+                p.0 = map.get(id).or(p.0);
+            }
+        });
+
+        // Built "offline", ids overlap with the live world
+        let section = Entities::new(256);
+        let root = section.add(None).unwrap();
+        section.set(root, 10).unwrap();
+        let children: Vec<_> = (0..3)
+            .map(|i| {
+                let e = section.add(None).unwrap();
+                section.set(e, 11 + i).unwrap();
+                section.set(e, Parent(Some(root))).unwrap();
+                e
+            })
+            .collect();
+        // Refers to the live world
+        let outer = section.add(None).unwrap();
+        section
+            .set(outer, Parent(Some(EntityId::new(100))))
+            .unwrap();
+        section.despawn_deferred(children[2]).unwrap();
+        assert_eq!(root, existing);
+
+        let map = world.append(section).unwrap();
+        assert_eq!(4, map.len());
+        assert!(map.get(children[2]).is_none());
+        let new_root = map.get(root).unwrap();
+        assert_ne!(existing, new_root);
+        assert!(world.is_alive(existing));
+        assert_eq!(
+            Some(1),
+            world.get::<i32, _, _>(existing, |v| v.copied()).unwrap()
+        );
+        assert_eq!(
+            Some(10),
+            world.get::<i32, _, _>(new_root, |v| v.copied()).unwrap()
+        );
+        for (i, child) in children[..2].iter().enumerate() {
+            let child = map.get(*child).unwrap();
+            assert_eq!(
+                Some(11 + i as i32),
+                world.get::<i32, _, _>(child, |v| v.copied()).unwrap()
+            );
+            assert_eq!(
+                Some(Parent(Some(new_root))),
+                world.get::<Parent, _, _>(child, |v| v.copied()).unwrap()
+            );
+        }
+        assert_eq!(
+            Some(Parent(Some(EntityId::new(100)))),
+            world
+                .get::<Parent, _, _>(map.get(outer).unwrap(), |v| v.copied())
+                .unwrap()
+        );
+
+        // Appended entities behave like native ones
+        world.remove(new_root).unwrap();
+        world.set(existing, 2).unwrap();
+        let e = world.add(Some(arch_id)).unwrap();
+        assert!(map.iter().all(|(_, new)| new != e));
+        let columns = HashSet::from([ComponentId::new::<i32>()]);
+        let (_, _, rows) = world.visit(&columns, |chunk| chunk.len());
+        assert_eq!(4, rows);
+    }
+
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Target {
+        entity: EntityId,
+        owner: Option<EntityId>,
+        seen: Vec<EntityId>,
+    }
+
+    #[test]
+    fn entity_refs() {
+        let world = Entities::new(256);
+        world.on_entity_ref::<Target, _>(|t| &mut t.entity);
+        world.on_entity_ref::<Target, _>(|t| &mut t.owner);
+        world.on_entity_ref::<Target, _>(|t| &mut t.seen);
+        let [a, b, c] = [(); 3].map(|_| world.add(None).unwrap());
+        world
+            .set(
+                a,
+                Target {
+                    entity: b,
+                    owner: Some(c),
+                    seen: vec![b, c],
+                },
+            )
+            .unwrap();
+
+        // Snapshot was saved when b and c had other ids
+        let map: EntityMap = [(b, c), (c, b)].into_iter().collect();
+        assert_eq!(a, map.translate(a));
+        world.remap(&map).unwrap();
+        assert_eq!(
+            Some(Target {
+                entity: c,
+                owner: Some(b),
+                seen: vec![c, b],
+            }),
+            world.get::<Target, _, _>(a, |v| v.cloned()).unwrap()
+        );
+
+        // All fields are fixed on merge too, reference to the live world is kept
+        let section = Entities::new(256);
+        let x = section.add(None).unwrap();
+        let y = section.add(None).unwrap();
+        section
+            .set(
+                x,
+                Target {
+                    entity: y,
+                    owner: Some(EntityId::new(100)),
+                    seen: vec![x],
+                },
+            )
+            .unwrap();
+        let map = world.append(section).unwrap();
+        let (x, y) = (map.get(x).unwrap(), map.get(y).unwrap());
+        assert_eq!(
+            Some(Target {
+                entity: y,
+                owner: Some(EntityId::new(100)),
+                seen: vec![x],
+            }),
+            world.get::<Target, _, _>(x, |v| v.cloned()).unwrap()
+        );
+    }
+    #[test]
+    fn query_cache() {
+        let entities = Entities::new(256);
+        let a1 = entities.add_archetype(build_archetype! {i32});
+        let a2 = entities.add_archetype(build_archetype! {i32, f64});
+        entities.add(Some(a1)).unwrap();
+        entities.add(Some(a2)).unwrap();
+        let ints = HashSet::from([ComponentId::new::<i32>()]);
+        let floats = HashSet::from([ComponentId::new::<f64>()]);
+        let count = |columns| entities.visit(columns, |chunk| chunk.len());
+
+        assert_eq!((2, 2, 2), count(&ints));
+        assert_eq!((1, 1, 1), count(&floats));
+        assert_eq!((2, 2, 2), count(&ints));
+        assert_eq!(2, entities.read().queries.len());
+
+        // New archetypes are picked up by cached queries, both added explicitly and by moving entity
+        let e = entities.add(None).unwrap();
+        entities.set(e, 1.5f64).unwrap();
+        entities.add_archetype(build_archetype! {i32, String});
+        // Archetype without entities has no chunks
+        assert_eq!((3, 2, 2), count(&ints));
+        assert_eq!((2, 2, 2), count(&floats));
+
+        let other = Entities::new(256);
+        let e = other.add(None).unwrap();
+        other.set(e, 1u8).unwrap();
+        other.set(e, 2i32).unwrap();
+        entities.append(other).unwrap();
+        assert_eq!((4, 3, 3), count(&ints));
+        assert_eq!(2, entities.read().queries.len());
+    }
+
+    #[test]
+    fn local_entities() {
+        let world = Entities::new(256);
+        let replicated = world.add(None).unwrap();
+        world.set(replicated, 1).unwrap();
+        let effects: Vec<_> = (0..3)
+            .map(|i| {
+                let e = world.add_local(None).unwrap();
+                world.set(e, 10 + i).unwrap();
+                e
+            })
+            .collect();
+        assert!(!replicated.is_local());
+        assert!(effects.iter().all(|e| e.is_local()));
+        assert!(!effects.contains(&replicated));
+
+        let other = Entities::new(256);
+        let e1 = other.add(None).unwrap();
+        let e2 = other.add_local(None).unwrap();
+        let map = world.append(other).unwrap();
+        assert!(!map.get(e1).unwrap().is_local());
+        assert!(map.get(e2).unwrap().is_local());
+
+        // Server world is reset
+        assert_eq!(4, world.remove_local().unwrap());
+        assert!(world.is_alive(replicated));
+        assert!(world.is_alive(map.get(e1).unwrap()));
+        assert!(!world.is_alive(effects[0]));
+        assert_eq!(0, world.remove_local().unwrap());
+    }
+
+    #[derive(Default)]
+    struct Frozen;
+
+    #[test]
+    fn tags() {
+        let entities = Entities::new(1024);
+        let plain = build_archetype! {i32};
+        let tagged = build_archetype! {i32, Frozen};
+        assert_eq!(plain.row_bytes(), tagged.row_bytes());
+        assert!(tagged.is_tag(&ComponentId::new::<Frozen>()));
+        assert!(!tagged.is_tag(&ComponentId::new::<i32>()));
+
+        let arch_id = entities.add_archetype(plain);
+        let ids: Vec<_> = (0..10)
+            .map(|_| entities.add(Some(arch_id)).unwrap())
+            .collect();
+        for e in ids.iter().step_by(2) {
+            entities.set(*e, Frozen).unwrap();
+        }
+        assert!(entities.has::<Frozen>(ids[0]));
+        assert!(!entities.has::<Frozen>(ids[1]));
+        assert!(entities.has::<i32>(ids[1]));
+
+        let frozen = HashSet::from([ComponentId::new::<Frozen>()]);
+        assert_eq!((1, 1, 5), entities.visit(&frozen, |chunk| chunk.len()));
+
+        // Tag column keeps row count only, without any heap allocation
+        let guard = entities.read();
+        for storage in guard.archetypes() {
+            for chunk in storage.unwrap().iter() {
+                if let Some(column) = chunk.get_column_for_type::<Frozen>() {
+                    let column = column.read().unwrap();
+                    let column = cast::<Frozen>(column.as_ref());
+                    assert_eq!(5, column.len());
+                    assert_eq!(usize::MAX, column.capacity());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn world_view() {
+        fn assert_sync<T: Send + Sync>() {}
+        assert_sync::<Entities>();
+
+        let entities = Entities::new(256);
+        let ids: Vec<_> = (0..10)
+            .map(|i| {
+                let e = entities.add(None).unwrap();
+                entities.set(e, i).unwrap();
+                if i % 2 == 0 {
+                    entities.set(e, i as f32).unwrap();
+                }
+                e
+            })
+            .collect();
+        entities.despawn_deferred(ids[9]).unwrap();
+
+        let view = entities.view();
+        let (ints, floats) = std::thread::scope(|scope| {
+            let ints = scope.spawn(|| {
+                let sum = AtomicUsize::new(0);
+                view.for_each::<i32, _>(|_, v| {
+                    sum.fetch_add(*v as usize, Ordering::Relaxed);
+                });
+                sum.into_inner()
+            });
+            let floats = scope.spawn(|| view.for_each::<f32, _>(|_, _| {}));
+            (ints.join().unwrap(), floats.join().unwrap())
+        });
+        assert_eq!((0..9).sum::<usize>(), ints);
+        assert_eq!(5, floats);
+        assert_eq!(
+            Some(Some(4.0)),
+            view.get::<f32, _, _>(ids[4], |v| v.copied())
+        );
+        assert!(view.has::<f32>(ids[2]));
+        assert!(!view.has::<f32>(ids[3]));
+        assert!(!view.is_alive(ids[9]));
+        drop(view);
+
+        entities.flush_despawns().unwrap();
+        assert_eq!(9, entities.view().for_each::<i32, _>(|_, _| {}));
+    }
+
+    #[test]
+    fn visit_optional() {
+        let entities = Entities::new(1024);
+        let named = entities.add_archetype(build_archetype! {i32, String});
+        let unnamed = entities.add_archetype(build_archetype! {i32});
+        for i in 0..3 {
+            let e = entities.add(Some(named)).unwrap();
+            entities.set(e, format!("e{i}")).unwrap();
+            entities.add(Some(unnamed)).unwrap();
+        }
+        let dead = entities.add(Some(unnamed)).unwrap();
+        entities.despawn_deferred(dead).unwrap();
+
+        let names = AtomicUsize::new(0);
+        let visited = entities.visit_2::<&mut i32, Option<&String>, _>(|v, name| {
+            *v += 1;
+            if name.is_some() {
+                names.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        assert_eq!(6, visited);
+        assert_eq!(3, names.load(Ordering::Relaxed));
+        assert_eq!(6, entities.visit_1::<&i32, _>(|v| assert_eq!(1, *v)));
+        let ids = Mutex::new(Vec::new());
+        entities.visit_2::<Entity, &String, _>(|id, _| ids.lock().unwrap().push(id));
+        assert_eq!(3, ids.lock().unwrap().len());
+        assert!(!ids.lock().unwrap().contains(&dead));
+    }
+
+    #[test]
+    fn spawn_builder() {
+        let entities = Entities::new(1024);
+        let e1 = entities
+            .spawn()
+            .with(1i32)
+            .with(2.5f64)
+            .with("a".to_string())
+            .with(3i32)
+            .finish()
+            .unwrap();
+        // Default archetype plus the final one, no intermediate archetypes
+        assert_eq!(2, entities.read().archetypes().count());
+        assert_eq!(Some(Some(3)), entities.get::<i32, _, _>(e1, |v| v.copied()));
+        assert_eq!(
+            Some(Some(2.5)),
+            entities.get::<f64, _, _>(e1, |v| v.copied())
+        );
+        assert_eq!(
+            Some(Some("a".to_string())),
+            entities.get::<String, _, _>(e1, |v| v.cloned())
+        );
+
+        // Same set of components in different order lands in the same archetype
+        let e2 = entities
+            .spawn()
+            .with("b".to_string())
+            .with(5i32)
+            .with(0.5f64)
+            .local()
+            .finish()
+            .unwrap();
+        assert_eq!(2, entities.read().archetypes().count());
+        assert!(e2.is_local());
+        assert_eq!(Some(Some(5)), entities.get::<i32, _, _>(e2, |v| v.copied()));
+        assert_eq!(Some(Some(3)), entities.get::<i32, _, _>(e1, |v| v.copied()));
+
+        let empty = entities.spawn().finish().unwrap();
+        assert!(entities.is_alive(empty));
+        assert!(!entities.has::<i32>(empty));
+    }
+
+    #[test]
+    fn compaction() {
+        #[derive(Default)]
+        struct Far;
+
+        let entities = Entities::new(4096);
+        entities.allow_compression::<i32>();
+        entities.allow_compression::<String>();
+        let far: Vec<_> = (0..200)
+            .map(|i| {
+                entities
+                    .spawn()
+                    .with(i)
+                    .with("far away".to_string())
+                    .with(Far)
+                    .finish()
+                    .unwrap()
+            })
+            .collect();
+        // No codec for f64
+        let near = entities.spawn().with(1.5f64).finish().unwrap();
+        let hot = entities.spawn().with(7i32).finish().unwrap();
+
+        let mut frozen = 0;
+        for _ in 0..3 {
+            assert!(entities.get::<i32, _, _>(hot, |v| v.copied()).is_some());
+            frozen += entities.compact(2).unwrap();
+        }
+        assert_eq!(1, frozen);
+        let size = entities.compressed_size();
+        assert!(size > 0);
+        assert!(size < far.len() * (size_of::<i32>() + size_of::<String>()));
+        assert!(entities.has::<Far>(far[0]));
+        assert_eq!(
+            Some(Some(1.5)),
+            entities.get::<f64, _, _>(near, |v| v.copied())
+        );
+
+        // Thawed on access
+        assert_eq!(
+            Some(Some(10)),
+            entities.get::<i32, _, _>(far[10], |v| v.copied())
+        );
+        assert_eq!(0, entities.compressed_size());
+        assert_eq!(
+            Some(Some("far away".to_string())),
+            entities.get::<String, _, _>(far[199], |v| v.cloned())
+        );
+
+        // Rows waiting for despawn are not compressed, so only archetype of `hot` is
+        entities.despawn_deferred(far[0]).unwrap();
+        assert_eq!(1, entities.compact(0).unwrap());
+        entities.flush_despawns().unwrap();
+        assert_eq!(1, entities.compact(0).unwrap());
+        assert_eq!(
+            Some(Some(7)),
+            entities.get::<i32, _, _>(hot, |v| v.copied())
+        );
+
+        entities.set(far[1], 2.5f64).unwrap();
+        entities.remove(far[2]).unwrap();
+        assert_eq!(
+            Some(Some(2.5)),
+            entities.get::<f64, _, _>(far[1], |v| v.copied())
+        );
+        assert_eq!(
+            Some(Some(1)),
+            entities.get::<i32, _, _>(far[1], |v| v.copied())
+        );
+        let view = entities.view();
+        assert_eq!(198, view.for_each::<String, _>(|_, _| {}));
+    }
 }
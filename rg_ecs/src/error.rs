@@ -15,6 +15,8 @@ pub enum EntityError {
     LockPoisoned,
     #[snafu(display("Index is out of bounds!"))]
     OutOfBounds,
+    #[snafu(display("Entity ids are exhausted!"))]
+    OutOfIds,
 }
 
 impl<T> From<PoisonError<T>> for EntityError {
@@ -22,3 +24,20 @@ impl<T> From<PoisonError<T>> for EntityError {
         EntityError::LockPoisoned
     }
 }
+
+///
+/// PrefabError
+///
+#[derive(Debug, Snafu)]
+pub enum PrefabError {
+    #[snafu(display("Component \"{name}\" is already registered!"))]
+    AlreadyRegistered { name: String },
+    #[snafu(display("Unknown component \"{name}\"!"))]
+    UnknownComponent { name: String },
+    #[snafu(display("Unknown prefab \"{name}\"!"))]
+    UnknownPrefab { name: String },
+    #[snafu(display("Prefab \"{name}\" inherits itself!"))]
+    CyclicPrefab { name: String },
+    #[snafu(display("Unable to parse \"{value}\" as {component}!"))]
+    ParseFailed { component: String, value: String },
+}
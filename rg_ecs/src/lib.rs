@@ -2,5 +2,7 @@ pub mod archetype;
 pub mod component;
 pub mod entity;
 pub mod error;
+pub mod playground;
+pub mod prefab;
+pub mod snapshot;
 pub mod visitor;
-pub mod playground;
\ No newline at end of file
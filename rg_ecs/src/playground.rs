@@ -91,7 +91,6 @@ where
     }
 }
 
-
 // struct Visitor1<A> {
 //     handler: H,
 //     _phantom: PhantomData<A>,
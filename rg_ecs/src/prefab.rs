@@ -0,0 +1,368 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    str::FromStr,
+    sync::Arc,
+};
+
+use rg_common::Symbol;
+use serde::Deserialize;
+
+use crate::{
+    archetype::{Archetype, ArchetypeBuilder},
+    entity::{Entities, EntityId},
+    error::{EntityError, PrefabError},
+};
+
+///
+/// Prefab definition as it's stored in data files
+///
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PrefabDef {
+    /// Name of the parent prefab, its components are inherited and may be overridden
+    #[serde(default)]
+    pub extends: Option<String>,
+    /// Component name -> component value
+    #[serde(default)]
+    pub components: BTreeMap<String, String>,
+}
+
+///
+/// Type-erased parsed component value
+///
+trait PrefabValue {
+    fn add_column(&self, builder: ArchetypeBuilder) -> ArchetypeBuilder;
+
+    fn set(&self, entities: &Entities, entity: EntityId) -> Result<(), EntityError>;
+}
+
+struct TypedPrefabValue<T>(T);
+
+impl<T> PrefabValue for TypedPrefabValue<T>
+where
+    T: Clone + Default + Send + Sync + 'static,
+{
+    fn add_column(&self, builder: ArchetypeBuilder) -> ArchetypeBuilder {
+        builder.add::<T>()
+    }
+
+    fn set(&self, entities: &Entities, entity: EntityId) -> Result<(), EntityError> {
+        entities.set(entity, self.0.clone())
+    }
+}
+
+///
+/// Type-erased component parser
+///
+trait ComponentType {
+    fn parse(&self, value: &str) -> Option<Arc<dyn PrefabValue>>;
+}
+
+struct TypedComponentType<T>(PhantomData<T>);
+
+impl<T> ComponentType for TypedComponentType<T>
+where
+    T: FromStr + Clone + Default + Send + Sync + 'static,
+{
+    fn parse(&self, value: &str) -> Option<Arc<dyn PrefabValue>> {
+        Some(Arc::new(TypedPrefabValue(value.parse::<T>().ok()?)))
+    }
+}
+
+///
+/// Maps component names used in data files to component types
+///
+#[derive(Default)]
+pub struct ComponentRegistry {
+    types: HashMap<Symbol, Box<dyn ComponentType>>,
+}
+
+impl ComponentRegistry {
+    ///
+    /// Registers component type under supplied name. Values are parsed with [`FromStr`].
+    ///
+    pub fn register<T>(&mut self, name: &str) -> Result<(), PrefabError>
+    where
+        T: FromStr + Clone + Default + Send + Sync + 'static,
+    {
+        let symbol = Symbol::intern(name);
+        if self.types.contains_key(&symbol) {
+            return Err(PrefabError::AlreadyRegistered {
+                name: name.to_owned(),
+            });
+        }
+        self.types
+            .insert(symbol, Box::new(TypedComponentType::<T>(PhantomData)));
+        Ok(())
+    }
+
+    fn parse(&self, name: &str, value: &str) -> Result<Arc<dyn PrefabValue>, PrefabError> {
+        let ty = Symbol::lookup(name)
+            .and_then(|s| self.types.get(&s))
+            .ok_or_else(|| PrefabError::UnknownComponent {
+                name: name.to_owned(),
+            })?;
+        ty.parse(value).ok_or_else(|| PrefabError::ParseFailed {
+            component: name.to_owned(),
+            value: value.to_owned(),
+        })
+    }
+}
+
+///
+/// Handle of the resolved prefab
+///
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[repr(transparent)]
+pub struct PrefabHandle(u32);
+
+///
+/// Prefab with inheritance resolved and all values parsed
+///
+struct Prefab {
+    archetype: Archetype,
+    values: BTreeMap<String, Arc<dyn PrefabValue>>,
+}
+
+///
+/// Collection of resolved prefabs
+///
+#[derive(Default)]
+pub struct Prefabs {
+    names: HashMap<String, PrefabHandle>,
+    prefabs: Vec<Prefab>,
+}
+
+impl Prefabs {
+    ///
+    /// Resolves and adds all supplied definitions. Parents may be defined in the same batch or added earlier.
+    ///
+    pub fn load(
+        &mut self,
+        defs: &HashMap<String, PrefabDef>,
+        registry: &ComponentRegistry,
+    ) -> Result<(), PrefabError> {
+        let mut names: Vec<_> = defs.keys().collect();
+        names.sort();
+        for name in names {
+            self.resolve(name, defs, registry, &mut Vec::new())?;
+        }
+        Ok(())
+    }
+
+    fn resolve(
+        &mut self,
+        name: &str,
+        defs: &HashMap<String, PrefabDef>,
+        registry: &ComponentRegistry,
+        path: &mut Vec<String>,
+    ) -> Result<PrefabHandle, PrefabError> {
+        if let Some(handle) = self.names.get(name) {
+            return Ok(*handle);
+        }
+        let def = defs.get(name).ok_or_else(|| PrefabError::UnknownPrefab {
+            name: name.to_owned(),
+        })?;
+        if path.iter().any(|v| v == name) {
+            return Err(PrefabError::CyclicPrefab {
+                name: name.to_owned(),
+            });
+        }
+        path.push(name.to_owned());
+        let mut values = match &def.extends {
+            Some(parent) => {
+                let handle = self.resolve(parent, defs, registry, path)?;
+                self.prefabs[handle.0 as usize].values.clone()
+            }
+            None => BTreeMap::new(),
+        };
+        path.pop();
+        for (component, value) in def.components.iter() {
+            values.insert(component.clone(), registry.parse(component, value)?);
+        }
+        Ok(self.insert(name, values))
+    }
+
+    fn insert(
+        &mut self,
+        name: &str,
+        values: BTreeMap<String, Arc<dyn PrefabValue>>,
+    ) -> PrefabHandle {
+        let archetype = values
+            .values()
+            .fold(ArchetypeBuilder::new(), |b, v| v.add_column(b))
+            .build();
+        let handle = PrefabHandle(self.prefabs.len() as u32);
+        self.prefabs.push(Prefab { archetype, values });
+        self.names.insert(name.to_owned(), handle);
+        handle
+    }
+
+    ///
+    /// Looks up prefab handle by name
+    ///
+    pub fn get(&self, name: &str) -> Option<PrefabHandle> {
+        self.names.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefabs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefabs.is_empty()
+    }
+}
+
+impl Entities {
+    ///
+    /// Spawns new entity from prefab. Entity is created directly in the final archetype, so no moves happen.
+    ///
+    pub fn spawn_prefab(
+        &self,
+        prefabs: &Prefabs,
+        handle: PrefabHandle,
+    ) -> Result<EntityId, EntityError> {
+        let prefab = prefabs
+            .prefabs
+            .get(handle.0 as usize)
+            .ok_or(EntityError::NotFound)?;
+        let arch_id = self.add_archetype(prefab.archetype.clone());
+        let entity = self.add(Some(arch_id))?;
+        for value in prefab.values.values() {
+            value.set(self, entity)?;
+        }
+        Ok(entity)
+    }
+}
+
+impl std::fmt::Debug for Prefabs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Prefabs")
+            .field("names", &self.names)
+            .finish_non_exhaustive()
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, str::FromStr};
+
+    use crate::{entity::Entities, error::PrefabError};
+
+    use super::{ComponentRegistry, PrefabDef, Prefabs};
+
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Health(i32);
+
+    impl FromStr for Health {
+        type Err = std::num::ParseIntError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            s.parse().map(Health)
+        }
+    }
+
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Name(String);
+
+    impl FromStr for Name {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Ok(Name(s.to_owned()))
+        }
+    }
+
+    fn registry() -> ComponentRegistry {
+        let mut reg = ComponentRegistry::default();
+        reg.register::<Health>("Health").unwrap();
+        reg.register::<Name>("Name").unwrap();
+        reg.register::<f32>("Speed").unwrap();
+        reg
+    }
+
+    fn defs(src: &str) -> HashMap<String, PrefabDef> {
+        toml::from_str(src).unwrap()
+    }
+
+    #[test]
+    fn spawn() {
+        let mut prefabs = Prefabs::default();
+        prefabs
+            .load(
+                &defs(
+                    r#"
+                    [base]
+                    components = { Health = "100", Name = "Unit" }
+
+                    [grunt]
+                    extends = "base"
+                    components = { Name = "Grunt", Speed = "2.5" }
+                    "#,
+                ),
+                &registry(),
+            )
+            .unwrap();
+        assert_eq!(2, prefabs.len());
+
+        let entities = Entities::new(1024);
+        let e1 = entities
+            .spawn_prefab(&prefabs, prefabs.get("grunt").unwrap())
+            .unwrap();
+        let e2 = entities
+            .spawn_prefab(&prefabs, prefabs.get("grunt").unwrap())
+            .unwrap();
+        let e3 = entities
+            .spawn_prefab(&prefabs, prefabs.get("base").unwrap())
+            .unwrap();
+        for e in [e1, e2] {
+            assert_eq!(
+                Some(Health(100)),
+                entities.get::<Health, _, _>(e, |v| v.cloned()).unwrap()
+            );
+            assert_eq!(
+                Some(Name("Grunt".to_owned())),
+                entities.get::<Name, _, _>(e, |v| v.cloned()).unwrap()
+            );
+            assert_eq!(
+                Some(2.5),
+                entities.get::<f32, _, _>(e, |v| v.copied()).unwrap()
+            );
+        }
+        assert_eq!(
+            Some(Name("Unit".to_owned())),
+            entities.get::<Name, _, _>(e3, |v| v.cloned()).unwrap()
+        );
+        assert!(entities.get::<f32, _, _>(e3, |v| v.copied()).is_none());
+    }
+
+    #[test]
+    fn errors() {
+        let reg = registry();
+        assert!(matches!(
+            reg.parse("Health", "lots"),
+            Err(PrefabError::ParseFailed { .. })
+        ));
+        assert!(matches!(
+            Prefabs::default().load(&defs("[a]\ncomponents = { Mana = \"1\" }"), &reg),
+            Err(PrefabError::UnknownComponent { .. })
+        ));
+        assert!(matches!(
+            Prefabs::default().load(&defs("[a]\nextends = \"b\""), &reg),
+            Err(PrefabError::UnknownPrefab { .. })
+        ));
+        assert!(matches!(
+            Prefabs::default().load(&defs("[a]\nextends = \"b\"\n[b]\nextends = \"a\""), &reg),
+            Err(PrefabError::CyclicPrefab { .. })
+        ));
+        let mut reg = reg;
+        assert!(matches!(
+            reg.register::<i32>("Health"),
+            Err(PrefabError::AlreadyRegistered { .. })
+        ));
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Debug, Display},
+    marker::PhantomData,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    archetype::Chunk,
+    component::cast,
+    entity::{Entities, EntityId},
+    error::EntityError,
+};
+
+///
+/// Component values of the single entity, formatted with [`Debug`]
+///
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub id: u32,
+    pub components: BTreeMap<String, String>,
+}
+
+///
+/// Human-readable world state at some tick, used to find where two simulations diverge
+///
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub tick: u64,
+    /// Ordered by entity id
+    pub entities: Vec<EntitySnapshot>,
+}
+
+///
+/// Type-erased column formatter
+///
+trait SnapshotColumn {
+    fn values(&self, chunk: &Chunk) -> Option<Vec<String>>;
+}
+
+struct TypedSnapshotColumn<T>(PhantomData<fn(&T)>);
+
+impl<T> SnapshotColumn for TypedSnapshotColumn<T>
+where
+    T: Debug + Default + 'static,
+{
+    fn values(&self, chunk: &Chunk) -> Option<Vec<String>> {
+        let guard = chunk.get_column_for_type::<T>()?.read().ok()?;
+        Some(
+            cast::<T>(guard.as_ref())
+                .iter()
+                .map(|v| format!("{v:?}"))
+                .collect(),
+        )
+    }
+}
+
+///
+/// Components included into snapshots, by name
+///
+#[derive(Default)]
+pub struct SnapshotRegistry {
+    columns: HashMap<String, Box<dyn SnapshotColumn>>,
+}
+
+impl SnapshotRegistry {
+    pub fn register<T>(&mut self, name: &str)
+    where
+        T: Debug + Default + 'static,
+    {
+        self.columns.insert(
+            name.to_owned(),
+            Box::new(TypedSnapshotColumn::<T>(PhantomData)),
+        );
+    }
+
+    ///
+    /// Captures registered components of all entities except local and dead ones
+    ///
+    pub fn capture(&self, entities: &Entities, tick: u64) -> Result<WorldSnapshot, EntityError> {
+        let mut result = BTreeMap::new();
+        let guard = entities.read();
+        for storage in guard.archetypes() {
+            let storage = storage?;
+            for chunk in storage.iter() {
+                let Some(ids) = chunk.get_column_for_type::<EntityId>() else {
+                    continue;
+                };
+                let ids = ids.read()?;
+                let ids = cast::<EntityId>(ids.as_ref());
+                let mut rows: Vec<_> = ids
+                    .iter()
+                    .map(|id| (*id, BTreeMap::<String, String>::new()))
+                    .collect();
+                for (name, column) in self.columns.iter() {
+                    for (row, value) in rows
+                        .iter_mut()
+                        .zip(column.values(chunk).unwrap_or_default())
+                    {
+                        row.1.insert(name.clone(), value);
+                    }
+                }
+                for (index, (id, components)) in rows.into_iter().enumerate() {
+                    if !id.is_local() && !chunk.is_dead(index) {
+                        result.insert(id, components);
+                    }
+                }
+            }
+        }
+        Ok(WorldSnapshot {
+            tick,
+            entities: result
+                .into_iter()
+                .map(|(id, components)| EntitySnapshot {
+                    id: id.id(),
+                    components,
+                })
+                .collect(),
+        })
+    }
+}
+
+///
+/// First difference between two snapshots. Missing entity or component has no value.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub entity: u32,
+    /// `None` if the whole entity is missing on one side
+    pub component: Option<String>,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = |v: &Option<String>| v.clone().unwrap_or_else(|| "<missing>".to_string());
+        match &self.component {
+            Some(name) => write!(
+                f,
+                "entity {}, {}: {} != {}",
+                self.entity,
+                name,
+                value(&self.left),
+                value(&self.right)
+            ),
+            None if self.left.is_some() => write!(f, "entity {}: only in left", self.entity),
+            None => write!(f, "entity {}: only in right", self.entity),
+        }
+    }
+}
+
+fn diff_entity(left: &EntitySnapshot, right: &EntitySnapshot) -> Option<Divergence> {
+    let names = left.components.keys().chain(right.components.keys());
+    let mut names: Vec<_> = names.collect();
+    names.sort();
+    names.dedup();
+    names.into_iter().find_map(|name| {
+        let (l, r) = (left.components.get(name), right.components.get(name));
+        (l != r).then(|| Divergence {
+            entity: left.id,
+            component: Some(name.clone()),
+            left: l.cloned(),
+            right: r.cloned(),
+        })
+    })
+}
+
+///
+/// Finds the first (by entity id, then component name) difference between snapshots
+///
+pub fn diff(left: &WorldSnapshot, right: &WorldSnapshot) -> Option<Divergence> {
+    let mut l = left.entities.iter().peekable();
+    let mut r = right.entities.iter().peekable();
+    loop {
+        let missing = |e: &EntitySnapshot, in_left: bool| Divergence {
+            entity: e.id,
+            component: None,
+            left: in_left.then(|| format!("{:?}", e.components)),
+            right: (!in_left).then(|| format!("{:?}", e.components)),
+        };
+        match (l.peek(), r.peek()) {
+            (None, None) => return None,
+            (Some(a), None) => return Some(missing(a, true)),
+            (None, Some(b)) => return Some(missing(b, false)),
+            (Some(a), Some(b)) if a.id < b.id => return Some(missing(a, true)),
+            (Some(a), Some(b)) if a.id > b.id => return Some(missing(b, false)),
+            (Some(a), Some(b)) => {
+                if let Some(d) = diff_entity(a, b) {
+                    return Some(d);
+                }
+                l.next();
+                r.next();
+            }
+        }
+    }
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use crate::entity::Entities;
+
+    use super::{diff, SnapshotRegistry, WorldSnapshot};
+
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct Health(i32);
+
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct Position(f32, f32);
+
+    fn world(hp: i32) -> (Entities, SnapshotRegistry) {
+        let entities = Entities::new(1024);
+        for i in 0..3 {
+            let e = entities.add(None).unwrap();
+            entities.set(e, Position(i as f32, 0.0)).unwrap();
+            if i > 0 {
+                entities.set(e, Health(hp * i)).unwrap();
+            }
+        }
+        entities.add_local(None).unwrap();
+        let mut registry = SnapshotRegistry::default();
+        registry.register::<Health>("health");
+        registry.register::<Position>("position");
+        (entities, registry)
+    }
+
+    #[test]
+    fn capture() {
+        let (entities, registry) = world(10);
+        let snap = registry.capture(&entities, 7).unwrap();
+        assert_eq!(7, snap.tick);
+        let ids: Vec<_> = snap.entities.iter().map(|e| e.id).collect();
+        assert_eq!(vec![0, 1, 2], ids);
+        assert!(!snap.entities[0].components.contains_key("health"));
+        assert_eq!("Health(20)", snap.entities[2].components["health"]);
+        assert_eq!(
+            "Position(1.0, 0.0)",
+            snap.entities[1].components["position"]
+        );
+
+        let text = toml::to_string(&snap).unwrap();
+        let loaded: WorldSnapshot = toml::from_str(&text).unwrap();
+        assert_eq!(snap, loaded);
+    }
+
+    #[test]
+    fn first_divergence() {
+        let (entities, registry) = world(10);
+        let left = registry.capture(&entities, 1).unwrap();
+        assert_eq!(None, diff(&left, &left));
+
+        let (entities, registry) = world(11);
+        let right = registry.capture(&entities, 1).unwrap();
+        let d = diff(&left, &right).unwrap();
+        assert_eq!(1, d.entity);
+        assert_eq!("entity 1, health: Health(10) != Health(11)", d.to_string());
+
+        let mut shorter = left.clone();
+        shorter.entities.remove(1);
+        let d = diff(&left, &shorter).unwrap();
+        assert_eq!("entity 1: only in left", d.to_string());
+        let mut no_health = left.clone();
+        no_health.entities[2].components.remove("health");
+        assert_eq!(
+            "entity 2, health: <missing> != Health(20)",
+            diff(&no_health, &left).unwrap().to_string()
+        );
+    }
+}
@@ -9,20 +9,32 @@ use std::{
 };
 
 use crate::{
-    archetype::Chunk,
+    archetype::{Chunk, COLUMN_ENTITY_ID},
     component::{cast, cast_mut, ComponentId, ComponentStorage, TypedComponentStorage},
+    entity::EntityId,
 };
 
 ///
-/// Locker
+/// Argument of the visitor handler: `&T`, `&mut T`, [`Entity`] or `Option` of the first two
 ///
-trait Locker {
+pub trait Locker {
     type Ty: 'static;
     type Guard<'g>;
     type Item<'r>;
     type Iter<'i>: Iterator<Item = Self::Item<'i>>;
 
-    fn lock(chunk: &Chunk) -> Self::Guard<'_>;
+    ///
+    /// Column which must be present in chunk, `None` if argument is optional
+    ///
+    fn component() -> Option<ComponentId> {
+        Some(ComponentId::new::<Self::Ty>())
+    }
+
+    fn try_lock(chunk: &Chunk) -> Option<Self::Guard<'_>>;
+
+    fn lock(chunk: &Chunk) -> Self::Guard<'_> {
+        Self::try_lock(chunk).unwrap()
+    }
 
     fn iter<'a>(guard: &'a mut Self::Guard<'_>) -> Self::Iter<'a>;
 }
@@ -36,12 +48,13 @@ where
     type Item<'r> = &'r mut T;
     type Iter<'i> = core::slice::IterMut<'i, T>;
 
-    fn lock(chunk: &Chunk) -> Self::Guard<'_> {
-        chunk
-            .get_column(ComponentId::new::<T>())
-            .unwrap()
-            .write()
-            .unwrap()
+    fn try_lock(chunk: &Chunk) -> Option<Self::Guard<'_>> {
+        let comp_id = ComponentId::new::<T>();
+        debug_assert_ne!(
+            comp_id, *COLUMN_ENTITY_ID,
+            "Entity ids are read-only, use Entity argument"
+        );
+        Some(chunk.get_column(comp_id)?.write().unwrap())
     }
 
     fn iter<'a>(guard: &'a mut Self::Guard<'_>) -> Self::Iter<'a> {
@@ -58,12 +71,8 @@ where
     type Item<'r> = &'r T;
     type Iter<'i> = core::slice::Iter<'i, T>;
 
-    fn lock(chunk: &Chunk) -> Self::Guard<'_> {
-        chunk
-            .get_column(ComponentId::new::<T>())
-            .unwrap()
-            .read()
-            .unwrap()
+    fn try_lock(chunk: &Chunk) -> Option<Self::Guard<'_>> {
+        Some(chunk.get_column(ComponentId::new::<T>())?.read().unwrap())
     }
 
     fn iter<'a>(guard: &'a mut Self::Guard<'_>) -> Self::Iter<'a> {
@@ -71,15 +80,82 @@ where
     }
 }
 
-fn comp_id<L: Locker>() -> ComponentId {
-    ComponentId::new::<L::Ty>()
+///
+/// Argument yielding id of the entity for each visited row
+///
+pub struct Entity;
+
+impl Locker for Entity {
+    type Ty = EntityId;
+    type Guard<'g> = RwLockReadGuard<'g, Box<dyn ComponentStorage>>;
+    type Item<'r> = EntityId;
+    type Iter<'i> = core::iter::Copied<core::slice::Iter<'i, EntityId>>;
+
+    fn try_lock(chunk: &Chunk) -> Option<Self::Guard<'_>> {
+        Some(chunk.get_column(*COLUMN_ENTITY_ID)?.read().unwrap())
+    }
+
+    fn iter<'a>(guard: &'a mut Self::Guard<'_>) -> Self::Iter<'a> {
+        cast::<EntityId>(guard.as_ref()).iter().copied()
+    }
+}
+
+///
+/// Iterator over optional column: yields `None` for every row if column is missing
+///
+pub enum OptionIter<I> {
+    Present(I),
+    Missing(usize),
+}
+
+impl<I: Iterator> Iterator for OptionIter<I> {
+    type Item = Option<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            OptionIter::Present(it) => it.next().map(Some),
+            OptionIter::Missing(0) => None,
+            OptionIter::Missing(left) => {
+                *left -= 1;
+                Some(None)
+            }
+        }
+    }
+}
+
+///
+/// Optional argument (`Option<&T>` or `Option<&mut T>`), matches chunks with and without the column
+///
+impl<L> Locker for Option<L>
+where
+    L: Locker,
+{
+    type Ty = L::Ty;
+    type Guard<'g> = (Option<L::Guard<'g>>, usize);
+    type Item<'r> = Option<L::Item<'r>>;
+    type Iter<'i> = OptionIter<L::Iter<'i>>;
+
+    fn component() -> Option<ComponentId> {
+        None
+    }
+
+    fn try_lock(chunk: &Chunk) -> Option<Self::Guard<'_>> {
+        Some((L::try_lock(chunk), chunk.len()))
+    }
+
+    fn iter<'a>(guard: &'a mut Self::Guard<'_>) -> Self::Iter<'a> {
+        match guard {
+            (Some(g), _) => OptionIter::Present(L::iter(g)),
+            (None, rows) => OptionIter::Missing(*rows),
+        }
+    }
 }
 
 ///
 /// Visitor1
 ///
-struct Visitor1<A, H> {
-    component: ComponentId,
+pub(crate) struct Visitor1<A, H> {
+    component: Option<ComponentId>,
     handler: H,
     _phantom: PhantomData<A>,
 }
@@ -89,31 +165,47 @@ where
     H: Fn(A::Item<'_>),
     A: Locker,
 {
-    fn new(handler: H) -> Self {
+    pub(crate) fn new(handler: H) -> Self {
         Visitor1 {
-            component: comp_id::<A>(),
+            component: A::component(),
             handler,
             _phantom: PhantomData::default(),
         }
     }
 
+    #[cfg(test)]
     fn accept(&self, columns: &HashSet<ComponentId>) -> bool {
-        columns.contains(&self.component)
+        self.component.is_none_or(|c| columns.contains(&c))
+    }
+
+    ///
+    /// Columns chunk must have to be visited
+    ///
+    pub(crate) fn columns(&self) -> HashSet<ComponentId> {
+        self.component.into_iter().collect()
     }
 
-    fn visit(&self, chunk: &Chunk) {
+    ///
+    /// Passes every live row of the chunk to handler, returns number of visited rows
+    ///
+    pub(crate) fn visit(&self, chunk: &Chunk) -> usize {
         let mut guard1 = A::lock(chunk);
-        let mut it1 = A::iter(&mut guard1);
-        while let Some(v1) = it1.next() {
-            (self.handler)(v1);
+        let it1 = A::iter(&mut guard1);
+        let mut count = 0;
+        for (i, v1) in it1.enumerate() {
+            if !chunk.is_dead(i) {
+                (self.handler)(v1);
+                count += 1;
+            }
         }
+        count
     }
 }
 
 ///
 /// Visitor2
 ///
-struct Visitor2<A, B, H> {
+pub(crate) struct Visitor2<A, B, H> {
     components: Vec<ComponentId>,
     handler: H,
     _phantom: PhantomData<(A, B)>,
@@ -125,35 +217,55 @@ where
     A: Locker,
     B: Locker,
 {
-    fn new(handler: H) -> Self {
+    pub(crate) fn new(handler: H) -> Self {
         Visitor2 {
-            components: vec![comp_id::<A>(), comp_id::<B>()],
+            components: [A::component(), B::component()]
+                .into_iter()
+                .flatten()
+                .collect(),
             handler,
             _phantom: PhantomData::default(),
         }
     }
 
+    #[cfg(test)]
     fn accept(&self, columns: &HashSet<ComponentId>) -> bool {
         self.components.iter().all(|c| columns.contains(c))
     }
 
-    fn visit(&self, chunk: &Chunk) {
+    pub(crate) fn columns(&self) -> HashSet<ComponentId> {
+        self.components.iter().copied().collect()
+    }
+
+    pub(crate) fn visit(&self, chunk: &Chunk) -> usize {
         let mut guard1 = A::lock(chunk);
         let mut guard2 = B::lock(chunk);
-        let mut it1 = A::iter(&mut guard1);
-        let mut it2 = B::iter(&mut guard2);
-        while let (Some(v1), Some(v2)) = (it1.next(), it2.next()) {
-            (self.handler)(v1, v2);
+        let it1 = A::iter(&mut guard1);
+        let it2 = B::iter(&mut guard2);
+        let mut count = 0;
+        for (i, (v1, v2)) in it1.zip(it2).enumerate() {
+            if !chunk.is_dead(i) {
+                (self.handler)(v1, v2);
+                count += 1;
+            }
         }
+        count
     }
 }
 
 #[cfg(test)]
 mod test {
 
-    use crate::{archetype::ArchetypeStorage, build_archetype, entity::EntityId};
+    use std::{
+        cell::{Cell, RefCell},
+        collections::HashSet,
+    };
 
-    use super::{Visitor1, Visitor2};
+    use crate::{
+        archetype::ArchetypeStorage, build_archetype, component::ComponentId, entity::EntityId,
+    };
+
+    use super::{Entity, Visitor1, Visitor2};
 
     #[test]
     fn visitor1() {
@@ -194,4 +306,66 @@ mod test {
             vis.visit(chunk);
         }
     }
+
+    #[test]
+    fn optional() {
+        let mut with_name = ArchetypeStorage::new(build_archetype![i32, String], 1000);
+        let mut without_name = ArchetypeStorage::new(build_archetype![i32], 1000);
+        for i in 0..3 {
+            with_name.add(EntityId::new(i));
+            without_name.add(EntityId::new(10 + i));
+        }
+        let columns = HashSet::from([ComponentId::new::<i32>(), ComponentId::new::<EntityId>()]);
+
+        let names = Cell::new(0);
+        let rows = Cell::new(0);
+        let vis = Visitor2::<&mut i32, Option<&String>, _>::new(|v, name| {
+            *v += 1;
+            rows.set(rows.get() + 1);
+            if name.is_some() {
+                names.set(names.get() + 1);
+            }
+        });
+        assert!(vis.accept(&columns));
+        for chunk in with_name.iter().chain(without_name.iter()) {
+            vis.visit(chunk);
+        }
+        assert_eq!(6, rows.get());
+        assert_eq!(3, names.get());
+
+        // Only optional argument: every row is visited with None
+        let seen = RefCell::new(Vec::new());
+        let vis = Visitor1::<Option<&mut String>, _>::new(|v| {
+            seen.borrow_mut().push(v.is_some());
+        });
+        assert!(vis.accept(&HashSet::new()));
+        for chunk in without_name.iter().chain(with_name.iter()) {
+            vis.visit(chunk);
+        }
+        assert_eq!(vec![false, false, false, true, true, true], *seen.borrow());
+    }
+
+    #[test]
+    fn entity() {
+        let mut storage = ArchetypeStorage::new(build_archetype![i32], 1000);
+        for i in 0..4 {
+            storage.add(EntityId::new(i));
+        }
+        let seen = RefCell::new(Vec::new());
+        let vis = Visitor2::<Entity, &mut i32, _>::new(|id, v| {
+            *v += 1;
+            seen.borrow_mut().push(id);
+        });
+        assert!(vis.accept(&HashSet::from([
+            ComponentId::new::<EntityId>(),
+            ComponentId::new::<i32>()
+        ])));
+        for chunk in storage.iter() {
+            vis.visit(chunk);
+        }
+        assert_eq!(
+            (0..4).map(EntityId::new).collect::<Vec<_>>(),
+            *seen.borrow()
+        );
+    }
 }
@@ -0,0 +1,244 @@
+use std::collections::VecDeque;
+use std::ops::{Add, Mul, Sub};
+
+use crate::vec3f::Vector3f;
+
+///
+/// Linear interpolation, `t` = 0 gives `self` and `t` = 1 gives `to`
+///
+pub trait Lerp: Sized {
+    fn lerp(&self, to: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for f64 {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        self + (to - self) * t as f64
+    }
+}
+
+impl Lerp for Vector3f {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        *self + (*to - *self) * t
+    }
+}
+
+///
+/// Interpolates angles in degrees along the shortest arc
+///
+pub fn lerp_angle(from: f32, to: f32, t: f32) -> f32 {
+    let delta = (to - from + 180.0).rem_euclid(360.0) - 180.0;
+    from + delta * t
+}
+
+///
+/// Spherical interpolation of unit vectors, falls back to normalized lerp when they are almost parallel
+///
+pub fn slerp(from: Vector3f, to: Vector3f, t: f32) -> Vector3f {
+    let cos = from.dot(to).clamp(-1.0, 1.0);
+    if cos > 0.9995 {
+        return from.lerp(&to, t).normalize();
+    }
+    let angle = cos.acos() * t;
+    let ortho = (to - from * cos).normalize();
+    from * angle.cos() + ortho * angle.sin()
+}
+
+///
+/// Cubic Hermite spline between `p0` and `p1` with tangents `m0` and `m1`
+///
+pub fn hermite<T>(p0: T, m0: T, p1: T, m1: T, t: f32) -> T
+where
+    T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p0 * (2.0 * t3 - 3.0 * t2 + 1.0)
+        + m0 * (t3 - 2.0 * t2 + t)
+        + p1 * (-2.0 * t3 + 3.0 * t2)
+        + m1 * (t3 - t2)
+}
+
+///
+/// Moves `current` towards `target` so that half of the distance is covered every `half_life` seconds.
+/// Result doesn't depend on how `dt` is split between frames.
+///
+pub fn smooth_exp<T: Lerp>(current: &T, target: &T, half_life: f32, dt: f32) -> T {
+    if half_life <= 0.0 {
+        return current.lerp(target, 1.0);
+    }
+    current.lerp(
+        target,
+        1.0 - (-dt * std::f32::consts::LN_2 / half_life).exp(),
+    )
+}
+
+///
+/// Critically damped spring following the target without overshoot
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spring<T> {
+    pub value: T,
+    pub velocity: T,
+    /// Angular frequency, higher is stiffer
+    pub frequency: f32,
+}
+
+impl<T> Spring<T>
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    pub fn new(value: T, velocity: T, frequency: f32) -> Self {
+        Spring {
+            value,
+            velocity,
+            frequency,
+        }
+    }
+
+    pub fn update(&mut self, target: T, dt: f32) -> T {
+        // Exact solution of x'' = -w^2 (x - target) - 2w x'
+        let w = self.frequency;
+        let decay = (-w * dt).exp();
+        let offset = self.value - target;
+        let temp = (self.velocity + offset * w) * dt;
+        self.value = target + (offset + temp) * decay;
+        self.velocity = (self.velocity - temp * w) * decay;
+        self.value
+    }
+}
+
+///
+/// Value replicated at server ticks and sampled at render frame rate. Client renders it slightly in the past
+/// (by interpolation delay), so there are samples on both sides of the render time.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Interpolated<T> {
+    /// (server time, value), ordered by time
+    samples: VecDeque<(f64, T)>,
+}
+
+impl<T: Lerp + Clone> Interpolated<T> {
+    const MAX_SAMPLES: usize = 32;
+
+    ///
+    /// Adds value received from server. Samples older than the last one came out of order and are ignored.
+    ///
+    pub fn push(&mut self, time: f64, value: T) {
+        if self.samples.back().is_some_and(|(last, _)| *last >= time) {
+            return;
+        }
+        if self.samples.len() == Self::MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((time, value));
+    }
+
+    ///
+    /// Value at the given time. It's clamped to the oldest and the newest samples, no extrapolation is made.
+    ///
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let next = self.samples.partition_point(|(t, _)| *t <= time);
+        if next == 0 {
+            return self.samples.front().map(|(_, v)| v.clone());
+        }
+        let (t0, v0) = &self.samples[next - 1];
+        let Some((t1, v1)) = self.samples.get(next) else {
+            return Some(v0.clone());
+        };
+        Some(v0.lerp(v1, ((time - t0) / (t1 - t0)) as f32))
+    }
+
+    ///
+    /// Drops samples which can't be used for times after `time`
+    ///
+    pub fn discard_before(&mut self, time: f64) {
+        while self.samples.get(1).is_some_and(|(t, _)| *t <= time) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use super::*;
+
+    #[test]
+    fn lerp() {
+        assert_eq!(2.5, 2.0.lerp(&3.0, 0.5));
+        assert_eq!(
+            Vector3f::new(1., 2., 3.),
+            Vector3f::zero().lerp(&Vector3f::new(2., 4., 6.), 0.5)
+        );
+    }
+
+    #[test]
+    fn angles() {
+        assert_relative_eq!(0.0, lerp_angle(350.0, 10.0, 0.5).rem_euclid(360.0));
+        assert_relative_eq!(-10.0, lerp_angle(10.0, -30.0, 0.5));
+        let v = slerp(Vector3f::new(1., 0., 0.), Vector3f::new(0., 1., 0.), 0.5);
+        assert_relative_eq!(1.0, v.length(), epsilon = 1e-6);
+        assert_relative_eq!(v.x, v.y, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn hermite_ends() {
+        assert_eq!(1.0, hermite(1.0, 5.0, 3.0, -2.0, 0.0));
+        assert_eq!(3.0, hermite(1.0, 5.0, 3.0, -2.0, 1.0));
+        // Zero tangents give smooth step
+        assert_eq!(2.0, hermite(1.0, 0.0, 3.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn smoothing() {
+        assert_relative_eq!(5.0, smooth_exp(&0.0, &10.0, 1.0, 1.0), epsilon = 1e-5);
+        // Two half-frames make the same result as one frame
+        let half = smooth_exp(&0.0, &10.0, 0.3, 0.05);
+        assert_relative_eq!(
+            smooth_exp(&0.0, &10.0, 0.3, 0.1),
+            smooth_exp(&half, &10.0, 0.3, 0.05),
+            epsilon = 1e-5
+        );
+
+        let mut spring = Spring::new(0.0, 0.0, 10.0);
+        let mut max = 0.0f32;
+        for _ in 0..200 {
+            max = max.max(spring.update(1.0, 0.01));
+        }
+        assert!(max <= 1.0);
+        assert_relative_eq!(1.0, spring.value, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn interpolated() {
+        let mut value = Interpolated::default();
+        assert_eq!(None, value.sample(1.0));
+        value.push(1.0, 10.0);
+        value.push(2.0, 20.0);
+        value.push(1.5, 0.0);
+        value.push(3.0, 40.0);
+        assert_eq!(3, value.len());
+        assert_eq!(Some(10.0), value.sample(0.5));
+        assert_eq!(Some(15.0), value.sample(1.5));
+        assert_eq!(Some(30.0), value.sample(2.5));
+        assert_eq!(Some(40.0), value.sample(4.0));
+        value.discard_before(2.5);
+        assert_eq!(2, value.len());
+        assert_eq!(Some(30.0), value.sample(2.5));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod interp;
 pub mod matrix;
 pub mod vec3f;
 pub mod vec4f;
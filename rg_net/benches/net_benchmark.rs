@@ -0,0 +1,113 @@
+use std::hint::black_box;
+use std::net::{Ipv4Addr, SocketAddr};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rg_net::packet::MAX_HEADER_SIZE;
+use rg_net::{check_header, write_header, Message, PacketView, PlayerInput, ScoreEntry};
+
+fn scoreboard(names: &[String]) -> Message<'_> {
+    Message::Scoreboard {
+        entries: names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ScoreEntry {
+                name,
+                score: i as i32,
+                ping: 30 + i as u16,
+                team: (i % 2) as u8 + 1,
+            })
+            .collect(),
+    }
+}
+
+fn input(seq: u32) -> Message<'static> {
+    Message::Input {
+        seq,
+        input: PlayerInput {
+            forward: 1,
+            side: -1,
+            yaw: 90.0,
+            pitch: -10.0,
+            buttons: 1,
+        },
+    }
+}
+
+///
+/// Datagram of the typical server frame: pong, scoreboard and a bunch of chat lines
+///
+fn payload(names: &[String]) -> Vec<u8> {
+    let mut payload = bitcode::encode(&Message::Pong {
+        time: 1.0,
+        clock: None,
+    });
+    payload.extend(bitcode::encode(&scoreboard(names)));
+    for name in names.iter().take(4) {
+        payload.extend(bitcode::encode(&Message::Chat {
+            from: name,
+            text: "see you at the rail gun",
+            team_only: false,
+        }));
+    }
+    payload
+}
+
+fn net_benchmark(c: &mut Criterion) {
+    let names: Vec<_> = (0..16).map(|i| format!("player #{i}")).collect();
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 1));
+    let payload = payload(&names);
+
+    c.bench_function("net encode input", |b| {
+        b.iter(|| bitcode::encode(&input(black_box(1))))
+    });
+    c.bench_function("net encode scoreboard", |b| {
+        let msg = scoreboard(&names);
+        b.iter(|| bitcode::encode(black_box(&msg)))
+    });
+    for checksum in [false, true] {
+        let mut packet = Vec::with_capacity(payload.len() + MAX_HEADER_SIZE);
+        write_header(&mut packet, &payload, checksum);
+        packet.extend_from_slice(&payload);
+        c.bench_function(&format!("net write header, checksum={checksum}"), |b| {
+            b.iter_batched_ref(
+                || Vec::with_capacity(packet.len()),
+                |out| write_header(out, black_box(&payload), checksum),
+                BatchSize::SmallInput,
+            )
+        });
+        c.bench_function(&format!("net check header, checksum={checksum}"), |b| {
+            b.iter(|| check_header(black_box(&packet)))
+        });
+    }
+    c.bench_function("net read frame", |b| {
+        b.iter(|| {
+            let mut view = PacketView::new(black_box(&payload), addr);
+            let mut count = 0;
+            while view.read().is_some() {
+                count += 1;
+            }
+            count
+        })
+    });
+    c.bench_function("net round trip 64 inputs", |b| {
+        b.iter(|| {
+            let mut payload = Vec::new();
+            for seq in 0..64 {
+                payload.extend(bitcode::encode(&input(seq)));
+            }
+            let mut packet = Vec::with_capacity(payload.len() + MAX_HEADER_SIZE);
+            write_header(&mut packet, &payload, true);
+            packet.extend_from_slice(&payload);
+            let header = check_header(&packet).unwrap();
+            let mut view = PacketView::new(&packet[header..], addr);
+            let mut count = 0;
+            while view.read().is_some() {
+                count += 1;
+            }
+            count
+        })
+    });
+}
+
+criterion_group!(benches, net_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+pub use message::Bytes;
+pub use message::Message;
+pub use message::PlayerInput;
+pub use message::ScoreEntry;
+pub use message::ServerClock;
+pub use message::PROTOCOL_VERSION;
+pub use packet::check_header;
+pub use packet::write_header;
+pub use packet::PacketView;
+pub use packet::MAX_DATAGRAM_SIZE;
+pub use packet::MAX_PAYLOAD_SIZE;
+
+pub mod message;
+pub mod packet;
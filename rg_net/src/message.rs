@@ -0,0 +1,280 @@
+use std::fmt::{Debug, Formatter};
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+
+use bitcode::__private::{Buffer, Decoder, Encoder, View};
+use bitcode::{Decode, Encode};
+use rg_common::{SessionId, Tick};
+
+///
+/// Bumped on every incompatible change of [`Message`] layout, server drops clients speaking another version
+///
+pub const PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub enum Message<'a> {
+    Ack,
+    Connect {
+        version: u32,
+        name: &'a str,
+        password: Bytes<'a>,
+        rate: u32,
+        ticket: Bytes<'a>,
+    },
+    Accepted,
+    Hello,
+    ServerInfo {
+        key: Bytes<'a>,
+    },
+    Ping {
+        time: f64,
+    },
+    Pong {
+        time: f64,
+        clock: Option<ServerClock>,
+    },
+    CallVote {
+        kind: &'a str,
+        arg: &'a str,
+    },
+    CastVote {
+        yes: bool,
+    },
+    VoteStatus {
+        kind: &'a str,
+        arg: &'a str,
+        yes: u16,
+        no: u16,
+        time_left: f32,
+    },
+    VoteEnded {
+        kind: &'a str,
+        arg: &'a str,
+        passed: bool,
+    },
+    Session {
+        token: SessionId,
+    },
+    Reconnect {
+        token: SessionId,
+    },
+    ///
+    /// Sent by server to the new address of the roaming client, session is moved only after client echoes it back
+    ///
+    Challenge {
+        nonce: u64,
+    },
+    ChallengeResponse {
+        token: SessionId,
+        nonce: u64,
+    },
+    ///
+    /// Server has dropped the client (kicked by admin)
+    ///
+    Disconnect {
+        reason: &'a str,
+    },
+    ///
+    /// Current game mode and its HUD state (encoded by the mode)
+    ///
+    ModeStatus {
+        mode: &'a str,
+        state: Bytes<'a>,
+    },
+    ///
+    /// Match is over, empty winner means draw
+    ///
+    MatchEnded {
+        mode: &'a str,
+        winner: &'a str,
+    },
+    Scoreboard {
+        entries: Vec<ScoreEntry<'a>>,
+    },
+    ///
+    /// Request to move to another team
+    ///
+    JoinTeam {
+        team: u8,
+    },
+    TeamAssigned {
+        team: u8,
+    },
+    ///
+    /// Chat line from client, team only lines are delivered to teammates
+    ///
+    Say {
+        text: &'a str,
+        team_only: bool,
+    },
+    ///
+    /// Chat line routed by server
+    ///
+    Chat {
+        from: &'a str,
+        text: &'a str,
+        team_only: bool,
+    },
+    ///
+    /// Player controls, sequence number lets server skip reordered inputs
+    ///
+    Input {
+        seq: u32,
+        input: PlayerInput,
+    },
+    ///
+    /// Connection quality band (0..4) measured by server, sent when it changes
+    ///
+    LinkQuality {
+        band: u8,
+    },
+    ///
+    /// Countdown between matches, empty map means the same map is played again
+    ///
+    Intermission {
+        next_map: &'a str,
+        time_left: u32,
+    },
+    ///
+    /// New match has started, sent on connect as well. Empty map if server has no map rotation.
+    ///
+    MapChanged {
+        map: &'a str,
+    },
+}
+
+///
+/// Movement controls sampled by client
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode)]
+pub struct PlayerInput {
+    /// -1 back, 1 forward
+    pub forward: i8,
+    /// -1 left, 1 right
+    pub side: i8,
+    /// Degrees
+    pub yaw: f32,
+    /// Degrees
+    pub pitch: f32,
+    pub buttons: u8,
+}
+
+///
+/// Scoreboard line of a single player
+///
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+pub struct ScoreEntry<'a> {
+    pub name: &'a str,
+    pub score: i32,
+    /// Round trip in milliseconds
+    pub ping: u16,
+    /// Zero means no team
+    pub team: u8,
+}
+
+///
+/// Server clock sampled when replying to ping
+///
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+pub struct ServerClock {
+    pub tick: Tick,
+    /// Seconds since server start
+    pub time: f64,
+}
+
+///
+/// Borrowed byte payload. Bitcode is only able to borrow `&str` from the input, so byte slices
+/// are encoded as a block of little-endian `u32` lengths followed by raw bytes.
+///
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Bytes<'a>(pub &'a [u8]);
+
+impl Debug for Bytes<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bytes({})", self.0.len())
+    }
+}
+
+impl Deref for Bytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl Encode for Bytes<'_> {
+    type Encoder = BytesEncoder;
+}
+
+impl<'a> Decode<'a> for Bytes<'a> {
+    type Decoder = BytesDecoder<'a>;
+}
+
+#[doc(hidden)]
+#[derive(Default)]
+pub struct BytesEncoder {
+    lengths: Vec<u8>,
+    bytes: Vec<u8>,
+}
+
+impl Buffer for BytesEncoder {
+    fn collect_into(&mut self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.lengths);
+        out.extend_from_slice(&self.bytes);
+        self.lengths.clear();
+        self.bytes.clear();
+    }
+
+    fn reserve(&mut self, additional: NonZeroUsize) {
+        self.lengths.reserve(additional.get() * size_of::<u32>());
+    }
+}
+
+impl Encoder<Bytes<'_>> for BytesEncoder {
+    fn encode(&mut self, t: &Bytes<'_>) {
+        self.lengths
+            .extend_from_slice(&(t.0.len() as u32).to_le_bytes());
+        self.bytes.extend_from_slice(t.0);
+    }
+}
+
+#[doc(hidden)]
+#[derive(Default)]
+pub struct BytesDecoder<'a> {
+    lengths: &'a [u8],
+    bytes: &'a [u8],
+}
+
+impl<'a> View<'a> for BytesDecoder<'a> {
+    fn populate(&mut self, input: &mut &'a [u8], length: usize) -> bitcode::__private::Result<()> {
+        let (lengths, rest) = input
+            .split_at_checked(length * size_of::<u32>())
+            .ok_or_else(eof)?;
+        let total = lengths
+            .chunks_exact(size_of::<u32>())
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()) as usize)
+            .sum();
+        let (bytes, rest) = rest.split_at_checked(total).ok_or_else(eof)?;
+        self.lengths = lengths;
+        self.bytes = bytes;
+        *input = rest;
+        Ok(())
+    }
+}
+
+impl<'a> Decoder<'a, Bytes<'a>> for BytesDecoder<'a> {
+    fn decode(&mut self) -> Bytes<'a> {
+        let (len, lengths) = self.lengths.split_at(size_of::<u32>());
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.lengths = lengths;
+        self.bytes = rest;
+        Bytes(bytes)
+    }
+}
+
+// Bitcode doesn't expose its error constructor, so borrow the one used by derived code.
+fn eof() -> bitcode::Error {
+    bitcode::__private::invalid_enum_variant::<()>().unwrap_err()
+}
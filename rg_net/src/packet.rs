@@ -0,0 +1,215 @@
+use std::net::SocketAddr;
+
+use bitcode::__private::{Decoder, View};
+use bitcode::Decode;
+use log::warn;
+
+use crate::message::Message;
+
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+
+///
+/// Every datagram starts with flags byte. With [`FLAG_CHECKSUM`] set it's followed by CRC32C of the payload
+/// (little-endian), so receiver may reject datagrams corrupted in a way weak UDP checksum doesn't catch.
+///
+pub const FLAG_CHECKSUM: u8 = 1;
+pub const MAX_HEADER_SIZE: usize = 5;
+pub const MAX_PAYLOAD_SIZE: usize = MAX_DATAGRAM_SIZE - MAX_HEADER_SIZE;
+
+pub fn write_header(out: &mut Vec<u8>, payload: &[u8], checksum: bool) {
+    if checksum {
+        out.push(FLAG_CHECKSUM);
+        out.extend_from_slice(&crc32c::crc32c(payload).to_le_bytes());
+    } else {
+        out.push(0);
+    }
+}
+
+///
+/// Returns header size or `None` if header is malformed or checksum doesn't match
+///
+pub fn check_header(data: &[u8]) -> Option<usize> {
+    let (&flags, rest) = data.split_first()?;
+    match flags {
+        0 => Some(1),
+        FLAG_CHECKSUM => {
+            let (crc, payload) = rest.split_first_chunk::<4>()?;
+            (crc32c::crc32c(payload) == u32::from_le_bytes(*crc)).then_some(MAX_HEADER_SIZE)
+        }
+        _ => None,
+    }
+}
+
+///
+/// Read-only view over the received datagram. Decoded messages borrow strings and byte payloads
+/// directly from the receive buffer, so nothing is copied on the hot path.
+///
+pub struct PacketView<'a> {
+    pub addr: SocketAddr,
+    slice: &'a [u8],
+    decoder: <Message<'a> as bitcode::Decode<'a>>::Decoder,
+}
+
+impl<'a> PacketView<'a> {
+    pub fn new(slice: &'a [u8], addr: SocketAddr) -> Self {
+        PacketView {
+            addr,
+            slice,
+            decoder: <Message<'_> as bitcode::Decode>::Decoder::default(),
+        }
+    }
+
+    ///
+    /// Returns number of bytes not decoded yet
+    ///
+    pub fn len(&self) -> usize {
+        self.slice.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slice.is_empty()
+    }
+
+    ///
+    /// Decodes next message. Returns `None` when datagram is exhausted or malformed (the rest of it is dropped then).
+    ///
+    pub fn read(&mut self) -> Option<Message<'a>> {
+        if self.is_empty() {
+            return None;
+        }
+        if let Err(e) = self.decoder.populate(&mut self.slice, 1) {
+            warn!("Dropping malformed datagram from {}: {e}", self.addr);
+            self.slice = &[];
+            return None;
+        }
+        Some(decode_inline_never(&mut self.decoder))
+    }
+}
+
+#[inline(never)]
+fn decode_inline_never<'a, T: Decode<'a>>(decoder: &mut T::Decoder) -> T {
+    decoder.decode()
+}
+
+///
+/// Tests
+///
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::message::{Bytes, Message, PROTOCOL_VERSION};
+
+    use super::{check_header, write_header, PacketView};
+
+    #[test]
+    fn packet_view_borrows_from_buffer() {
+        let mut buf = bitcode::encode(&Message::Connect {
+            version: PROTOCOL_VERSION,
+            name: "player",
+            password: Bytes(&[1, 2, 3, 4, 5]),
+            rate: 0,
+            ticket: Bytes(&[]),
+        });
+        buf.extend(bitcode::encode(&Message::ServerInfo { key: Bytes(&[]) }));
+        buf.extend(bitcode::encode(&Message::Ping { time: 1.5 }));
+        let range = buf.as_ptr_range();
+        let mut view = PacketView::new(&buf, SocketAddr::from((Ipv4Addr::LOCALHOST, 1)));
+
+        match view.read() {
+            Some(Message::Connect { name, password, .. }) => {
+                assert_eq!("player", name);
+                assert_eq!(&[1, 2, 3, 4, 5], password.0);
+                assert!(range.contains(&name.as_ptr()));
+                assert!(range.contains(&password.as_ptr()));
+            }
+            m => panic!("Unexpected message: {m:?}"),
+        }
+        assert!(matches!(view.read(), Some(Message::ServerInfo { key }) if key.is_empty()));
+        assert!(matches!(view.read(), Some(Message::Ping { time }) if time == 1.5));
+        assert!(view.read().is_none());
+    }
+
+    #[test]
+    fn malformed_packet_is_dropped() {
+        let buf = bitcode::encode(&Message::Connect {
+            version: PROTOCOL_VERSION,
+            name: "player",
+            password: Bytes(&[1, 2, 3, 4, 5]),
+            rate: 0,
+            ticket: Bytes(&[]),
+        });
+        let mut view = PacketView::new(
+            &buf[..buf.len() - 2],
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 1)),
+        );
+        assert!(view.read().is_none());
+        assert!(view.read().is_none());
+    }
+
+    ///
+    /// Quick stand-in for the `packet` fuzz target. Seed may be set with `RG_NET_SEED` to reproduce a failure.
+    ///
+    #[test]
+    fn random_datagrams_are_rejected_gracefully() {
+        let seed = std::env::var("RG_NET_SEED")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0x5eed);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 1));
+        let valid = bitcode::encode(&Message::Connect {
+            version: PROTOCOL_VERSION,
+            name: "player",
+            password: Bytes(&[1, 2, 3]),
+            rate: 0,
+            ticket: Bytes(&[]),
+        });
+        for i in 0..20_000 {
+            // Half of inputs are valid packets with a few bytes flipped, others are pure noise
+            let buf: Vec<u8> = if i % 2 == 0 {
+                let mut buf = valid.clone();
+                for _ in 0..rng.gen_range(1..4) {
+                    let at = rng.gen_range(0..buf.len());
+                    buf[at] = rng.gen();
+                }
+                buf
+            } else {
+                (0..rng.gen_range(0..64)).map(|_| rng.gen()).collect()
+            };
+            let _ = check_header(&buf);
+            let mut view = PacketView::new(&buf, addr);
+            let mut count = 0;
+            while view.read().is_some() {
+                count += 1;
+                assert!(
+                    count <= buf.len(),
+                    "Endless packet at input {i}, reproduce with RG_NET_SEED={seed}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn checksum() {
+        let payload = bitcode::encode(&Message::Ping { time: 1.0 });
+        for (enabled, size) in [(false, 1), (true, 5)] {
+            let mut packet = Vec::new();
+            write_header(&mut packet, &payload, enabled);
+            packet.extend_from_slice(&payload);
+            assert_eq!(Some(size), check_header(&packet));
+        }
+        let mut packet = Vec::new();
+        write_header(&mut packet, &payload, true);
+        packet.extend_from_slice(&payload);
+        let last = packet.len() - 1;
+        packet[last] ^= 0x10;
+        assert_eq!(None, check_header(&packet));
+        assert_eq!(None, check_header(&packet[..3]));
+        assert_eq!(None, check_header(&[]));
+        assert_eq!(None, check_header(&[7, 1, 2]));
+    }
+}